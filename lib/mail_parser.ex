@@ -1,6 +1,30 @@
 defmodule MailParser do
   @moduledoc """
   NIF binding of mail_parser using Rustler.
+
+  Functions that parse a raw message or copy attachment contents run on
+  dirty CPU schedulers, so large messages do not block the normal
+  schedulers.
+
+  Raw messages can be given as iodata, e.g. as a list of header and body
+  chunks, sparing the caller a call to `IO.iodata_to_binary/1`. Lists are
+  copied once into a new binary, from which attachment contents are
+  returned as sub-binaries just as they are from a binary.
+
+  ## Errors
+
+  Failures are returned as `{:error, reason}`, where `reason` is one of:
+
+    * `:empty_input` - the raw message is empty or blank.
+    * `:not_a_message` - no header is found in the raw message.
+    * `{:io, posix, path}` - the file at `path` could not be read or
+      written, with `posix` the POSIX error code, e.g. `:enoent` or
+      `:enospc`.
+    * `:limit_exceeded` - an attachment exceeds one of the limits, with
+      `on_limit: :error`.
+    * `:budget_exceeded` - the call decoded more than `:max_memory_bytes`.
+
+  along with the reasons specific to some functions, documented with them.
   """
 
   mix_config = Mix.Project.config()
@@ -15,14 +39,437 @@ defmodule MailParser do
     force_build: System.get_env("FORCE_BUILD") in ["1", "true"],
     version: version
 
-  alias __MODULE__.Attachment
+  alias __MODULE__.{Address, Attachment, Contact, ExtractedAttachment, Header}
+
+  @typedoc """
+  A handle to a message parsed by `parse/1`.
+  """
+  @opaque message :: reference()
+  @opaque mbox :: reference()
+  @opaque maildir :: reference()
+  @opaque parse_task :: {reference(), pid(), reference()}
+
+  @type parse_error :: {:error, :empty_input | :not_a_message}
+
+  @type io_error :: {:error, {:io, File.posix(), Path.t()}}
+
+  @type limit_error :: {:error, :limit_exceeded | :budget_exceeded}
+
+  @type parse_option :: {:lenient, boolean()} | {:max_memory_bytes, non_neg_integer()}
+
+  @type extract_option ::
+          {:include_inline, boolean()}
+          | {:mime_types, [String.t()]}
+          | {:match_content_type, :declared | :detected}
+          | {:extensions, [String.t()]}
+          | {:name_glob, String.t()}
+          | {:min_size, non_neg_integer()}
+          | {:max_size, non_neg_integer()}
+          | {:max_attachment_size, non_neg_integer()}
+          | {:max_total_size, non_neg_integer()}
+          | {:max_attachments, non_neg_integer()}
+          | {:max_depth, non_neg_integer()}
+          | {:on_limit, :error | :skip}
+          | {:nested, :flatten | :keep | :both}
+          | {:digests, [:sha256 | :sha1 | :md5]}
+          | {:dedupe, boolean()}
+          | {:expand_tnef, boolean()}
+          | {:uudecode, boolean()}
+          | {:expand_archives, boolean()}
+          | {:max_archive_entry_size, non_neg_integer()}
+          | {:max_archive_size, non_neg_integer()}
+          | {:name_fallback, :untitled | :extension | :content_id | {:indexed, String.t()}}
+          | {:max_memory_bytes, non_neg_integer()}
+          | {:include_part_headers, boolean()}
+          | {:decompress, boolean()}
+          | {:max_decompressed_size, non_neg_integer()}
+
+  @type message_spec :: %{
+          optional(:headers) => [{String.t(), String.t()}] | %{String.t() => String.t()},
+          optional(:text) => String.t() | nil,
+          optional(:html) => String.t() | nil,
+          optional(:attachments) => [attachment_spec() | Attachment.t()]
+        }
+
+  @type attachment_spec :: %{
+          required(:content_bytes) => binary(),
+          optional(:name) => String.t(),
+          optional(:content_type) => String.t() | nil,
+          optional(:inline) => boolean(),
+          optional(:content_id) => String.t() | nil
+        }
+
+  @type strip_option :: extract_option() | {:placeholder, String.t() | nil}
+
+  @type json_option :: extract_option() | {:content_bytes, boolean()}
+
+  @type body_option :: {:decompress, boolean()} | {:max_decompressed_size, non_neg_integer()}
+
+  @type html_option ::
+          body_option()
+          | {:sanitize, boolean()}
+          | {:remote_images, :allow | :block | String.t()}
+
+  @type disk_option ::
+          extract_option()
+          | {:sanitize, :strict | :reject}
+          | {:on_conflict, :overwrite | :error | :rename}
+          | {:atomic, boolean()}
+          | {:fsync, boolean()}
+          | {:path_template, String.t()}
+
+  @type stream_option :: extract_option() | {:chunk_size, pos_integer()}
+
+  @type date_format :: :unix | :iso8601
+
+  @type warning :: %{
+          reason:
+            :missing_headers
+            | :truncated_headers
+            | :truncated_body
+            | :missing_boundary
+            | :invalid_encoding_in_part,
+          path: [non_neg_integer()]
+        }
+
+  @type thread_info :: %{
+          message_id: String.t() | nil,
+          in_reply_to: [String.t()],
+          references: [String.t()],
+          base_subject: String.t() | nil
+        }
+
+  @type list_info :: %{
+          id: String.t() | nil,
+          name: String.t() | nil,
+          unsubscribe: [String.t()],
+          one_click_unsubscribe: String.t() | nil,
+          post: [String.t()],
+          precedence: String.t() | nil,
+          auto_submitted: String.t() | nil,
+          suppress_auto_reply: boolean()
+        }
+
+  @type security_info :: %{
+          signed: boolean(),
+          encrypted: boolean(),
+          protocol: :smime | :pgp | nil,
+          digest_algorithm: String.t() | nil,
+          encryption_algorithm: String.t() | nil,
+          signers: [signer()]
+        }
+
+  @type signer :: %{
+          name: String.t() | nil,
+          email: String.t() | nil,
+          organization: String.t() | nil,
+          issuer: String.t() | nil,
+          serial_number: String.t()
+        }
+
+  @type html_with_inline_parts :: %{
+          html: String.t(),
+          inline_parts: %{String.t() => %{content_type: String.t(), content_bytes: binary()}}
+        }
+
+  @type part_structure :: %{
+          path: [non_neg_integer()],
+          content_type: String.t() | nil,
+          disposition: :inline | :attachment | nil,
+          size: non_neg_integer(),
+          encoding: String.t() | nil,
+          children: [part_structure()]
+        }
+
+  @type part :: %{
+          path: [non_neg_integer()],
+          content_type: String.t() | nil,
+          headers: [Header.t()],
+          content_bytes: binary()
+        }
+
+  @type attachment_info :: %{
+          name: String.t(),
+          raw_name: String.t() | nil,
+          content_type: String.t() | nil,
+          size: non_neg_integer(),
+          disposition: :inline | :attachment,
+          content_id: String.t() | nil,
+          path: [non_neg_integer()],
+          digests: Attachment.digests(),
+          part_headers: Attachment.part_headers() | nil,
+          duplicates: non_neg_integer()
+        }
+
+  @type embedded_message :: %{
+          path: [non_neg_integer()],
+          content_type: String.t() | nil,
+          message: message()
+        }
+
+  @type stats :: %{
+          size: non_neg_integer(),
+          header_size: non_neg_integer(),
+          parts: non_neg_integer(),
+          attachments: non_neg_integer(),
+          attachments_size: non_neg_integer(),
+          encodings: [String.t()],
+          depth: non_neg_integer(),
+          has_text_body: boolean(),
+          has_html_body: boolean()
+        }
+
+  @type mbox_option :: extract_option() | {:max_messages, non_neg_integer()}
+
+  @type metrics :: %{
+          parses: non_neg_integer(),
+          parse_duration: non_neg_integer(),
+          bytes_parsed: non_neg_integer(),
+          attachments_decoded: non_neg_integer(),
+          bytes_written: non_neg_integer()
+        }
+
+  @type maildir_option ::
+          {:max_messages, non_neg_integer()}
+          | {:parallel, boolean()}
+          | {:max_memory_bytes, non_neg_integer()}
+
+  @type maildir_result ::
+          {Path.t(), {:ok, message()} | parse_error() | io_error() | {:error, :budget_exceeded}}
+
+  @type addresses :: %{
+          from: [Address.t()],
+          to: [Address.t()],
+          cc: [Address.t()],
+          bcc: [Address.t()],
+          reply_to: [Address.t()],
+          sender: [Address.t()]
+        }
+
+  @type delivery_status :: %{
+          type: :delivery_status,
+          reporting_mta: String.t() | nil,
+          recipients: [recipient_status()],
+          original_headers: [Header.t()]
+        }
+
+  @type recipient_status :: %{
+          final_recipient: String.t() | nil,
+          original_recipient: String.t() | nil,
+          action: String.t() | nil,
+          status: String.t() | nil,
+          diagnostic_code: String.t() | nil,
+          remote_mta: String.t() | nil
+        }
+
+  @type disposition_notification :: %{
+          type: :disposition_notification,
+          reporting_ua: String.t() | nil,
+          original_recipient: String.t() | nil,
+          final_recipient: String.t() | nil,
+          original_message_id: String.t() | nil,
+          disposition: String.t() | nil,
+          original_headers: [Header.t()]
+        }
+
+  @type auth_results :: %{
+          authentication_results: [auth_result()],
+          received_spf: [received_spf()],
+          dkim_signatures: [dkim_signature()]
+        }
+
+  @type auth_result :: %{
+          authserv_id: String.t() | nil,
+          method: String.t(),
+          result: String.t(),
+          reason: String.t() | nil,
+          domain: String.t() | nil,
+          selector: String.t() | nil,
+          properties: %{String.t() => String.t()}
+        }
+
+  @type received_spf :: %{
+          result: String.t(),
+          domain: String.t() | nil,
+          client_ip: String.t() | nil
+        }
+
+  @type dkim_signature :: %{
+          domain: String.t() | nil,
+          selector: String.t() | nil,
+          algorithm: String.t() | nil
+        }
+
+  @type received_chain :: %{hops: [hop()], originating_ip: String.t() | nil}
+
+  @type hop :: %{
+          from_host: String.t() | nil,
+          from_ip: String.t() | nil,
+          by_host: String.t() | nil,
+          protocol: String.t() | nil,
+          timestamp: integer() | nil
+        }
+
+  @type participant :: %{
+          name: String.t() | nil,
+          email: String.t() | nil,
+          status: String.t() | nil
+        }
+
+  @type calendar_event :: %{
+          method: String.t() | nil,
+          uid: String.t() | nil,
+          summary: String.t() | nil,
+          organizer: participant() | nil,
+          attendees: [participant()],
+          dtstart: String.t() | nil,
+          dtend: String.t() | nil,
+          timezone: String.t() | nil,
+          rrule: String.t() | nil
+        }
 
   @doc """
-  Parses a string containing a RFC5322 raw message and extracts all nested
+  Parses a binary containing a RFC5322 raw message and extracts all nested
   attachments.
 
-  A best-effort is made to parse the message and if no headers are found
-  `:error` is returned.
+  The raw message does not need to be valid UTF-8, so 8-bit bodies and
+  broken encodings are accepted. A best-effort is made to parse the message
+  and if no headers are found `{:error, :not_a_message}` is returned.
+
+  ### Options
+
+    * `:include_inline` - whether inline parts, such as images referenced
+      from the HTML body, are returned along with regular attachments.
+      Defaults to `true`.
+
+    * `:mime_types` - only returns attachments whose content type matches
+      one of the given patterns. Patterns are matched case-insensitively
+      and either side may be a `*` wildcard, e.g. `"image/*"` or
+      `"*/pdf"`. Attachments without a content type never match.
+
+    * `:match_content_type` - which content type of an attachment the
+      `:mime_types` patterns are matched against. `:declared` uses its
+      Content-Type header, `:detected` the content type detected from its
+      contents, such as `"application/pdf"` for an attachment declared as
+      `application/octet-stream`, falling back to the declared one when
+      none is detected. Defaults to `:declared`.
+
+    * `:extensions` - only returns attachments whose name ends with one of
+      the given extensions, matched case-insensitively with or without
+      their leading dot, e.g. `[".pdf", "docx", ".tar.gz"]`.
+
+    * `:name_glob` - only returns attachments whose name matches the given
+      glob pattern case-insensitively, where `*` matches any sequence of
+      characters and `?` any single character, e.g. `"invoice*.pdf"`.
+
+    * `:min_size` - only returns attachments of at least this decoded
+      size, in bytes, e.g. to leave out tracking pixels.
+
+    * `:max_size` - only returns attachments of at most this decoded size,
+      in bytes. Unlike with `:max_attachment_size`, larger attachments are
+      left out regardless of `:on_limit`.
+
+    * `:max_attachment_size` - the maximum decoded size of a single
+      attachment, in bytes.
+
+    * `:max_total_size` - the maximum decoded size of all returned
+      attachments, in bytes.
+
+    * `:max_attachments` - the maximum number of returned attachments.
+
+    * `:max_depth` - the maximum number of nested messages to descend into
+      when collecting attachments. Defaults to `10`.
+
+    * `:on_limit` - what to do when an attachment or nested message exceeds
+      one of the limits above. `:error` aborts the extraction and returns
+      `{:error, :limit_exceeded}`, `:skip` leaves it out.
+      Defaults to `:error`.
+
+    * `:nested` - how attached messages are returned. `:flatten` returns
+      the attachments of attached messages, `:keep` returns each attached
+      message as a single `.eml` attachment without descending into it and
+      `:both` returns the message followed by its attachments.
+      The message wrapped by an opaque-signed S/MIME part
+      (`application/pkcs7-mime`) is treated as an attached message, so
+      that its attachments are returned instead of the `smime.p7m` part.
+      Defaults to `:flatten`.
+
+    * `:digests` - the digests computed for every returned attachment, any
+      of `:sha256`, `:sha1` and `:md5`. They are returned hex encoded in
+      the `:digests` map of each attachment. Defaults to `[]`.
+
+    * `:dedupe` - when `true`, attachments whose contents are identical to
+      those of an earlier attachment, e.g. a logo repeated in every message
+      of a forwarded chain, are left out. Duplicates do not count towards
+      the limits above. The number of duplicates left out of each returned
+      attachment is reported in its `:duplicates` field. Defaults to
+      `false`.
+
+    * `:expand_tnef` - when `true`, TNEF parts (`application/ms-tnef`,
+      usually named `winmail.dat`) sent by Outlook are replaced by the files
+      they contain, with the names and content types recorded in them.
+      Parts that cannot be decoded are returned as is. Defaults to `false`.
+
+    * `:uudecode` - when `true`, files embedded as uuencoded
+      `begin 644 name` ... `end` blocks in the text body of a message, as
+      sent by mailers predating MIME, are returned as attachments before
+      the MIME attachments of the message. Defaults to `false`.
+
+    * `:expand_archives` - when `true`, ZIP, gzip and tar attachments,
+      recognized by their contents, are replaced by the files they contain,
+      named after their path within the archive. Gzip compressed tar
+      archives are unpacked as tar archives, but archives found within
+      archives are not. Archives that are malformed, encrypted or exceed
+      any of the limits below are returned as is. The filters and limits of
+      the other options apply to the unpacked files. Defaults to `false`.
+
+    * `:max_archive_entry_size` - the maximum decompressed size of a file
+      unpacked from an archive, in bytes. Defaults to 64 MiB.
+
+    * `:max_archive_size` - the maximum decompressed size of all the files
+      unpacked from an archive, in bytes. Decompression stops as soon as it
+      is exceeded, protecting against zip bombs. Defaults to 256 MiB.
+
+    * `:name_fallback` - how attachments without a name are named.
+      `:untitled` names them `"untitled"`, or `"untitled.eml"` for attached
+      messages. `:extension` adds the extension of their detected or
+      declared content type, e.g. `"untitled.pdf"`. `:content_id` uses
+      their Content-ID up to its `@`, e.g. `"image001.png"` for
+      `<image001.png@01D9C3A1>`, adding an extension if it has none.
+      `{:indexed, pattern}` replaces `%d` in `pattern` by the position of
+      the attachment among the returned ones, starting at 1, and adds an
+      extension, e.g. `"attachment-3.pdf"` for `{:indexed, "attachment-%d"}`.
+      Names are given once attachments are selected, so `:extensions` and
+      `:name_glob` match unnamed attachments as `"untitled"`.
+      Defaults to `:untitled`.
+
+    * `:max_memory_bytes` - the maximum number of bytes the call may
+      decode, counting the bodies of the message decoded by the parser and
+      the files unpacked from archives, TNEF parts, uuencoded text and
+      opaque-signed parts. Archives are unpacked within the budget left,
+      so a zip bomb is stopped as soon as it exceeds it. The bodies decoded
+      by the parser are only counted once the message is parsed, so the
+      parser may decode more than the budget before the call is aborted.
+      The call is aborted with `{:error, :budget_exceeded}` regardless of
+      `:on_limit`. Unlimited by default.
+
+    * `:include_part_headers` - when `true`, the headers of the part of
+      each attachment are returned in its `:part_headers`, such as its
+      Content-Description, its Content-Disposition along with parameters
+      like `size` and `creation-date`, or the X-Attachment-Id set by Gmail.
+      Defaults to `false`.
+
+    * `:decompress` - when `true`, attachments compressed by a gateway are
+      replaced by their payload: parts declaring a `gzip` or `deflate`
+      Content-Encoding header keep their name and content type, and gzip
+      files other than tar archives, such as gzipped `.eml` files, are
+      named after the file they compress, e.g. `"forward.eml"` for
+      `"forward.eml.gz"`. Attachments that are malformed or exceed
+      `:max_decompressed_size` are returned as is. Defaults to `false`.
+
+    * `:max_decompressed_size` - the maximum decompressed size of an
+      attachment, in bytes. Decompression stops as soon as it is exceeded.
+      Defaults to 64 MiB.
 
   ### Example
 
@@ -30,6 +477,1184 @@ defmodule MailParser do
       {:ok, [%MailParser.Attachment{name: "example.pdf", content_type: "application/pdf", content_bytes: "..."}]}
 
   """
-  @spec extract_nested_attachments(String.t()) :: {:ok, [Attachment.t()]} | :error
-  def extract_nested_attachments(_raw_message), do: :erlang.nif_error(:nif_not_loaded)
+  @spec extract_nested_attachments(iodata(), [extract_option()]) ::
+          {:ok, [Attachment.t()]} | parse_error() | limit_error()
+  def extract_nested_attachments(_raw_message, _opts \\ []),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a binary containing a RFC5322 raw message and writes all nested
+  attachments to `directory`, returning the path and metadata of each
+  written file.
+
+  Attachment names are turned into safe file names so that no attachment
+  can be written outside of `directory`. If any write fails, no attachment
+  is left behind in `directory`, as detailed for `:atomic` below.
+
+  ### Options
+
+  Accepts the same options as `extract_nested_attachments/2`, as well as:
+
+    * `:sanitize` - how attachment names that are not safe file names are
+      handled. `:strict` strips path components, control and reserved
+      characters, prefixes reserved device names such as `CON` and
+      truncates overly long names. `:reject` aborts the extraction with
+      `{:error, {:unsafe_filename, name}}` instead. Defaults to `:strict`.
+
+    * `:on_conflict` - what to do when a file name is already taken, either
+      by an existing file or by an earlier attachment with the same name.
+      `:overwrite` replaces the file, `:error` aborts the extraction with
+      `{:error, {:file_exists, path}}` and `:rename` appends `-1`, `-2`, ...
+      to the file name until it is unique. Defaults to `:overwrite`.
+
+    * `:atomic` - when `true`, attachments are written to temporary files in
+      `directory` and only renamed into place once all of them were written,
+      so a crash never leaves a truncated file behind. If a rename fails,
+      the files renamed so far are removed and the files they replaced are
+      restored. When `false`, files are written in place, and the files the
+      call created are removed if a write fails, files that existed before
+      being left overwritten. Either way, the directories created for the
+      attachments are removed on failure. Defaults to `true`.
+
+    * `:fsync` - when `true`, written files, and with `:atomic` the
+      directories they are renamed into, are flushed to disk before
+      returning. Defaults to `false`.
+
+    * `:path_template` - the path of each file relative to `directory`,
+      with `/` separating the subdirectories created for it. The
+      placeholders `{name}`, `{stem}` and `{ext}` are replaced by the safe
+      file name of the attachment, without and with only its extension,
+      `{index}` by its position in the extraction, starting from 0,
+      `{message_id}` by the Message-ID of the message, `{sha256}` by the hex
+      encoded SHA-256 digest of the attachment and `{date:format}` by the
+      Date of the message in UTC, formatted with `%Y`, `%m`, `%d`, `%H`,
+      `%M` and `%S`. `{date}` is short for `{date:%Y-%m-%d}`, and missing
+      Message-ID and Date headers are replaced by `unknown`. Defaults to
+      `"{name}"`, e.g. `"{message_id}/{index}-{name}"` or
+      `"{date:%Y/%m}/{sha256}{ext}"`. Templates that are absolute, contain
+      `..` or an unknown placeholder raise `ArgumentError`.
+
+  ### Example
+
+      iex> MailParser.extract_attachments_to_disk(raw_message, "/tmp/attachments")
+      {:ok, [%MailParser.ExtractedAttachment{path: "/tmp/attachments/example.pdf", ...}]}
+
+  """
+  @spec extract_attachments_to_disk(iodata(), Path.t(), [disk_option()]) ::
+          {:ok, [ExtractedAttachment.t()]}
+          | parse_error()
+          | io_error()
+          | {:error,
+             :limit_exceeded
+             | :budget_exceeded
+             | {:unsafe_filename, String.t()}
+             | {:file_exists, Path.t()}}
+  def extract_attachments_to_disk(_raw_message, _directory, _opts \\ []),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a binary containing a RFC5322 raw message and returns a stream of
+  its nested attachments, each as a `{info, chunks}` tuple, for piping
+  attachments to object storage or a socket without building whole copies
+  of them.
+
+  `info` describes the attachment as `list_attachments/2` does and `chunks`
+  is a stream of binaries making up its contents. Chunks are read from the
+  parsed message as they are consumed, and reference it rather than copy
+  it, so the message is kept in memory as long as the stream or any chunk
+  is. Attachments may be read in any order, and more than once.
+
+  Decoding is not streamed: the whole message is parsed, and every
+  attachment decoded, when the stream is returned, so memory peaks at the
+  size of the decoded attachments no matter how the chunks are consumed.
+
+  ### Options
+
+  Accepts the same options as `extract_nested_attachments/2`, as well as:
+
+    * `:chunk_size` - the maximum size of each chunk, in bytes. Defaults to
+      65536.
+
+  ### Example
+
+      iex> {:ok, stream} = MailParser.extract_attachments_stream(raw_message)
+      iex> Enum.each(stream, fn {%{name: name}, chunks} -> upload(name, chunks) end)
+      :ok
+
+  """
+  @spec extract_attachments_stream(iodata(), [stream_option()]) ::
+          {:ok, Enumerable.t()} | parse_error() | limit_error()
+  def extract_attachments_stream(raw_message, opts \\ []) do
+    with {:ok, stream, attachments} <- open_attachment_stream(raw_message, opts) do
+      attachments =
+        attachments
+        |> Stream.with_index()
+        |> Stream.map(fn {info, index} -> {info, attachment_chunks(stream, index)} end)
+
+      {:ok, attachments}
+    end
+  end
+
+  defp attachment_chunks(stream, index) do
+    Stream.unfold(0, fn offset ->
+      case read_attachment_chunk(stream, index, offset) do
+        nil -> nil
+        chunk -> {chunk, offset + byte_size(chunk)}
+      end
+    end)
+  end
+
+  @doc false
+  def open_attachment_stream(_raw_message, _opts), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc false
+  def read_attachment_chunk(_stream, _index, _offset), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a binary containing a RFC5322 raw message and returns a handle to
+  the parsed message.
+
+  The handle can be passed to the `get_*` functions to query the message
+  without parsing it again. If no headers are found `{:error, :not_a_message}`
+  is returned.
+
+  ### Options
+
+    * `:lenient` - whether damaged messages are salvaged, returning
+      `{:ok, message, warnings}` with the problems found in the message, in
+      the order of the parts, as listed by `get_mime_structure/1`, they were
+      found in. Input without any header is then parsed as the body of a
+      message without headers. Defaults to `false`.
+
+    * `:max_memory_bytes` - the maximum size of the bodies of the parsed
+      message, all of which it keeps a copy of, in bytes. Larger messages
+      are dropped with `{:error, :budget_exceeded}`. The size is checked
+      once the message is parsed, so the parser may use more than the
+      budget before the message is dropped. Unlimited by default.
+
+  The warning reasons are:
+
+    * `:missing_headers` - no header was found.
+    * `:truncated_headers` - the headers are not followed by a body.
+    * `:truncated_body` - a multipart does not end with its closing
+      delimiter.
+    * `:missing_boundary` - a multipart has no boundary, or its boundary is
+      not found, and its body is kept as is.
+    * `:invalid_encoding_in_part` - the body of a part, or an attached
+      message, could not be decoded and is kept as is.
+
+  ### Example
+
+      iex> {:ok, message} = MailParser.parse(raw_message)
+      iex> MailParser.get_subject(message)
+      "Bestellung 0340/2022"
+
+      iex> MailParser.parse(truncated_message, lenient: true)
+      {:ok, #Reference<0.1.2.3>, [%{reason: :truncated_body, path: []}]}
+
+  """
+  @spec parse(iodata(), [parse_option()]) ::
+          {:ok, message()}
+          | {:ok, message(), [warning()]}
+          | parse_error()
+          | {:error, :budget_exceeded}
+  def parse(_raw_message, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a raw message as `parse/2` does, without running on a dirty
+  scheduler.
+
+  The message is parsed on a pool of native threads, one per core, while
+  the calling process waits for the result in a `receive`, leaving its
+  scheduler to other processes. This suits systems whose dirty schedulers
+  are saturated. iolists are flattened on the pool too.
+
+  The parse itself cannot be split into timeslices, so the pool threads
+  are native threads the schedulers of the VM do not account for. Parses
+  started while every thread of the pool is busy are queued, without
+  bound, until one is free: callers starting parses faster than they
+  complete should limit how many they run at a time.
+
+  Accepts the same options as `parse/2`. Use `start_parse/2` instead for a
+  parse that can be cancelled.
+
+  ### Example
+
+      iex> {:ok, message} = MailParser.parse_yielding(raw_message)
+      iex> MailParser.get_subject(message)
+      "Bestellung 0340/2022"
+
+  """
+  @spec parse_yielding(iodata(), [parse_option()]) ::
+          {:ok, message()}
+          | {:ok, message(), [warning()]}
+          | parse_error()
+          | {:error, :budget_exceeded}
+  def parse_yielding(raw_message, opts \\ []) do
+    {:ok, task} = start_parse(raw_message, opts)
+    await_parse(task)
+  end
+
+  @doc """
+  Starts parsing a raw message as `parse_yielding/2` does, returning a
+  handle to await the parsed message with `await_parse/1` or to cancel the
+  parse with `cancel_parse/1`, which any process holding it can do.
+
+  The result is sent to the calling process, which alone can await it.
+  Like the reply of a `Task`, it is left in its mailbox if never awaited.
+
+  Accepts the same options as `parse/2`.
+
+  ### Example
+
+      iex> {:ok, task} = MailParser.start_parse(raw_message)
+      iex> {:ok, message} = MailParser.await_parse(task)
+      iex> MailParser.get_subject(message)
+      "Bestellung 0340/2022"
+
+  """
+  @spec start_parse(iodata(), [parse_option()]) :: {:ok, parse_task()}
+  def start_parse(raw_message, opts \\ []) do
+    ref = make_ref()
+    {:ok, task} = start_parse_task(raw_message, opts, ref)
+    {:ok, {ref, self(), task}}
+  end
+
+  @doc """
+  Waits for a parse started by `start_parse/2` in the calling process to
+  complete, as `parse_yielding/2` does, and returns its result.
+
+  Returns `{:error, :cancelled}` as soon as the parse is cancelled. The
+  result of a parse can only be awaited once.
+  """
+  @spec await_parse(parse_task()) ::
+          {:ok, message()}
+          | {:ok, message(), [warning()]}
+          | parse_error()
+          | {:error, :budget_exceeded | :cancelled}
+  def await_parse({ref, _owner, task}) do
+    receive do
+      {^ref, result} ->
+        cond do
+          parse_cancelled(task) -> {:error, :cancelled}
+          result == :badarg -> raise ArgumentError, "raw message is not iodata"
+          true -> result
+        end
+    end
+  end
+
+  @doc """
+  Cancels a parse started by `start_parse/2`. The message is dropped as
+  soon as the parser returns, or as soon as it is awaited if it was parsed
+  already, rather than kept until the task is garbage collected.
+
+  A parse that has started is not interrupted: cancellation is checked
+  before parsing and once the parser returns only, so the parse keeps its
+  thread busy until it completes.
+  """
+  @spec cancel_parse(parse_task()) :: :ok
+  def cancel_parse({ref, owner, task}) do
+    if cancel_parse_task(task), do: send(owner, {ref, {:error, :cancelled}})
+    :ok
+  end
+
+  @doc false
+  def start_parse_task(_raw_message, _opts, _ref), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc false
+  def cancel_parse_task(_task), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc false
+  def parse_cancelled(_task), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the subject of a parsed message, if any.
+  """
+  @spec get_subject(message()) :: String.t() | nil
+  def get_subject(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns all nested attachments of a parsed message.
+
+  Accepts the same options as `extract_nested_attachments/2`.
+  """
+  @spec get_attachments(message(), [extract_option()]) ::
+          [Attachment.t()] | limit_error()
+  def get_attachments(_message, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the first text body of a parsed message, if any.
+
+  HTML-only messages have their body converted to plain text.
+  """
+  @spec get_body(message()) :: String.t() | nil
+  def get_body(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the preferred text/plain body of a parsed message, if any.
+
+  The body is decoded from its transfer encoding and declared charset, such
+  as ISO-8859-1 or Shift_JIS, into UTF-8. Of the parts of a
+  multipart/alternative, the text/plain one is picked. Unlike `get_body/1`,
+  HTML-only messages are not converted and `nil` is returned.
+
+  ### Options
+
+    * `:decompress` - when `true`, a body declaring a `gzip` or `deflate`
+      Content-Encoding header, as added by some gateways, is decompressed
+      before being decoded from its charset. Bodies that are malformed or
+      exceed `:max_decompressed_size` are returned as parsed. Defaults to
+      `false`.
+
+    * `:max_decompressed_size` - the maximum decompressed size of the body,
+      in bytes. Defaults to 64 MiB.
+  """
+  @spec get_text_body(message(), [body_option()]) :: String.t() | nil
+  def get_text_body(_message, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the preferred text/html body of a parsed message, if any, decoded
+  as `get_text_body/2` does.
+
+  Of the parts of a multipart/alternative, the text/html one is picked.
+  Text-only messages are not converted and `nil` is returned.
+
+  ### Options
+
+  Accepts the same options as `get_text_body/2`, as well as:
+
+    * `:sanitize` - when `true`, the HTML is made safe to embed in a web
+      client: scripts, embedded documents and frames, comments, `<base>`,
+      `<meta>` and `<link>` elements, event handler attributes such as
+      `onclick`, `javascript:` URLs and form actions leaving the document
+      are removed and tags are rebuilt from the attributes that are kept,
+      with their values escaped. Links and other URL attributes keep
+      relative, `http:`, `https:`, `mailto:` and `cid:` URLs only, and the
+      contents of `<style>`, `<title>` and `<textarea>` elements are
+      escaped. Defaults to `false`.
+
+    * `:remote_images` - how the images of sanitized HTML loaded from
+      `http:` and `https:` URLs, along with the other resources loaded when
+      it is displayed, such as the sources and posters of videos and audio
+      and SVG images, are handled. `:allow` keeps them, `:block`
+      removes their URLs, along with `srcset` attributes and styles loading
+      URLs, through `url()`, `image-set()` or `@import`, and a template
+      such as
+      `"https://proxy.example.com/?url={url}"` rewrites them, with `{url}`
+      replaced by the percent-encoded image URL. `cid:` and `data:` images
+      are kept. Defaults to `:allow`.
+
+  ### Example
+
+      iex> MailParser.get_html_body(message, sanitize: true, remote_images: :block)
+      "<p>Hello</p><img alt=\"Tracking pixel\">"
+
+  """
+  @spec get_html_body(message(), [html_option()]) :: String.t() | nil
+  def get_html_body(_message, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the text body of a parsed message as plain text, if any.
+
+  HTML-only messages have their HTML body converted to readable text: tags
+  are stripped, entities decoded and whitespace collapsed, block elements
+  are put on lines of their own and links rendered as `text (href)`.
+
+  ### Options
+
+    * `:max_length` - maximum length of the returned text, in characters.
+      The text is cut at a word boundary where possible.
+
+  ### Example
+
+      iex> MailParser.get_plaintext(message, max_length: 20)
+      "Please find attached"
+
+  """
+  @spec get_plaintext(message(), [{:max_length, non_neg_integer()}]) :: String.t() | nil
+  def get_plaintext(_message, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns a single line preview of up to `length` characters of the body
+  of a parsed message, as shown in an inbox.
+
+  The preview is based on `get_plaintext/2`, leaving out quoted lines,
+  reply headers such as `On ... wrote:` and everything after a signature
+  delimiter or an original message separator.
+
+  ### Example
+
+      iex> MailParser.get_preview(message, 40)
+      "Sounds good, see you on Tuesday then."
+
+  """
+  @spec get_preview(message(), non_neg_integer()) :: String.t()
+  def get_preview(_message, _length \\ 120), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the newly written part of the body of a parsed message, for
+  ingesting replies into ticketing systems.
+
+  The text is based on `get_plaintext/2`, leaving out quoted lines and
+  reply headers such as `On ... wrote:`, even when wrapped over two lines,
+  everything after a signature delimiter, an original message separator or
+  an Outlook-style `From:` and `Sent:` header block, and mobile signatures
+  such as `Sent from my iPhone` ending the text. Line breaks are kept, as
+  `\\n`. Returns `nil` for messages without a body.
+
+  ### Example
+
+      iex> MailParser.get_reply_text(message)
+      "Sounds good.\\n\\nSee you on Tuesday."
+
+  """
+  @spec get_reply_text(message()) :: String.t() | nil
+  def get_reply_text(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a binary containing a RFC5322 raw message and extracts the From,
+  To, Cc, Bcc, Reply-To and Sender addresses.
+
+  Members of address groups are flattened into the list of their header.
+  If no headers are found `{:error, :not_a_message}` is returned.
+
+  ### Example
+
+      iex> MailParser.extract_addresses(raw_message)
+      {:ok, %{from: [%MailParser.Address{name: "Joe Schmoe", email: "joe@example.com"}], to: [...], ...}}
+
+  """
+  @spec extract_addresses(iodata()) :: {:ok, addresses()} | parse_error()
+  def extract_addresses(_raw_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the addresses of a parsed message, as `extract_addresses/1` does.
+  """
+  @spec get_addresses(message()) :: addresses()
+  def get_addresses(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns every occurrence of the header `name` of a parsed message, in
+  the order they appear in. Header names are matched case-insensitively.
+
+  ### Example
+
+      iex> MailParser.get_header(message, "X-Spam-Status")
+      [%MailParser.Header{name: "X-Spam-Status", value: "No, score=-1.2", raw_value: "No, score=-1.2"}]
+
+  """
+  @spec get_header(message(), String.t()) :: [Header.t()]
+  def get_header(_message, _name), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns all headers of a parsed message, in the order they appear in.
+  """
+  @spec get_headers(message()) :: [Header.t()]
+  def get_headers(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses the header block of a raw message only, leaving its body and MIME
+  structure alone, which makes it much faster than `parse/1` for routing
+  and deduplication decisions.
+
+  Returns the decoded values of the headers keyed by lowercase name, each
+  with its values in the order they appear in. If no headers are found
+  `{:error, :not_a_message}` is returned.
+
+  ### Example
+
+      iex> MailParser.parse_headers(raw_message)
+      {:ok, %{"subject" => ["Bestellung 0340/2022"], "received" => ["from ...", "from ..."], ...}}
+
+  """
+  @spec parse_headers(iodata()) :: {:ok, %{String.t() => [String.t()]}} | parse_error()
+  def parse_headers(_raw_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the Date of a parsed message normalized to UTC, either as a Unix
+  timestamp or as an ISO 8601 string depending on `format`.
+
+  Besides RFC 5322 dates, the deviations commonly found in the wild are
+  accepted: missing day of week, seconds or zone (UTC is assumed), two
+  digit years, obsolete zone names such as `EST`, asctime and ISO 8601
+  dates. Returns `nil` if the message has no Date or it cannot be parsed.
+
+  ### Example
+
+      iex> MailParser.get_date(message)
+      1652774704
+      iex> MailParser.get_date(message, :iso8601)
+      "2022-05-17T08:05:04Z"
+
+  """
+  @spec get_date(message(), date_format()) :: integer() | String.t() | nil
+  def get_date(_message, _format \\ :unix), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the Resent-Date of every resent block of a parsed message, in the
+  order they appear in, as `get_date/2` does. Dates that cannot be parsed
+  are skipped.
+  """
+  @spec get_resent_dates(message(), date_format()) :: [integer() | String.t()]
+  def get_resent_dates(_message, _format \\ :unix), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the headers of a parsed message needed for threading.
+
+  Message ids are returned without their angle brackets, `:references` in
+  the order they appear in. `:base_subject` is the subject with reply and
+  forward prefixes such as `Re:`, `Fwd:` or `Aw:` and mailing list tags
+  removed.
+
+  ### Example
+
+      iex> MailParser.get_thread_info(message)
+      %{message_id: "b@example.com", in_reply_to: ["a@example.com"], references: ["a@example.com"], base_subject: "Invoice"}
+
+  """
+  @spec get_thread_info(message()) :: thread_info()
+  def get_thread_info(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the MIME tree of a parsed message, without any bodies.
+
+  Each part carries its `:path` from the root part, a list of indexes into
+  the `:children` of each ancestor. The children of a multipart are its
+  parts, an attached message has its root part as its only child.
+  `:disposition` and `:encoding` are the declared Content-Disposition and
+  Content-Transfer-Encoding, if any. `:size` is the decoded size of the
+  body in bytes, or the raw size for multiparts.
+
+  ### Example
+
+      iex> MailParser.get_mime_structure(message)
+      %{path: [], content_type: "multipart/mixed", children: [%{path: [0], content_type: "text/plain", ...}, ...], ...}
+
+  """
+  @spec get_mime_structure(message()) :: part_structure()
+  def get_mime_structure(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a binary containing a RFC5322 raw message and returns the part at
+  `path`, as listed by `get_mime_structure/1`, with its headers and decoded
+  body.
+
+  Returns `{:error, :part_not_found}` if there is no part at `path`.
+
+  ### Example
+
+      iex> MailParser.extract_part(raw_message, [0, 1])
+      {:ok, %{path: [0, 1], content_type: "application/pdf", headers: [...], content_bytes: <<...>>}}
+
+  """
+  @spec extract_part(iodata(), [non_neg_integer()]) ::
+          {:ok, part()} | parse_error() | {:error, :part_not_found}
+  def extract_part(_raw_message, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the part of a parsed message at `path`, as `extract_part/2` does,
+  or `nil` if there is none.
+  """
+  @spec get_part(message(), [non_neg_integer()]) :: part() | nil
+  def get_part(_message, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the bytes of the part of a parsed message at `path`, as listed by
+  `get_mime_structure/1`, exactly as they appear in the message: its
+  headers followed by its body, still transfer encoded. Returns `nil` if
+  there is no part at `path`.
+
+  The line break preceding the delimiter that follows a part belongs to the
+  delimiter (RFC 2046), so it is left out. This is the content signed by a
+  `multipart/signed` message (RFC 1847), whose first part can be verified
+  against its signature as returned. The root part, at `[]`, is the whole
+  message.
+  """
+  @spec get_raw_part(message(), [non_neg_integer()]) :: binary() | nil
+  def get_raw_part(_message, _path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the messages attached to a parsed message, such as forwarded
+  messages and the original message of a bounce, each as a parsed message
+  of its own.
+
+  Messages attached to attached messages are returned too, so that every
+  message found in the MIME tree is listed once, in the order they appear
+  in the message. `:path` is the path of the part holding each message, as
+  listed by `get_mime_structure/1`.
+
+  The returned messages are not copies: each references the part of
+  `message` holding it, keeping `message` alive as long as it is.
+
+  ### Example
+
+      iex> MailParser.get_embedded_messages(message)
+      [%{path: [1], content_type: "message/rfc822", message: #Reference<...>}]
+
+  """
+  @spec get_embedded_messages(message()) :: [embedded_message()]
+  def get_embedded_messages(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Lists the attachments of a parsed message without copying their contents.
+
+  Accepts the same options as `extract_nested_attachments/2`. `:size` is the
+  decoded size in bytes and `:path` can be passed to `get_part/2` to fetch
+  a single attachment later on.
+
+  ### Example
+
+      iex> MailParser.list_attachments(message)
+      [%{name: "invoice.pdf", content_type: "application/pdf", size: 3028, path: [1], ...}]
+
+  """
+  @spec list_attachments(message(), [extract_option()]) ::
+          [attachment_info()] | limit_error()
+  def list_attachments(_message, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns size and structure statistics of a parsed message, computed in a
+  single pass without copying any body, for quota accounting and triage.
+
+  `:size` is the size of the raw message and `:header_size` the size of its
+  header block, blank line included. `:parts` counts every MIME part,
+  multipart containers and the parts of attached messages included.
+  `:attachments` and `:attachments_size` count the attachments returned by
+  `list_attachments/2` with the default options, and their decoded size in
+  bytes. `:encodings` lists the Content-Transfer-Encodings declared by the
+  parts, lowercased and sorted, and `:depth` is how deeply attached
+  messages are nested, 0 for messages without attached messages.
+
+  ### Example
+
+      iex> MailParser.stats(message)
+      %{size: 48213, header_size: 1024, parts: 4, attachments: 1, encodings: ["base64", "quoted-printable"], ...}
+
+  """
+  @spec stats(message()) :: stats()
+  def stats(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses every message of the mbox file at `path`.
+
+  Messages are split on `From ` lines, with `>From ` quoted lines unquoted,
+  and returned in order as `{:ok, message}`, or `{:error, reason}` for
+  messages that cannot be parsed.
+
+  ### Options
+
+    * `:max_messages` - the maximum number of messages read from the file.
+
+    * `:max_memory_bytes` - the maximum size of the bodies decoded for each
+      message, in bytes. The messages keep what was read from the file as
+      the storage of their other bodies. Larger messages are returned as
+      `{:error, :budget_exceeded}`. Unlimited by default.
+
+  ### Example
+
+      iex> MailParser.parse_mbox("archive.mbox")
+      {:ok, [{:ok, #Reference<...>}, {:ok, #Reference<...>}]}
+
+  """
+  @spec parse_mbox(Path.t(), [mbox_option()]) ::
+          {:ok, [{:ok, message()} | parse_error() | {:error, :budget_exceeded}]} | io_error()
+  def parse_mbox(_path, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Extracts the attachments of every message of the mbox file at `path`, as
+  `extract_nested_attachments/2` does for a single message.
+
+  Results are returned per message, in order. Accepts the same options as
+  `extract_nested_attachments/2` and `parse_mbox/2`.
+
+  ### Example
+
+      iex> MailParser.extract_attachments_from_mbox("archive.mbox")
+      {:ok, [{:ok, [%MailParser.Attachment{name: "notes.txt", ...}]}, {:ok, []}]}
+
+  """
+  @spec extract_attachments_from_mbox(Path.t(), [mbox_option()]) ::
+          {:ok, [{:ok, [Attachment.t()]} | parse_error() | limit_error()]}
+          | io_error()
+  def extract_attachments_from_mbox(_path, _opts \\ []),
+    do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Opens the mbox file at `path` to read its messages one at a time with
+  `mbox_next/2`, keeping memory bounded by the size of a single message.
+  """
+  @spec mbox_open(Path.t()) :: {:ok, mbox()} | io_error()
+  def mbox_open(_path), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Reads and parses the next message of an mbox opened with `mbox_open/1`.
+
+  Returns `:eof` once all messages were read or the mbox was closed, and
+  `{:error, reason}` for a message that cannot be parsed; reading can
+  continue with the next message in that case.
+
+  ### Options
+
+  Accepts the same options as `extract_nested_attachments/2`, as well as:
+
+    * `:attachments` - when `true`, the attachments of the message are
+      returned instead of the parsed message. Defaults to `false`.
+
+  `:max_memory_bytes` bounds the bodies decoded for the parsed message as
+  it does for `parse_mbox/2`.
+
+  ### Example
+
+      iex> {:ok, mbox} = MailParser.mbox_open("archive.mbox")
+      iex> MailParser.mbox_next(mbox, attachments: true)
+      {:ok, [%MailParser.Attachment{name: "notes.txt", ...}]}
+
+  """
+  @spec mbox_next(mbox(), [extract_option() | {:attachments, boolean()}]) ::
+          {:ok, message()}
+          | {:ok, [Attachment.t()]}
+          | :eof
+          | parse_error()
+          | io_error()
+          | limit_error()
+  def mbox_next(_mbox, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Closes an mbox opened with `mbox_open/1`. The file is otherwise closed
+  once the mbox is garbage collected.
+  """
+  @spec mbox_close(mbox()) :: :ok
+  def mbox_close(_mbox), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses every message of the Maildir at `root`.
+
+  Messages are read from `cur/`, then from `new/`, each sorted by file
+  name, hidden files being skipped. They are returned in that order as
+  `{path, {:ok, message}}`, or `{path, {:error, reason}}` for messages that
+  cannot be read or parsed.
+
+  ### Options
+
+    * `:max_messages` - the maximum number of messages read from the
+      Maildir.
+
+    * `:parallel` - when `true`, messages are read and parsed on several
+      threads. Defaults to `false`.
+
+    * `:max_memory_bytes` - the maximum size of the bodies decoded for each
+      message, as for `parse_mbox/2`. Unlimited by default.
+
+  ### Example
+
+      iex> MailParser.parse_maildir("Maildir", parallel: true)
+      {:ok, [{"Maildir/cur/1700000000.1.host:2,S", {:ok, #Reference<...>}}]}
+
+  """
+  @spec parse_maildir(Path.t(), [maildir_option()]) :: {:ok, [maildir_result()]} | io_error()
+  def parse_maildir(_root, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Opens the Maildir at `root` to read its messages one at a time with
+  `maildir_next/1`, in the order of `parse_maildir/2`.
+
+  The messages are listed when opening: messages delivered afterwards are
+  not read, and messages removed afterwards are returned as
+  `{:error, {:io, :enoent, path}}`.
+  """
+  @spec maildir_open(Path.t()) :: {:ok, maildir()} | io_error()
+  def maildir_open(_root), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Reads and parses the next message of a Maildir opened with
+  `maildir_open/1`, returning `:eof` once all messages were read.
+
+  ### Example
+
+      iex> {:ok, maildir} = MailParser.maildir_open("Maildir")
+      iex> MailParser.maildir_next(maildir)
+      {"Maildir/cur/1700000000.1.host:2,S", {:ok, #Reference<...>}}
+
+  """
+  @spec maildir_next(maildir()) :: maildir_result() | :eof
+  def maildir_next(_maildir), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Reads and parses the message file at `path`, such as an `.eml` file, as
+  `parse/2` does, without loading it into a binary first.
+
+  The message keeps what was read as the storage of its bodies rather than
+  a copy of them, so only the bodies that had to be decoded count towards
+  `:max_memory_bytes`. Accepts the same options as `parse/2`.
+  """
+  @spec parse_file(Path.t(), [parse_option()]) ::
+          {:ok, message()}
+          | {:ok, message(), [warning()]}
+          | parse_error()
+          | io_error()
+          | {:error, :budget_exceeded}
+  def parse_file(_path, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Extracts the attachments of the message file at `path`, as
+  `extract_nested_attachments/2` does.
+
+  The file is read straight into a single binary which the returned
+  attachments reference.
+  """
+  @spec extract_attachments_from_file(Path.t(), [extract_option()]) ::
+          {:ok, [Attachment.t()]} | parse_error() | io_error() | limit_error()
+  def extract_attachments_from_file(_path, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Extracts the attachments of many messages at once, as
+  `extract_nested_attachments/2` does for each of them.
+
+  Each input is either a raw message or `{:file, path}` for a message file.
+  The messages are parsed in parallel on a thread pool and the results are
+  returned in the order of the inputs, one per message:
+
+    * `{:ok, attachments}` - the attachments of the message.
+    * `{:error, :empty_input | :not_a_message}` - the message could not be
+      parsed.
+    * `{:error, :limit_exceeded}` - a limit was exceeded with
+      `on_limit: :error`.
+    * `{:error, :budget_exceeded}` - the message decoded more than
+      `:max_memory_bytes`.
+    * `{:error, {:io, posix, path}}` - the message file could not be read.
+
+  ### Example
+
+      iex> MailParser.extract_attachments_batch([raw_message, {:file, "message.eml"}])
+      [{:ok, [%MailParser.Attachment{name: "invoice.pdf", ...}]}, {:error, :not_a_message}]
+
+  """
+  @spec extract_attachments_batch([iodata() | {:file, Path.t()}], [extract_option()]) :: [
+          {:ok, [Attachment.t()]} | parse_error() | io_error() | limit_error()
+        ]
+  def extract_attachments_batch(_inputs, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the events of the calendar invitations of a message, found in
+  its `text/calendar` and `application/ics` parts.
+
+  Each event carries the `METHOD` of its calendar, e.g. `"REQUEST"` or
+  `"CANCEL"`. `:dtstart` and `:dtend` are converted to ISO 8601, with the
+  `TZID` of the start in `:timezone` for local times. The `:status` of an
+  attendee is its lowercased `PARTSTAT`, e.g. `"accepted"`, and `:rrule`
+  the raw recurrence rule. Events repeated in several parts, as when an
+  invitation is sent both inline and as an `.ics` attachment, are returned
+  once.
+
+  ### Example
+
+      iex> MailParser.extract_calendar_events(raw_message)
+      {:ok, [%{method: "REQUEST", summary: "Weekly standup", dtstart: "2024-06-03T10:00:00", ...}]}
+
+  """
+  @spec extract_calendar_events(iodata()) :: {:ok, [calendar_event()]} | parse_error()
+  def extract_calendar_events(_raw_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the contacts of the vCards attached to a message, found in its
+  `text/vcard` parts and `.vcf` attachments, in order.
+
+  The vCards themselves are still returned as attachments by
+  `extract_nested_attachments/2`.
+
+  ### Example
+
+      iex> MailParser.extract_contacts(raw_message)
+      {:ok, [%MailParser.Contact{name: "Jane Doe", emails: ["jane@example.com"], ...}]}
+
+  """
+  @spec extract_contacts(iodata()) :: {:ok, [Contact.t()]} | parse_error()
+  def extract_contacts(_raw_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Parses a delivery status notification, i.e. a bounce sent as a
+  `multipart/report; report-type=delivery-status` message, or a message
+  disposition notification, i.e. a read receipt sent as a
+  `multipart/report; report-type=disposition-notification` message. The
+  `:type` of the returned map tells them apart.
+
+  For delivery status notifications, returns the status of each recipient,
+  with the address and MTA types such as `rfc822;` and `dns;` left out, and
+  the headers of the original message, from either the attached message or
+  its `text/rfc822-headers` part. `:status` is the enhanced status code,
+  e.g. `"5.1.1"`, and `:action` the lowercased action, e.g. `"failed"` or
+  `"delayed"`.
+
+  For disposition notifications, returns the `:disposition` of the original
+  message, e.g. `"displayed"` or `"deleted"`, its `:original_message_id`,
+  without angle brackets, and the user agent that sent the notification.
+
+  Returns `{:error, :not_a_report}` for messages that are neither.
+
+  ### Example
+
+      iex> MailParser.parse_dsn(raw_message)
+      {:ok, %{type: :delivery_status, recipients: [%{action: "failed", status: "5.1.1", ...}], ...}}
+
+  """
+  @spec parse_dsn(iodata()) ::
+          {:ok, delivery_status() | disposition_notification()}
+          | parse_error()
+          | {:error, :not_a_report}
+  def parse_dsn(_raw_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the Authentication-Results, Received-SPF and DKIM-Signature
+  headers of a parsed message, in order and parsed into maps.
+
+  Each method of an Authentication-Results header is returned as a separate
+  result, e.g. `%{method: "dkim", result: "pass", ...}`, with the host that
+  added the header as `:authserv_id` and every `ptype.property=value` in
+  `:properties`. `:domain` is taken from the `header.d`, `header.i`,
+  `smtp.mailfrom`, `header.from` or `smtp.helo` property, in that order,
+  and `:selector` from `header.s`. Comments are left out.
+
+  Received-SPF headers are returned with their lowercased result, the
+  domain of the envelope sender, or the HELO name, and the client IP.
+  DKIM-Signature headers are returned with their `d=`, `s=` and `a=` tags.
+
+  These headers can be added by anyone along the way, so only the ones
+  added by trusted hosts, as told by `:authserv_id`, should be relied on.
+
+  ### Example
+
+      iex> MailParser.get_auth_results(message)
+      %{authentication_results: [%{method: "dkim", result: "pass", domain: "example.com", ...}], ...}
+
+  """
+  @spec get_auth_results(message()) :: auth_results()
+  def get_auth_results(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the Received headers of a parsed message, parsed into hops, along
+  with the IP address the message most likely originated from.
+
+  Each relay prepends a Received header, so hops are returned most recent
+  first. Each hop holds the host name given in the `from` clause, the
+  address the connection came from, the host that added the header, the
+  protocol of the `with` clause, e.g. `"ESMTPS"`, and the date of the
+  header as a Unix timestamp. Clauses that are missing or cannot be parsed
+  are `nil`.
+
+  `:originating_ip` is the address of the oldest hop that is neither
+  private, loopback, link-local nor shared, skipping the hops between the
+  sender and its own submission server.
+
+  Received headers can be forged by the sender, so only the hops added by
+  trusted hosts should be relied on.
+
+  ### Example
+
+      iex> MailParser.get_received_chain(message)
+      %{hops: [%{from_host: "mx.example.net", from_ip: "198.51.100.7", ...}, ...], originating_ip: "203.0.113.42"}
+
+  """
+  @spec get_received_chain(message()) :: received_chain()
+  def get_received_chain(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the mailing list headers of a parsed message.
+
+  `:id` and `:name` are the identifier and description of the List-Id
+  header. `:unsubscribe` and `:post` are the URIs of the List-Unsubscribe
+  and List-Post headers, in order and without their angle brackets.
+  `:one_click_unsubscribe` is the HTTPS URI of List-Unsubscribe when the
+  List-Unsubscribe-Post header allows unsubscribing with a single POST
+  request (RFC 8058), which is `nil` otherwise.
+
+  `:precedence` and `:auto_submitted` are the lowercased values of the
+  Precedence and Auto-Submitted headers, without parameters.
+  `:suppress_auto_reply` tells whether the message is list, bulk or
+  automated mail, which vacation responders and other automatic replies
+  should leave unanswered (RFC 3834).
+
+  ### Example
+
+      iex> MailParser.get_list_info(message)
+      %{id: "news.example.org", one_click_unsubscribe: "https://example.org/unsubscribe?id=42", ...}
+
+  """
+  @spec get_list_info(message()) :: list_info()
+  def get_list_info(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns the preferred text/html body of a parsed message, if any, along
+  with the parts its `cid:` URLs refer to.
+
+  `:inline_parts` maps each Content-ID referred to by the HTML body, without
+  its angle brackets, to the content type and decoded contents of its part.
+  `cid:` URLs that do not refer to any part of the message are left as is.
+
+  ### Options
+
+    * `:data_uris` - whether to replace the `cid:` URLs of the HTML body with
+      `data:` URIs embedding the contents of their parts, so that the HTML
+      can be rendered as is. Defaults to `false`.
+
+  ### Example
+
+      iex> MailParser.get_html_with_inline_parts(message, data_uris: true)
+      %{html: "<img src=\"data:image/png;base64,iVBORw0KGgo=\">", inline_parts: %{"logo@example.com" => %{...}}}
+
+  """
+  @spec get_html_with_inline_parts(message(), [{:data_uris, boolean()}]) ::
+          html_with_inline_parts() | nil
+  def get_html_with_inline_parts(_message, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns whether a parsed message is signed or encrypted with S/MIME or
+  PGP/MIME.
+
+  multipart/signed, multipart/encrypted and S/MIME (`application/pkcs7-mime`
+  and `application/pkcs7-signature`) parts are recognized. `:protocol` is
+  `:smime` or `:pgp`, and is `nil` for messages that are neither signed nor
+  encrypted.
+
+  `:digest_algorithm` is the digest algorithm of the signature, e.g.
+  `"sha256"`, and `:encryption_algorithm` the content encryption algorithm
+  of S/MIME encrypted messages, e.g. `"aes-256-cbc"`. `:signers` describes
+  the certificates S/MIME messages were signed with, as included in the
+  signature. PGP signatures return no signers.
+
+  Signatures are not verified: the signers are only those the message
+  claims to be signed by.
+
+  ### Example
+
+      iex> MailParser.get_security_info(message)
+      %{signed: true, protocol: :smime, digest_algorithm: "sha256", signers: [%{name: "John Doe", ...}], ...}
+
+  """
+  @spec get_security_info(message()) :: security_info()
+  def get_security_info(_message), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns a raw message with the selected attachments removed, or replaced
+  by a text placeholder.
+
+  The attachments to strip are selected with the options of
+  `extract_nested_attachments/2`, and default to every attachment,
+  including those of attached messages. The rest of the message, headers
+  and bodies included, is returned byte for byte. Only whole MIME parts are
+  stripped, so `:expand_tnef`, `:expand_archives` and `:uudecode` are
+  ignored, and the attachments of an opaque-signed message are kept, as
+  are attachments making up the whole body of a message.
+
+  ### Options
+
+    * `:placeholder` - the text of the `text/plain` part replacing each
+      stripped attachment, in which `{name}`, `{content_type}`, `{size}`
+      and `{sha256}` are replaced by the name, content type, decoded size
+      and hex encoded SHA-256 digest of the attachment. Stripped
+      attachments are removed altogether when `nil`. Defaults to `nil`.
+
+  ### Example
+
+      iex> MailParser.strip_attachments(raw_message,
+      ...>   mime_types: ["application/pdf"],
+      ...>   placeholder: "Stored as s3://attachments/{sha256}"
+      ...> )
+      {:ok, "From: ..."}
+
+  """
+  @spec strip_attachments(iodata(), [strip_option()]) ::
+          {:ok, binary()} | parse_error() | limit_error()
+  def strip_attachments(_raw_message, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Composes a MIME message from headers, bodies and attachments, returning
+  the raw message.
+
+  `:headers` are written in order, with non-ASCII words, such as those of
+  a subject or of display names, encoded as RFC 2047 encoded-words, and
+  folded to 78 characters where possible. They must not break lines, and
+  MIME-Version and Content-* headers, which are generated, must not be
+  given. Date, Message-ID and the other headers are written as given only.
+
+  The `:text` and `:html` bodies are UTF-8 encoded, and quoted-printable
+  encoded unless they are short-lined ASCII text. When both are given, they
+  are alternatives to each other. Attachments are base64 encoded, with
+  names that are not ASCII encoded as per RFC 2231 and RFC 2047.
+  Attachments with `inline: true`, or an `:inline` disposition, are related
+  to the HTML body, which refers to them by their `:content_id`. Attachment
+  names, content types and content IDs must not break lines either, and
+  content types must be of the `type/subtype` form.
+  `MailParser.Attachment` structs can be passed as attachments as is.
+
+  Raises `ArgumentError` for invalid input. The boundaries are derived from
+  the contents, so the same input always composes the same message.
+
+  ### Example
+
+      iex> MailParser.build_message(%{
+      ...>   headers: [{"From", "Jöhn Doe <john@example.com>"}, {"Subject", "Grüße"}],
+      ...>   text: "Hello",
+      ...>   attachments: [%{name: "notes.txt", content_type: "text/plain", content_bytes: "Notes"}]
+      ...> })
+      "From: =?utf-8?q?J=C3=B6hn?= Doe <john@example.com>\r\nSubject: =?utf-8?q?Gr=C3=BC=C3=9Fe?=\r\n..."
+
+  """
+  @spec build_message(message_spec()) :: binary()
+  def build_message(_message_spec), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Serializes a parsed message to a JSON object, for indexing pipelines that
+  would otherwise build its Elixir representation only to encode it.
+
+  The object holds the `"subject"`, `"message_id"` and `"date"`, as an ISO
+  8601 string in UTC, of the message, its `"from"`, `"to"`, `"cc"`,
+  `"bcc"`, `"reply_to"` and `"sender"` addresses, its decoded `"headers"`,
+  its `"text_body"` and `"html_body"`, and a description of its
+  `"attachments"`, selected with the options of
+  `extract_nested_attachments/2`, including their `"part_headers"` with
+  `include_part_headers: true`. Missing values are `null`.
+
+  ### Options
+
+    * `:content_bytes` - whether the contents of attachments are included,
+      base64 encoded. Defaults to `false`.
+
+  ### Example
+
+      iex> MailParser.to_json(message, digests: [:sha256])
+      ~s({"subject":"Hello","message_id":"1234@example.com","date":"2022-05-17T08:05:04Z",...})
+
+  """
+  @spec to_json(message(), [json_option()]) :: binary() | limit_error()
+  def to_json(_message, _opts \\ []), do: :erlang.nif_error(:nif_not_loaded)
+
+  @doc """
+  Returns counters of the work done by the native library since it was
+  loaded, across all processes:
+
+    * `:parses` - the number of raw messages parsed.
+
+    * `:parse_duration` - the time spent parsing raw messages, in
+      nanoseconds.
+
+    * `:bytes_parsed` - the size of the raw messages parsed.
+
+    * `:attachments_decoded` - the number of attachments selected for
+      extraction.
+
+    * `:bytes_written` - the bytes written to files by
+      `extract_attachments_to_disk/3`.
+
+  Counters only ever grow: the cost of a call, or of a period, is the
+  difference of two readings, which can be reported with `:telemetry`.
+  Readings taken around a call include the work of concurrent calls.
+
+  ### Example
+
+      iex> MailParser.get_metrics()
+      %{parses: 12, parse_duration: 1843210, bytes_parsed: 403112, ...}
+
+  """
+  @spec get_metrics() :: metrics()
+  def get_metrics, do: :erlang.nif_error(:nif_not_loaded)
 end
@@ -0,0 +1,25 @@
+defmodule MailParser.Address do
+  @moduledoc """
+  An address from an address header such as From or To.
+
+  Addresses listed as members of a group (e.g. `Friends: a@example.com;`)
+  carry the group name in `:group`.
+
+  Internationalized addresses (RFC 6532), sent as raw UTF-8 in messages
+  relayed over SMTPUTF8, are returned as found in `:email`, along with
+  `:ascii_email`, where the domain is Punycode encoded as in
+  `"joerg@xn--mnchen-3ya.de"`, and `:unicode_email`, where it is decoded as
+  in `"joerg@münchen.de"`. `:ascii_email` is `nil` when the local part is
+  not ASCII, as such an address has no ASCII form.
+  """
+
+  @type t :: %__MODULE__{
+          name: String.t() | nil,
+          email: String.t() | nil,
+          group: String.t() | nil,
+          ascii_email: String.t() | nil,
+          unicode_email: String.t() | nil
+        }
+
+  defstruct [:name, :email, :group, :ascii_email, :unicode_email]
+end
@@ -1,13 +1,80 @@
 defmodule MailParser.Attachment do
   @moduledoc """
   A message attachment.
+
+  To avoid copying, `:content_bytes` may be a sub-binary referencing the raw
+  message (or the parsed message handle) it was extracted from. Use
+  `:binary.copy/1` when keeping a small attachment around for longer than
+  the message itself.
+
+  `:name` is decoded to UTF-8 from the `filename` parameter of the
+  Content-Disposition header or else the `name` parameter of the
+  Content-Type header, whether encoded as per RFC 2231, including split
+  `filename*0*=` continuations, or as RFC 2047 encoded words. `:raw_name`
+  holds the parameter as found in the header, with its continuations
+  joined, or is `nil` when the attachment is not named by a header, as for
+  unnamed attachments and the files unpacked from TNEF parts or archives.
+
+  `:disposition` tells a regular attachment apart from an inline part, such
+  as an image referenced by its `:content_id` from the HTML body (`cid:`).
+  It is taken from the Content-Disposition header; parts without one are
+  considered inline when they have a Content-ID.
+
+  `:detected_content_type` is the content type detected from the signature
+  the contents start with, e.g. `"application/pdf"` or `"image/png"`,
+  regardless of the declared one. ZIP archives are told apart from the
+  Office Open XML and OpenDocument files based on them. It is `nil` when no
+  known signature is found, as for text files.
+
+  `:encrypted` tells whether the attachment is password protected: a ZIP
+  archive with encrypted entries, or an encrypted PDF or Office document.
+  Its contents cannot be inspected further without the password.
+
+  `:digests` holds the lowercase hex encoded digests requested with the
+  `:digests` option, keyed by algorithm.
+
+  `:part_headers` holds the headers of the part of the attachment with the
+  `:include_part_headers` option, as their unfolded, RFC 2047 decoded
+  values keyed by lowercase name, in the order they appear in, e.g.
+  `%{"x-attachment-id" => ["f_lnb1"]}`. It is empty for the files unpacked
+  from TNEF parts, archives or uuencoded text, and `nil` without the
+  option.
+
+  `:duplicates` is the number of attachments identical to this one left out
+  with the `:dedupe` option, and is always `0` without it.
   """
 
+  @type disposition :: :inline | :attachment
+
+  @type digests :: %{optional(:sha256 | :sha1 | :md5) => String.t()}
+
+  @type part_headers :: %{String.t() => [String.t()]}
+
   @type t :: %__MODULE__{
           name: String.t(),
+          raw_name: String.t() | nil,
           content_bytes: binary,
-          content_type: String.t() | nil
+          content_type: String.t() | nil,
+          disposition: disposition(),
+          content_id: String.t() | nil,
+          digests: digests(),
+          detected_content_type: String.t() | nil,
+          encrypted: boolean(),
+          part_headers: part_headers() | nil,
+          duplicates: non_neg_integer()
         }
 
-  defstruct [:name, :content_type, :content_bytes]
+  defstruct [
+    :name,
+    :raw_name,
+    :content_type,
+    :content_bytes,
+    :disposition,
+    :content_id,
+    :detected_content_type,
+    :part_headers,
+    digests: %{},
+    encrypted: false,
+    duplicates: 0
+  ]
 end
@@ -0,0 +1,17 @@
+defmodule MailParser.Contact do
+  @moduledoc """
+  A contact from a vCard attached to a message.
+
+  `:name` is the formatted name of the contact or, for vCards without one,
+  its structured name. `:org` is the organization name, without its units.
+  """
+
+  @type t :: %__MODULE__{
+          name: String.t() | nil,
+          emails: [String.t()],
+          phones: [String.t()],
+          org: String.t() | nil
+        }
+
+  defstruct [:name, :org, emails: [], phones: []]
+end
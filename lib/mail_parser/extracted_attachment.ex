@@ -0,0 +1,32 @@
+defmodule MailParser.ExtractedAttachment do
+  @moduledoc """
+  An attachment written to disk by `MailParser.extract_attachments_to_disk/3`.
+
+  `:original_name` is the attachment name as found in the message, before
+  it was turned into the file name of `:path`. `:sha256` is the lowercase
+  hex encoded SHA-256 digest of the written contents, `:digests` holds the
+  digests requested with the `:digests` option. `:duplicates` is the
+  number of attachments identical to this one left out, and not written,
+  with the `:dedupe` option.
+  """
+
+  @type t :: %__MODULE__{
+          path: Path.t(),
+          original_name: String.t(),
+          content_type: String.t() | nil,
+          size_bytes: non_neg_integer(),
+          sha256: String.t(),
+          digests: MailParser.Attachment.digests(),
+          duplicates: non_neg_integer()
+        }
+
+  defstruct [
+    :path,
+    :original_name,
+    :content_type,
+    :size_bytes,
+    :sha256,
+    digests: %{},
+    duplicates: 0
+  ]
+end
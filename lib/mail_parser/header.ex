@@ -0,0 +1,16 @@
+defmodule MailParser.Header do
+  @moduledoc """
+  A header field of a message.
+
+  `:value` holds the unfolded value with RFC 2047 encoded words decoded,
+  `:raw_value` the value as it appears in the message.
+  """
+
+  @type t :: %__MODULE__{
+          name: String.t(),
+          value: String.t(),
+          raw_value: String.t()
+        }
+
+  defstruct [:name, :value, :raw_value]
+end
@@ -0,0 +1,115 @@
+use mail_parser::{Addr, HeaderValue, Message};
+use rustler::{Atom, NifMap, NifResult, NifStruct, ResourceArc};
+
+use crate::atoms;
+use crate::error::parse_message;
+use crate::idna;
+use crate::iodata::RawMessage;
+use crate::message::MessageResource;
+
+#[derive(NifStruct)]
+#[module = "MailParser.Address"]
+pub struct Address {
+    pub name: Option<String>,
+    pub email: Option<String>,
+    pub group: Option<String>,
+    /// The address with its domain in ASCII form, unless its local part is
+    /// not ASCII, in which case it can only be delivered over SMTPUTF8.
+    pub ascii_email: Option<String>,
+    /// The address with its domain in Unicode form.
+    pub unicode_email: Option<String>,
+}
+
+impl Address {
+    fn new(addr: &Addr, group: Option<&str>) -> Self {
+        let email = addr.address.as_deref();
+        let (local_part, domain) = email
+            .and_then(|email| email.rsplit_once('@'))
+            .map_or((email, None), |(local_part, domain)| {
+                (Some(local_part), Some(domain))
+            });
+
+        let ascii_email = match (local_part, domain) {
+            (Some(local_part), _) if !local_part.is_ascii() => None,
+            (Some(local_part), Some(domain)) => {
+                idna::to_ascii(domain).map(|domain| format!("{local_part}@{domain}"))
+            }
+            _ => email.map(str::to_string),
+        };
+
+        let unicode_email = match (local_part, domain) {
+            (Some(local_part), Some(domain)) => {
+                Some(format!("{local_part}@{}", idna::to_unicode(domain)))
+            }
+            _ => email.map(str::to_string),
+        };
+
+        Address {
+            name: addr.name.as_deref().map(str::to_string),
+            email: email.map(str::to_string),
+            group: group.map(str::to_string),
+            ascii_email,
+            unicode_email,
+        }
+    }
+}
+
+#[derive(NifMap)]
+pub struct Addresses {
+    from: Vec<Address>,
+    to: Vec<Address>,
+    cc: Vec<Address>,
+    bcc: Vec<Address>,
+    reply_to: Vec<Address>,
+    sender: Vec<Address>,
+}
+
+impl From<&Message<'_>> for Addresses {
+    fn from(message: &Message) -> Self {
+        Addresses {
+            from: addresses(message.from()),
+            to: addresses(message.to()),
+            cc: addresses(message.cc()),
+            bcc: addresses(message.bcc()),
+            reply_to: addresses(message.reply_to()),
+            sender: addresses(message.sender()),
+        }
+    }
+}
+
+/// Flattens an address header into its addresses, keeping track of the
+/// group each address belongs to.
+pub fn addresses(value: &HeaderValue) -> Vec<Address> {
+    match value {
+        HeaderValue::Address(addr) => vec![Address::new(addr, None)],
+        HeaderValue::AddressList(list) => {
+            list.iter().map(|addr| Address::new(addr, None)).collect()
+        }
+        HeaderValue::Group(group) => group
+            .addresses
+            .iter()
+            .map(|addr| Address::new(addr, group.name.as_deref()))
+            .collect(),
+        HeaderValue::GroupList(groups) => groups
+            .iter()
+            .flat_map(|group| {
+                group
+                    .addresses
+                    .iter()
+                    .map(|addr| Address::new(addr, group.name.as_deref()))
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_addresses(raw_message: RawMessage) -> NifResult<(Atom, Addresses)> {
+    let message = parse_message(raw_message.as_slice())?;
+    Ok((atoms::ok(), Addresses::from(&message)))
+}
+
+#[rustler::nif]
+fn get_addresses(resource: ResourceArc<MessageResource>) -> Addresses {
+    Addresses::from(resource.message())
+}
@@ -0,0 +1,454 @@
+use crate::inflate::{crc32, inflate, InflateError};
+use crate::sniff::detect_content_type;
+
+pub const ZIP_LOCAL_FILE_HEADER: &[u8] = b"PK\x03\x04";
+const ZIP_CENTRAL_DIRECTORY_HEADER: &[u8] = b"PK\x01\x02";
+pub const ZIP_END_OF_CENTRAL_DIRECTORY: &[u8] = b"PK\x05\x06";
+
+/// Flag set on encrypted ZIP entries.
+const ZIP_ENCRYPTED_FLAG: u16 = 0x0001;
+
+const ZIP_STORED: u16 = 0;
+const ZIP_DEFLATED: u16 = 8;
+
+/// Size recorded for the entries whose actual size is in a ZIP64 field.
+const ZIP64_SIZE: u32 = u32::MAX;
+
+const GZIP_SIGNATURE: &[u8] = b"\x1f\x8b";
+const GZIP_DEFLATE: u8 = 8;
+
+const GZIP_FHCRC: u8 = 0x02;
+const GZIP_FEXTRA: u8 = 0x04;
+const GZIP_FNAME: u8 = 0x08;
+const GZIP_FCOMMENT: u8 = 0x10;
+
+const TAR_BLOCK_SIZE: usize = 512;
+
+/// Maximum number of entries unpacked from an archive, so that archives of
+/// countless empty files cannot exhaust memory either.
+const MAX_ENTRIES: usize = 10_000;
+
+/// A file unpacked from an archive attachment.
+pub struct ArchiveEntry {
+    /// Path of the file within the archive.
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+/// Caps on the decompressed size of the files unpacked from an archive,
+/// protecting against archives decompressing to huge sizes (zip bombs).
+pub struct ArchiveLimits {
+    /// Maximum decompressed size of a single file, in bytes.
+    pub max_entry_size: usize,
+    /// Maximum decompressed size of all the files of an archive, in bytes.
+    pub max_total_size: usize,
+}
+
+/// Why an archive could not be fully unpacked.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExpandError {
+    /// The archive is malformed, encrypted or uses an unsupported
+    /// compression method.
+    Invalid,
+    /// The archive exceeds any of its limits.
+    TooLarge,
+}
+
+impl From<InflateError> for ExpandError {
+    fn from(error: InflateError) -> Self {
+        match error {
+            InflateError::Malformed => ExpandError::Invalid,
+            InflateError::TooLarge => ExpandError::TooLarge,
+        }
+    }
+}
+
+/// Tracks the files unpacked from an archive against its limits.
+struct Unpacked<'l> {
+    entries: Vec<ArchiveEntry>,
+    total_size: usize,
+    limits: &'l ArchiveLimits,
+    /// Whether unpacking stopped at the size limits.
+    too_large: bool,
+}
+
+impl Unpacked<'_> {
+    /// Returns the maximum decompressed size of the next file.
+    fn max_entry_size(&self) -> usize {
+        let remaining_size = self.limits.max_total_size.saturating_sub(self.total_size);
+        self.limits.max_entry_size.min(remaining_size)
+    }
+
+    /// Checks that a file of `size` bytes fits within the limits.
+    fn fits(&mut self, size: usize) -> Option<()> {
+        self.too_large = size > self.max_entry_size();
+        (!self.too_large).then_some(())
+    }
+
+    fn push(&mut self, name: String, contents: Vec<u8>) -> Option<()> {
+        self.fits(contents.len())?;
+
+        if self.entries.len() >= MAX_ENTRIES {
+            return None;
+        }
+
+        self.total_size += contents.len();
+        self.entries.push(ArchiveEntry { name, contents });
+        Some(())
+    }
+}
+
+/// Returns whether a file is a ZIP, gzip or tar archive, by its contents.
+pub fn is_archive(bytes: &[u8]) -> bool {
+    matches!(
+        detect_content_type(bytes),
+        Some("application/zip" | "application/gzip" | "application/x-tar")
+    )
+}
+
+/// Unpacks the files of a ZIP, gzip or tar archive named `name`, in order.
+/// Gzip compressed tar archives are unpacked as tar archives.
+///
+/// Fails when the archive cannot be fully unpacked, because it is
+/// malformed, encrypted, uses an unsupported compression method or exceeds
+/// any of `limits`, so that it can be returned as is. Directories are left
+/// out.
+pub fn expand(
+    bytes: &[u8],
+    name: &str,
+    limits: &ArchiveLimits,
+) -> Result<Vec<ArchiveEntry>, ExpandError> {
+    let mut unpacked = Unpacked {
+        entries: Vec::new(),
+        total_size: 0,
+        limits,
+        too_large: false,
+    };
+
+    match unpack(bytes, name, &mut unpacked) {
+        Some(()) => Ok(unpacked.entries),
+        None if unpacked.too_large => Err(ExpandError::TooLarge),
+        None => Err(ExpandError::Invalid),
+    }
+}
+
+fn unpack(bytes: &[u8], name: &str, unpacked: &mut Unpacked) -> Option<()> {
+    match detect_content_type(bytes)? {
+        "application/zip" => unzip(bytes, unpacked),
+        "application/gzip" => {
+            // Tar archives are checked against the limits file by file.
+            let (file_name, contents) = match gunzip(bytes, unpacked.limits.max_total_size) {
+                Ok(file) => file,
+                Err(error) => {
+                    unpacked.too_large = error == ExpandError::TooLarge;
+                    return None;
+                }
+            };
+
+            if is_tar(&contents) {
+                untar(&contents, unpacked)
+            } else {
+                let file_name = file_name.unwrap_or_else(|| gunzipped_name(name));
+                unpacked.push(file_name, contents)
+            }
+        }
+        "application/x-tar" => untar(bytes, unpacked),
+        _ => None,
+    }
+}
+
+/// An entry of the central directory of a ZIP archive.
+pub struct ZipEntry<'a> {
+    pub name: &'a [u8],
+    flags: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    local_header_offset: u32,
+}
+
+impl ZipEntry<'_> {
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & ZIP_ENCRYPTED_FLAG != 0
+    }
+}
+
+/// Iterates over the entries of the central directory of a ZIP archive.
+pub struct ZipEntries<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    /// Number of entries left, as recorded by the end of central directory
+    /// record.
+    remaining: u16,
+}
+
+impl<'a> Iterator for ZipEntries<'a> {
+    type Item = ZipEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (bytes, offset) = (self.bytes, self.offset);
+
+        if self.remaining == 0 || bytes.get(offset..offset + 4)? != ZIP_CENTRAL_DIRECTORY_HEADER {
+            return None;
+        }
+
+        let name_len = usize::from(u16_at(bytes, offset + 28)?);
+        let extra_len = usize::from(u16_at(bytes, offset + 30)?);
+        let comment_len = usize::from(u16_at(bytes, offset + 32)?);
+
+        let entry = ZipEntry {
+            name: bytes.get(offset + 46..offset + 46 + name_len)?,
+            flags: u16_at(bytes, offset + 8)?,
+            method: u16_at(bytes, offset + 10)?,
+            crc: u32_at(bytes, offset + 16)?,
+            compressed_size: u32_at(bytes, offset + 20)?,
+            size: u32_at(bytes, offset + 24)?,
+            local_header_offset: u32_at(bytes, offset + 42)?,
+        };
+
+        self.offset += 46 + name_len + extra_len + comment_len;
+        self.remaining -= 1;
+        Some(entry)
+    }
+}
+
+/// Returns the entries of a ZIP archive, as listed by its central
+/// directory, or `None` if it has no end of central directory record.
+/// Iteration stops at the first malformed entry.
+pub fn zip_entries(bytes: &[u8]) -> Option<ZipEntries<'_>> {
+    // The end of central directory record is followed by a comment of up to
+    // 64 KiB.
+    let search_start = bytes.len().saturating_sub(22 + usize::from(u16::MAX));
+    let end_of_central_directory = bytes[search_start..]
+        .windows(4)
+        .rposition(|window| window == ZIP_END_OF_CENTRAL_DIRECTORY)
+        .map(|pos| search_start + pos)?;
+
+    Some(ZipEntries {
+        bytes,
+        offset: usize::try_from(u32_at(bytes, end_of_central_directory + 16)?).ok()?,
+        remaining: u16_at(bytes, end_of_central_directory + 10)?,
+    })
+}
+
+fn unzip(bytes: &[u8], unpacked: &mut Unpacked) -> Option<()> {
+    let mut entries = zip_entries(bytes)?;
+
+    for entry in entries.by_ref() {
+        if entry.name.ends_with(b"/") {
+            continue;
+        }
+
+        if entry.is_encrypted() || entry.size == ZIP64_SIZE || entry.compressed_size == ZIP64_SIZE {
+            return None;
+        }
+
+        let size = usize::try_from(entry.size).ok()?;
+
+        // Reject entries whose recorded size exceeds the limits up front,
+        // rather than after decompressing them.
+        unpacked.fits(size)?;
+
+        let offset = usize::try_from(entry.local_header_offset).ok()?;
+
+        if bytes.get(offset..offset + 4)? != ZIP_LOCAL_FILE_HEADER {
+            return None;
+        }
+
+        let name_len = usize::from(u16_at(bytes, offset + 26)?);
+        let extra_len = usize::from(u16_at(bytes, offset + 28)?);
+        let start = offset + 30 + name_len + extra_len;
+        let data = bytes.get(start..start + usize::try_from(entry.compressed_size).ok()?)?;
+
+        let contents = match entry.method {
+            ZIP_STORED => data.to_vec(),
+            ZIP_DEFLATED => inflate(data, size).ok()?.0,
+            _ => return None,
+        };
+
+        if contents.len() != size || crc32(&contents) != entry.crc {
+            return None;
+        }
+
+        unpacked.push(String::from_utf8_lossy(entry.name).into_owned(), contents)?;
+    }
+
+    // Some entries were malformed.
+    (entries.remaining == 0).then_some(())
+}
+
+/// Decompresses the first member of a gzip file (RFC 1952) to at most
+/// `max_size` bytes, returning the name of the original file, if recorded,
+/// and its contents.
+pub fn gunzip(bytes: &[u8], max_size: usize) -> Result<(Option<String>, Vec<u8>), ExpandError> {
+    if !bytes.starts_with(GZIP_SIGNATURE) || bytes.get(2) != Some(&GZIP_DEFLATE) {
+        return Err(ExpandError::Invalid);
+    }
+
+    let flags = *bytes.get(3).ok_or(ExpandError::Invalid)?;
+    // Modification time, extra flags and operating system.
+    let mut pos = 10;
+
+    if flags & GZIP_FEXTRA != 0 {
+        pos += 2 + usize::from(u16_at(bytes, pos).ok_or(ExpandError::Invalid)?);
+    }
+
+    let take_string = |pos: &mut usize| {
+        let len = bytes.get(*pos..)?.iter().position(|&byte| byte == 0)?;
+        let string: String = bytes[*pos..*pos + len]
+            .iter()
+            .map(|&byte| byte as char)
+            .collect();
+        *pos += len + 1;
+        Some(string)
+    };
+
+    let name = match flags & GZIP_FNAME {
+        0 => None,
+        _ => Some(take_string(&mut pos).ok_or(ExpandError::Invalid)?),
+    };
+
+    if flags & GZIP_FCOMMENT != 0 {
+        take_string(&mut pos).ok_or(ExpandError::Invalid)?;
+    }
+
+    if flags & GZIP_FHCRC != 0 {
+        pos += 2;
+    }
+
+    let deflated = bytes.get(pos..).ok_or(ExpandError::Invalid)?;
+    let (contents, len) = inflate(deflated, max_size)?;
+    let trailer = pos + len;
+
+    if u32_at(bytes, trailer) != Some(crc32(&contents))
+        || u32_at(bytes, trailer + 4) != Some(contents.len() as u32)
+    {
+        return Err(ExpandError::Invalid);
+    }
+
+    Ok((name.filter(|name| !name.is_empty()), contents))
+}
+
+/// Returns the name of the file compressed in a gzip file named `name`
+/// without an original name, e.g. `report.txt` for `report.txt.gz`.
+pub fn gunzipped_name(name: &str) -> String {
+    let lowercase_name = name.to_ascii_lowercase();
+
+    if lowercase_name.ends_with(".tgz") {
+        format!("{}.tar", &name[..name.len() - 4])
+    } else if lowercase_name.ends_with(".gz") && name.len() > 3 {
+        name[..name.len() - 3].to_string()
+    } else {
+        "untitled".to_string()
+    }
+}
+
+pub fn is_tar(bytes: &[u8]) -> bool {
+    detect_content_type(bytes) == Some("application/x-tar")
+}
+
+/// Unpacks the regular files of a tar archive, either in the POSIX
+/// (ustar) or the GNU format.
+fn untar(bytes: &[u8], unpacked: &mut Unpacked) -> Option<()> {
+    let mut offset = 0;
+    // Name of the next entry, given by a preceding GNU long name or POSIX
+    // extended header entry.
+    let mut long_name = None;
+
+    while let Some(header) = bytes.get(offset..offset + TAR_BLOCK_SIZE) {
+        // The archive ends with two blocks of zeros.
+        if header.iter().all(|&byte| byte == 0) {
+            break;
+        }
+
+        let size = usize::try_from(octal(&header[124..136])?).ok()?;
+        let start = offset + TAR_BLOCK_SIZE;
+        let contents = bytes.get(start..start.checked_add(size)?)?;
+        offset = start + size.div_ceil(TAR_BLOCK_SIZE) * TAR_BLOCK_SIZE;
+
+        match header[156] {
+            b'0' | b'\0' | b'7' => {
+                let name = long_name.take().unwrap_or_else(|| tar_name(header));
+
+                if !name.ends_with('/') {
+                    unpacked.push(name, contents.to_vec())?;
+                }
+            }
+            b'L' => long_name = Some(null_terminated(contents)),
+            b'x' => long_name = pax_path(contents).or(long_name),
+            // Directories, links and extended headers.
+            _ => (),
+        }
+    }
+
+    Some(())
+}
+
+/// Returns the `path` record of a POSIX extended header, made of
+/// `<length> <key>=<value>\n` records.
+fn pax_path(records: &[u8]) -> Option<String> {
+    let mut rest = records;
+    let mut path = None;
+
+    while !rest.is_empty() {
+        let space = rest.iter().position(|&byte| byte == b' ')?;
+        let len: usize = std::str::from_utf8(&rest[..space]).ok()?.parse().ok()?;
+        let record = rest.get(space + 1..len)?.strip_suffix(b"\n")?;
+
+        if let Some(value) = record.strip_prefix(b"path=") {
+            path = Some(String::from_utf8_lossy(value).into_owned());
+        }
+
+        rest = &rest[len..];
+    }
+
+    path
+}
+
+/// Returns the name of a tar entry, prefixed by the directory recorded in
+/// the ustar header.
+fn tar_name(header: &[u8]) -> String {
+    let name = null_terminated(&header[..100]);
+    let prefix = match &header[257..262] == b"ustar" {
+        true => null_terminated(&header[345..500]),
+        false => String::new(),
+    };
+
+    match prefix.is_empty() {
+        true => name,
+        false => format!("{prefix}/{name}"),
+    }
+}
+
+fn null_terminated(bytes: &[u8]) -> String {
+    let len = bytes
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+/// Parses a number of a tar header, in octal and padded with spaces or
+/// zeros.
+fn octal(field: &[u8]) -> Option<u64> {
+    let mut digits = field
+        .iter()
+        .skip_while(|&&byte| byte == b' ')
+        .take_while(|&&byte| byte != b' ' && byte != 0);
+
+    digits.try_fold(0u64, |number, &byte| match byte {
+        b'0'..=b'7' => number.checked_mul(8)?.checked_add(u64::from(byte - b'0')),
+        _ => None,
+    })
+}
+
+pub fn u16_at(bytes: &[u8], offset: usize) -> Option<u16> {
+    let bytes = bytes.get(offset..offset + 2)?;
+    Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+pub fn u32_at(bytes: &[u8], offset: usize) -> Option<u32> {
+    let bytes = bytes.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
@@ -0,0 +1,845 @@
+use std::collections::HashMap;
+
+use mail_parser::{Message, MessagePart, MimeHeaders, PartType};
+use rustler::{Binary, Encoder, Env, Error, NewBinary, NifStruct, NifUnitEnum, ResourceArc, Term};
+use sha2::{Digest, Sha256};
+
+use crate::archive::{self, ArchiveEntry, ArchiveLimits, ExpandError};
+use crate::atoms;
+use crate::budget::{decoded_size, Budget, BudgetExceeded};
+use crate::decompress::{decompress_attachment, DecompressedFile};
+use crate::digest::{digests, Digests};
+use crate::filename::{declared_name, DeclaredName};
+use crate::header::header_map;
+use crate::message::MessageResource;
+use crate::metrics;
+use crate::options::{NameFallback, Nested, OnLimit, Options};
+use crate::security::{self, WrappedAttachment};
+use crate::sniff;
+use crate::tnef::{self, TnefAttachment};
+use crate::uuencode::{self, UuencodedFile};
+
+/// Content type of unpacked files that do not declare one.
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+#[derive(NifStruct)]
+#[module = "MailParser.Attachment"]
+pub struct Attachment<'a> {
+    name: String,
+    raw_name: Option<String>,
+    content_type: Option<String>,
+    content_bytes: Binary<'a>,
+    disposition: Disposition,
+    content_id: Option<String>,
+    digests: Digests,
+    detected_content_type: Option<String>,
+    encrypted: bool,
+    part_headers: Option<HashMap<String, Vec<String>>>,
+    duplicates: usize,
+}
+
+/// Returns the `type/subtype` content type of a part, if declared.
+pub fn content_type(part: &MessagePart) -> Option<String> {
+    part.content_type().map(|content_type| {
+        let roottype = content_type.ctype();
+
+        match content_type.subtype() {
+            Some(subtype) => format!("{roottype}/{subtype}"),
+            None => roottype.to_string(),
+        }
+    })
+}
+
+/// Returns the name of an attachment, falling back to `"untitled"`, or
+/// `"untitled.eml"` for attached messages.
+pub fn attachment_name<'m>(part: &'m MessagePart) -> &'m str {
+    match part.attachment_name() {
+        Some(name) => name,
+        None if part.is_message() => "untitled.eml",
+        None => "untitled",
+    }
+}
+
+/// Returns the decoded contents of an attachment.
+///
+/// Attached messages are returned as the raw message they were parsed from.
+/// Unless it was transfer encoded, a nested message shares the raw message
+/// of its parent and is delimited by the offsets of its root part.
+pub fn contents<'m>(part: &'m MessagePart) -> &'m [u8] {
+    match part.message() {
+        Some(message) => {
+            let root_part = message.root_part();
+
+            message
+                .raw_message
+                .get(root_part.offset_header..root_part.offset_end)
+                .unwrap_or_default()
+        }
+        None => part.contents(),
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, NifUnitEnum)]
+pub enum Disposition {
+    Inline,
+    Attachment,
+}
+
+/// Returns the disposition of a part as declared by its Content-Disposition
+/// header. Parts without one are considered inline when they can be
+/// referenced from the message body, i.e. when they have a Content-ID or
+/// were classified as inline by the parser.
+pub fn disposition(part: &MessagePart) -> Disposition {
+    match part.content_disposition() {
+        Some(content_disposition) if content_disposition.is_inline() => Disposition::Inline,
+        Some(content_disposition) if content_disposition.is_attachment() => Disposition::Attachment,
+        _ if part.content_id().is_some() || matches!(part.body, PartType::InlineBinary(_)) => {
+            Disposition::Inline
+        }
+        _ => Disposition::Attachment,
+    }
+}
+
+/// An attachment selected from a message: either one of its parts or a
+/// file unpacked from one.
+pub enum AttachmentPart<'m> {
+    /// A part of the message, along with the raw message its offsets are
+    /// relative to and its declared name.
+    Part(&'m MessagePart<'m>, &'m [u8], Option<DeclaredName>),
+    /// A part without a name of its own, along with the raw message its
+    /// offsets are relative to and the name given to it by `name_fallback`.
+    Unnamed(&'m MessagePart<'m>, &'m [u8], String),
+    /// A file unpacked from a TNEF part, with `expand_tnef: true`.
+    Tnef(&'m MessagePart<'m>, TnefAttachment<'m>),
+    /// A file decoded from a uuencoded block of a text body, with
+    /// `uudecode: true`.
+    Uuencoded(&'m MessagePart<'m>, UuencodedFile),
+    /// A file unpacked from a ZIP, gzip or tar attachment, with
+    /// `expand_archives: true`.
+    Archived(&'m MessagePart<'m>, ArchiveEntry),
+    /// An attachment of the message wrapped by an opaque-signed S/MIME
+    /// part.
+    Wrapped(&'m MessagePart<'m>, WrappedAttachment),
+    /// A compressed part, along with the raw message its offsets are
+    /// relative to and its decompressed contents, with `decompress: true`.
+    Decompressed(&'m MessagePart<'m>, &'m [u8], DecompressedFile),
+}
+
+impl<'m> AttachmentPart<'m> {
+    pub fn name(&self) -> &str {
+        match self {
+            AttachmentPart::Part(part, _, declared_name) => match declared_name {
+                Some(declared_name) => &declared_name.name,
+                None if part.is_message() => "untitled.eml",
+                None => "untitled",
+            },
+            AttachmentPart::Unnamed(_, _, name) => name,
+            AttachmentPart::Tnef(_, attachment) => attachment.name.as_deref().unwrap_or("untitled"),
+            AttachmentPart::Uuencoded(_, file) => &file.name,
+            AttachmentPart::Archived(_, entry) => &entry.name,
+            AttachmentPart::Wrapped(_, attachment) => match &attachment.name {
+                Some(name) => name,
+                None if self.extension() == Some("eml") => "untitled.eml",
+                None => "untitled",
+            },
+            AttachmentPart::Decompressed(_, _, file) => match &file.name {
+                Some(name) => name,
+                None if self.extension() == Some("eml") => "untitled.eml",
+                None => "untitled",
+            },
+        }
+    }
+
+    /// Returns the name of the attachment, unless it has none.
+    pub fn given_name(&self) -> Option<&str> {
+        match self {
+            AttachmentPart::Part(_, _, declared_name) => declared_name
+                .as_ref()
+                .map(|declared_name| declared_name.name.as_str()),
+            AttachmentPart::Tnef(_, attachment) => attachment.name.as_deref(),
+            AttachmentPart::Wrapped(_, attachment) => attachment.name.as_deref(),
+            AttachmentPart::Decompressed(_, _, file) => file.name.as_deref(),
+            _ => Some(self.name()),
+        }
+    }
+
+    /// Returns the name of the attachment as found in the headers of its
+    /// part, before decoding, unless it has none.
+    pub fn raw_name(&self) -> Option<&str> {
+        match self {
+            AttachmentPart::Part(_, _, declared_name) => declared_name
+                .as_ref()
+                .map(|declared_name| declared_name.raw_name.as_str()),
+            AttachmentPart::Wrapped(_, attachment) => attachment.raw_name.as_deref(),
+            AttachmentPart::Decompressed(_, _, file) => file.raw_name.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the extension of the detected content type of the attachment,
+    /// or else of its declared one, if known.
+    fn extension(&self) -> Option<&'static str> {
+        self.detected_content_type()
+            .and_then(sniff::extension)
+            .or_else(|| self.content_type().as_deref().and_then(sniff::extension))
+    }
+
+    /// Names the attachment with `name_fallback` if it has no name, as the
+    /// attachment at `index` among the returned ones.
+    fn name_unnamed(&mut self, index: usize, name_fallback: &NameFallback) {
+        if self.given_name().is_some() {
+            return;
+        }
+
+        let extension = self.extension();
+        let with_extension = |stem: &str| match extension {
+            Some(extension) => format!("{stem}.{extension}"),
+            None => stem.to_string(),
+        };
+
+        let content_id = self
+            .content_id()
+            .and_then(|content_id| content_id.split('@').next())
+            .filter(|local_part| !local_part.is_empty());
+
+        let name = match (name_fallback, content_id) {
+            (NameFallback::Untitled, _) => self.name().to_string(),
+            (NameFallback::ContentId, Some(local_part)) if local_part.contains('.') => {
+                local_part.to_string()
+            }
+            (NameFallback::ContentId, Some(local_part)) => with_extension(local_part),
+            (NameFallback::Extension | NameFallback::ContentId, _) => with_extension("untitled"),
+            (NameFallback::Indexed(pattern), _) => {
+                with_extension(&pattern.replace("%d", &(index + 1).to_string()))
+            }
+        };
+
+        match self {
+            AttachmentPart::Part(part, raw_message, _) => {
+                *self = AttachmentPart::Unnamed(part, raw_message, name)
+            }
+            AttachmentPart::Tnef(_, attachment) => attachment.name = Some(name),
+            AttachmentPart::Wrapped(_, attachment) => attachment.name = Some(name),
+            AttachmentPart::Decompressed(_, _, file) => file.name = Some(name),
+            _ => {}
+        }
+    }
+
+    /// Returns the content type detected from the contents of the
+    /// attachment, if they start with a known signature.
+    pub fn detected_content_type(&self) -> Option<&'static str> {
+        sniff::detect_content_type(self.contents())
+    }
+
+    pub fn content_type(&self) -> Option<String> {
+        match self {
+            AttachmentPart::Part(part, ..) | AttachmentPart::Unnamed(part, ..) => {
+                content_type(part)
+            }
+            AttachmentPart::Tnef(_, attachment) => attachment.content_type.clone(),
+            AttachmentPart::Uuencoded(..) => Some(DEFAULT_CONTENT_TYPE.to_string()),
+            AttachmentPart::Archived(..) => Some(
+                self.detected_content_type()
+                    .unwrap_or(DEFAULT_CONTENT_TYPE)
+                    .to_string(),
+            ),
+            AttachmentPart::Wrapped(_, attachment) => attachment.content_type.clone(),
+            AttachmentPart::Decompressed(_, _, file) => file.content_type.clone(),
+        }
+    }
+
+    pub fn contents(&self) -> &[u8] {
+        match self {
+            AttachmentPart::Part(part, ..) | AttachmentPart::Unnamed(part, ..) => contents(part),
+            AttachmentPart::Tnef(_, attachment) => attachment.contents,
+            AttachmentPart::Uuencoded(_, file) => &file.contents,
+            AttachmentPart::Archived(_, entry) => &entry.contents,
+            AttachmentPart::Wrapped(_, attachment) => &attachment.contents,
+            AttachmentPart::Decompressed(_, _, file) => &file.contents,
+        }
+    }
+
+    pub fn disposition(&self) -> Disposition {
+        match self {
+            AttachmentPart::Part(part, ..)
+            | AttachmentPart::Unnamed(part, ..)
+            | AttachmentPart::Decompressed(part, ..) => disposition(part),
+            AttachmentPart::Tnef(..)
+            | AttachmentPart::Uuencoded(..)
+            | AttachmentPart::Archived(..) => Disposition::Attachment,
+            AttachmentPart::Wrapped(_, attachment) => attachment.disposition,
+        }
+    }
+
+    pub fn content_id(&self) -> Option<&str> {
+        match self {
+            AttachmentPart::Part(part, ..)
+            | AttachmentPart::Unnamed(part, ..)
+            | AttachmentPart::Decompressed(part, ..) => part.content_id(),
+            AttachmentPart::Tnef(_, attachment) => attachment.content_id.as_deref(),
+            AttachmentPart::Uuencoded(..) | AttachmentPart::Archived(..) => None,
+            AttachmentPart::Wrapped(_, attachment) => attachment.content_id.as_deref(),
+        }
+    }
+
+    /// Returns the decoded values of the headers of the part the attachment
+    /// is, keyed by lowercase name. Files unpacked from a part have none.
+    pub fn part_headers(&self) -> HashMap<String, Vec<String>> {
+        match self {
+            AttachmentPart::Part(part, raw_message, _)
+            | AttachmentPart::Unnamed(part, raw_message, _)
+            | AttachmentPart::Decompressed(part, raw_message, _) => {
+                header_map(raw_message, &part.headers)
+            }
+            AttachmentPart::Wrapped(_, attachment) => attachment.part_headers.clone(),
+            AttachmentPart::Tnef(..)
+            | AttachmentPart::Uuencoded(..)
+            | AttachmentPart::Archived(..) => HashMap::new(),
+        }
+    }
+
+    /// Returns the part of the message the attachment was found in.
+    pub fn part(&self) -> &'m MessagePart<'m> {
+        match self {
+            AttachmentPart::Part(part, ..)
+            | AttachmentPart::Unnamed(part, ..)
+            | AttachmentPart::Tnef(part, _)
+            | AttachmentPart::Uuencoded(part, _)
+            | AttachmentPart::Archived(part, _)
+            | AttachmentPart::Wrapped(part, _)
+            | AttachmentPart::Decompressed(part, ..) => part,
+        }
+    }
+}
+
+impl<'a> Attachment<'a> {
+    pub fn new(
+        env: Env<'a>,
+        source: &Source<'a>,
+        attachment: &AttachmentPart,
+        duplicates: usize,
+        options: &Options,
+    ) -> Self {
+        // Decoded files are owned by the attachment rather than the source,
+        // and are dropped with it.
+        let content_bytes = match attachment {
+            AttachmentPart::Uuencoded(..)
+            | AttachmentPart::Archived(..)
+            | AttachmentPart::Wrapped(..)
+            | AttachmentPart::Decompressed(..) => new_binary(env, attachment.contents()),
+            _ => source.binary(env, attachment.contents()),
+        };
+
+        Attachment {
+            name: attachment.name().to_string(),
+            raw_name: attachment.raw_name().map(str::to_string),
+            content_type: attachment.content_type(),
+            content_bytes,
+            disposition: attachment.disposition(),
+            content_id: attachment.content_id().map(str::to_string),
+            digests: digests(&options.digests, attachment.contents()),
+            detected_content_type: attachment.detected_content_type().map(str::to_string),
+            encrypted: sniff::is_encrypted(attachment.contents()),
+            part_headers: options
+                .include_part_headers
+                .then(|| attachment.part_headers()),
+            duplicates,
+        }
+    }
+}
+
+/// An attachment selected off the scheduler thread, whose contents are only
+/// turned into a binary once back on it.
+pub struct DetachedAttachment {
+    name: String,
+    raw_name: Option<String>,
+    content_type: Option<String>,
+    contents: DetachedContents,
+    disposition: Disposition,
+    content_id: Option<String>,
+    digests: Digests,
+    detected_content_type: Option<String>,
+    encrypted: bool,
+    part_headers: Option<HashMap<String, Vec<String>>>,
+    duplicates: usize,
+}
+
+/// The contents of a detached attachment: a range of the raw message it was
+/// parsed from when not transfer encoded, a copy otherwise.
+enum DetachedContents {
+    Range(usize, usize),
+    Owned(Vec<u8>),
+}
+
+impl DetachedAttachment {
+    pub fn new(
+        raw_message: &[u8],
+        attachment: &AttachmentPart,
+        duplicates: usize,
+        options: &Options,
+    ) -> Self {
+        let bytes = attachment.contents();
+        let offset = (bytes.as_ptr() as usize).wrapping_sub(raw_message.as_ptr() as usize);
+
+        let contents = if offset <= raw_message.len() && bytes.len() <= raw_message.len() - offset {
+            DetachedContents::Range(offset, bytes.len())
+        } else {
+            DetachedContents::Owned(bytes.to_vec())
+        };
+
+        DetachedAttachment {
+            name: attachment.name().to_string(),
+            raw_name: attachment.raw_name().map(str::to_string),
+            content_type: attachment.content_type(),
+            contents,
+            disposition: attachment.disposition(),
+            content_id: attachment.content_id().map(str::to_string),
+            digests: digests(&options.digests, bytes),
+            detected_content_type: attachment.detected_content_type().map(str::to_string),
+            encrypted: sniff::is_encrypted(bytes),
+            part_headers: options
+                .include_part_headers
+                .then(|| attachment.part_headers()),
+            duplicates,
+        }
+    }
+
+    /// Turns the attachment into one referencing `raw_message`, the binary
+    /// it was detached from.
+    pub fn attach<'a>(self, env: Env<'a>, raw_message: Binary<'a>) -> Attachment<'a> {
+        let content_bytes = match self.contents {
+            DetachedContents::Range(offset, len) => raw_message
+                .make_subbinary(offset, len)
+                .unwrap_or_else(|_| NewBinary::new(env, 0).into()),
+            DetachedContents::Owned(bytes) => new_binary(env, &bytes),
+        };
+
+        Attachment {
+            name: self.name,
+            raw_name: self.raw_name,
+            content_type: self.content_type,
+            content_bytes,
+            disposition: self.disposition,
+            content_id: self.content_id,
+            digests: self.digests,
+            detected_content_type: self.detected_content_type,
+            encrypted: self.encrypted,
+            part_headers: self.part_headers,
+            duplicates: self.duplicates,
+        }
+    }
+}
+
+/// The owner of the bytes a message was parsed from.
+///
+/// Attachment contents that are still referenced by their owner are returned
+/// as sub-binaries instead of being copied into a fresh binary.
+pub enum Source<'a> {
+    /// The raw message binary passed to the NIF.
+    Binary(Binary<'a>),
+    /// A message parsed by `parse/1`.
+    Resource(ResourceArc<MessageResource>),
+}
+
+impl<'a> Source<'a> {
+    pub fn binary(&self, env: Env<'a>, bytes: &[u8]) -> Binary<'a> {
+        match self {
+            Source::Binary(raw_message) => {
+                let start = raw_message.as_ptr() as usize;
+                let offset = (bytes.as_ptr() as usize).wrapping_sub(start);
+
+                match raw_message.make_subbinary(offset, bytes.len()) {
+                    Ok(binary) => binary,
+                    Err(_) => new_binary(env, bytes),
+                }
+            }
+            // SAFETY: `bytes` points into the message of the resource, which
+            // the resource owns or keeps alive along with what it borrows
+            // from, all immutable and outliving the binary referencing them.
+            Source::Resource(resource) => unsafe { resource.make_binary_unsafe(env, |_| bytes) },
+        }
+    }
+}
+
+/// Copies `bytes` into a fresh binary.
+fn new_binary<'a>(env: Env<'a>, bytes: &[u8]) -> Binary<'a> {
+    let mut binary = NewBinary::new(env, bytes.len());
+    binary.as_mut_slice().copy_from_slice(bytes);
+    binary.into()
+}
+
+/// Returned as `{:error, :limit_exceeded}` when an attachment exceeds one
+/// of the configured limits and `on_limit: :error` is set, or as
+/// `{:error, :budget_exceeded}` when the call exceeds `max_memory_bytes`.
+pub enum LimitExceeded {
+    Attachment,
+    MemoryBudget,
+}
+
+impl From<BudgetExceeded> for LimitExceeded {
+    fn from(_: BudgetExceeded) -> Self {
+        LimitExceeded::MemoryBudget
+    }
+}
+
+impl Encoder for LimitExceeded {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            LimitExceeded::Attachment => atoms::limit_exceeded().encode(env),
+            LimitExceeded::MemoryBudget => atoms::budget_exceeded().encode(env),
+        }
+    }
+}
+
+impl From<LimitExceeded> for Error {
+    fn from(limit_exceeded: LimitExceeded) -> Self {
+        Error::Term(Box::new(limit_exceeded))
+    }
+}
+
+pub fn get_attachments<'a>(
+    env: Env<'a>,
+    source: &Source<'a>,
+    message: &Message,
+    options: &Options,
+) -> Result<Vec<Attachment<'a>>, LimitExceeded> {
+    let (attachments, duplicates) = select_attachments_with_duplicates(message, options)?;
+
+    let attachments = attachments
+        .into_iter()
+        .zip(duplicates)
+        .map(|(attachment, duplicates)| {
+            Attachment::new(env, source, &attachment, duplicates, options)
+        })
+        .collect();
+
+    Ok(attachments)
+}
+
+/// Walks the attachments of a message and of its nested messages, in
+/// order, and returns the ones passing the filters and limits in `options`.
+/// With `expand_tnef: true`, TNEF parts are replaced by the files they
+/// contain, with `expand_archives: true` so are archives, with
+/// `decompress: true` compressed attachments are replaced by their payload,
+/// and with `uudecode: true` the files uuencoded in the text body of each
+/// message are returned before its attachments.
+///
+/// The message wrapped by an opaque-signed S/MIME part is handled like an
+/// attached message, as the part itself is of no use without it.
+///
+/// Attachments without a name are named by `options.name_fallback` once
+/// selected, so the filters match them as `"untitled"`.
+///
+/// The decoded bodies of the message and the files decoded while walking
+/// it are charged to the `options.max_memory_bytes` budget of the call.
+///
+/// An explicit stack is used so that the nesting depth is bounded by
+/// `options.max_depth` rather than by the native stack.
+pub fn select_attachments<'m>(
+    message: &'m Message,
+    options: &Options,
+) -> Result<Vec<AttachmentPart<'m>>, LimitExceeded> {
+    let (attachments, _) = select_attachments_with_duplicates(message, options)?;
+    Ok(attachments)
+}
+
+/// Selects the attachments of a message as `select_attachments` does, along
+/// with the number of duplicates of each left out by `options.dedupe`.
+pub fn select_attachments_with_duplicates<'m>(
+    message: &'m Message,
+    options: &Options,
+) -> Result<(Vec<AttachmentPart<'m>>, Vec<usize>), LimitExceeded> {
+    let mut budget = Budget::new(options.max_memory_bytes);
+    budget.charge(decoded_size(message))?;
+
+    let Selection {
+        mut attachments,
+        duplicates,
+        ..
+    } = select_attachments_at(message, options, 0, &mut budget)?;
+
+    if options.name_fallback != NameFallback::Untitled {
+        for (index, attachment) in attachments.iter_mut().enumerate() {
+            attachment.name_unnamed(index, &options.name_fallback);
+        }
+    }
+
+    metrics::record_attachments(attachments.len());
+    Ok((attachments, duplicates))
+}
+
+/// Selects the attachments of a message found `depth` messages deep.
+fn select_attachments_at<'m>(
+    message: &'m Message,
+    options: &Options,
+    depth: usize,
+    budget: &mut Budget,
+) -> Result<Selection<'m>, LimitExceeded> {
+    let mut selection = Selection::default();
+    let mut stack = vec![(message, message.attachments(), depth)];
+
+    if options.uudecode {
+        selection.extend(uuencoded_files(message, budget)?, options)?;
+    }
+
+    while let Some((message, parts, depth)) = stack.last_mut() {
+        let (message, depth) = (*message, *depth);
+
+        let Some(attachment) = parts.next() else {
+            stack.pop();
+            continue;
+        };
+
+        if let Some(nested_message) = attachment.message() {
+            if options.nested != Nested::Keep {
+                if depth < options.max_depth {
+                    if options.uudecode {
+                        selection.extend(uuencoded_files(nested_message, budget)?, options)?;
+                    }
+
+                    stack.push((nested_message, nested_message.attachments(), depth + 1));
+                } else if let OnLimit::Error = options.on_limit {
+                    return Err(LimitExceeded::Attachment);
+                }
+            }
+
+            if options.nested == Nested::Flatten {
+                continue;
+            }
+        }
+
+        let signed_content = match options.nested {
+            Nested::Keep => None,
+            _ => security::signed_content(attachment),
+        };
+
+        // The wrapped message is parsed on the fly, so its attachments are
+        // selected as a whole rather than descended into from the stack.
+        let signed_message = signed_content.as_deref().and_then(Message::parse);
+        let mut wrapped_attachments = Vec::new();
+        let mut wrapped_duplicates = Vec::new();
+
+        if let Some(signed_message) = &signed_message {
+            if depth < options.max_depth {
+                budget.charge(signed_content.as_ref().map_or(0, Vec::len))?;
+                budget.charge(decoded_size(signed_message))?;
+
+                let wrapped = select_attachments_at(signed_message, options, depth + 1, budget)?;
+                wrapped_attachments = wrapped
+                    .attachments
+                    .iter()
+                    .map(|wrapped| AttachmentPart::Wrapped(attachment, wrapped.into()))
+                    .collect();
+                wrapped_duplicates = wrapped.duplicates;
+                budget.charge(total_size(&wrapped_attachments))?;
+            } else if let OnLimit::Error = options.on_limit {
+                return Err(LimitExceeded::Attachment);
+            }
+
+            if options.nested == Nested::Flatten {
+                selection.extend_counted(
+                    wrapped_attachments.into_iter().zip(wrapped_duplicates),
+                    options,
+                )?;
+                continue;
+            }
+        }
+
+        let declared_name = declared_name(&message.raw_message, attachment);
+
+        let decompressed_file = match options.decompress {
+            true => {
+                decompressed_file(message, attachment, declared_name.as_ref(), options, budget)?
+            }
+            false => None,
+        };
+
+        let files = if let Some(file) = decompressed_file {
+            Some(vec![AttachmentPart::Decompressed(
+                attachment,
+                &message.raw_message,
+                file,
+            )])
+        } else if options.expand_tnef && tnef::is_tnef(attachment) {
+            tnef::decode(contents(attachment)).map(|tnef_attachments| {
+                tnef_attachments
+                    .into_iter()
+                    .map(|tnef_attachment| AttachmentPart::Tnef(attachment, tnef_attachment))
+                    .collect()
+            })
+        } else if options.expand_archives && archive::is_archive(contents(attachment)) {
+            // Archives are unpacked within the budget left, rather than
+            // checked against it once unpacked.
+            let remaining = budget.remaining().unwrap_or(usize::MAX);
+            let limits = ArchiveLimits {
+                max_entry_size: options.max_archive_entry_size,
+                max_total_size: options.max_archive_size.min(remaining),
+            };
+
+            let name = declared_name
+                .as_ref()
+                .map_or("untitled", |declared_name| declared_name.name.as_str());
+
+            match archive::expand(contents(attachment), name, &limits) {
+                Ok(entries) => {
+                    let entries: Vec<_> = entries
+                        .into_iter()
+                        .map(|entry| AttachmentPart::Archived(attachment, entry))
+                        .collect();
+                    budget.charge(total_size(&entries))?;
+                    Some(entries)
+                }
+                Err(ExpandError::TooLarge) if remaining < options.max_archive_size => {
+                    return Err(LimitExceeded::MemoryBudget);
+                }
+                Err(_) => None,
+            }
+        } else {
+            None
+        };
+
+        selection.extend(
+            files.unwrap_or_else(|| {
+                vec![AttachmentPart::Part(
+                    attachment,
+                    &message.raw_message,
+                    declared_name,
+                )]
+            }),
+            options,
+        )?;
+        selection.extend_counted(
+            wrapped_attachments.into_iter().zip(wrapped_duplicates),
+            options,
+        )?;
+    }
+
+    Ok(selection)
+}
+
+/// Decompresses an attachment compressed by a gateway within the budget
+/// left, charging it, or returns `None` if it is not compressed or cannot
+/// be decompressed, so that it is returned as is.
+fn decompressed_file(
+    message: &Message,
+    attachment: &MessagePart,
+    declared_name: Option<&DeclaredName>,
+    options: &Options,
+    budget: &mut Budget,
+) -> Result<Option<DecompressedFile>, LimitExceeded> {
+    let remaining = budget.remaining().unwrap_or(usize::MAX);
+    let max_size = options.max_decompressed_size.min(remaining);
+
+    match decompress_attachment(message, attachment, declared_name, max_size) {
+        Ok(file) => {
+            budget.charge(file.as_ref().map_or(0, |file| file.contents.len()))?;
+            Ok(file)
+        }
+        Err(ExpandError::TooLarge) if remaining < options.max_decompressed_size => {
+            Err(LimitExceeded::MemoryBudget)
+        }
+        Err(_) => Ok(None),
+    }
+}
+
+/// Returns the files uuencoded in the text body of a message, charging
+/// them to the budget.
+fn uuencoded_files<'m>(
+    message: &'m Message,
+    budget: &mut Budget,
+) -> Result<Vec<AttachmentPart<'m>>, BudgetExceeded> {
+    let files: Vec<_> = message
+        .text_body
+        .iter()
+        .flat_map(|&index| {
+            let part = &message.parts[index];
+
+            let files = match &part.body {
+                PartType::Text(text) => uuencode::decode(text),
+                _ => Vec::new(),
+            };
+
+            files
+                .into_iter()
+                .map(move |file| AttachmentPart::Uuencoded(part, file))
+        })
+        .collect();
+
+    budget.charge(total_size(&files))?;
+    Ok(files)
+}
+
+fn total_size(attachments: &[AttachmentPart]) -> usize {
+    attachments
+        .iter()
+        .map(|attachment| attachment.contents().len())
+        .sum()
+}
+
+/// The attachments selected so far by `select_attachments`.
+#[derive(Default)]
+struct Selection<'m> {
+    attachments: Vec<AttachmentPart<'m>>,
+    /// The number of duplicates of each attachment left out by
+    /// `options.dedupe`.
+    duplicates: Vec<usize>,
+    total_size: usize,
+    /// The index of the attachment selected for each digest.
+    seen_digests: HashMap<[u8; 32], usize>,
+}
+
+impl<'m> Selection<'m> {
+    /// Adds the candidates passing the filters and limits in `options`.
+    fn extend(
+        &mut self,
+        candidates: impl IntoIterator<Item = AttachmentPart<'m>>,
+        options: &Options,
+    ) -> Result<(), LimitExceeded> {
+        self.extend_counted(
+            candidates.into_iter().map(|candidate| (candidate, 0)),
+            options,
+        )
+    }
+
+    /// Adds the candidates passing the filters and limits in `options`,
+    /// each along with the number of its duplicates already left out.
+    fn extend_counted(
+        &mut self,
+        candidates: impl IntoIterator<Item = (AttachmentPart<'m>, usize)>,
+        options: &Options,
+    ) -> Result<(), LimitExceeded> {
+        for (candidate, duplicates) in candidates {
+            if !options.accepts(&candidate) {
+                continue;
+            }
+
+            let digest: Option<[u8; 32]> = options
+                .dedupe
+                .then(|| Sha256::digest(candidate.contents()).into());
+
+            if let Some(&index) = digest.and_then(|digest| self.seen_digests.get(&digest)) {
+                self.duplicates[index] += duplicates + 1;
+                continue;
+            }
+
+            let size = candidate.contents().len();
+
+            if options.exceeds_limits(self.attachments.len(), size, self.total_size) {
+                match options.on_limit {
+                    OnLimit::Error => return Err(LimitExceeded::Attachment),
+                    OnLimit::Skip => continue,
+                }
+            }
+
+            if let Some(digest) = digest {
+                self.seen_digests.insert(digest, self.attachments.len());
+            }
+
+            self.total_size += size;
+            self.attachments.push(candidate);
+            self.duplicates.push(duplicates);
+        }
+
+        Ok(())
+    }
+}
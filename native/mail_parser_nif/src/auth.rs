@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use rustler::{NifMap, ResourceArc};
+
+use crate::content_line::split_unquoted;
+use crate::header::unfolded_values;
+use crate::message::MessageResource;
+
+#[derive(NifMap)]
+pub struct AuthResults {
+    authentication_results: Vec<AuthResult>,
+    received_spf: Vec<ReceivedSpf>,
+    dkim_signatures: Vec<DkimSignature>,
+}
+
+/// The result of one authentication method of an Authentication-Results
+/// header, e.g. `dkim=pass header.d=example.com header.s=s1`.
+#[derive(NifMap)]
+pub struct AuthResult {
+    authserv_id: Option<String>,
+    method: String,
+    result: String,
+    reason: Option<String>,
+    domain: Option<String>,
+    selector: Option<String>,
+    /// Every `ptype.property=value` of the result, e.g. `"smtp.mailfrom"`.
+    properties: HashMap<String, String>,
+}
+
+#[derive(NifMap)]
+pub struct ReceivedSpf {
+    result: String,
+    domain: Option<String>,
+    client_ip: Option<String>,
+}
+
+#[derive(NifMap)]
+pub struct DkimSignature {
+    domain: Option<String>,
+    selector: Option<String>,
+    algorithm: Option<String>,
+}
+
+/// Removes the parenthesized, possibly nested, comments of a header value,
+/// except within quoted strings.
+fn strip_comments(value: &str) -> String {
+    let mut stripped = String::with_capacity(value.len());
+    let mut depth = 0usize;
+    let mut quoted = false;
+    let mut escaped = false;
+
+    for ch in value.chars() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            '"' if depth == 0 => quoted = !quoted,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted && depth > 0 => {
+                depth -= 1;
+                continue;
+            }
+            _ => (),
+        }
+
+        if depth == 0 {
+            stripped.push(ch);
+        }
+    }
+
+    stripped
+}
+
+/// Splits a `key=value` pair, unquoting the value.
+fn key_value(pair: &str) -> Option<(String, String)> {
+    let (key, value) = pair.split_once('=')?;
+    let value = value.trim().trim_matches('"');
+    Some((key.trim().to_ascii_lowercase(), value.to_string()))
+}
+
+/// Returns the domain of an address such as `jane@example.com`, or the
+/// value itself if it is not an address.
+fn domain(value: &str) -> String {
+    value.rsplit('@').next().unwrap_or(value).to_string()
+}
+
+/// Parses an Authentication-Results header (RFC 8601), e.g.
+/// `mx.example.com; spf=pass smtp.mailfrom=example.com; dkim=fail`.
+fn authentication_results(value: &str) -> Vec<AuthResult> {
+    let value = strip_comments(value);
+    let mut segments = split_unquoted(&value, |ch| ch == ';').into_iter();
+
+    let authserv_id = segments
+        .next()
+        .and_then(|segment| segment.split_whitespace().next())
+        .map(str::to_string);
+
+    segments
+        .filter_map(|segment| {
+            let mut pairs = split_unquoted(segment.trim(), char::is_whitespace)
+                .into_iter()
+                .filter_map(key_value);
+
+            let (method, result) = pairs.next()?;
+            let mut reason = None;
+            let mut properties = HashMap::new();
+
+            for (key, value) in pairs {
+                if key == "reason" {
+                    reason = Some(value);
+                } else {
+                    properties.insert(key, value);
+                }
+            }
+
+            let domain = [
+                "header.d",
+                "header.i",
+                "smtp.mailfrom",
+                "header.from",
+                "smtp.helo",
+            ]
+            .iter()
+            .find_map(|property| properties.get(*property))
+            .map(|value| domain(value));
+
+            Some(AuthResult {
+                authserv_id: authserv_id.clone(),
+                // Drop the version of the method, e.g. `dkim/1`.
+                method: method.split('/').next().unwrap_or_default().to_string(),
+                result: result.to_ascii_lowercase(),
+                reason,
+                domain,
+                selector: properties.get("header.s").cloned(),
+                properties,
+            })
+        })
+        .collect()
+}
+
+/// Parses a Received-SPF header (RFC 7208), e.g. `pass (comment)
+/// client-ip=192.0.2.1; envelope-from="jane@example.com"`.
+fn received_spf(value: &str) -> Option<ReceivedSpf> {
+    let value = strip_comments(value);
+    let value = value.trim();
+    let (result, pairs) = value.split_once(char::is_whitespace).unwrap_or((value, ""));
+
+    if result.is_empty() {
+        return None;
+    }
+
+    let pairs: HashMap<String, String> =
+        split_unquoted(pairs, |ch| ch == ';' || ch.is_whitespace())
+            .into_iter()
+            .filter_map(key_value)
+            .collect();
+
+    Some(ReceivedSpf {
+        result: result.trim().to_ascii_lowercase(),
+        domain: pairs
+            .get("envelope-from")
+            .or_else(|| pairs.get("helo"))
+            .map(|value| domain(value)),
+        client_ip: pairs.get("client-ip").cloned(),
+    })
+}
+
+/// Parses the tags of a DKIM-Signature header (RFC 6376).
+fn dkim_signature(value: &str) -> DkimSignature {
+    let tags: HashMap<String, String> = value
+        .split(';')
+        .filter_map(key_value)
+        .map(|(tag, value)| (tag, value.split_whitespace().collect()))
+        .collect();
+
+    DkimSignature {
+        domain: tags.get("d").cloned(),
+        selector: tags.get("s").cloned(),
+        algorithm: tags.get("a").cloned(),
+    }
+}
+
+#[rustler::nif]
+fn get_auth_results(resource: ResourceArc<MessageResource>) -> AuthResults {
+    let message = resource.message();
+
+    AuthResults {
+        authentication_results: unfolded_values(message, "Authentication-Results")
+            .iter()
+            .flat_map(|value| authentication_results(value))
+            .collect(),
+        received_spf: unfolded_values(message, "Received-SPF")
+            .iter()
+            .filter_map(|value| received_spf(value))
+            .collect(),
+        dkim_signatures: unfolded_values(message, "DKIM-Signature")
+            .iter()
+            .map(|value| dkim_signature(value))
+            .collect(),
+    }
+}
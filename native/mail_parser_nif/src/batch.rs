@@ -0,0 +1,143 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use rayon::prelude::*;
+use rustler::{Atom, Decoder, Encoder, Env, Error, NifResult, OwnedBinary, Term};
+
+use crate::atoms;
+use crate::attachment::{select_attachments_with_duplicates, DetachedAttachment, LimitExceeded};
+use crate::error::{parse_message, ErrorReason};
+use crate::iodata::RawMessage;
+use crate::options::Options;
+
+/// A message of a batch: either a raw message or `{:file, path}`.
+enum Input<'a> {
+    Raw(RawMessage<'a>),
+    File(String),
+}
+
+impl<'a> Decoder<'a> for Input<'a> {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        if term.is_binary() || term.is_list() {
+            return Ok(Input::Raw(term.decode()?));
+        }
+
+        let (tag, path): (Atom, String) = term.decode()?;
+
+        if tag == atoms::file() {
+            Ok(Input::File(path))
+        } else {
+            Err(Error::BadArg)
+        }
+    }
+}
+
+/// The bytes a worker thread parses a message from. Unlike binaries, they
+/// can be shared across threads.
+enum Job<'b> {
+    Raw(&'b [u8]),
+    File(&'b str),
+}
+
+/// The result of extracting the attachments of one message of a batch.
+enum Outcome {
+    Attachments(Vec<DetachedAttachment>),
+    Error(ErrorReason),
+    LimitExceeded(LimitExceeded),
+}
+
+fn detach_attachments(raw_message: &[u8], options: &Options) -> Outcome {
+    let message = match parse_message(raw_message) {
+        Ok(message) => message,
+        Err(reason) => return Outcome::Error(reason),
+    };
+
+    match select_attachments_with_duplicates(&message, options) {
+        Ok((parts, duplicates)) => Outcome::Attachments(
+            parts
+                .into_iter()
+                .zip(duplicates)
+                .map(|(attachment, duplicates)| {
+                    DetachedAttachment::new(raw_message, &attachment, duplicates, options)
+                })
+                .collect(),
+        ),
+        Err(limit_exceeded) => Outcome::LimitExceeded(limit_exceeded),
+    }
+}
+
+/// Reads the file at `path` into a binary allocated off the scheduler
+/// thread, to be handed over to the calling process afterwards.
+fn read_owned_binary(path: &str) -> Result<OwnedBinary, ErrorReason> {
+    let io_error = |error| ErrorReason::io(&error, Path::new(path));
+
+    let mut file = File::open(path).map_err(io_error)?;
+    let len = usize::try_from(file.metadata().map_err(io_error)?.len())
+        .map_err(|_| ErrorReason::Io(atoms::efbig(), path.to_string()))?;
+
+    let mut binary =
+        OwnedBinary::new(len).ok_or_else(|| ErrorReason::Io(atoms::enomem(), path.to_string()))?;
+    file.read_exact(binary.as_mut_slice()).map_err(io_error)?;
+
+    Ok(binary)
+}
+
+fn run_job(job: &Job, options: &Options) -> (Option<OwnedBinary>, Outcome) {
+    match job {
+        Job::Raw(raw_message) => (None, detach_attachments(raw_message, options)),
+        Job::File(path) => match read_owned_binary(path) {
+            Ok(binary) => {
+                let outcome = detach_attachments(binary.as_slice(), options);
+                (Some(binary), outcome)
+            }
+            Err(reason) => (None, Outcome::Error(reason)),
+        },
+    }
+}
+
+/// Extracts the attachments of many messages at once, parsing them in
+/// parallel on the rayon thread pool.
+///
+/// Terms can only be built on the calling thread, so the workers return
+/// detached attachments that are turned into sub-binaries of their raw
+/// message once all of them are done.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_attachments_batch<'a>(
+    env: Env<'a>,
+    inputs: Vec<Input<'a>>,
+    options: Options,
+) -> Vec<Term<'a>> {
+    let jobs: Vec<Job> = inputs
+        .iter()
+        .map(|input| match input {
+            Input::Raw(raw_message) => Job::Raw(raw_message.as_slice()),
+            Input::File(path) => Job::File(path),
+        })
+        .collect();
+
+    let outcomes: Vec<_> = jobs.par_iter().map(|job| run_job(job, &options)).collect();
+
+    inputs
+        .iter()
+        .zip(outcomes)
+        .map(|(input, (owned_binary, outcome))| match outcome {
+            Outcome::Attachments(attachments) => {
+                let raw_message = match (owned_binary, input) {
+                    (Some(owned_binary), _) => owned_binary.release(env),
+                    (None, Input::Raw(raw_message)) => **raw_message,
+                    (None, Input::File(_)) => return atoms::error().encode(env),
+                };
+
+                let attachments: Vec<_> = attachments
+                    .into_iter()
+                    .map(|attachment| attachment.attach(env, raw_message))
+                    .collect();
+
+                (atoms::ok(), attachments).encode(env)
+            }
+            Outcome::Error(reason) => (atoms::error(), reason).encode(env),
+            Outcome::LimitExceeded(limit_exceeded) => (atoms::error(), limit_exceeded).encode(env),
+        })
+        .collect()
+}
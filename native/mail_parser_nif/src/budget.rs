@@ -0,0 +1,77 @@
+use std::borrow::Cow;
+
+use mail_parser::{Message, PartType};
+
+use crate::error::ErrorReason;
+
+/// Bytes a call may decode, set by the `max_memory_bytes` option: the
+/// bodies decoded by the parser and the files unpacked from archives, TNEF
+/// parts, uuencoded text and opaque-signed parts.
+pub struct Budget {
+    remaining: Option<usize>,
+}
+
+/// Returned when a call decodes more than its budget allows.
+pub struct BudgetExceeded;
+
+impl From<BudgetExceeded> for ErrorReason {
+    fn from(_: BudgetExceeded) -> Self {
+        ErrorReason::BudgetExceeded
+    }
+}
+
+impl Budget {
+    pub fn new(max_memory_bytes: Option<usize>) -> Self {
+        Budget {
+            remaining: max_memory_bytes,
+        }
+    }
+
+    /// Returns the bytes left to decode, if limited.
+    pub fn remaining(&self) -> Option<usize> {
+        self.remaining
+    }
+
+    /// Takes `size` bytes out of the budget, failing if fewer are left.
+    pub fn charge(&mut self, size: usize) -> Result<(), BudgetExceeded> {
+        if let Some(remaining) = &mut self.remaining {
+            *remaining = remaining.checked_sub(size).ok_or(BudgetExceeded)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the size of the bodies of a message and of its nested messages
+/// that were decoded into buffers of their own, rather than borrowed from
+/// the raw message, including the decoded raw messages of nested messages.
+///
+/// It is charged once the message is parsed, as the parser cannot be
+/// stopped while decoding, so that the budget bounds what a call keeps
+/// rather than the peak memory of the parse.
+pub fn decoded_size(message: &Message) -> usize {
+    let mut size = 0;
+    let mut stack = vec![message];
+
+    while let Some(message) = stack.pop() {
+        for part in &message.parts {
+            size += match &part.body {
+                PartType::Text(Cow::Owned(text)) | PartType::Html(Cow::Owned(text)) => text.len(),
+                PartType::Binary(Cow::Owned(bytes)) | PartType::InlineBinary(Cow::Owned(bytes)) => {
+                    bytes.len()
+                }
+                PartType::Message(nested_message) => {
+                    stack.push(nested_message);
+
+                    match &nested_message.raw_message {
+                        Cow::Owned(raw_message) => raw_message.len(),
+                        Cow::Borrowed(_) => 0,
+                    }
+                }
+                _ => 0,
+            };
+        }
+    }
+
+    size
+}
@@ -0,0 +1,168 @@
+use mail_parser::{Message, MessagePart, MimeHeaders};
+use rustler::{Atom, NifMap, NifResult};
+
+use crate::atoms;
+use crate::content_line::{content_lines, ContentLine};
+use crate::error::parse_message;
+use crate::iodata::RawMessage;
+
+#[derive(NifMap, PartialEq)]
+pub struct CalendarEvent {
+    method: Option<String>,
+    uid: Option<String>,
+    summary: Option<String>,
+    organizer: Option<Participant>,
+    attendees: Vec<Participant>,
+    dtstart: Option<String>,
+    dtend: Option<String>,
+    timezone: Option<String>,
+    rrule: Option<String>,
+}
+
+/// The organizer or an attendee of an event.
+#[derive(NifMap, PartialEq)]
+pub struct Participant {
+    name: Option<String>,
+    email: Option<String>,
+    /// Participation status of an attendee, e.g. `"accepted"`.
+    status: Option<String>,
+}
+
+impl Participant {
+    fn new(line: &ContentLine) -> Self {
+        let value = line.value.trim();
+        let email = match value.get(..7) {
+            Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => &value[7..],
+            _ => value,
+        };
+
+        Participant {
+            name: line.param("CN").map(str::to_string),
+            email: (!email.is_empty()).then(|| email.to_string()),
+            status: line.param("PARTSTAT").map(str::to_ascii_lowercase),
+        }
+    }
+}
+
+/// Returns whether a part holds an iCalendar object, either as
+/// `text/calendar` or, for `.ics` attachments, as `application/ics`.
+fn is_calendar(part: &MessagePart) -> bool {
+    part.content_type().is_some_and(|content_type| {
+        let subtype = content_type.subtype().unwrap_or_default();
+
+        match content_type.ctype() {
+            ctype if ctype.eq_ignore_ascii_case("text") => subtype.eq_ignore_ascii_case("calendar"),
+            ctype if ctype.eq_ignore_ascii_case("application") => {
+                subtype.eq_ignore_ascii_case("ics")
+            }
+            _ => false,
+        }
+    })
+}
+
+/// Returns the events of an iCalendar object, each with the `METHOD` of
+/// the calendar it belongs to.
+fn events(text: &str) -> Vec<CalendarEvent> {
+    let mut events = Vec::new();
+    let mut method = None;
+    let mut event: Option<CalendarEvent> = None;
+    // Depth of the components nested in the current event, such as alarms,
+    // whose properties do not describe the event.
+    let mut nested_depth = 0;
+
+    for line in content_lines(text) {
+        let Some(current_event) = event.as_mut() else {
+            match line.name.as_str() {
+                "METHOD" => method = Some(line.text().trim().to_string()),
+                "BEGIN" if line.value.trim().eq_ignore_ascii_case("VEVENT") => {
+                    event = Some(CalendarEvent {
+                        method: method.clone(),
+                        uid: None,
+                        summary: None,
+                        organizer: None,
+                        attendees: Vec::new(),
+                        dtstart: None,
+                        dtend: None,
+                        timezone: None,
+                        rrule: None,
+                    });
+                }
+                _ => (),
+            }
+
+            continue;
+        };
+
+        match line.name.as_str() {
+            "BEGIN" => nested_depth += 1,
+            "END" if nested_depth > 0 => nested_depth -= 1,
+            "END" => events.extend(event.take()),
+            _ if nested_depth > 0 => (),
+            "UID" => current_event.uid = Some(line.text()),
+            "SUMMARY" => current_event.summary = Some(line.text()),
+            "ORGANIZER" => current_event.organizer = Some(Participant::new(&line)),
+            "ATTENDEE" => current_event.attendees.push(Participant::new(&line)),
+            "DTSTART" => {
+                current_event.dtstart = Some(date_time(&line.value));
+                current_event.timezone = line.param("TZID").map(str::to_string);
+            }
+            "DTEND" => current_event.dtend = Some(date_time(&line.value)),
+            "RRULE" => current_event.rrule = Some(line.value.trim().to_string()),
+            _ => (),
+        }
+    }
+
+    events
+}
+
+/// Formats an iCalendar date or date-time, e.g. `20240603T100000Z`, as
+/// ISO 8601. Values in any other format are returned as is.
+fn date_time(value: &str) -> String {
+    let value = value.trim();
+    let is_digits = |range: std::ops::Range<usize>| {
+        value
+            .get(range)
+            .is_some_and(|digits| digits.bytes().all(|byte| byte.is_ascii_digit()))
+    };
+
+    let date = || format!("{}-{}-{}", &value[..4], &value[4..6], &value[6..8]);
+
+    match value.len() {
+        8 if is_digits(0..8) => date(),
+        15 | 16 if is_digits(0..8) && value.as_bytes()[8] == b'T' && is_digits(9..15) => {
+            let utc = match &value[15..] {
+                "Z" | "z" => "Z",
+                "" => "",
+                _ => return value.to_string(),
+            };
+
+            let time = format!("{}:{}:{}", &value[9..11], &value[11..13], &value[13..15]);
+            format!("{}T{time}{utc}", date())
+        }
+        _ => value.to_string(),
+    }
+}
+
+/// Returns the events of the calendar parts of a message, in order.
+///
+/// Invitations usually carry the same calendar both inline and as an
+/// `.ics` attachment, so events identical to an earlier one are left out.
+fn calendar_events(message: &Message) -> Vec<CalendarEvent> {
+    let mut calendar_events: Vec<CalendarEvent> = Vec::new();
+
+    for part in message.parts.iter().filter(|part| is_calendar(part)) {
+        for event in events(&String::from_utf8_lossy(part.contents())) {
+            if !calendar_events.contains(&event) {
+                calendar_events.push(event);
+            }
+        }
+    }
+
+    calendar_events
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_calendar_events(raw_message: RawMessage) -> NifResult<(Atom, Vec<CalendarEvent>)> {
+    let message = parse_message(raw_message.as_slice())?;
+    Ok((atoms::ok(), calendar_events(&message)))
+}
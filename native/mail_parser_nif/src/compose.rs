@@ -0,0 +1,635 @@
+use rustler::types::MapIterator;
+use rustler::{Atom, Binary, Decoder, Env, Error, NewBinary, NifResult, Term};
+use sha2::{Digest, Sha256};
+
+use crate::atoms;
+use crate::attachment::{Disposition, DEFAULT_CONTENT_TYPE};
+use crate::inline::base64;
+
+const CRLF: &str = "\r\n";
+
+/// Header lines are folded to this length where possible (RFC 5322).
+const MAX_HEADER_LINE_LEN: usize = 78;
+
+/// Maximum length of quoted-printable and base64 encoded lines (RFC 2045).
+const MAX_ENCODED_LINE_LEN: usize = 76;
+
+/// Maximum length of the encoded text of an RFC 2047 encoded-word, which
+/// is at most 75 characters long including its `=?utf-8?q?` and `?=`.
+const MAX_ENCODED_WORD_TEXT_LEN: usize = 63;
+
+/// Maximum length of a line of a 7bit body (RFC 5322).
+const MAX_7BIT_LINE_LEN: usize = 998;
+
+/// Boundaries start with `=_`, which cannot appear in quoted-printable or
+/// base64 encoded bodies, and which 7bit bodies are checked not to contain.
+const BOUNDARY_PREFIX: &str = "=_";
+
+/// A message to compose, decoded from the map passed to `build_message/1`.
+#[derive(Default)]
+struct MessageSpec<'a> {
+    headers: Vec<(String, String)>,
+    text: Option<String>,
+    html: Option<String>,
+    attachments: Vec<AttachmentSpec<'a>>,
+}
+
+/// An attachment to compose, decoded from a map. Keys other than the ones
+/// below are ignored, so that `MailParser.Attachment` structs can be passed
+/// as is.
+struct AttachmentSpec<'a> {
+    name: String,
+    content_type: Option<String>,
+    contents: Binary<'a>,
+    inline: bool,
+    content_id: Option<String>,
+}
+
+impl<'a> Decoder<'a> for MessageSpec<'a> {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut message_spec = MessageSpec::default();
+
+        for (key, value) in MapIterator::new(term).ok_or(Error::BadArg)? {
+            let key: Atom = key.decode()?;
+
+            if key == atoms::headers() {
+                message_spec.headers = match MapIterator::new(value) {
+                    Some(headers) => headers
+                        .map(|(name, value)| Ok((name.decode()?, value.decode()?)))
+                        .collect::<NifResult<_>>()?,
+                    None => value.decode()?,
+                };
+            } else if key == atoms::text() {
+                message_spec.text = value.decode()?;
+            } else if key == atoms::html() {
+                message_spec.html = value.decode()?;
+            } else if key == atoms::attachments() {
+                message_spec.attachments = value.decode()?;
+            } else {
+                return Err(Error::BadArg);
+            }
+        }
+
+        if !message_spec
+            .headers
+            .iter()
+            .all(|(name, value)| is_valid_header(name, value))
+        {
+            return Err(Error::BadArg);
+        }
+
+        Ok(message_spec)
+    }
+}
+
+impl<'a> Decoder<'a> for AttachmentSpec<'a> {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let (mut name, mut content_type, mut contents) = (None, None, None);
+        let (mut inline, mut content_id) = (false, None);
+
+        for (key, value) in MapIterator::new(term).ok_or(Error::BadArg)? {
+            let Ok(key) = key.decode::<Atom>() else {
+                continue;
+            };
+
+            if key == atoms::name() {
+                name = value.decode()?;
+            } else if key == atoms::content_type() {
+                content_type = value.decode()?;
+            } else if key == atoms::content_bytes() {
+                contents = Some(value.decode()?);
+            } else if key == atoms::inline() {
+                inline = value.decode()?;
+            } else if key == atoms::disposition() {
+                inline = value.decode::<Disposition>()? == Disposition::Inline;
+            } else if key == atoms::content_id() {
+                content_id = value.decode()?;
+            }
+        }
+
+        let attachment_spec = AttachmentSpec {
+            name: name.unwrap_or_else(|| "untitled".to_string()),
+            content_type,
+            contents: contents.ok_or(Error::BadArg)?,
+            inline,
+            content_id,
+        };
+
+        // The fields end up in headers, which they must not break.
+        let fields = [
+            Some(&attachment_spec.name),
+            attachment_spec.content_type.as_ref(),
+            attachment_spec.content_id.as_ref(),
+        ];
+
+        let is_invalid_content_type = attachment_spec
+            .content_type
+            .as_deref()
+            .is_some_and(|content_type| !is_content_type(content_type));
+
+        if is_invalid_content_type
+            || fields
+                .iter()
+                .flatten()
+                .any(|field| field.contains(['\r', '\n']))
+        {
+            return Err(Error::BadArg);
+        }
+
+        Ok(attachment_spec)
+    }
+}
+
+/// Returns whether a value is a `type/subtype` content type, both being
+/// tokens (RFC 2045).
+fn is_content_type(value: &str) -> bool {
+    let is_token = |token: &str| {
+        !token.is_empty()
+            && token
+                .bytes()
+                .all(|byte| byte.is_ascii_graphic() && !b"()<>@,;:\\\"/[]?=".contains(&byte))
+    };
+
+    value
+        .split_once('/')
+        .is_some_and(|(type_, subtype)| is_token(type_) && is_token(subtype))
+}
+
+/// Returns whether a header can be written as given. Values must not break
+/// lines, which would let them inject headers, and the headers describing
+/// the MIME structure are always generated.
+fn is_valid_header(name: &str, value: &str) -> bool {
+    let is_valid_name = !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| matches!(byte, b'!'..=b'9' | b';'..=b'~'));
+
+    let is_generated = name.eq_ignore_ascii_case("MIME-Version")
+        || name
+            .get(..8)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("Content-"));
+
+    is_valid_name && !is_generated && !value.contains(['\r', '\n'])
+}
+
+/// A MIME entity of the composed message.
+enum Entity<'s, 'a> {
+    Text(&'static str, &'s str),
+    Attachment(&'s AttachmentSpec<'a>),
+    Multipart(&'static str, Vec<Entity<'s, 'a>>),
+}
+
+impl<'s, 'a> Entity<'s, 'a> {
+    /// Arranges the bodies and attachments of a message. Inline attachments
+    /// are related to the HTML body, which is an alternative to the text
+    /// body.
+    fn new(message_spec: &'s MessageSpec<'a>) -> Self {
+        let is_related =
+            |attachment: &AttachmentSpec| attachment.inline && message_spec.html.is_some();
+        let (related, mixed): (Vec<_>, Vec<_>) = message_spec
+            .attachments
+            .iter()
+            .partition(|attachment| is_related(attachment));
+
+        let html = message_spec.html.as_deref().map(|html| {
+            let html = Entity::Text("html", html);
+
+            match related.is_empty() {
+                true => html,
+                false => Entity::Multipart(
+                    "related",
+                    [html]
+                        .into_iter()
+                        .chain(related.into_iter().map(Entity::Attachment))
+                        .collect(),
+                ),
+            }
+        });
+
+        let text = message_spec
+            .text
+            .as_deref()
+            .map(|text| Entity::Text("plain", text));
+
+        let body = match (text, html) {
+            (Some(text), Some(html)) => Entity::Multipart("alternative", vec![text, html]),
+            (Some(body), None) | (None, Some(body)) => body,
+            (None, None) => Entity::Text("plain", ""),
+        };
+
+        match mixed.is_empty() {
+            true => body,
+            false => Entity::Multipart(
+                "mixed",
+                [body]
+                    .into_iter()
+                    .chain(mixed.into_iter().map(Entity::Attachment))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+/// Writes MIME entities, numbering their boundaries.
+struct Composer {
+    output: String,
+    boundary_seed: String,
+    boundary_count: usize,
+}
+
+impl Composer {
+    fn header(&mut self, name: &str, value: &str) {
+        self.output.push_str(name);
+        self.output.push(':');
+        let mut line_len = name.len() + 1;
+
+        // Fold before the words that would not fit on the current line, as
+        // long as at least one word was written on it.
+        for (index, word) in value.split(' ').enumerate() {
+            if index > 0 && line_len + 1 + word.len() > MAX_HEADER_LINE_LEN {
+                self.output.push_str(CRLF);
+                line_len = 0;
+            }
+
+            self.output.push(' ');
+            self.output.push_str(word);
+            line_len += 1 + word.len();
+        }
+
+        self.output.push_str(CRLF);
+    }
+
+    fn entity(&mut self, entity: &Entity) {
+        match entity {
+            Entity::Text(subtype, text) => {
+                let (encoding, body) = encode_text(text);
+                self.header("Content-Type", &format!("text/{subtype}; charset=utf-8"));
+                self.header("Content-Transfer-Encoding", encoding);
+                self.output.push_str(CRLF);
+                self.output.push_str(&body);
+            }
+            Entity::Attachment(attachment) => {
+                let disposition = match attachment.inline {
+                    true => "inline",
+                    false => "attachment",
+                };
+                let content_type = attachment
+                    .content_type
+                    .as_deref()
+                    .unwrap_or(DEFAULT_CONTENT_TYPE);
+
+                self.header(
+                    "Content-Type",
+                    &format!(
+                        "{content_type}; name={}",
+                        rfc2047_parameter(&attachment.name)
+                    ),
+                );
+                self.header(
+                    "Content-Disposition",
+                    &format!(
+                        "{disposition}; {}",
+                        rfc2231_parameter("filename", &attachment.name)
+                    ),
+                );
+                self.header("Content-Transfer-Encoding", "base64");
+
+                if let Some(content_id) = &attachment.content_id {
+                    let content_id = content_id.trim_start_matches('<').trim_end_matches('>');
+                    self.header("Content-ID", &format!("<{content_id}>"));
+                }
+
+                self.output.push_str(CRLF);
+                let encoded = base64(attachment.contents.as_slice());
+                let lines: Vec<&str> = encoded
+                    .as_bytes()
+                    .chunks(MAX_ENCODED_LINE_LEN)
+                    .map(|line| std::str::from_utf8(line).unwrap_or_default())
+                    .collect();
+                self.output.push_str(&lines.join(CRLF));
+            }
+            Entity::Multipart(subtype, parts) => {
+                let boundary = format!(
+                    "{BOUNDARY_PREFIX}{}_{}",
+                    self.boundary_seed, self.boundary_count
+                );
+                self.boundary_count += 1;
+
+                self.header(
+                    "Content-Type",
+                    &format!("multipart/{subtype}; boundary=\"{boundary}\""),
+                );
+                self.output.push_str(CRLF);
+
+                for part in parts {
+                    self.output.push_str(&format!("--{boundary}{CRLF}"));
+                    self.entity(part);
+                    self.output.push_str(CRLF);
+                }
+
+                self.output.push_str(&format!("--{boundary}--"));
+            }
+        }
+    }
+}
+
+/// Returns the transfer encoding of a text body along with the encoded
+/// body, with its line breaks turned into CRLF.
+fn encode_text(text: &str) -> (&'static str, String) {
+    let lines: Vec<&str> = text
+        .split('\n')
+        .map(|line| line.strip_suffix('\r').unwrap_or(line))
+        .collect();
+
+    let is_7bit = text.is_ascii()
+        && !text.contains(BOUNDARY_PREFIX)
+        && lines.iter().all(|line| {
+            line.len() <= MAX_7BIT_LINE_LEN && !line.contains('\r') && !line.contains('\0')
+        });
+
+    match is_7bit {
+        true => ("7bit", lines.join(CRLF)),
+        false => (
+            "quoted-printable",
+            lines
+                .iter()
+                .map(|line| quoted_printable(line))
+                .collect::<Vec<_>>()
+                .join(CRLF),
+        ),
+    }
+}
+
+/// Encodes a line as quoted-printable (RFC 2045), with soft line breaks
+/// keeping encoded lines within 76 characters.
+fn quoted_printable(line: &str) -> String {
+    let mut encoded = String::with_capacity(line.len());
+    let mut line_len = 0;
+    let bytes = line.as_bytes();
+
+    for (pos, &byte) in bytes.iter().enumerate() {
+        let is_last = pos + 1 == bytes.len();
+
+        // Whitespace ending a line would be stripped in transit.
+        let is_literal =
+            matches!(byte, b'!'..=b'<' | b'>'..=b'~') || (matches!(byte, b' ' | b'\t') && !is_last);
+        let len = if is_literal { 1 } else { 3 };
+
+        // Leave room for the `=` of the soft line break, unless this is the
+        // last character of the line.
+        let max_len = if is_last {
+            MAX_ENCODED_LINE_LEN
+        } else {
+            MAX_ENCODED_LINE_LEN - 1
+        };
+
+        if line_len + len > max_len {
+            encoded.push('=');
+            encoded.push_str(CRLF);
+            line_len = 0;
+        }
+
+        match is_literal {
+            true => encoded.push(byte as char),
+            false => {
+                encoded.push_str(&format!("={byte:02X}"));
+            }
+        }
+
+        line_len += len;
+    }
+
+    encoded
+}
+
+/// Encodes text as RFC 2047 encoded-words using the Q encoding, restricted
+/// to the characters allowed in phrases so that they can be used in any
+/// header.
+fn encoded_words(text: &str) -> String {
+    let mut words = vec![String::new()];
+
+    for ch in text.chars() {
+        let mut encoded_ch = String::new();
+
+        for &byte in ch.encode_utf8(&mut [0; 4]).as_bytes() {
+            match byte {
+                b' ' => encoded_ch.push('_'),
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'!' | b'*' | b'+' | b'-' | b'/' => {
+                    encoded_ch.push(byte as char)
+                }
+                _ => {
+                    encoded_ch.push_str(&format!("={byte:02X}"));
+                }
+            }
+        }
+
+        // Characters are not split across encoded-words.
+        let word = words
+            .last_mut()
+            .filter(|word| word.len() + encoded_ch.len() <= MAX_ENCODED_WORD_TEXT_LEN);
+
+        match word {
+            Some(word) => word.push_str(&encoded_ch),
+            None => words.push(encoded_ch),
+        }
+    }
+
+    words
+        .iter()
+        .map(|word| format!("=?utf-8?q?{word}?="))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Encodes the non-ASCII words of a header value as RFC 2047
+/// encoded-words. Consecutive words are encoded together, and quoted
+/// strings, such as display names, are unquoted first. Words that include
+/// an `@` are left as is, as international addresses cannot be encoded.
+fn encode_header_value(value: &str) -> String {
+    if value.is_ascii() {
+        return value.to_string();
+    }
+
+    let mut encoded = String::with_capacity(value.len());
+    let mut pending = String::new();
+    let mut pending_space = String::new();
+
+    for token in header_tokens(value) {
+        let is_space = token.starts_with([' ', '\t']);
+
+        if is_space {
+            pending_space.push_str(token);
+            continue;
+        }
+
+        if !token.is_ascii() && !token.contains('@') {
+            let word = match token.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+                Some(quoted) => quoted.replace("\\\"", "\"").replace("\\\\", "\\"),
+                None => token.to_string(),
+            };
+
+            if pending.is_empty() {
+                encoded.push_str(&pending_space);
+            } else {
+                pending.push_str(&pending_space);
+            }
+
+            pending.push_str(&word);
+            pending_space.clear();
+            continue;
+        }
+
+        if !pending.is_empty() {
+            encoded.push_str(&encoded_words(&pending));
+            pending.clear();
+        }
+
+        encoded.push_str(&pending_space);
+        encoded.push_str(token);
+        pending_space.clear();
+    }
+
+    if !pending.is_empty() {
+        encoded.push_str(&encoded_words(&pending));
+    }
+
+    encoded.push_str(&pending_space);
+    encoded
+}
+
+/// Splits a header value into whitespace, quoted strings and the words in
+/// between.
+fn header_tokens(value: &str) -> Vec<&str> {
+    let bytes = value.as_bytes();
+    let is_space = |byte: u8| matches!(byte, b' ' | b'\t');
+    let mut tokens = Vec::new();
+    let mut start = 0;
+
+    while start < bytes.len() {
+        let mut end = start + 1;
+
+        match bytes[start] {
+            b'"' => {
+                while end < bytes.len() {
+                    match bytes[end] {
+                        b'\\' => end += 2,
+                        b'"' => break,
+                        _ => end += 1,
+                    }
+                }
+
+                end = (end + 1).min(bytes.len());
+            }
+            byte if is_space(byte) => {
+                while end < bytes.len() && is_space(bytes[end]) {
+                    end += 1;
+                }
+            }
+            _ => {
+                while end < bytes.len() && !is_space(bytes[end]) && bytes[end] != b'"' {
+                    end += 1;
+                }
+            }
+        }
+
+        tokens.push(&value[start..end]);
+        start = end;
+    }
+
+    tokens
+}
+
+/// Returns a quoted parameter value, encoded as RFC 2047 encoded-words if
+/// it is not ASCII, as understood by mail clients that do not support
+/// RFC 2231.
+fn rfc2047_parameter(value: &str) -> String {
+    match value.is_ascii() {
+        true => quote(value),
+        false => format!("\"{}\"", encoded_words(value)),
+    }
+}
+
+/// Returns a parameter, percent-encoded as per RFC 2231 if it is not ASCII
+/// and split into continuations if long.
+fn rfc2231_parameter(name: &str, value: &str) -> String {
+    if value.is_ascii() {
+        return format!("{name}={}", quote(value));
+    }
+
+    let mut sections = vec!["utf-8''".to_string()];
+
+    for &byte in value.as_bytes() {
+        let mut encoded_byte = String::new();
+
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                encoded_byte.push(byte as char)
+            }
+            _ => {
+                encoded_byte.push_str(&format!("%{byte:02X}"));
+            }
+        }
+
+        let section = sections
+            .last_mut()
+            .filter(|section| section.len() + encoded_byte.len() <= MAX_ENCODED_WORD_TEXT_LEN);
+
+        match section {
+            Some(section) => section.push_str(&encoded_byte),
+            None => sections.push(encoded_byte),
+        }
+    }
+
+    match sections.as_slice() {
+        [section] => format!("{name}*={section}"),
+        _ => sections
+            .iter()
+            .enumerate()
+            .map(|(index, section)| format!("{name}*{index}*={section}"))
+            .collect::<Vec<_>>()
+            .join("; "),
+    }
+}
+
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Composes a message, writing the given headers in order, followed by the
+/// generated MIME headers and the body.
+fn compose(message_spec: &MessageSpec) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(message_spec.text.as_deref().unwrap_or_default());
+    hasher.update(message_spec.html.as_deref().unwrap_or_default());
+
+    for attachment in &message_spec.attachments {
+        hasher.update(attachment.contents.as_slice());
+    }
+
+    let mut composer = Composer {
+        output: String::new(),
+        // Derived from the contents so that the output is reproducible.
+        boundary_seed: hasher.finalize()[..8]
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect(),
+        boundary_count: 0,
+    };
+
+    for (name, value) in &message_spec.headers {
+        composer.header(name, &encode_header_value(value));
+    }
+
+    composer.header("MIME-Version", "1.0");
+    composer.entity(&Entity::new(message_spec));
+    composer.output.push_str(CRLF);
+    composer.output
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn build_message<'a>(env: Env<'a>, message_spec: MessageSpec<'a>) -> Binary<'a> {
+    let message = compose(&message_spec);
+    let mut binary = NewBinary::new(env, message.len());
+    binary.as_mut_slice().copy_from_slice(message.as_bytes());
+    binary.into()
+}
@@ -0,0 +1,144 @@
+/// A content line of an iCalendar or vCard object, e.g.
+/// `ATTENDEE;CN="Jane Doe";PARTSTAT=ACCEPTED:mailto:jane@example.com`.
+pub struct ContentLine {
+    /// Name of the property, uppercased and without its group.
+    pub name: String,
+    /// Parameters of the property, with uppercased names and unquoted
+    /// values.
+    pub params: Vec<(String, String)>,
+    /// Raw value of the property, still escaped.
+    pub value: String,
+}
+
+impl ContentLine {
+    /// Returns the value of the parameter `name`, matched
+    /// case-insensitively.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param_name, _)| param_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the value of the property with escaped characters decoded.
+    pub fn text(&self) -> String {
+        unescape(&self.value)
+    }
+
+    /// Returns the `;` separated components of a structured value, such as
+    /// the family and given names of a vCard `N` property, decoded.
+    pub fn components(&self) -> Vec<String> {
+        let mut components = Vec::new();
+        let mut start = 0;
+        let mut escaped = false;
+
+        for (pos, ch) in self.value.char_indices() {
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                ';' => {
+                    components.push(unescape(&self.value[start..pos]));
+                    start = pos + 1;
+                }
+                _ => (),
+            }
+        }
+
+        components.push(unescape(&self.value[start..]));
+        components
+    }
+}
+
+/// Splits an iCalendar or vCard object into its content lines, unfolding
+/// lines continued on the next one. Malformed lines are skipped.
+pub fn content_lines(text: &str) -> Vec<ContentLine> {
+    let mut unfolded_lines: Vec<String> = Vec::new();
+
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), unfolded_lines.last_mut()) {
+            (Some(continuation), Some(unfolded_line)) => unfolded_line.push_str(continuation),
+            _ => unfolded_lines.push(line.to_string()),
+        }
+    }
+
+    unfolded_lines
+        .iter()
+        .filter_map(|line| content_line(line))
+        .collect()
+}
+
+fn content_line(line: &str) -> Option<ContentLine> {
+    let mut quoted = false;
+    let value_start = line.char_indices().find_map(|(pos, ch)| {
+        match ch {
+            '"' => quoted = !quoted,
+            ':' if !quoted => return Some(pos),
+            _ => (),
+        }
+        None
+    })?;
+
+    let mut segments = split_unquoted(&line[..value_start], |ch| ch == ';').into_iter();
+    let name = segments.next()?.trim();
+    // Drop the group of grouped vCard properties, e.g. `item1.EMAIL`.
+    let name = name.rsplit('.').next().unwrap_or(name);
+
+    if name.is_empty() {
+        return None;
+    }
+
+    let params = segments
+        .filter_map(|param| {
+            let (param_name, value) = param.split_once('=')?;
+            let value = value.trim().trim_matches('"');
+            Some((param_name.trim().to_ascii_uppercase(), value.to_string()))
+        })
+        .collect();
+
+    Some(ContentLine {
+        name: name.to_ascii_uppercase(),
+        params,
+        value: line[value_start + 1..].to_string(),
+    })
+}
+
+/// Splits `text` on the characters matching `separator`, except within
+/// double quotes.
+pub fn split_unquoted(text: &str, separator: impl Fn(char) -> bool) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut quoted = false;
+    let mut start = 0;
+
+    for (pos, ch) in text.char_indices() {
+        if ch == '"' {
+            quoted = !quoted;
+        } else if separator(ch) && !quoted {
+            segments.push(&text[start..pos]);
+            start = pos + ch.len_utf8();
+        }
+    }
+
+    segments.push(&text[start..]);
+    segments
+}
+
+/// Decodes the `\n`, `\,`, `\;` and `\\` escapes of a text value.
+pub fn unescape(value: &str) -> String {
+    let mut text = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            text.push(ch);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n' | 'N') => text.push('\n'),
+            Some(escaped) => text.push(escaped),
+            None => text.push('\\'),
+        }
+    }
+
+    text
+}
@@ -0,0 +1,172 @@
+use mail_parser::{DateTime, Message};
+use rustler::{Encoder, Env, NifUnitEnum, ResourceArc, Term};
+
+use crate::header::raw_value;
+use crate::message::MessageResource;
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// Obsolete zone names allowed by RFC 5322, along with their offset in
+/// minutes.
+const ZONES: [(&str, i64); 12] = [
+    ("ut", 0),
+    ("utc", 0),
+    ("gmt", 0),
+    ("z", 0),
+    ("est", -5 * 60),
+    ("edt", -4 * 60),
+    ("cst", -6 * 60),
+    ("cdt", -5 * 60),
+    ("mst", -7 * 60),
+    ("mdt", -6 * 60),
+    ("pst", -8 * 60),
+    ("pdt", -7 * 60),
+];
+
+#[derive(Clone, Copy, NifUnitEnum)]
+pub enum DateFormat {
+    /// Seconds since the Unix epoch.
+    Unix,
+    /// An ISO 8601 string in UTC, e.g. `2022-05-17T08:05:04Z`.
+    Iso8601,
+}
+
+/// A date normalized to UTC, encoded in the requested format.
+pub struct Date {
+    timestamp: i64,
+    format: DateFormat,
+}
+
+impl Encoder for Date {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self.format {
+            DateFormat::Unix => self.timestamp.encode(env),
+            DateFormat::Iso8601 => DateTime::from_timestamp(self.timestamp)
+                .to_rfc3339()
+                .encode(env),
+        }
+    }
+}
+
+/// Parses a date header value, returning its Unix timestamp.
+///
+/// On top of RFC 5322 dates this accepts the deviations commonly found in
+/// the wild: missing day of week, seconds or zone (UTC is assumed), two
+/// and three digit years, obsolete and trailing zone names, dashes between
+/// the date fields, asctime dates and RFC 3339 dates.
+pub fn parse_date(value: &str) -> Option<i64> {
+    let value = strip_comments(value);
+
+    if let Some(date) = DateTime::parse_rfc3339(value.trim()).filter(DateTime::is_valid) {
+        return Some(date.to_timestamp());
+    }
+
+    let (mut day, mut month, mut year) = (None, None, None);
+    let (mut hour, mut minute, mut second) = (0, 0, 0);
+    let mut offset = None;
+
+    let tokens = value
+        .split(|ch: char| ch.is_whitespace() || ch == ',')
+        .flat_map(|token| match token.strip_prefix(['+', '-']) {
+            Some(_) => vec![token],
+            None => token.split('-').collect(),
+        })
+        .filter(|token| !token.is_empty());
+
+    for token in tokens {
+        if token.contains(':') {
+            let mut fields = token.split(':').map(str::parse::<u8>);
+            hour = fields.next()?.ok()?;
+            minute = fields.next()?.ok()?;
+            second = fields.next().transpose().ok()?.unwrap_or(0);
+        } else if let Some(zone) = token.strip_prefix(['+', '-']) {
+            let zone: i64 = zone.parse().ok().filter(|_| zone.len() == 4)?;
+            let minutes = zone / 100 * 60 + zone % 100;
+            offset = Some(if token.starts_with('-') {
+                -minutes
+            } else {
+                minutes
+            });
+        } else if token.bytes().all(|byte| byte.is_ascii_digit()) {
+            let number: u16 = token.parse().ok()?;
+
+            if day.is_none() && token.len() <= 2 {
+                day = Some(number);
+            } else if year.is_none() {
+                year = Some(match token.len() {
+                    1 | 2 if number < 50 => number + 2000,
+                    1..=3 => number + 1900,
+                    _ => number,
+                });
+            }
+        } else {
+            let token = token.to_ascii_lowercase();
+
+            if let Some(index) = MONTHS.iter().position(|name| token.starts_with(name)) {
+                month = Some(index as u8 + 1);
+            } else if let Some((_, minutes)) = ZONES.iter().find(|(name, _)| *name == token) {
+                offset = offset.or(Some(*minutes));
+            }
+        }
+    }
+
+    let offset = offset.unwrap_or(0);
+    let date = DateTime {
+        year: year?,
+        month: month?,
+        day: u8::try_from(day?).ok()?,
+        hour,
+        minute,
+        second,
+        tz_before_gmt: offset < 0,
+        tz_hour: (offset.abs() / 60) as u8,
+        tz_minute: (offset.abs() % 60) as u8,
+    };
+
+    date.is_valid().then(|| date.to_timestamp())
+}
+
+fn strip_comments(value: &str) -> String {
+    let mut depth = 0usize;
+
+    value
+        .chars()
+        .filter(|ch| match ch {
+            '(' => {
+                depth += 1;
+                false
+            }
+            ')' => {
+                depth = depth.saturating_sub(1);
+                false
+            }
+            _ => depth == 0,
+        })
+        .collect()
+}
+
+/// Returns the timestamps of the top level headers named `name`, skipping
+/// the ones that cannot be parsed.
+pub fn dates<'m>(message: &'m Message, name: &'m str) -> impl Iterator<Item = i64> + 'm {
+    message
+        .headers()
+        .iter()
+        .filter(move |header| header.name().eq_ignore_ascii_case(name))
+        .filter_map(|header| parse_date(&String::from_utf8_lossy(raw_value(message, header))))
+}
+
+#[rustler::nif]
+fn get_date(resource: ResourceArc<MessageResource>, format: DateFormat) -> Option<Date> {
+    dates(resource.message(), "Date")
+        .next()
+        .map(|timestamp| Date { timestamp, format })
+}
+
+#[rustler::nif]
+fn get_resent_dates(resource: ResourceArc<MessageResource>, format: DateFormat) -> Vec<Date> {
+    dates(resource.message(), "Resent-Date")
+        .map(|timestamp| Date { timestamp, format })
+        .collect()
+}
@@ -0,0 +1,220 @@
+use std::borrow::Cow;
+
+use mail_parser::decoders::base64::base64_decode;
+use mail_parser::decoders::charsets::map::charset_decoder;
+use mail_parser::decoders::quoted_printable::quoted_printable_decode;
+use mail_parser::{Encoding, Message, MessagePart, MimeHeaders};
+use rustler::{Atom, Decoder, Error, NifResult, Term};
+
+use crate::archive::{gunzip, gunzipped_name, is_tar, ExpandError};
+use crate::atoms;
+use crate::attachment::{self, DEFAULT_CONTENT_TYPE};
+use crate::filename::DeclaredName;
+use crate::header::raw_value;
+use crate::inflate::inflate;
+use crate::sniff;
+
+/// Default for `max_decompressed_size`: 64 MiB.
+pub const DEFAULT_MAX_DECOMPRESSED_SIZE: usize = 64 << 20;
+
+/// Compression of the contents of a part, as declared by the
+/// Content-Encoding header some gateways add, borrowed from HTTP.
+#[derive(Clone, Copy)]
+enum Compression {
+    Gzip,
+    Deflate,
+}
+
+/// Options accepted by `get_text_body/2` and `get_html_body/2`.
+pub struct BodyOptions {
+    /// Whether bodies declaring a gzip or deflate Content-Encoding are
+    /// decompressed.
+    pub decompress: bool,
+    /// Maximum decompressed size of a body, in bytes.
+    pub max_decompressed_size: usize,
+}
+
+impl Default for BodyOptions {
+    fn default() -> Self {
+        BodyOptions {
+            decompress: false,
+            max_decompressed_size: DEFAULT_MAX_DECOMPRESSED_SIZE,
+        }
+    }
+}
+
+impl BodyOptions {
+    /// Sets the option `key` from its keyword list value, failing with
+    /// `BadArg` for unknown options.
+    pub fn set(&mut self, key: Atom, value: Term) -> NifResult<()> {
+        if key == atoms::decompress() {
+            self.decompress = value.decode()?;
+        } else if key == atoms::max_decompressed_size() {
+            self.max_decompressed_size = value.decode()?;
+        } else {
+            return Err(Error::BadArg);
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Decoder<'a> for BodyOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut body_options = BodyOptions::default();
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            body_options.set(key, value)?;
+        }
+
+        Ok(body_options)
+    }
+}
+
+/// An attachment decompressed with `decompress: true`.
+pub struct DecompressedFile {
+    pub name: Option<String>,
+    pub raw_name: Option<String>,
+    pub content_type: Option<String>,
+    pub contents: Vec<u8>,
+}
+
+/// Returns the compression declared by the Content-Encoding header of a part
+/// of `message`, if any.
+fn declared_compression(message: &Message, part: &MessagePart) -> Option<Compression> {
+    let header = part
+        .headers
+        .iter()
+        .find(|header| header.name().eq_ignore_ascii_case("Content-Encoding"))?;
+
+    match String::from_utf8_lossy(raw_value(message, header))
+        .trim()
+        .to_ascii_lowercase()
+        .as_str()
+    {
+        "gzip" | "x-gzip" => Some(Compression::Gzip),
+        "deflate" => Some(Compression::Deflate),
+        _ => None,
+    }
+}
+
+/// Returns the body of a part of `message` decoded from its transfer
+/// encoding only, as the parser also decodes text bodies from their
+/// charset, which mangles compressed ones.
+fn transfer_decoded<'m>(message: &'m Message, part: &MessagePart) -> Option<Cow<'m, [u8]>> {
+    let body = message.raw_message.get(part.offset_body..part.offset_end)?;
+
+    match part.encoding {
+        Encoding::Base64 => base64_decode(body).map(Cow::Owned),
+        Encoding::QuotedPrintable => quoted_printable_decode(body).map(Cow::Owned),
+        Encoding::None => Some(Cow::Borrowed(body)),
+    }
+}
+
+/// Returns whether a deflate stream starts with the zlib header (RFC 1950)
+/// HTTP calls for, which some servers leave out.
+fn is_zlib(bytes: &[u8]) -> bool {
+    matches!(bytes, [cmf, flg, ..]
+        if cmf & 0x0f == 8 && flg & 0x20 == 0 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0)
+}
+
+/// Decompresses `bytes` to at most `max_size` bytes.
+fn decompress(
+    bytes: &[u8],
+    compression: Compression,
+    max_size: usize,
+) -> Result<Vec<u8>, ExpandError> {
+    match compression {
+        Compression::Gzip => Ok(gunzip(bytes, max_size)?.1),
+        Compression::Deflate => {
+            let stream = if is_zlib(bytes) { &bytes[2..] } else { bytes };
+            Ok(inflate(stream, max_size)?.0)
+        }
+    }
+}
+
+/// Returns the text of a body part of `message` declaring a gzip or deflate
+/// Content-Encoding, decompressed and decoded from its declared charset,
+/// with `decompress: true`.
+///
+/// Returns `None` for bodies that are not compressed, or that cannot be
+/// decompressed within `max_decompressed_size`, which are returned as
+/// parsed.
+pub fn decompressed_text(
+    message: &Message,
+    part: &MessagePart,
+    body_options: &BodyOptions,
+) -> Option<String> {
+    if !body_options.decompress {
+        return None;
+    }
+
+    let compression = declared_compression(message, part)?;
+    let body = transfer_decoded(message, part)?;
+    let text = decompress(&body, compression, body_options.max_decompressed_size).ok()?;
+
+    let decoder = part
+        .content_type()
+        .and_then(|content_type| content_type.attribute("charset"))
+        .and_then(|charset| charset_decoder(charset.as_bytes()));
+
+    Some(match decoder {
+        Some(decoder) => decoder(&text),
+        None => String::from_utf8_lossy(&text).into_owned(),
+    })
+}
+
+/// Decompresses an attachment of `message` to at most `max_size` bytes:
+/// either a part declaring a gzip or deflate Content-Encoding, which keeps
+/// its name and content type, or a gzip file other than a tar archive,
+/// such as a gzipped `.eml` file, which is named after the file it
+/// compresses.
+///
+/// Returns `Ok(None)` for attachments that are not compressed.
+pub fn decompress_attachment(
+    message: &Message,
+    part: &MessagePart,
+    declared_name: Option<&DeclaredName>,
+    max_size: usize,
+) -> Result<Option<DecompressedFile>, ExpandError> {
+    if let Some(compression) = declared_compression(message, part) {
+        let body = transfer_decoded(message, part).ok_or(ExpandError::Invalid)?;
+
+        return Ok(Some(DecompressedFile {
+            name: declared_name.map(|declared_name| declared_name.name.clone()),
+            raw_name: declared_name.map(|declared_name| declared_name.raw_name.clone()),
+            content_type: attachment::content_type(part),
+            contents: decompress(&body, compression, max_size)?,
+        }));
+    }
+
+    let compressed = attachment::contents(part);
+
+    if sniff::detect_content_type(compressed) != Some("application/gzip") {
+        return Ok(None);
+    }
+
+    let (name, contents) = gunzip(compressed, max_size)?;
+
+    // Compressed tar archives are left to `expand_archives`.
+    if is_tar(&contents) {
+        return Ok(None);
+    }
+
+    let name = name.unwrap_or_else(|| {
+        gunzipped_name(declared_name.map_or("untitled", |declared_name| &declared_name.name))
+    });
+    let content_type = sniff::detect_content_type(&contents)
+        .or_else(|| {
+            let (_, extension) = name.rsplit_once('.')?;
+            sniff::content_type(extension)
+        })
+        .unwrap_or(DEFAULT_CONTENT_TYPE);
+
+    Ok(Some(DecompressedFile {
+        name: Some(name),
+        raw_name: None,
+        content_type: Some(content_type.to_string()),
+        contents,
+    }))
+}
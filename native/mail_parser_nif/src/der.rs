@@ -0,0 +1,171 @@
+pub const BOOLEAN: u8 = 0x01;
+pub const INTEGER: u8 = 0x02;
+pub const OCTET_STRING: u8 = 0x04;
+pub const OBJECT_IDENTIFIER: u8 = 0x06;
+pub const SEQUENCE: u8 = 0x30;
+pub const SET: u8 = 0x31;
+
+const UTF8_STRING: u8 = 0x0C;
+const PRINTABLE_STRING: u8 = 0x13;
+const TELETEX_STRING: u8 = 0x14;
+const IA5_STRING: u8 = 0x16;
+const BMP_STRING: u8 = 0x1E;
+
+/// Bit set in the tag of constructed values, whose contents are values.
+const CONSTRUCTED: u8 = 0x20;
+
+/// Maximum nesting of the values read recursively: indefinite length
+/// values and constructed strings.
+const MAX_NESTING: usize = 32;
+
+/// Returns the tag of the constructed, context-specific value `[number]`.
+pub const fn context(number: u8) -> u8 {
+    0xA0 | number
+}
+
+/// A value of an ASN.1 structure.
+#[derive(Clone, Copy)]
+pub struct Value<'d> {
+    pub tag: u8,
+    pub contents: &'d [u8],
+}
+
+/// Reads the values of a DER encoded structure in order, or of a BER
+/// encoded one, as produced by S/MIME agents streaming their output.
+///
+/// Iteration stops at the first malformed value.
+pub struct Reader<'d> {
+    bytes: &'d [u8],
+}
+
+impl<'d> Reader<'d> {
+    pub fn new(bytes: &'d [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    /// Reads the next value, returning its contents if it has the given
+    /// tag.
+    pub fn expect(&mut self, tag: u8) -> Option<&'d [u8]> {
+        let value = self.next()?;
+        (value.tag == tag).then_some(value.contents)
+    }
+
+    /// Reads the next value if it has the given tag, as for OPTIONAL
+    /// fields, leaving it to be read otherwise.
+    pub fn optional(&mut self, tag: u8) -> Option<&'d [u8]> {
+        let (value, rest) = read_value(self.bytes, 0)?;
+
+        if value.tag != tag {
+            return None;
+        }
+
+        self.bytes = rest;
+        Some(value.contents)
+    }
+}
+
+impl<'d> Iterator for Reader<'d> {
+    type Item = Value<'d>;
+
+    fn next(&mut self) -> Option<Value<'d>> {
+        let (value, rest) = read_value(self.bytes, 0)?;
+        self.bytes = rest;
+        Some(value)
+    }
+}
+
+/// Returns the bytes of an OCTET STRING, which BER allows to split into a
+/// constructed string of smaller ones.
+pub fn octet_string(value: Value) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    append_octets(value, 0, &mut bytes)?;
+    Some(bytes)
+}
+
+fn append_octets(value: Value, nesting: usize, bytes: &mut Vec<u8>) -> Option<()> {
+    match value.tag {
+        OCTET_STRING => bytes.extend_from_slice(value.contents),
+        tag if tag == OCTET_STRING | CONSTRUCTED && nesting < MAX_NESTING => {
+            for segment in Reader::new(value.contents) {
+                append_octets(segment, nesting + 1, bytes)?;
+            }
+        }
+        _ => return None,
+    }
+
+    Some(())
+}
+
+/// Decodes the string types found in certificate names.
+pub fn string(value: Value) -> Option<String> {
+    match value.tag {
+        UTF8_STRING | PRINTABLE_STRING | TELETEX_STRING | IA5_STRING => {
+            Some(String::from_utf8_lossy(value.contents).into_owned())
+        }
+        BMP_STRING => {
+            let units = value
+                .contents
+                .chunks_exact(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]));
+
+            Some(
+                char::decode_utf16(units)
+                    .map(|ch| ch.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+/// Reads the value `bytes` start with, returning it along with the bytes
+/// following it.
+fn read_value(bytes: &[u8], nesting: usize) -> Option<(Value<'_>, &[u8])> {
+    let (&tag, rest) = bytes.split_first()?;
+
+    // Tag numbers above 30 are not used by the structures read.
+    if tag & 0x1F == 0x1F {
+        return None;
+    }
+
+    let (&first, rest) = rest.split_first()?;
+
+    let (len, rest) = match first {
+        0x80 if tag & CONSTRUCTED != 0 && nesting < MAX_NESTING => {
+            let len = indefinite_len(rest, nesting)?;
+
+            // Skip the two zero bytes ending the contents.
+            return Some((
+                Value {
+                    tag,
+                    contents: &rest[..len],
+                },
+                &rest[len + 2..],
+            ));
+        }
+        0..=0x7F => (usize::from(first), rest),
+        0x81..=0x84 => {
+            let len_bytes = rest.get(..usize::from(first & 0x7F))?;
+            let len = len_bytes
+                .iter()
+                .fold(0, |len, &byte| len << 8 | usize::from(byte));
+            (len, &rest[len_bytes.len()..])
+        }
+        _ => return None,
+    };
+
+    let contents = rest.get(..len)?;
+    Some((Value { tag, contents }, &rest[len..]))
+}
+
+/// Returns the length of the contents of an indefinite length value, which
+/// are values up to two zero bytes.
+fn indefinite_len(bytes: &[u8], nesting: usize) -> Option<usize> {
+    let mut rest = bytes;
+
+    while !rest.starts_with(&[0, 0]) {
+        rest = read_value(rest, nesting + 1)?.1;
+    }
+
+    Some(bytes.len() - rest.len())
+}
@@ -0,0 +1,38 @@
+use std::collections::HashMap;
+
+use md5::Md5;
+use rustler::NifUnitEnum;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, NifUnitEnum)]
+pub enum DigestAlgorithm {
+    Sha256,
+    Sha1,
+    Md5,
+}
+
+/// Lowercase hex encoded digests of an attachment, keyed by algorithm.
+pub type Digests = HashMap<DigestAlgorithm, String>;
+
+pub fn digests(algorithms: &[DigestAlgorithm], bytes: &[u8]) -> Digests {
+    algorithms
+        .iter()
+        .map(|&algorithm| {
+            let digest = match algorithm {
+                DigestAlgorithm::Sha256 => hex_digest::<Sha256>(bytes),
+                DigestAlgorithm::Sha1 => hex_digest::<Sha1>(bytes),
+                DigestAlgorithm::Md5 => hex_digest::<Md5>(bytes),
+            };
+
+            (algorithm, digest)
+        })
+        .collect()
+}
+
+fn hex_digest<D: Digest>(bytes: &[u8]) -> String {
+    D::digest(bytes)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
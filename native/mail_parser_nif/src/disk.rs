@@ -0,0 +1,582 @@
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use mail_parser::{DateTime, Message};
+use rustler::{Atom, Decoder, Error, NifResult, NifStruct, NifUnitEnum, Term};
+use sha2::{Digest, Sha256};
+
+use crate::atoms;
+use crate::attachment::{select_attachments_with_duplicates, AttachmentPart};
+use crate::date::dates;
+use crate::digest::{digests, DigestAlgorithm, Digests};
+use crate::error::{parse_message, ErrorReason};
+use crate::iodata::RawMessage;
+use crate::metrics;
+use crate::options::Options;
+use crate::path_template::{is_relative, Fields, PathTemplate, UNKNOWN};
+
+/// Windows device names, which must not be used as file names regardless
+/// of their extension.
+const RESERVED_NAMES: [&str; 22] = [
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Maximum length of a file name in bytes on common file systems.
+const MAX_NAME_LEN: usize = 255;
+
+/// Characters that are not allowed in file names on at least one common
+/// file system.
+const RESERVED_CHARS: [char; 7] = ['<', '>', ':', '"', '|', '?', '*'];
+
+/// An attachment written to disk.
+#[derive(NifStruct)]
+#[module = "MailParser.ExtractedAttachment"]
+struct ExtractedAttachment {
+    path: String,
+    original_name: String,
+    content_type: Option<String>,
+    size_bytes: usize,
+    sha256: String,
+    digests: Digests,
+    duplicates: usize,
+}
+
+impl ExtractedAttachment {
+    fn new(
+        attachment: &AttachmentPart,
+        path: &Path,
+        sha256: String,
+        duplicates: usize,
+        options: &Options,
+    ) -> Self {
+        // The SHA-256 digest is computed once, for the path template too.
+        let algorithms: Vec<DigestAlgorithm> = options
+            .digests
+            .iter()
+            .copied()
+            .filter(|&algorithm| algorithm != DigestAlgorithm::Sha256)
+            .collect();
+        let mut digests = digests(&algorithms, attachment.contents());
+
+        if options.digests.contains(&DigestAlgorithm::Sha256) {
+            digests.insert(DigestAlgorithm::Sha256, sha256.clone());
+        }
+
+        ExtractedAttachment {
+            path: path.to_string_lossy().into_owned(),
+            original_name: attachment.name().to_string(),
+            content_type: attachment.content_type(),
+            size_bytes: attachment.contents().len(),
+            sha256,
+            digests,
+            duplicates,
+        }
+    }
+}
+
+/// Options accepted by `extract_attachments_to_disk/3`, on top of the
+/// attachment extraction options.
+struct DiskOptions {
+    options: Options,
+    sanitize: Sanitize,
+    on_conflict: OnConflict,
+    /// Write attachments to temporary files and rename them into place.
+    atomic: bool,
+    /// Flush written files, and the directories they are renamed into, to
+    /// disk.
+    fsync: bool,
+    path_template: PathTemplate,
+}
+
+#[derive(Clone, Copy, NifUnitEnum)]
+enum Sanitize {
+    /// Rewrite unsafe attachment names into safe file names.
+    Strict,
+    /// Abort the extraction when an attachment name is not a safe file name.
+    Reject,
+}
+
+#[derive(Clone, Copy, NifUnitEnum)]
+enum OnConflict {
+    /// Replace existing files, including ones written for an earlier
+    /// attachment with the same name.
+    Overwrite,
+    /// Abort the extraction with `{:error, {:file_exists, path}}`.
+    Error,
+    /// Append `-1`, `-2`, ... to the file name until it is unique.
+    Rename,
+}
+
+impl<'a> Decoder<'a> for DiskOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut disk_options = DiskOptions {
+            options: Options::default(),
+            sanitize: Sanitize::Strict,
+            on_conflict: OnConflict::Overwrite,
+            atomic: true,
+            fsync: false,
+            path_template: PathTemplate::default(),
+        };
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::sanitize() {
+                disk_options.sanitize = value.decode()?;
+            } else if key == atoms::on_conflict() {
+                disk_options.on_conflict = value.decode()?;
+            } else if key == atoms::atomic() {
+                disk_options.atomic = value.decode()?;
+            } else if key == atoms::fsync() {
+                disk_options.fsync = value.decode()?;
+            } else if key == atoms::path_template() {
+                disk_options.path_template = value.decode()?;
+            } else {
+                disk_options.options.set(key, value)?;
+            }
+        }
+
+        Ok(disk_options)
+    }
+}
+
+/// Turns an attachment name into a file name that cannot resolve outside
+/// of the directory it is joined to.
+///
+/// Path components are stripped, control and reserved characters removed or
+/// replaced, reserved device names prefixed and overly long names truncated
+/// while keeping their extension.
+fn sanitize(name: &str) -> String {
+    let base_name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+
+    let name: String = base_name
+        .chars()
+        .filter(|ch| !ch.is_control())
+        .map(|ch| {
+            if RESERVED_CHARS.contains(&ch) {
+                '_'
+            } else {
+                ch
+            }
+        })
+        .collect();
+
+    let name = name.trim_matches(|ch: char| ch == '.' || ch.is_whitespace());
+
+    if name.is_empty() {
+        return "untitled".to_string();
+    }
+
+    let stem = name.split('.').next().unwrap_or_default();
+    let name = if RESERVED_NAMES
+        .iter()
+        .any(|reserved| reserved.eq_ignore_ascii_case(stem.trim_end()))
+    {
+        format!("_{name}")
+    } else {
+        name.to_string()
+    };
+
+    truncate(name)
+}
+
+fn truncate(name: String) -> String {
+    if name.len() <= MAX_NAME_LEN {
+        return name;
+    }
+
+    let extension = match name.rfind('.') {
+        Some(pos) if name.len() - pos <= 16 => &name[pos..],
+        _ => "",
+    };
+
+    let mut end = MAX_NAME_LEN - extension.len();
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}{extension}", &name[..end])
+}
+
+/// The placeholders of a path template shared by the attachments of a
+/// message.
+struct MessageFields {
+    message_id: String,
+    date: Option<DateTime>,
+}
+
+impl MessageFields {
+    fn new(message: &Message) -> Self {
+        MessageFields {
+            message_id: message.message_id().map_or_else(
+                || UNKNOWN.to_string(),
+                |message_id| sanitize(&message_id.replace(['/', '\\'], "_")),
+            ),
+            date: dates(message, "Date").next().map(DateTime::from_timestamp),
+        }
+    }
+}
+
+/// Returns the path the attachment at `index`, of hex encoded SHA-256
+/// digest `sha256`, is written to, as laid out by the path template, or
+/// `None` if the name is rejected.
+fn attachment_path(
+    directory: &Path,
+    attachment: &AttachmentPart,
+    index: usize,
+    sha256: &str,
+    message_fields: &MessageFields,
+    disk_options: &DiskOptions,
+) -> Option<PathBuf> {
+    let name = attachment.name();
+    let file_name = sanitize(name);
+
+    if matches!(disk_options.sanitize, Sanitize::Reject) && file_name != name {
+        return None;
+    }
+
+    let path = disk_options.path_template.render(&Fields {
+        name: &file_name,
+        index,
+        message_id: &message_fields.message_id,
+        sha256,
+        date: message_fields.date.as_ref(),
+    });
+
+    // Placeholders never expand to separators, this is a last line of
+    // defense against resolving outside of `directory`.
+    match is_relative(&path) {
+        true => Some(directory.join(path)),
+        false => None,
+    }
+}
+
+/// Makes sure no two attachments, and no attachment and existing file,
+/// share a path unless `on_conflict` is `Overwrite`.
+fn resolve_conflicts<'m>(
+    attachments: Vec<(AttachmentPart<'m>, PathBuf)>,
+    on_conflict: OnConflict,
+) -> Result<Vec<(AttachmentPart<'m>, PathBuf)>, Error> {
+    let mut taken = HashSet::new();
+
+    attachments
+        .into_iter()
+        .map(|(attachment, path)| {
+            let is_taken = |path: &Path| taken.contains(path) || path.exists();
+
+            let path = match on_conflict {
+                OnConflict::Overwrite => path,
+                OnConflict::Error if is_taken(&path) => return Err(file_exists(&path)),
+                OnConflict::Error => path,
+                OnConflict::Rename => (0..)
+                    .map(|n| numbered_path(&path, n))
+                    .find(|path| !is_taken(path))
+                    .unwrap_or(path),
+            };
+
+            taken.insert(path.clone());
+            Ok((attachment, path))
+        })
+        .collect()
+}
+
+/// Returns `path` with `-n` appended to its file stem, or `path` itself
+/// for `n == 0`.
+fn numbered_path(path: &Path, n: usize) -> PathBuf {
+    if n == 0 {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+
+    let file_name = match path.extension() {
+        Some(extension) => format!("{stem}-{n}.{}", extension.to_string_lossy()),
+        None => format!("{stem}-{n}"),
+    };
+
+    path.with_file_name(file_name)
+}
+
+fn file_exists(path: &Path) -> Error {
+    let path = path.to_string_lossy().into_owned();
+    Error::Term(Box::new((atoms::file_exists(), path)))
+}
+
+fn unsafe_filename(attachment: &AttachmentPart) -> Error {
+    let name = attachment.name().to_string();
+    Error::Term(Box::new((atoms::unsafe_filename(), name)))
+}
+
+/// Creates the directories below `directory` that attachments are laid out
+/// in by the path template, returning the ones created. `directory` itself
+/// must exist.
+fn create_directories(
+    directory: &Path,
+    attachments: &[(AttachmentPart, PathBuf)],
+) -> Result<Vec<PathBuf>, ErrorReason> {
+    let mut created_directories = Vec::new();
+
+    for (_, path) in attachments {
+        let mut ancestors: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .take_while(|ancestor| *ancestor != directory)
+            .collect();
+        ancestors.reverse();
+
+        for ancestor in ancestors {
+            match fs::create_dir(ancestor) {
+                Ok(()) => created_directories.push(ancestor.to_path_buf()),
+                Err(error) if error.kind() != io::ErrorKind::AlreadyExists => {
+                    remove_directories(&created_directories);
+                    return Err(ErrorReason::io(&error, ancestor));
+                }
+                Err(_) => {}
+            }
+        }
+    }
+
+    Ok(created_directories)
+}
+
+/// Writes every attachment to its path.
+///
+/// With `atomic`, attachments are written to temporary files in `directory`
+/// and only renamed into place once all of them were written, so that no
+/// truncated file is ever visible at an attachment path. Otherwise each file
+/// is written in place and, when a write fails, the files created so far are
+/// removed, files that existed before being left overwritten. Either way,
+/// the directories created for the attachments are removed on failure.
+fn write_to_disk(
+    directory: &Path,
+    attachments: &[(AttachmentPart, PathBuf)],
+    disk_options: &DiskOptions,
+) -> Result<(), Error> {
+    let created_directories = create_directories(directory, attachments)?;
+
+    let result = match disk_options.atomic {
+        true => write_atomically(directory, attachments, disk_options.fsync),
+        false => write_in_place(attachments, disk_options.fsync),
+    };
+
+    if result.is_err() {
+        remove_directories(&created_directories);
+    }
+
+    result.map_err(Error::from)
+}
+
+fn write_in_place(
+    attachments: &[(AttachmentPart, PathBuf)],
+    fsync: bool,
+) -> Result<(), ErrorReason> {
+    let mut created_paths = Vec::with_capacity(attachments.len());
+
+    for (attachment, path) in attachments {
+        if !path.exists() {
+            created_paths.push(path);
+        }
+
+        if let Err(error) = write_file(path, attachment.contents(), false, fsync) {
+            remove_files(created_paths);
+            return Err(ErrorReason::io(&error, path));
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes every attachment to a temporary file, then renames them into
+/// place. Files found at the attachment paths are moved aside while
+/// renaming, so that they are restored if a rename fails, and only removed
+/// once every attachment is in place.
+fn write_atomically(
+    directory: &Path,
+    attachments: &[(AttachmentPart, PathBuf)],
+    fsync: bool,
+) -> Result<(), ErrorReason> {
+    let mut temp_paths = Vec::with_capacity(attachments.len());
+
+    for (attachment, _) in attachments {
+        let temp_path = temp_path(directory);
+        let result = write_file(&temp_path, attachment.contents(), true, fsync)
+            .map_err(|error| ErrorReason::io(&error, &temp_path));
+        temp_paths.push(temp_path);
+
+        if let Err(reason) = result {
+            remove_files(&temp_paths);
+            return Err(reason);
+        }
+    }
+
+    let mut renamed: Vec<(&Path, Option<PathBuf>)> = Vec::with_capacity(attachments.len());
+
+    for (temp_path, (_, path)) in temp_paths.iter().zip(attachments) {
+        match replace_file(directory, temp_path, path) {
+            Ok(backup_path) => renamed.push((path, backup_path)),
+            Err(error) => {
+                // Undone in reverse, so that a file replaced twice ends up
+                // as it was before the call.
+                for (path, backup_path) in renamed.iter().rev() {
+                    let _ = fs::remove_file(path);
+
+                    if let Some(backup_path) = backup_path {
+                        let _ = fs::rename(backup_path, path);
+                    }
+                }
+
+                remove_files(&temp_paths);
+                return Err(ErrorReason::io(&error, path));
+            }
+        }
+    }
+
+    remove_files(
+        renamed
+            .iter()
+            .filter_map(|(_, backup_path)| backup_path.as_ref()),
+    );
+
+    if fsync {
+        let directories: HashSet<&Path> = attachments
+            .iter()
+            .filter_map(|(_, path)| path.parent())
+            .collect();
+
+        for directory in directories {
+            sync_directory(directory).map_err(|error| ErrorReason::io(&error, directory))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Renames `temp_path` to `path`, moving the file found at `path`, if any,
+/// to a temporary file of `directory` first and returning its path.
+fn replace_file(directory: &Path, temp_path: &Path, path: &Path) -> io::Result<Option<PathBuf>> {
+    let backup_path = match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_file() => {
+            let backup_path = self::temp_path(directory);
+            fs::rename(path, &backup_path)?;
+            Some(backup_path)
+        }
+        _ => None,
+    };
+
+    if let Err(error) = fs::rename(temp_path, path) {
+        if let Some(backup_path) = &backup_path {
+            let _ = fs::rename(backup_path, path);
+        }
+
+        return Err(error);
+    }
+
+    Ok(backup_path)
+}
+
+fn write_file(path: &Path, contents: &[u8], create_new: bool, fsync: bool) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .create_new(create_new)
+        .open(path)?;
+
+    file.write_all(contents)?;
+
+    if fsync {
+        file.sync_all()?;
+    }
+
+    metrics::record_write(contents.len());
+    Ok(())
+}
+
+/// Returns a path for a temporary file in `directory` that is unique within
+/// this process, hidden from directory listings on Unix.
+fn temp_path(directory: &Path) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    directory.join(format!(".mail_parser-{}-{count}.tmp", process::id()))
+}
+
+fn remove_files<P: AsRef<Path>>(paths: impl IntoIterator<Item = P>) {
+    for path in paths {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Removes directories created in order, innermost first, leaving the ones
+/// that are not empty.
+fn remove_directories(directories: &[PathBuf]) {
+    for directory in directories.iter().rev() {
+        let _ = fs::remove_dir(directory);
+    }
+}
+
+/// Makes the renames into `directory` durable.
+#[cfg(unix)]
+fn sync_directory(directory: &Path) -> io::Result<()> {
+    File::open(directory)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_directory: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn extract_attachments_to_disk<'a>(
+    raw_message: RawMessage<'a>,
+    directory: String,
+    disk_options: DiskOptions,
+) -> NifResult<(Atom, Vec<ExtractedAttachment>)> {
+    let message = parse_message(raw_message.as_slice())?;
+    let directory = Path::new(&directory);
+
+    let fields = MessageFields::new(&message);
+
+    let (attachments, duplicates) =
+        select_attachments_with_duplicates(&message, &disk_options.options)?;
+    let sha256_digests: Vec<String> = attachments
+        .iter()
+        .map(|attachment| format!("{:x}", Sha256::digest(attachment.contents())))
+        .collect();
+
+    let attachments = attachments
+        .into_iter()
+        .zip(&sha256_digests)
+        .enumerate()
+        .map(|(index, (attachment, sha256))| {
+            match attachment_path(
+                directory,
+                &attachment,
+                index,
+                sha256,
+                &fields,
+                &disk_options,
+            ) {
+                Some(path) => Ok((attachment, path)),
+                None => Err(unsafe_filename(&attachment)),
+            }
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    let attachments = resolve_conflicts(attachments, disk_options.on_conflict)?;
+    write_to_disk(directory, &attachments, &disk_options)?;
+
+    let extracted_attachments = attachments
+        .iter()
+        .zip(sha256_digests)
+        .zip(duplicates)
+        .map(|(((attachment, path), sha256), duplicates)| {
+            ExtractedAttachment::new(attachment, path, sha256, duplicates, &disk_options.options)
+        })
+        .collect();
+
+    Ok((atoms::ok(), extracted_attachments))
+}
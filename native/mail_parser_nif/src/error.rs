@@ -0,0 +1,119 @@
+use std::io;
+use std::path::Path;
+use std::time::Instant;
+
+use mail_parser::Message;
+use rustler::{Atom, Encoder, Env, Error, Term};
+
+use crate::atoms;
+use crate::metrics;
+
+/// Reason of an error, returned as `{:error, reason}`.
+#[derive(Debug)]
+pub enum ErrorReason {
+    /// The raw message is empty or blank.
+    EmptyInput,
+    /// No header is found in the raw message.
+    NotAMessage,
+    /// The message is neither a delivery status notification nor a message
+    /// disposition notification.
+    NotAReport,
+    /// No part is found at the requested path.
+    PartNotFound,
+    /// The call decoded more than `max_memory_bytes`.
+    BudgetExceeded,
+    /// A file could not be read or written, with the POSIX error code, e.g.
+    /// `enospc`, and the path of the file.
+    Io(Atom, String),
+}
+
+impl ErrorReason {
+    /// Returns the reason of a failure to parse a raw message.
+    pub fn parse(raw_message: &[u8]) -> Self {
+        match raw_message.iter().all(u8::is_ascii_whitespace) {
+            true => ErrorReason::EmptyInput,
+            false => ErrorReason::NotAMessage,
+        }
+    }
+
+    pub fn io(error: &io::Error, path: &Path) -> Self {
+        let code = error.raw_os_error().and_then(errno_code);
+
+        ErrorReason::Io(
+            code.unwrap_or_else(|| kind_code(error.kind())),
+            path.to_string_lossy().into_owned(),
+        )
+    }
+}
+
+impl Encoder for ErrorReason {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            ErrorReason::EmptyInput => atoms::empty_input().encode(env),
+            ErrorReason::NotAMessage => atoms::not_a_message().encode(env),
+            ErrorReason::NotAReport => atoms::not_a_report().encode(env),
+            ErrorReason::PartNotFound => atoms::part_not_found().encode(env),
+            ErrorReason::BudgetExceeded => atoms::budget_exceeded().encode(env),
+            ErrorReason::Io(code, path) => (atoms::io(), *code, path).encode(env),
+        }
+    }
+}
+
+impl From<ErrorReason> for Error {
+    fn from(reason: ErrorReason) -> Self {
+        Error::Term(Box::new(reason))
+    }
+}
+
+/// Parses a raw message, failing with the reason no message was found.
+pub fn parse_message(raw_message: &[u8]) -> Result<Message<'_>, ErrorReason> {
+    let start = Instant::now();
+    let message = Message::parse(raw_message);
+    metrics::record_parse(raw_message.len(), start.elapsed());
+
+    message.ok_or_else(|| ErrorReason::parse(raw_message))
+}
+
+/// Returns the POSIX error code of an errno value, for the values shared by
+/// Linux, macOS and the BSDs.
+#[cfg(unix)]
+fn errno_code(errno: i32) -> Option<Atom> {
+    Some(match errno {
+        1 => atoms::eperm(),
+        2 => atoms::enoent(),
+        4 => atoms::eintr(),
+        5 => atoms::eio(),
+        12 => atoms::enomem(),
+        13 => atoms::eacces(),
+        16 => atoms::ebusy(),
+        17 => atoms::eexist(),
+        18 => atoms::exdev(),
+        20 => atoms::enotdir(),
+        21 => atoms::eisdir(),
+        22 => atoms::einval(),
+        23 => atoms::enfile(),
+        24 => atoms::emfile(),
+        27 => atoms::efbig(),
+        28 => atoms::enospc(),
+        30 => atoms::erofs(),
+        32 => atoms::epipe(),
+        _ => return None,
+    })
+}
+
+#[cfg(not(unix))]
+fn errno_code(_errno: i32) -> Option<Atom> {
+    None
+}
+
+fn kind_code(kind: io::ErrorKind) -> Atom {
+    match kind {
+        io::ErrorKind::NotFound => atoms::enoent(),
+        io::ErrorKind::PermissionDenied => atoms::eacces(),
+        io::ErrorKind::AlreadyExists => atoms::eexist(),
+        io::ErrorKind::InvalidInput => atoms::einval(),
+        io::ErrorKind::Interrupted => atoms::eintr(),
+        io::ErrorKind::OutOfMemory => atoms::enomem(),
+        _ => atoms::eio(),
+    }
+}
@@ -0,0 +1,52 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+use rustler::{Atom, Binary, Env, NewBinary, NifResult, Term};
+
+use crate::atoms;
+use crate::attachment::{get_attachments, Attachment, Source};
+use crate::error::{parse_message, ErrorReason};
+use crate::message::{encode_parsed, parse_buffer, ParseOptions};
+use crate::options::Options;
+
+/// Reads the file at `path` straight into a binary, without going through
+/// an intermediate buffer.
+fn read_binary<'a>(env: Env<'a>, path: &str) -> Result<Binary<'a>, ErrorReason> {
+    let io_error = |error| ErrorReason::io(&error, Path::new(path));
+
+    let mut file = File::open(path).map_err(io_error)?;
+    let len = file.metadata().map_err(io_error)?.len();
+    let len =
+        usize::try_from(len).map_err(|_| ErrorReason::Io(atoms::efbig(), path.to_string()))?;
+
+    let mut binary = NewBinary::new(env, len);
+    file.read_exact(binary.as_mut_slice()).map_err(io_error)?;
+
+    Ok(binary.into())
+}
+
+/// Reads and parses the file at `path`, the parsed message borrowing its
+/// bodies from what was read.
+#[rustler::nif(schedule = "DirtyIo")]
+fn parse_file<'a>(env: Env<'a>, path: String, parse_options: ParseOptions) -> NifResult<Term<'a>> {
+    let raw_message = fs::read(&path).map_err(|error| ErrorReason::io(&error, Path::new(&path)))?;
+    let parsed = parse_buffer(raw_message, &parse_options)?;
+
+    Ok(encode_parsed(env, parsed))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn extract_attachments_from_file<'a>(
+    env: Env<'a>,
+    path: String,
+    options: Options,
+) -> NifResult<(Atom, Vec<Attachment<'a>>)> {
+    let raw_message = read_binary(env, &path)?;
+    let message = parse_message(raw_message.as_slice())?;
+
+    let source = Source::Binary(raw_message);
+    let attachments = get_attachments(env, &source, &message, &options)?;
+
+    Ok((atoms::ok(), attachments))
+}
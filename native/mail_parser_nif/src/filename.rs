@@ -0,0 +1,306 @@
+use mail_parser::decoders::base64::base64_decode;
+use mail_parser::decoders::charsets::map::charset_decoder;
+use mail_parser::decoders::quoted_printable::quoted_printable_decode_char;
+use mail_parser::{HeaderName, MessagePart, MimeHeaders, RfcHeader};
+
+/// The name of a part, as declared by the `filename` parameter of its
+/// Content-Disposition header or else by the `name` parameter of its
+/// Content-Type header.
+pub struct DeclaredName {
+    /// The name decoded to UTF-8.
+    pub name: String,
+    /// The name as found in the header, with its RFC 2231 continuations
+    /// joined in order.
+    pub raw_name: String,
+}
+
+/// Returns the declared name of a part of the message parsed from
+/// `raw_message`, if any.
+///
+/// Names are decoded here rather than by the parser, which mishandles the
+/// whitespace between adjacent RFC 2047 words and keeps whichever of the
+/// `filename` and `filename*` parameters comes last.
+pub fn declared_name(raw_message: &[u8], part: &MessagePart) -> Option<DeclaredName> {
+    let declared_names = [
+        (RfcHeader::ContentDisposition, "filename"),
+        (RfcHeader::ContentType, "name"),
+    ];
+
+    let declared_name = declared_names.iter().find_map(|(header_name, parameter)| {
+        let header_name = HeaderName::Rfc(*header_name);
+        let header = part
+            .headers
+            .iter()
+            .find(|header| header.name == header_name)?;
+        let value = raw_message.get(header.offset_start..header.offset_end)?;
+
+        parameter_value(&parameters(value), parameter)
+    });
+
+    // Names the parser found in a header too damaged to be parsed again
+    // are kept as is.
+    declared_name.or_else(|| {
+        part.attachment_name().map(|name| DeclaredName {
+            name: name.to_string(),
+            raw_name: name.to_string(),
+        })
+    })
+}
+
+/// Returns the parameters of a raw header value, as lowercased attribute
+/// names and unquoted values, with the header unfolded.
+fn parameters(value: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let value: Vec<u8> = value
+        .iter()
+        .copied()
+        .filter(|&byte| byte != b'\r' && byte != b'\n')
+        .collect();
+
+    let mut parameters = Vec::new();
+    let mut segments = split_unquoted(&value).into_iter();
+    // The first segment is the content type or disposition itself.
+    segments.next();
+
+    for segment in segments {
+        let Some(pos) = segment.iter().position(|&byte| byte == b'=') else {
+            continue;
+        };
+
+        let attribute = String::from_utf8_lossy(segment[..pos].trim_ascii()).to_ascii_lowercase();
+        parameters.push((attribute, unquote(segment[pos + 1..].trim_ascii())));
+    }
+
+    parameters
+}
+
+/// Splits a header value at the semicolons outside of quoted strings.
+fn split_unquoted(value: &[u8]) -> Vec<&[u8]> {
+    let mut segments = Vec::new();
+    let (mut start, mut quoted, mut escaped) = (0, false, false);
+
+    for (pos, &byte) in value.iter().enumerate() {
+        match byte {
+            _ if escaped => escaped = false,
+            b'\\' if quoted => escaped = true,
+            b'"' => quoted = !quoted,
+            b';' if !quoted => {
+                segments.push(&value[start..pos]);
+                start = pos + 1;
+            }
+            _ => {}
+        }
+    }
+
+    segments.push(&value[start..]);
+    segments
+}
+
+fn unquote(value: &[u8]) -> Vec<u8> {
+    let Some(quoted) = value.strip_prefix(b"\"") else {
+        return value.to_vec();
+    };
+
+    let mut unquoted = Vec::with_capacity(quoted.len());
+    let mut bytes = quoted.iter();
+
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'\\' => unquoted.extend(bytes.next()),
+            b'"' => break,
+            _ => unquoted.push(byte),
+        }
+    }
+
+    unquoted
+}
+
+/// Returns the value of the parameter `name`, preferring its RFC 2231
+/// forms, either `name*` or the `name*0`, `name*1`, ... continuations, over
+/// the plain one as RFC 6266 recommends.
+fn parameter_value(parameters: &[(String, Vec<u8>)], name: &str) -> Option<DeclaredName> {
+    let mut sections: Vec<(usize, bool, &[u8])> = parameters
+        .iter()
+        .filter_map(|(attribute, value)| {
+            let section = attribute.strip_prefix(name)?.strip_prefix('*')?;
+            let (section, extended) = match section.strip_suffix('*') {
+                Some(section) => (section, true),
+                None => (section, false),
+            };
+
+            Some((section.parse().ok()?, extended, value.as_slice()))
+        })
+        .collect();
+
+    if !sections.is_empty() {
+        sections.sort_by_key(|&(section, _, _)| section);
+        sections.dedup_by_key(|&mut (section, _, _)| section);
+
+        let raw_name: Vec<u8> = sections
+            .iter()
+            .flat_map(|(_, _, value)| *value)
+            .copied()
+            .collect();
+        let mut charset = None;
+        let mut bytes = Vec::new();
+
+        for (section, extended, value) in sections {
+            match (section, extended) {
+                (0, true) => {
+                    let (section_charset, value) = split_charset(value);
+                    charset = Some(section_charset);
+                    bytes.extend(percent_decode(value));
+                }
+                (_, true) => bytes.extend(percent_decode(value)),
+                (_, false) => bytes.extend_from_slice(value),
+            }
+        }
+
+        return Some(DeclaredName {
+            name: decode_charset(charset.unwrap_or_default(), &bytes),
+            raw_name: String::from_utf8_lossy(&raw_name).into_owned(),
+        });
+    }
+
+    let extended_name = format!("{name}*");
+
+    if let Some((_, value)) = parameters
+        .iter()
+        .find(|(attribute, _)| *attribute == extended_name)
+    {
+        let (charset, encoded) = split_charset(value);
+
+        return Some(DeclaredName {
+            name: decode_charset(charset, &percent_decode(encoded)),
+            raw_name: String::from_utf8_lossy(value).into_owned(),
+        });
+    }
+
+    let (_, value) = parameters.iter().find(|(attribute, _)| attribute == name)?;
+    let raw_name = String::from_utf8_lossy(value).into_owned();
+
+    Some(DeclaredName {
+        name: decode_encoded_words(&raw_name),
+        raw_name,
+    })
+}
+
+/// Splits an RFC 2231 extended value into its charset and its encoded
+/// text, skipping the language in between.
+fn split_charset(value: &[u8]) -> (&[u8], &[u8]) {
+    let mut fields = value.splitn(3, |&byte| byte == b'\'');
+
+    match (fields.next(), fields.next(), fields.next()) {
+        (Some(charset), Some(_), Some(encoded)) => (charset, encoded),
+        _ => (b"", value),
+    }
+}
+
+fn percent_decode(value: &[u8]) -> Vec<u8> {
+    hex_decode(value, b'%')
+}
+
+/// Decodes the bytes of a value escaped as `escape` followed by two hex
+/// digits, keeping malformed escapes as is.
+fn hex_decode(value: &[u8], escape: u8) -> Vec<u8> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut pos = 0;
+
+    while pos < value.len() {
+        let escaped = match value.get(pos..pos + 3) {
+            Some(&[byte, hex1, hex2]) if byte == escape => quoted_printable_decode_char(hex1, hex2),
+            _ => None,
+        };
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                pos += 3;
+            }
+            None => {
+                decoded.push(value[pos]);
+                pos += 1;
+            }
+        }
+    }
+
+    decoded
+}
+
+/// Decodes text in `charset` to UTF-8, assuming UTF-8 for unknown charsets.
+fn decode_charset(charset: &[u8], bytes: &[u8]) -> String {
+    match charset_decoder(charset) {
+        Some(decoder) => decoder(bytes),
+        None => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Decodes the RFC 2047 encoded words of a text, ignoring the whitespace
+/// between adjacent words. Adjacent words in the same charset are decoded
+/// together, as some mailers split multibyte characters across them.
+fn decode_encoded_words(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut pending: Option<(&str, Vec<u8>)> = None;
+    let mut rest = text;
+
+    while let Some(ch) = rest.chars().next() {
+        let Some((charset, bytes, len)) = encoded_word(rest) else {
+            if let Some((charset, bytes)) = pending.take() {
+                decoded.push_str(&decode_charset(charset.as_bytes(), &bytes));
+            }
+
+            decoded.push(ch);
+            rest = &rest[ch.len_utf8()..];
+            continue;
+        };
+
+        match &mut pending {
+            Some((pending_charset, pending_bytes))
+                if pending_charset.eq_ignore_ascii_case(charset) =>
+            {
+                pending_bytes.extend(bytes);
+            }
+            _ => {
+                if let Some((charset, bytes)) = pending.replace((charset, bytes)) {
+                    decoded.push_str(&decode_charset(charset.as_bytes(), &bytes));
+                }
+            }
+        }
+
+        rest = &rest[len..];
+
+        let next = rest.trim_start();
+        if encoded_word(next).is_some() {
+            rest = next;
+        }
+    }
+
+    if let Some((charset, bytes)) = pending {
+        decoded.push_str(&decode_charset(charset.as_bytes(), &bytes));
+    }
+
+    decoded
+}
+
+/// Decodes the `=?charset?encoding?text?=` encoded word a text starts with,
+/// returning its charset, its decoded bytes and its length.
+fn encoded_word(text: &str) -> Option<(&str, Vec<u8>, usize)> {
+    let word = text.strip_prefix("=?")?;
+    let (charset, word) = word.split_once('?')?;
+    let (encoding, word) = word.split_once('?')?;
+    let (encoded, rest) = word.split_once("?=")?;
+
+    if charset.is_empty() || encoded.contains(char::is_whitespace) {
+        return None;
+    }
+
+    // RFC 2231 allows a language after the charset, e.g. `utf-8*en`.
+    let charset = charset.split('*').next().unwrap_or_default();
+
+    let bytes = match encoding {
+        "B" | "b" => base64_decode(encoded.as_bytes())?,
+        "Q" | "q" => hex_decode(encoded.replace('_', " ").as_bytes(), b'='),
+        _ => return None,
+    };
+
+    Some((charset, bytes, text.len() - rest.len()))
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use mail_parser::parsers::MessageStream;
+use mail_parser::{HeaderValue, Message};
+use rustler::{Atom, NifResult, NifStruct, ResourceArc};
+
+use crate::atoms;
+use crate::error::ErrorReason;
+use crate::iodata::RawMessage;
+use crate::message::MessageResource;
+
+#[derive(NifStruct)]
+#[module = "MailParser.Header"]
+pub struct Header {
+    pub name: String,
+    pub value: String,
+    pub raw_value: String,
+}
+
+impl Header {
+    fn new(raw_message: &[u8], header: &mail_parser::Header) -> Self {
+        let raw_value = raw_header_value(raw_message, header);
+
+        // Every header is decoded as unstructured text, so that the value of
+        // e.g. an address header is the unfolded, RFC 2047 decoded header
+        // rather than its parsed representation.
+        let value = match MessageStream::new(raw_value).parse_unstructured() {
+            HeaderValue::Text(text) => text.into_owned(),
+            HeaderValue::TextList(texts) => texts.join(" "),
+            _ => String::new(),
+        };
+
+        Header {
+            name: header.name().to_string(),
+            value,
+            raw_value: String::from_utf8_lossy(raw_value).trim().to_string(),
+        }
+    }
+}
+
+/// Returns the value of a header as it appears in the message, including
+/// the folding whitespace and the line break ending it.
+pub fn raw_value<'m>(message: &'m Message, header: &mail_parser::Header) -> &'m [u8] {
+    raw_header_value(&message.raw_message, header)
+}
+
+fn raw_header_value<'r>(raw_message: &'r [u8], header: &mail_parser::Header) -> &'r [u8] {
+    raw_message
+        .get(header.offset_start..header.offset_end)
+        .unwrap_or_default()
+}
+
+/// Returns the values of the top level headers named `name` of a message,
+/// in order, with their folding whitespace collapsed.
+pub fn unfolded_values(message: &Message, name: &str) -> Vec<String> {
+    message
+        .headers()
+        .iter()
+        .filter(|header| header.name().eq_ignore_ascii_case(name))
+        .map(|header| {
+            String::from_utf8_lossy(raw_value(message, header))
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .collect()
+}
+
+/// Returns the headers named `name` of a part of `message`, or all of them
+/// if no name is given, in the order they appear in.
+pub fn headers(
+    message: &Message,
+    part_headers: &[mail_parser::Header],
+    name: Option<&str>,
+) -> Vec<Header> {
+    part_headers
+        .iter()
+        .filter(|header| match name {
+            Some(name) => header.name().eq_ignore_ascii_case(name),
+            None => true,
+        })
+        .map(|header| Header::new(&message.raw_message, header))
+        .collect()
+}
+
+#[rustler::nif]
+fn get_header(resource: ResourceArc<MessageResource>, name: &str) -> Vec<Header> {
+    headers(resource.message(), resource.message().headers(), Some(name))
+}
+
+#[rustler::nif]
+fn get_headers(resource: ResourceArc<MessageResource>) -> Vec<Header> {
+    headers(resource.message(), resource.message().headers(), None)
+}
+
+/// Returns the decoded values of headers keyed by lowercase name, in the
+/// order they appear in, given the raw message their offsets are relative
+/// to.
+pub fn header_map(
+    raw_message: &[u8],
+    headers: &[mail_parser::Header],
+) -> HashMap<String, Vec<String>> {
+    let mut header_map: HashMap<String, Vec<String>> = HashMap::new();
+
+    for header in headers {
+        let header = Header::new(raw_message, header);
+        header_map
+            .entry(header.name.to_lowercase())
+            .or_default()
+            .push(header.value);
+    }
+
+    header_map
+}
+
+/// Parses the header block of a raw message only, returning the decoded
+/// values of its headers keyed by lowercase name, in the order they appear
+/// in. The body is not looked at.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_headers(raw_message: RawMessage) -> NifResult<(Atom, HashMap<String, Vec<String>>)> {
+    let raw_message = raw_message.as_slice();
+    let mut parsed_headers = Vec::new();
+    MessageStream::new(raw_message).parse_headers(&mut parsed_headers);
+
+    if parsed_headers.is_empty() {
+        return Err(ErrorReason::parse(raw_message).into());
+    }
+
+    Ok((atoms::ok(), header_map(raw_message, &parsed_headers)))
+}
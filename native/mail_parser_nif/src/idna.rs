@@ -0,0 +1,174 @@
+/// Prefix of the labels of internationalized domain names that are
+/// Punycode encoded (RFC 3492, RFC 5891).
+const ACE_PREFIX: &str = "xn--";
+
+/// Parameters of the Punycode bootstring algorithm (RFC 3492).
+const BASE: u32 = 36;
+const T_MIN: u32 = 1;
+const T_MAX: u32 = 26;
+const SKEW: u32 = 38;
+const DAMP: u32 = 700;
+const INITIAL_BIAS: u32 = 72;
+const INITIAL_N: u32 = 0x80;
+
+/// Returns the ASCII form of a domain, with its non-ASCII labels lowercased
+/// and Punycode encoded, or `None` if a label cannot be encoded.
+pub fn to_ascii(domain: &str) -> Option<String> {
+    let labels: Option<Vec<String>> = domain
+        .split('.')
+        .map(|label| match label.is_ascii() {
+            true => Some(label.to_string()),
+            false => encode(&label.to_lowercase()).map(|encoded| format!("{ACE_PREFIX}{encoded}")),
+        })
+        .collect();
+
+    Some(labels?.join("."))
+}
+
+/// Returns the Unicode form of a domain, with its `xn--` labels decoded.
+/// Labels that are not valid Punycode are kept as is.
+pub fn to_unicode(domain: &str) -> String {
+    domain
+        .split('.')
+        .map(|label| {
+            let decoded = label
+                .get(..ACE_PREFIX.len())
+                .filter(|prefix| prefix.eq_ignore_ascii_case(ACE_PREFIX))
+                .and_then(|_| decode(&label[ACE_PREFIX.len()..]));
+
+            decoded.unwrap_or_else(|| label.to_string())
+        })
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn adapt(delta: u32, num_points: u32, first_time: bool) -> u32 {
+    let mut delta = if first_time { delta / DAMP } else { delta / 2 };
+    delta += delta / num_points;
+
+    let mut k = 0;
+    while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+        delta /= BASE - T_MIN;
+        k += BASE;
+    }
+
+    k + (BASE - T_MIN + 1) * delta / (delta + SKEW)
+}
+
+fn threshold(k: u32, bias: u32) -> u32 {
+    k.saturating_sub(bias).clamp(T_MIN, T_MAX)
+}
+
+fn encode_digit(digit: u32) -> char {
+    match digit {
+        0..=25 => char::from(b'a' + digit as u8),
+        _ => char::from(b'0' + (digit - 26) as u8),
+    }
+}
+
+fn decode_digit(ch: char) -> Option<u32> {
+    match ch {
+        'a'..='z' => Some(ch as u32 - 'a' as u32),
+        'A'..='Z' => Some(ch as u32 - 'A' as u32),
+        '0'..='9' => Some(ch as u32 - '0' as u32 + 26),
+        _ => None,
+    }
+}
+
+/// Punycode encodes a label, failing on overflow.
+fn encode(label: &str) -> Option<String> {
+    let code_points: Vec<u32> = label.chars().map(u32::from).collect();
+    let mut output: String = label.chars().filter(char::is_ascii).collect();
+
+    let basic_count = output.len() as u32;
+    let mut handled = basic_count;
+
+    if basic_count > 0 {
+        output.push('-');
+    }
+
+    let (mut n, mut delta, mut bias) = (INITIAL_N, 0u32, INITIAL_BIAS);
+
+    while (handled as usize) < code_points.len() {
+        let m = code_points.iter().copied().filter(|&cp| cp >= n).min()?;
+        delta = delta.checked_add((m - n).checked_mul(handled + 1)?)?;
+        n = m;
+
+        for &cp in &code_points {
+            if cp < n {
+                delta = delta.checked_add(1)?;
+            }
+
+            if cp == n {
+                let mut q = delta;
+                let mut k = BASE;
+
+                loop {
+                    let t = threshold(k, bias);
+                    if q < t {
+                        break;
+                    }
+
+                    output.push(encode_digit(t + (q - t) % (BASE - t)));
+                    q = (q - t) / (BASE - t);
+                    k += BASE;
+                }
+
+                output.push(encode_digit(q));
+                bias = adapt(delta, handled + 1, handled == basic_count);
+                delta = 0;
+                handled += 1;
+            }
+        }
+
+        delta = delta.checked_add(1)?;
+        n = n.checked_add(1)?;
+    }
+
+    Some(output)
+}
+
+/// Decodes a Punycode encoded label, failing on invalid input.
+fn decode(encoded: &str) -> Option<String> {
+    let (basic, deltas) = match encoded.rfind('-') {
+        Some(pos) => (&encoded[..pos], &encoded[pos + 1..]),
+        None => ("", encoded),
+    };
+
+    if !basic.is_ascii() {
+        return None;
+    }
+
+    let mut output: Vec<char> = basic.chars().collect();
+    let (mut n, mut i, mut bias) = (INITIAL_N, 0u32, INITIAL_BIAS);
+    let mut digits = deltas.chars().peekable();
+
+    while digits.peek().is_some() {
+        let old_i = i;
+        let mut weight = 1u32;
+        let mut k = BASE;
+
+        loop {
+            let digit = decode_digit(digits.next()?)?;
+            i = i.checked_add(digit.checked_mul(weight)?)?;
+
+            let t = threshold(k, bias);
+            if digit < t {
+                break;
+            }
+
+            weight = weight.checked_mul(BASE - t)?;
+            k += BASE;
+        }
+
+        let length = output.len() as u32 + 1;
+        bias = adapt(i - old_i, length, old_i == 0);
+        n = n.checked_add(i / length)?;
+        i %= length;
+
+        output.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+
+    Some(output.into_iter().collect())
+}
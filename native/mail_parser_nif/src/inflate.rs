@@ -0,0 +1,331 @@
+/// Base lengths of the length symbols 257 to 285, and their extra bits.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+/// Base distances of the distance symbols 0 to 29, and their extra bits.
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// Order in which the code lengths of the code length alphabet are stored.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const MAX_CODE_LENGTH: usize = 15;
+
+const END_OF_BLOCK: u16 = 256;
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+/// Why a DEFLATE stream could not be decompressed.
+#[derive(Debug, PartialEq, Eq)]
+pub enum InflateError {
+    Malformed,
+    /// The stream decompresses to more than the maximum length.
+    TooLarge,
+}
+
+/// Decompresses a DEFLATE stream (RFC 1951), returning the decompressed
+/// bytes and the length of the stream.
+///
+/// Fails for malformed streams and for streams decompressing to more than
+/// `max_len` bytes, which is checked as they are decompressed.
+pub fn inflate(data: &[u8], max_len: usize) -> Result<(Vec<u8>, usize), InflateError> {
+    let mut inflater = Inflater {
+        bits: BitReader {
+            data,
+            pos: 0,
+            buffer: 0,
+            count: 0,
+        },
+        output: Vec::new(),
+        max_len,
+        too_large: false,
+    };
+
+    match inflater.inflate() {
+        Some(len) => Ok((inflater.output, len)),
+        None if inflater.too_large => Err(InflateError::TooLarge),
+        None => Err(InflateError::Malformed),
+    }
+}
+
+/// Computes the CRC-32 checksum of ZIP and gzip files.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        CRC32_TABLE[usize::from((crc as u8) ^ byte)] ^ (crc >> 8)
+    })
+}
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut index = 0;
+
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = match crc & 1 {
+                1 => 0xEDB8_8320 ^ (crc >> 1),
+                _ => crc >> 1,
+            };
+            bit += 1;
+        }
+
+        table[index] = crc;
+        index += 1;
+    }
+
+    table
+}
+
+/// Reads the bits of a DEFLATE stream, least significant bit first.
+struct BitReader<'d> {
+    data: &'d [u8],
+    pos: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn take(&mut self, len: u32) -> Option<u16> {
+        while self.count < len {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            self.buffer |= u64::from(byte) << self.count;
+            self.count += 8;
+        }
+
+        let value = self.buffer & ((1 << len) - 1);
+        self.buffer >>= len;
+        self.count -= len;
+        Some(value as u16)
+    }
+
+    /// Drops the bits left in the current byte.
+    fn align(&mut self) {
+        self.buffer = 0;
+        self.count = 0;
+    }
+}
+
+/// A canonical Huffman code, given by the number of codes of each length
+/// and the symbols ordered by code.
+struct Huffman {
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// Builds the code of the given code lengths, one per symbol. Returns
+    /// `None` if the lengths describe more codes than can exist.
+    fn new(lengths: &[u8]) -> Option<Self> {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+
+        for &len in lengths {
+            counts[usize::from(len)] += 1;
+        }
+
+        let mut left = 1i32;
+
+        for &count in &counts[1..] {
+            left = (left << 1) - i32::from(count);
+
+            if left < 0 {
+                return None;
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_LENGTH + 1];
+
+        for len in 1..MAX_CODE_LENGTH {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = vec![0; usize::from(offsets[MAX_CODE_LENGTH] + counts[MAX_CODE_LENGTH])];
+
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                let offset = &mut offsets[usize::from(len)];
+                symbols[usize::from(*offset)] = symbol as u16;
+                *offset += 1;
+            }
+        }
+
+        counts[0] = 0;
+        Some(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, bits: &mut BitReader) -> Option<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+
+        for &count in &self.counts[1..] {
+            code |= i32::from(bits.take(1)?);
+            let count = i32::from(count);
+
+            if code - count < first {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        None
+    }
+}
+
+fn fixed_codes() -> Option<(Huffman, Huffman)> {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    Some((Huffman::new(&lengths)?, Huffman::new(&[5; 30])?))
+}
+
+struct Inflater<'d> {
+    bits: BitReader<'d>,
+    output: Vec<u8>,
+    max_len: usize,
+    /// Whether decompression stopped at `max_len`.
+    too_large: bool,
+}
+
+impl Inflater<'_> {
+    /// Decompresses the blocks of the stream, returning its length.
+    fn inflate(&mut self) -> Option<usize> {
+        loop {
+            let is_last = self.bits.take(1)? == 1;
+
+            match self.bits.take(2)? {
+                0 => self.stored_block()?,
+                1 => {
+                    let (literals, distances) = fixed_codes()?;
+                    self.compressed_block(&literals, &distances)?;
+                }
+                2 => {
+                    let (literals, distances) = self.dynamic_codes()?;
+                    self.compressed_block(&literals, &distances)?;
+                }
+                _ => return None,
+            }
+
+            if is_last {
+                return Some(self.bits.pos);
+            }
+        }
+    }
+
+    fn reserve(&mut self, len: usize) -> Option<()> {
+        self.too_large = self.output.len() + len > self.max_len;
+        (!self.too_large).then_some(())
+    }
+
+    fn stored_block(&mut self) -> Option<()> {
+        self.bits.align();
+
+        let header = self.bits.data.get(self.bits.pos..self.bits.pos + 4)?;
+        let len = u16::from_le_bytes([header[0], header[1]]);
+
+        if len != !u16::from_le_bytes([header[2], header[3]]) {
+            return None;
+        }
+
+        let start = self.bits.pos + 4;
+        let block = self.bits.data.get(start..start + usize::from(len))?;
+        self.reserve(block.len())?;
+        self.output.extend_from_slice(block);
+        self.bits.pos = start + block.len();
+        Some(())
+    }
+
+    fn dynamic_codes(&mut self) -> Option<(Huffman, Huffman)> {
+        let literal_count = usize::from(self.bits.take(5)?) + 257;
+        let distance_count = usize::from(self.bits.take(5)?) + 1;
+        let code_length_count = usize::from(self.bits.take(4)?) + 4;
+
+        let mut code_length_lengths = [0u8; 19];
+
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_length_lengths[symbol] = self.bits.take(3)? as u8;
+        }
+
+        let code_lengths = Huffman::new(&code_length_lengths)?;
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+
+        while lengths.len() < literal_count + distance_count {
+            let (len, repeat) = match code_lengths.decode(&mut self.bits)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => (*lengths.last()?, 3 + self.bits.take(2)?),
+                17 => (0, 3 + self.bits.take(3)?),
+                _ => (0, 11 + self.bits.take(7)?),
+            };
+
+            if lengths.len() + usize::from(repeat) > literal_count + distance_count {
+                return None;
+            }
+
+            lengths.resize(lengths.len() + usize::from(repeat), len);
+        }
+
+        if lengths[usize::from(END_OF_BLOCK)] == 0 {
+            return None;
+        }
+
+        let (literal_lengths, distance_lengths) = lengths.split_at(literal_count);
+        Some((
+            Huffman::new(literal_lengths)?,
+            Huffman::new(distance_lengths)?,
+        ))
+    }
+
+    fn compressed_block(&mut self, literals: &Huffman, distances: &Huffman) -> Option<()> {
+        loop {
+            let symbol = literals.decode(&mut self.bits)?;
+
+            if symbol < END_OF_BLOCK {
+                self.reserve(1)?;
+                self.output.push(symbol as u8);
+                continue;
+            }
+
+            if symbol == END_OF_BLOCK {
+                return Some(());
+            }
+
+            let index = usize::from(symbol - 257);
+            let len =
+                *LENGTH_BASE.get(index)? + self.bits.take(u32::from(LENGTH_EXTRA_BITS[index]))?;
+
+            let index = usize::from(distances.decode(&mut self.bits)?);
+            let distance = usize::from(*DISTANCE_BASE.get(index)?)
+                + usize::from(self.bits.take(u32::from(DISTANCE_EXTRA_BITS[index]))?);
+
+            if distance > self.output.len() {
+                return None;
+            }
+
+            self.reserve(usize::from(len))?;
+
+            // The copied bytes may overlap the ones being written.
+            for _ in 0..len {
+                self.output.push(self.output[self.output.len() - distance]);
+            }
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use mail_parser::{Message, MessagePart, MimeHeaders, PartType};
+use rustler::{Atom, Binary, Decoder, Env, Error, NifMap, NifResult, ResourceArc, Term};
+
+use crate::atoms;
+use crate::attachment::{content_type, Source, DEFAULT_CONTENT_TYPE};
+use crate::message::MessageResource;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Options accepted by `get_html_with_inline_parts/2`, decoded from a
+/// keyword list.
+#[derive(Default)]
+pub struct InlineOptions {
+    /// Whether to replace the `cid:` URLs of the HTML body with `data:` URIs.
+    pub data_uris: bool,
+}
+
+impl<'a> Decoder<'a> for InlineOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut inline_options = InlineOptions::default();
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::data_uris() {
+                inline_options.data_uris = value.decode()?;
+            } else {
+                return Err(Error::BadArg);
+            }
+        }
+
+        Ok(inline_options)
+    }
+}
+
+#[derive(NifMap)]
+pub struct HtmlWithInlineParts<'a> {
+    html: String,
+    inline_parts: HashMap<String, InlinePart<'a>>,
+}
+
+#[derive(NifMap)]
+pub struct InlinePart<'a> {
+    content_type: String,
+    content_bytes: Binary<'a>,
+}
+
+/// Returns the position and length of the `cid:` URLs of an HTML document,
+/// along with the percent-decoded Content-ID each one refers to.
+fn cid_urls(html: &str) -> Vec<(usize, usize, String)> {
+    let mut urls = Vec::new();
+    let mut end = 0;
+
+    // Lowercasing ASCII characters keeps the positions of the HTML.
+    for (start, _) in html.to_ascii_lowercase().match_indices("cid:") {
+        if start < end {
+            continue;
+        }
+
+        let id = &html[start + 4..];
+        let id = &id[..id
+            .find(|ch: char| ch.is_whitespace() || matches!(ch, '"' | '\'' | '(' | ')' | '<' | '>'))
+            .unwrap_or(id.len())];
+
+        if !id.is_empty() {
+            end = start + 4 + id.len();
+            urls.push((start, end - start, percent_decode(id)));
+        }
+    }
+
+    urls
+}
+
+/// Decodes the `%XX` escapes of a URL, leaving malformed ones as is.
+fn percent_decode(text: &str) -> String {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(decoded) if byte == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Encodes bytes as standard, padded base64.
+pub fn base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let group = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                let sextet = (group >> (18 - 6 * index)) & 0x3F;
+                encoded.push(BASE64_ALPHABET[sextet as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+/// Returns the preferred HTML body of a message and the parts its `cid:`
+/// URLs refer to, with the URLs replaced by `data:` URIs if requested.
+/// URLs that do not refer to a part are left as is.
+fn html_with_inline_parts<'m>(
+    message: &'m Message,
+    inline_options: &InlineOptions,
+) -> Option<(String, HashMap<String, &'m MessagePart<'m>>)> {
+    let html = message
+        .html_body
+        .iter()
+        .find_map(|&index| match &message.parts[index].body {
+            PartType::Html(html) => Some(html.as_ref()),
+            _ => None,
+        })?;
+
+    let content_ids: HashMap<&str, &MessagePart> = message
+        .parts
+        .iter()
+        .filter(|part| !part.is_multipart())
+        .filter_map(|part| Some((part.content_id()?, part)))
+        .collect();
+
+    let mut inline_parts = HashMap::new();
+    let mut rewritten_html = String::with_capacity(html.len());
+    let mut end = 0;
+
+    for (start, len, content_id) in cid_urls(html) {
+        let Some(&part) = content_ids.get(content_id.as_str()) else {
+            continue;
+        };
+
+        if inline_options.data_uris {
+            let content_type = content_type(part);
+            let content_type = content_type.as_deref().unwrap_or(DEFAULT_CONTENT_TYPE);
+
+            rewritten_html.push_str(&html[end..start]);
+            rewritten_html.push_str(&format!("data:{content_type};base64,"));
+            rewritten_html.push_str(&base64(part.contents()));
+            end = start + len;
+        }
+
+        inline_parts.insert(content_id, part);
+    }
+
+    rewritten_html.push_str(&html[end..]);
+    Some((rewritten_html, inline_parts))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_html_with_inline_parts<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<MessageResource>,
+    inline_options: InlineOptions,
+) -> Option<HtmlWithInlineParts<'a>> {
+    let (html, parts) = html_with_inline_parts(resource.message(), &inline_options)?;
+    let source = Source::Resource(resource.clone());
+
+    let inline_parts = parts
+        .into_iter()
+        .map(|(content_id, part)| {
+            let inline_part = InlinePart {
+                content_type: content_type(part)
+                    .unwrap_or_else(|| DEFAULT_CONTENT_TYPE.to_string()),
+                content_bytes: source.binary(env, part.contents()),
+            };
+
+            (content_id, inline_part)
+        })
+        .collect();
+
+    Some(HtmlWithInlineParts { html, inline_parts })
+}
@@ -0,0 +1,84 @@
+use std::ops::Deref;
+
+use rustler::{Binary, Decoder, Error, NewBinary, NifResult, Term};
+
+/// A raw message passed as iodata: a binary, used as is, or a possibly
+/// improper list of binaries, bytes and nested lists, copied once into a
+/// new binary so that attachments can still be returned as sub-binaries.
+pub struct RawMessage<'a>(Binary<'a>);
+
+impl<'a> Decoder<'a> for RawMessage<'a> {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        if term.is_binary() {
+            return Ok(RawMessage(term.decode()?));
+        }
+
+        let chunks = chunks(term)?;
+        let size = chunks.iter().map(|chunk| chunk.len()).sum();
+        let mut binary = NewBinary::new(term.get_env(), size);
+        let mut pos = 0;
+
+        for chunk in chunks {
+            binary.as_mut_slice()[pos..pos + chunk.len()].copy_from_slice(&chunk);
+            pos += chunk.len();
+        }
+
+        Ok(RawMessage(binary.into()))
+    }
+}
+
+impl<'a> Deref for RawMessage<'a> {
+    type Target = Binary<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// A chunk of an iolist: the bytes of a binary, or a single byte.
+enum Chunk<'a> {
+    Binary(Binary<'a>),
+    Byte([u8; 1]),
+}
+
+impl Deref for Chunk<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Chunk::Binary(binary) => binary.as_slice(),
+            Chunk::Byte(byte) => byte,
+        }
+    }
+}
+
+/// Returns the chunks of an iolist, in order, failing with `BadArg` for
+/// anything that is not iodata.
+///
+/// An explicit stack is used so that deeply nested lists cannot overflow
+/// the native stack.
+fn chunks(iolist: Term) -> NifResult<Vec<Chunk>> {
+    let mut chunks = Vec::new();
+    // Each term is pushed along with whether it is the tail of a list,
+    // which may only be a binary or a list.
+    let mut stack = vec![(iolist, true)];
+
+    while let Some((term, is_tail)) = stack.pop() {
+        if term.is_binary() {
+            chunks.push(Chunk::Binary(term.decode()?));
+        } else if term.is_empty_list() {
+            continue;
+        } else if term.is_list() {
+            let (head, tail) = term.list_get_cell()?;
+            stack.push((tail, true));
+            stack.push((head, false));
+        } else if is_tail {
+            return Err(Error::BadArg);
+        } else {
+            let byte = u8::try_from(term.decode::<u32>()?).map_err(|_| Error::BadArg)?;
+            chunks.push(Chunk::Byte([byte]));
+        }
+    }
+
+    Ok(chunks)
+}
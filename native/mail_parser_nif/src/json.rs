@@ -0,0 +1,282 @@
+use mail_parser::{DateTime, Message};
+use rustler::{Atom, Binary, Decoder, Env, NewBinary, NifResult, ResourceArc, Term};
+
+use crate::address::{addresses, Address};
+use crate::atoms;
+use crate::attachment::{select_attachments, AttachmentPart, Disposition, LimitExceeded};
+use crate::date::dates;
+use crate::digest::{digests, DigestAlgorithm};
+use crate::header::headers;
+use crate::inline::base64;
+use crate::message::{html_body, text_body, MessageResource};
+use crate::options::Options;
+use crate::sniff;
+
+/// Options accepted by `to_json/2`, on top of the attachment extraction
+/// options selecting the attachments to describe.
+struct JsonOptions {
+    options: Options,
+    /// Whether the contents of attachments are included, base64 encoded.
+    content_bytes: bool,
+}
+
+impl<'a> Decoder<'a> for JsonOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut json_options = JsonOptions {
+            options: Options::default(),
+            content_bytes: false,
+        };
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::content_bytes() {
+                json_options.content_bytes = value.decode()?;
+            } else {
+                json_options.options.set(key, value)?;
+            }
+        }
+
+        Ok(json_options)
+    }
+}
+
+/// Writes a JSON document, inserting the commas between the members of
+/// objects and the elements of arrays.
+#[derive(Default)]
+struct JsonWriter {
+    json: String,
+    /// Whether the next value is the first of its object or array, or the
+    /// value of a key, and is not preceded by a comma.
+    first: bool,
+}
+
+impl JsonWriter {
+    fn separate(&mut self) {
+        if !self.first && !self.json.is_empty() {
+            self.json.push(',');
+        }
+
+        self.first = false;
+    }
+
+    fn begin(&mut self, bracket: char) {
+        self.separate();
+        self.json.push(bracket);
+        self.first = true;
+    }
+
+    fn end(&mut self, bracket: char) {
+        self.json.push(bracket);
+        self.first = false;
+    }
+
+    fn key(&mut self, key: &str) {
+        self.separate();
+        self.push_string(key);
+        self.json.push(':');
+        self.first = true;
+    }
+
+    fn string(&mut self, value: Option<&str>) {
+        self.separate();
+
+        match value {
+            Some(value) => self.push_string(value),
+            None => self.json.push_str("null"),
+        }
+    }
+
+    fn number(&mut self, value: usize) {
+        self.separate();
+        self.json.push_str(&value.to_string());
+    }
+
+    fn boolean(&mut self, value: bool) {
+        self.separate();
+        self.json.push_str(if value { "true" } else { "false" });
+    }
+
+    fn push_string(&mut self, value: &str) {
+        self.json.push('"');
+
+        for ch in value.chars() {
+            match ch {
+                '"' => self.json.push_str("\\\""),
+                '\\' => self.json.push_str("\\\\"),
+                '\n' => self.json.push_str("\\n"),
+                '\r' => self.json.push_str("\\r"),
+                '\t' => self.json.push_str("\\t"),
+                ch if u32::from(ch) < 0x20 => {
+                    self.json.push_str(&format!("\\u{:04x}", u32::from(ch)));
+                }
+                ch => self.json.push(ch),
+            }
+        }
+
+        self.json.push('"');
+    }
+
+    fn addresses(&mut self, key: &str, addresses: &[Address]) {
+        self.key(key);
+        self.begin('[');
+
+        for address in addresses {
+            self.begin('{');
+            self.key("name");
+            self.string(address.name.as_deref());
+            self.key("email");
+            self.string(address.email.as_deref());
+            self.key("group");
+            self.string(address.group.as_deref());
+            self.end('}');
+        }
+
+        self.end(']');
+    }
+
+    fn attachment(&mut self, attachment: &AttachmentPart, json_options: &JsonOptions) {
+        let contents = attachment.contents();
+
+        self.begin('{');
+        self.key("name");
+        self.string(Some(attachment.name()));
+        self.key("raw_name");
+        self.string(attachment.raw_name());
+        self.key("content_type");
+        self.string(attachment.content_type().as_deref());
+        self.key("detected_content_type");
+        self.string(attachment.detected_content_type());
+        self.key("disposition");
+        self.string(Some(match attachment.disposition() {
+            Disposition::Inline => "inline",
+            Disposition::Attachment => "attachment",
+        }));
+        self.key("content_id");
+        self.string(attachment.content_id());
+        self.key("size");
+        self.number(contents.len());
+        self.key("encrypted");
+        self.boolean(sniff::is_encrypted(contents));
+
+        let mut digests: Vec<_> = digests(&json_options.options.digests, contents)
+            .into_iter()
+            .collect();
+        digests.sort_by_key(|(algorithm, _)| algorithm_name(*algorithm));
+
+        self.key("digests");
+        self.begin('{');
+
+        for (algorithm, digest) in &digests {
+            self.key(algorithm_name(*algorithm));
+            self.string(Some(digest));
+        }
+
+        self.end('}');
+
+        if json_options.options.include_part_headers {
+            let mut part_headers: Vec<_> = attachment.part_headers().into_iter().collect();
+            part_headers.sort();
+
+            self.key("part_headers");
+            self.begin('{');
+
+            for (name, values) in &part_headers {
+                self.key(name);
+                self.begin('[');
+
+                for value in values {
+                    self.string(Some(value));
+                }
+
+                self.end(']');
+            }
+
+            self.end('}');
+        }
+
+        if json_options.content_bytes {
+            self.key("content_bytes");
+            self.string(Some(&base64(contents)));
+        }
+
+        self.end('}');
+    }
+}
+
+fn algorithm_name(algorithm: DigestAlgorithm) -> &'static str {
+    match algorithm {
+        DigestAlgorithm::Sha256 => "sha256",
+        DigestAlgorithm::Sha1 => "sha1",
+        DigestAlgorithm::Md5 => "md5",
+    }
+}
+
+/// Serializes a message into a JSON object holding its subject, Message-ID,
+/// date, addresses, headers, bodies and attachments.
+fn to_json_string(message: &Message, json_options: &JsonOptions) -> Result<String, LimitExceeded> {
+    let attachments = select_attachments(message, &json_options.options)?;
+    let mut writer = JsonWriter::default();
+
+    writer.begin('{');
+    writer.key("subject");
+    writer.string(message.subject());
+    writer.key("message_id");
+    writer.string(message.message_id());
+    writer.key("date");
+    writer.string(
+        dates(message, "Date")
+            .next()
+            .map(|timestamp| DateTime::from_timestamp(timestamp).to_rfc3339())
+            .as_deref(),
+    );
+
+    writer.addresses("from", &addresses(message.from()));
+    writer.addresses("to", &addresses(message.to()));
+    writer.addresses("cc", &addresses(message.cc()));
+    writer.addresses("bcc", &addresses(message.bcc()));
+    writer.addresses("reply_to", &addresses(message.reply_to()));
+    writer.addresses("sender", &addresses(message.sender()));
+
+    writer.key("headers");
+    writer.begin('[');
+
+    for header in headers(message, message.headers(), None) {
+        writer.begin('{');
+        writer.key("name");
+        writer.string(Some(&header.name));
+        writer.key("value");
+        writer.string(Some(&header.value));
+        writer.end('}');
+    }
+
+    writer.end(']');
+
+    writer.key("text_body");
+    writer.string(text_body(message));
+    writer.key("html_body");
+    writer.string(html_body(message));
+
+    writer.key("attachments");
+    writer.begin('[');
+
+    for attachment in &attachments {
+        writer.attachment(attachment, json_options);
+    }
+
+    writer.end(']');
+    writer.end('}');
+
+    Ok(writer.json)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn to_json<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<MessageResource>,
+    json_options: JsonOptions,
+) -> NifResult<Binary<'a>> {
+    let json = to_json_string(resource.message(), &json_options)?;
+
+    let mut binary = NewBinary::new(env, json.len());
+    binary.as_mut_slice().copy_from_slice(json.as_bytes());
+    Ok(binary.into())
+}
@@ -1,81 +1,226 @@
-use mail_parser::{Message, MessagePart, MimeHeaders};
-use rustler::{Atom, Binary, Env, Error, NifResult, NifStruct, OwnedBinary, Term};
-use rustler::{Decoder, Encoder};
+use rustler::{Atom, Env, NifResult, Term};
 
-mod atoms {
-    rustler::atoms! {
-        ok
-    }
-}
-
-#[derive(Clone, Debug, NifStruct)]
-#[module = "MailParser.Attachment"]
-struct Attachment {
-    name: String,
-    content_type: Option<String>,
-    content_bytes: ContentBytes,
-}
-
-impl From<&MessagePart<'_>> for Attachment {
-    fn from(part: &MessagePart) -> Self {
-        let name = part.attachment_name().unwrap_or("untitled").to_string();
-        let content_bytes = ContentBytes::new(part.contents());
-
-        let content_type = part.content_type().map(|content_type| {
-            let roottype = content_type.ctype();
+use attachment::{get_attachments, Attachment, Source};
+use error::parse_message;
+use iodata::RawMessage;
+use options::Options;
 
-            match content_type.subtype() {
-                Some(subtype) => format!("{roottype}/{subtype}"),
-                None => roottype.to_string(),
-            }
-        });
+mod address;
+mod archive;
+mod attachment;
+mod auth;
+mod batch;
+mod budget;
+mod calendar;
+mod compose;
+mod content_line;
+mod date;
+mod decompress;
+mod der;
+mod digest;
+mod disk;
+mod error;
+mod file;
+mod filename;
+mod header;
+mod idna;
+mod inflate;
+mod inline;
+mod iodata;
+mod json;
+mod list;
+mod maildir;
+mod mbox;
+mod message;
+mod metrics;
+mod options;
+mod path_template;
+mod received;
+mod report;
+mod sanitize;
+mod security;
+mod sniff;
+mod stats;
+mod stream;
+mod strip;
+mod structure;
+mod text;
+mod thread;
+mod tnef;
+mod uuencode;
+mod vcard;
+mod warning;
+mod yielding;
 
-        Attachment {
-            name,
-            content_bytes,
-            content_type,
-        }
-    }
-}
-
-#[derive(Clone, Debug)]
-struct ContentBytes(Vec<u8>);
-
-impl ContentBytes {
-    fn new(content_bytes: &[u8]) -> Self {
-        ContentBytes(content_bytes.to_vec())
+mod atoms {
+    rustler::atoms! {
+        ok,
+        include_inline,
+        mime_types,
+        max_attachment_size,
+        max_total_size,
+        max_attachments,
+        max_depth,
+        on_limit,
+        limit_exceeded,
+        sanitize,
+        unsafe_filename,
+        on_conflict,
+        file_exists,
+        atomic,
+        fsync,
+        max_length,
+        nested,
+        digests,
+        dedupe,
+        error,
+        max_messages,
+        attachments,
+        eof,
+        file,
+        expand_tnef,
+        uudecode,
+        data_uris,
+        match_content_type,
+        extensions,
+        name_glob,
+        min_size,
+        max_size,
+        expand_archives,
+        max_archive_entry_size,
+        max_archive_size,
+        placeholder,
+        headers,
+        text,
+        html,
+        name,
+        content_type,
+        content_bytes,
+        inline,
+        disposition,
+        content_id,
+        lenient,
+        empty_input,
+        not_a_message,
+        not_a_report,
+        part_not_found,
+        io,
+        eperm,
+        enoent,
+        eintr,
+        eio,
+        enomem,
+        eacces,
+        ebusy,
+        eexist,
+        exdev,
+        enotdir,
+        eisdir,
+        einval,
+        enfile,
+        emfile,
+        efbig,
+        enospc,
+        erofs,
+        epipe,
+        name_fallback,
+        untitled,
+        extension,
+        indexed,
+        max_memory_bytes,
+        budget_exceeded,
+        include_part_headers,
+        chunk_size,
+        path_template,
+        remote_images,
+        allow,
+        block,
+        parallel,
+        decompress,
+        max_decompressed_size
     }
 }
 
-impl Encoder for ContentBytes {
-    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
-        let mut owned_binary = OwnedBinary::new(self.0.len()).expect("allocation failed");
-        owned_binary.as_mut_slice().copy_from_slice(&self.0);
-        Binary::from_owned(owned_binary, env).encode(env)
-    }
-}
-impl Decoder<'_> for ContentBytes {
-    fn decode(term: Term) -> NifResult<ContentBytes> {
-        Ok(Self(term.to_binary().to_vec()))
-    }
-}
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_nested_attachments<'a>(
+    env: Env<'a>,
+    raw_message: RawMessage<'a>,
+    options: Options,
+) -> NifResult<(Atom, Vec<Attachment<'a>>)> {
+    let message = parse_message(raw_message.as_slice())?;
+    let source = Source::Binary(*raw_message);
+    let attachments = get_attachments(env, &source, &message, &options)?;
 
-fn get_attachments(message: &Message) -> Vec<Attachment> {
-    message
-        .attachments()
-        .flat_map(|attachment| match attachment.message() {
-            Some(nested_message) => get_attachments(nested_message),
-            None => Vec::from([attachment.into()]),
-        })
-        .collect()
+    Ok((atoms::ok(), attachments))
 }
 
-#[rustler::nif]
-fn extract_nested_attachments(raw_message: &str) -> NifResult<(Atom, Vec<Attachment>)> {
-    match Message::parse(raw_message.as_bytes()) {
-        Some(message) => Ok((atoms::ok(), get_attachments(&message))),
-        None => Err(Error::Atom("error")),
-    }
+#[allow(non_local_definitions)]
+fn load(env: Env, _info: Term) -> bool {
+    rustler::resource!(message::MessageResource, env);
+    rustler::resource!(mbox::MboxResource, env);
+    rustler::resource!(maildir::MaildirResource, env);
+    rustler::resource!(yielding::ParseTask, env);
+    rustler::resource!(stream::AttachmentStream, env);
+    true
 }
 
-rustler::init!("Elixir.MailParser", [extract_nested_attachments]);
+rustler::init!(
+    "Elixir.MailParser",
+    [
+        extract_nested_attachments,
+        disk::extract_attachments_to_disk,
+        message::parse,
+        yielding::start_parse,
+        yielding::cancel_parse,
+        yielding::parse_cancelled,
+        message::get_subject,
+        message::get_message_attachments,
+        message::get_body,
+        message::get_text_body,
+        message::get_html_body,
+        address::extract_addresses,
+        address::get_addresses,
+        header::get_header,
+        header::get_headers,
+        header::parse_headers,
+        date::get_date,
+        date::get_resent_dates,
+        thread::get_thread_info,
+        text::get_plaintext,
+        text::get_preview,
+        text::get_reply_text,
+        structure::get_mime_structure,
+        structure::extract_part,
+        structure::get_part,
+        structure::get_raw_part,
+        structure::get_embedded_messages,
+        structure::list_attachments,
+        mbox::parse_mbox,
+        mbox::extract_attachments_from_mbox,
+        mbox::mbox_open,
+        mbox::mbox_next,
+        mbox::mbox_close,
+        maildir::parse_maildir,
+        maildir::maildir_open,
+        maildir::maildir_next,
+        file::parse_file,
+        file::extract_attachments_from_file,
+        batch::extract_attachments_batch,
+        calendar::extract_calendar_events,
+        vcard::extract_contacts,
+        report::parse_dsn,
+        auth::get_auth_results,
+        received::get_received_chain,
+        list::get_list_info,
+        inline::get_html_with_inline_parts,
+        security::get_security_info,
+        strip::strip_attachments,
+        compose::build_message,
+        json::to_json,
+        stats::stats,
+        stream::open_attachment_stream,
+        stream::read_attachment_chunk,
+        metrics::get_metrics
+    ],
+    load = load
+);
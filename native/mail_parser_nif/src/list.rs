@@ -0,0 +1,108 @@
+use mail_parser::Message;
+use rustler::{NifMap, ResourceArc};
+
+use crate::header::unfolded_values;
+use crate::message::MessageResource;
+
+#[derive(NifMap)]
+pub struct ListInfo {
+    /// Identifier of the List-Id header, e.g. `"dev.example.org"`.
+    id: Option<String>,
+    /// Description of the List-Id header, e.g. `"Developers"`.
+    name: Option<String>,
+    unsubscribe: Vec<String>,
+    /// URL to unsubscribe from with a single POST request (RFC 8058).
+    one_click_unsubscribe: Option<String>,
+    post: Vec<String>,
+    precedence: Option<String>,
+    auto_submitted: Option<String>,
+    /// Whether the message is bulk or automated mail, which should not be
+    /// answered automatically (RFC 3834).
+    suppress_auto_reply: bool,
+}
+
+impl From<&Message<'_>> for ListInfo {
+    fn from(message: &Message) -> Self {
+        let header = |name| unfolded_values(message, name).into_iter().next();
+        let (id, name) = header("List-Id")
+            .map(|value| list_id(&value))
+            .unwrap_or_default();
+        let unsubscribe = header("List-Unsubscribe").map_or_else(Vec::new, |value| uris(&value));
+
+        let is_one_click = header("List-Unsubscribe-Post").is_some_and(|value| {
+            value.split(',').any(|param| {
+                param
+                    .trim()
+                    .eq_ignore_ascii_case("List-Unsubscribe=One-Click")
+            })
+        });
+
+        let one_click_unsubscribe = unsubscribe
+            .iter()
+            .find(|uri| {
+                uri.get(..8)
+                    .is_some_and(|scheme| scheme.eq_ignore_ascii_case("https://"))
+            })
+            .filter(|_| is_one_click)
+            .cloned();
+
+        let precedence = header("Precedence").map(|value| value.trim().to_ascii_lowercase());
+        let auto_submitted = header("Auto-Submitted").map(|value| {
+            // Drop the parameters, e.g. `auto-replied; owner-email=...`.
+            let keyword = value.split(';').next().unwrap_or_default();
+            keyword.trim().to_ascii_lowercase()
+        });
+
+        let suppress_auto_reply = id.is_some()
+            || precedence
+                .as_deref()
+                .is_some_and(|precedence| matches!(precedence, "bulk" | "list" | "junk"))
+            || auto_submitted
+                .as_deref()
+                .is_some_and(|auto_submitted| auto_submitted != "no");
+
+        ListInfo {
+            id,
+            name,
+            unsubscribe,
+            one_click_unsubscribe,
+            post: header("List-Post").map_or_else(Vec::new, |value| uris(&value)),
+            precedence,
+            auto_submitted,
+            suppress_auto_reply,
+        }
+    }
+}
+
+/// Splits a List-Id header, e.g. `"Developers" <dev.example.org>`, into
+/// its identifier and description.
+fn list_id(value: &str) -> (Option<String>, Option<String>) {
+    let (name, id) = match value.rsplit_once('<') {
+        Some((name, rest)) => (name, rest.split('>').next().unwrap_or_default()),
+        None => ("", value),
+    };
+
+    let non_empty = |text: &str| (!text.is_empty()).then(|| text.to_string());
+    (
+        non_empty(id.trim()),
+        non_empty(name.trim().trim_matches('"').trim()),
+    )
+}
+
+/// Returns the URIs of a List-* header, which are enclosed in angle
+/// brackets, e.g. `<mailto:dev@example.org>, <https://example.org/post>`.
+/// Values such as the `NO` of a List-Post header return no URIs.
+fn uris(value: &str) -> Vec<String> {
+    value
+        .split('<')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('>'))
+        .map(|(uri, _)| uri.split_whitespace().collect::<String>())
+        .filter(|uri| !uri.is_empty())
+        .collect()
+}
+
+#[rustler::nif]
+fn get_list_info(resource: ResourceArc<MessageResource>) -> ListInfo {
+    ListInfo::from(resource.message())
+}
@@ -0,0 +1,162 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::vec;
+
+use rayon::prelude::*;
+use rustler::{Atom, Decoder, Encoder, Env, Error, NifResult, ResourceArc, Term};
+
+use crate::atoms;
+use crate::error::ErrorReason;
+use crate::message::{parse_buffer, MessageResource, ParseOptions};
+
+/// Subdirectories of a Maildir holding messages, read in this order.
+const MESSAGE_DIRECTORIES: [&str; 2] = ["cur", "new"];
+
+/// Options accepted by `parse_maildir/2`.
+#[derive(Default)]
+struct MaildirOptions {
+    /// Maximum number of messages read from the Maildir.
+    max_messages: Option<usize>,
+    /// Whether messages are read and parsed in parallel.
+    parallel: bool,
+    /// Maximum size of the bodies decoded for each message, in bytes.
+    max_memory_bytes: Option<usize>,
+}
+
+impl<'a> Decoder<'a> for MaildirOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut maildir_options = MaildirOptions::default();
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::max_messages() {
+                maildir_options.max_messages = Some(value.decode()?);
+            } else if key == atoms::parallel() {
+                maildir_options.parallel = value.decode()?;
+            } else if key == atoms::max_memory_bytes() {
+                maildir_options.max_memory_bytes = Some(value.decode()?);
+            } else {
+                return Err(Error::BadArg);
+            }
+        }
+
+        Ok(maildir_options)
+    }
+}
+
+/// A Maildir whose messages are read one at a time by `maildir_next/1`.
+pub struct MaildirResource {
+    paths: Mutex<vec::IntoIter<PathBuf>>,
+}
+
+/// Returns the paths of the messages of the Maildir at `root`, those of
+/// `cur/` then those of `new/`, each sorted by file name, which starts
+/// with the delivery time. Hidden files are skipped.
+fn message_paths(root: &str) -> Result<Vec<PathBuf>, ErrorReason> {
+    let mut paths = Vec::new();
+
+    for directory in MESSAGE_DIRECTORIES {
+        let directory = Path::new(root).join(directory);
+        let io_error = |error| ErrorReason::io(&error, &directory);
+
+        let mut directory_paths = Vec::new();
+
+        for entry in fs::read_dir(&directory).map_err(io_error)? {
+            let entry = entry.map_err(io_error)?;
+
+            let is_hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !is_hidden && entry.file_type().map_err(io_error)?.is_file() {
+                directory_paths.push(entry.path());
+            }
+        }
+
+        directory_paths.sort();
+        paths.extend(directory_paths);
+    }
+
+    Ok(paths)
+}
+
+/// Reads and parses the message at `path`, the parsed message borrowing its
+/// bodies from what was read, and failing if the bodies it had to decode
+/// exceed `max_memory_bytes`.
+fn parse_file(
+    path: &Path,
+    max_memory_bytes: Option<usize>,
+) -> Result<MessageResource, ErrorReason> {
+    let raw_message = fs::read(path).map_err(|error| ErrorReason::io(&error, path))?;
+
+    let parse_options = ParseOptions {
+        lenient: false,
+        max_memory_bytes,
+    };
+
+    let (resource, _) = parse_buffer(raw_message, &parse_options)?;
+    Ok(resource)
+}
+
+/// Returns `{path, {:ok, message}}`, or `{path, {:error, reason}}` for
+/// messages that cannot be read or parsed.
+fn encode_result<'a>(
+    env: Env<'a>,
+    path: &Path,
+    result: Result<MessageResource, ErrorReason>,
+) -> Term<'a> {
+    let path = path.to_string_lossy().into_owned();
+
+    match result {
+        Ok(resource) => (path, (atoms::ok(), ResourceArc::new(resource))).encode(env),
+        Err(reason) => (path, (atoms::error(), reason)).encode(env),
+    }
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn parse_maildir<'a>(
+    env: Env<'a>,
+    root: String,
+    maildir_options: MaildirOptions,
+) -> NifResult<(Atom, Vec<Term<'a>>)> {
+    let mut paths = message_paths(&root)?;
+    paths.truncate(maildir_options.max_messages.unwrap_or(usize::MAX));
+
+    // Terms can only be built on the calling thread, so the workers only
+    // read and parse the messages.
+    let max_memory_bytes = maildir_options.max_memory_bytes;
+    let results: Vec<_> = match maildir_options.parallel {
+        true => paths
+            .par_iter()
+            .map(|path| parse_file(path, max_memory_bytes))
+            .collect(),
+        false => paths
+            .iter()
+            .map(|path| parse_file(path, max_memory_bytes))
+            .collect(),
+    };
+
+    let results = paths
+        .iter()
+        .zip(results)
+        .map(|(path, result)| encode_result(env, path, result))
+        .collect();
+
+    Ok((atoms::ok(), results))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn maildir_open(root: String) -> NifResult<(Atom, ResourceArc<MaildirResource>)> {
+    let resource = MaildirResource {
+        paths: Mutex::new(message_paths(&root)?.into_iter()),
+    };
+
+    Ok((atoms::ok(), ResourceArc::new(resource)))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn maildir_next(env: Env, resource: ResourceArc<MaildirResource>) -> NifResult<Term> {
+    let mut paths = resource.paths.lock().map_err(|_| Error::BadArg)?;
+
+    match paths.next() {
+        Some(path) => Ok(encode_result(env, &path, parse_file(&path, None))),
+        None => Ok(atoms::eof().encode(env)),
+    }
+}
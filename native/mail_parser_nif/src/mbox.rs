@@ -0,0 +1,223 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Mutex;
+
+use mail_parser::mailbox::mbox::MessageIterator;
+use rustler::{
+    Atom, Binary, Decoder, Encoder, Env, Error, NewBinary, NifResult, ResourceArc, Term,
+};
+
+use crate::atoms;
+use crate::attachment::{get_attachments, Source};
+use crate::error::{parse_message, ErrorReason};
+use crate::message::{encode_parsed, parse_buffer, ParseOptions};
+use crate::options::Options;
+
+/// Options accepted by the mbox NIFs, on top of the attachment extraction
+/// options.
+struct MboxOptions {
+    options: Options,
+    /// Maximum number of messages read from the mailbox.
+    max_messages: Option<usize>,
+}
+
+impl<'a> Decoder<'a> for MboxOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut mbox_options = MboxOptions {
+            options: Options::default(),
+            max_messages: None,
+        };
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::max_messages() {
+                mbox_options.max_messages = Some(value.decode()?);
+            } else {
+                mbox_options.options.set(key, value)?;
+            }
+        }
+
+        Ok(mbox_options)
+    }
+}
+
+/// An open mbox file read one message at a time by `mbox_next/2`.
+pub struct MboxResource {
+    path: String,
+    messages: Mutex<Option<MessageIterator<File>>>,
+}
+
+/// Options accepted by `mbox_next/2`, on top of the attachment extraction
+/// options.
+struct NextOptions {
+    options: Options,
+    /// Whether the attachments of the message are returned rather than the
+    /// parsed message.
+    attachments: bool,
+}
+
+impl<'a> Decoder<'a> for NextOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut next_options = NextOptions {
+            options: Options::default(),
+            attachments: false,
+        };
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::attachments() {
+                next_options.attachments = value.decode()?;
+            } else {
+                next_options.options.set(key, value)?;
+            }
+        }
+
+        Ok(next_options)
+    }
+}
+
+/// Reads the messages of the mbox file at `path`, with `>From ` quoted
+/// lines unquoted, and maps each of them with `f`.
+fn map_messages<'a>(
+    path: &str,
+    max_messages: Option<usize>,
+    mut f: impl FnMut(Vec<u8>) -> Term<'a>,
+) -> NifResult<Vec<Term<'a>>> {
+    let file = File::open(path).map_err(|error| io_error(&error, path))?;
+
+    MessageIterator::new(file)
+        .take(max_messages.unwrap_or(usize::MAX))
+        .map(|message| match message {
+            Ok(message) => Ok(f(message.unwrap_contents())),
+            Err(_) => Err(read_error(path)),
+        })
+        .collect()
+}
+
+/// Copies a message into a binary, so that its attachments can reference it
+/// as sub-binaries as they do for `extract_nested_attachments/2`.
+fn to_binary<'a>(env: Env<'a>, raw_message: &[u8]) -> Binary<'a> {
+    let mut binary = NewBinary::new(env, raw_message.len());
+    binary.as_mut_slice().copy_from_slice(raw_message);
+    binary.into()
+}
+
+fn io_error(error: &io::Error, path: &str) -> Error {
+    ErrorReason::io(error, Path::new(path)).into()
+}
+
+/// The mbox reader does not report the cause of read errors.
+fn read_error(path: &str) -> Error {
+    ErrorReason::Io(atoms::eio(), path.to_string()).into()
+}
+
+fn parse_error(env: Env, reason: ErrorReason) -> Term {
+    (atoms::error(), reason).encode(env)
+}
+
+/// Parses a message of the mailbox, the parsed message borrowing its bodies
+/// from what was read, and failing if the bodies it had to decode exceed
+/// `max_memory_bytes`.
+fn parse_raw_message<'a>(env: Env<'a>, raw_message: Vec<u8>, options: &Options) -> Term<'a> {
+    let parse_options = ParseOptions {
+        lenient: false,
+        max_memory_bytes: options.max_memory_bytes,
+    };
+
+    match parse_buffer(raw_message, &parse_options) {
+        Ok(parsed) => encode_parsed(env, parsed),
+        Err(reason) => parse_error(env, reason),
+    }
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn parse_mbox<'a>(
+    env: Env<'a>,
+    path: String,
+    mbox_options: MboxOptions,
+) -> NifResult<(Atom, Vec<Term<'a>>)> {
+    let messages = map_messages(&path, mbox_options.max_messages, |raw_message| {
+        parse_raw_message(env, raw_message, &mbox_options.options)
+    })?;
+
+    Ok((atoms::ok(), messages))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn extract_attachments_from_mbox<'a>(
+    env: Env<'a>,
+    path: String,
+    mbox_options: MboxOptions,
+) -> NifResult<(Atom, Vec<Term<'a>>)> {
+    let results = map_messages(&path, mbox_options.max_messages, |raw_message| {
+        let raw_message = to_binary(env, &raw_message);
+
+        let message = match parse_message(raw_message.as_slice()) {
+            Ok(message) => message,
+            Err(reason) => return parse_error(env, reason),
+        };
+
+        let source = Source::Binary(raw_message);
+
+        match get_attachments(env, &source, &message, &mbox_options.options) {
+            Ok(attachments) => (atoms::ok(), attachments).encode(env),
+            Err(limit_exceeded) => (atoms::error(), limit_exceeded).encode(env),
+        }
+    })?;
+
+    Ok((atoms::ok(), results))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn mbox_open(path: String) -> NifResult<(Atom, ResourceArc<MboxResource>)> {
+    let file = File::open(&path).map_err(|error| io_error(&error, &path))?;
+
+    let resource = MboxResource {
+        path,
+        messages: Mutex::new(Some(MessageIterator::new(file))),
+    };
+
+    Ok((atoms::ok(), ResourceArc::new(resource)))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn mbox_next<'a>(
+    env: Env<'a>,
+    resource: ResourceArc<MboxResource>,
+    next_options: NextOptions,
+) -> NifResult<Term<'a>> {
+    let mut messages = resource.messages.lock().map_err(|_| Error::BadArg)?;
+    let Some(messages) = messages.as_mut() else {
+        return Ok(atoms::eof().encode(env));
+    };
+
+    let raw_message = match messages.next() {
+        Some(Ok(message)) => message.unwrap_contents(),
+        Some(Err(_)) => return Err(read_error(&resource.path)),
+        None => return Ok(atoms::eof().encode(env)),
+    };
+
+    if !next_options.attachments {
+        return Ok(parse_raw_message(env, raw_message, &next_options.options));
+    }
+
+    let raw_message = to_binary(env, &raw_message);
+
+    let message = match parse_message(raw_message.as_slice()) {
+        Ok(message) => message,
+        Err(reason) => return Ok(parse_error(env, reason)),
+    };
+
+    let source = Source::Binary(raw_message);
+    let attachments = get_attachments(env, &source, &message, &next_options.options)?;
+
+    Ok((atoms::ok(), attachments).encode(env))
+}
+
+#[rustler::nif(schedule = "DirtyIo")]
+fn mbox_close(resource: ResourceArc<MboxResource>) -> Atom {
+    if let Ok(mut messages) = resource.messages.lock() {
+        messages.take();
+    }
+
+    atoms::ok()
+}
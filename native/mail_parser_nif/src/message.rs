@@ -0,0 +1,302 @@
+use std::borrow::Cow;
+
+use mail_parser::{Message, MessagePart, PartType};
+use rustler::{Atom, Decoder, Encoder, Env, Error, NifResult, ResourceArc, Term};
+
+use crate::atoms;
+use crate::attachment::{get_attachments, Attachment, Source};
+use crate::budget::{decoded_size, Budget};
+use crate::decompress::{decompressed_text, BodyOptions};
+use crate::error::{parse_message, ErrorReason};
+use crate::iodata::RawMessage;
+use crate::options::Options;
+use crate::sanitize::{sanitize_html, HtmlOptions};
+use crate::structure::part_at;
+use crate::warning::{warnings, Warning, WarningReason};
+
+/// A parsed message kept alive on the Rust side so that it can be queried
+/// repeatedly without parsing the raw message again.
+pub struct MessageResource {
+    storage: Storage,
+}
+
+/// Where the contents of a parsed message are kept.
+enum Storage {
+    /// A message owning all of its contents.
+    Owned(Message<'static>),
+    /// A message borrowing its contents from the raw message it was parsed
+    /// from, which is declared after it so that it is dropped last.
+    Borrowed {
+        message: Message<'static>,
+        _raw_message: Vec<u8>,
+    },
+    /// A message attached to the message of another resource, held by the
+    /// part at `path`, as given to `part_at/2`.
+    Nested {
+        parent: ResourceArc<MessageResource>,
+        path: Vec<usize>,
+    },
+}
+
+impl MessageResource {
+    /// Wraps a message owning all of its contents.
+    pub fn new(message: Message<'static>) -> Self {
+        MessageResource {
+            storage: Storage::Owned(message),
+        }
+    }
+
+    /// Wraps the message attached to the message of `parent` in the part at
+    /// `path`, referencing it rather than copying it, if that part holds a
+    /// message.
+    pub fn nested(parent: ResourceArc<MessageResource>, path: Vec<usize>) -> Option<Self> {
+        nested_message(parent.message(), &path)?;
+
+        Some(MessageResource {
+            storage: Storage::Nested { parent, path },
+        })
+    }
+
+    pub fn message(&self) -> &Message<'static> {
+        match &self.storage {
+            Storage::Owned(message) | Storage::Borrowed { message, .. } => message,
+            Storage::Nested { parent, path } => nested_message(parent.message(), path)
+                .expect("the part of a nested message holds it"),
+        }
+    }
+}
+
+/// Returns the message attached in the part at `path` of a message, if that
+/// part holds one.
+fn nested_message<'m>(
+    message: &'m Message<'static>,
+    path: &[usize],
+) -> Option<&'m Message<'static>> {
+    match &part_at(message, path)?.1.body {
+        PartType::Message(nested_message) => Some(nested_message),
+        _ => None,
+    }
+}
+
+/// Options accepted by `parse/2`, decoded from a keyword list.
+#[derive(Default)]
+pub struct ParseOptions {
+    /// Whether whatever could be parsed of a damaged message is returned
+    /// along with the problems found in it.
+    pub lenient: bool,
+    /// Maximum size of the decoded bodies of the parsed message, in bytes.
+    pub max_memory_bytes: Option<usize>,
+}
+
+/// A parsed message, along with the problems found in it when parsed
+/// leniently.
+pub type Parsed = (MessageResource, Option<Vec<Warning>>);
+
+impl<'a> Decoder<'a> for ParseOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut parse_options = ParseOptions::default();
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::lenient() {
+                parse_options.lenient = value.decode()?;
+            } else if key == atoms::max_memory_bytes() {
+                parse_options.max_memory_bytes = Some(value.decode()?);
+            } else {
+                return Err(Error::BadArg);
+            }
+        }
+
+        Ok(parse_options)
+    }
+}
+
+/// Parses a message leniently, taking input without any header as the body
+/// of a message without headers.
+fn parse_lenient(raw_message: &[u8]) -> Result<(Message<'_>, Vec<Warning>), ErrorReason> {
+    match parse_message(raw_message) {
+        Ok(message) => {
+            let warnings = warnings(&message);
+            return Ok((message, warnings));
+        }
+        Err(ErrorReason::NotAMessage) => (),
+        Err(reason) => return Err(reason),
+    }
+
+    // A leading blank line ends an empty header block.
+    let mut headerless_message = Vec::with_capacity(raw_message.len() + 1);
+    headerless_message.push(b'\n');
+    headerless_message.extend_from_slice(raw_message);
+
+    let message = parse_message(&headerless_message)?;
+    let mut warnings = warnings(&message);
+    warnings.insert(0, Warning::new(WarningReason::MissingHeaders, Vec::new()));
+
+    Ok((message.into_owned(), warnings))
+}
+
+/// Parses a raw message as `parse/2` does, failing if the bodies of the
+/// parsed message, all copied into it, exceed `max_memory_bytes`.
+pub fn parse_owned(
+    raw_message: &[u8],
+    parse_options: &ParseOptions,
+) -> Result<Parsed, ErrorReason> {
+    let (message, warnings) = parse_with_options(raw_message, parse_options)?;
+    let message = message.into_owned();
+
+    let mut budget = Budget::new(parse_options.max_memory_bytes);
+    budget.charge(decoded_size(&message))?;
+
+    Ok((MessageResource::new(message), warnings))
+}
+
+/// Parses a raw message as `parse/2` does, keeping the raw message as the
+/// storage of the parsed message rather than copying its bodies, so that
+/// only the bodies that had to be decoded count towards
+/// `max_memory_bytes`.
+pub fn parse_buffer(
+    raw_message: Vec<u8>,
+    parse_options: &ParseOptions,
+) -> Result<Parsed, ErrorReason> {
+    // SAFETY: the heap buffer of `raw_message` is never written to and is
+    // not moved when `raw_message` is, and it is dropped after the message
+    // borrowing from it.
+    let bytes: &'static [u8] = unsafe { &*(raw_message.as_slice() as *const [u8]) };
+    let (message, warnings) = parse_with_options(bytes, parse_options)?;
+
+    let mut budget = Budget::new(parse_options.max_memory_bytes);
+    budget.charge(decoded_size(&message))?;
+
+    let resource = MessageResource {
+        storage: Storage::Borrowed {
+            message,
+            _raw_message: raw_message,
+        },
+    };
+
+    Ok((resource, warnings))
+}
+
+/// Parses a raw message, leniently if set by the options, in which case the
+/// problems found in it are returned too.
+fn parse_with_options<'x>(
+    raw_message: &'x [u8],
+    parse_options: &ParseOptions,
+) -> Result<(Message<'x>, Option<Vec<Warning>>), ErrorReason> {
+    match parse_options.lenient {
+        true => {
+            let (message, warnings) = parse_lenient(raw_message)?;
+            Ok((message, Some(warnings)))
+        }
+        false => Ok((parse_message(raw_message)?, None)),
+    }
+}
+
+/// Returns `{:ok, message}`, or `{:ok, message, warnings}` for a message
+/// parsed leniently.
+pub fn encode_parsed(env: Env, (resource, warnings): Parsed) -> Term {
+    let resource = ResourceArc::new(resource);
+
+    match warnings {
+        Some(warnings) => (atoms::ok(), resource, warnings).encode(env),
+        None => (atoms::ok(), resource).encode(env),
+    }
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse<'a>(
+    env: Env<'a>,
+    raw_message: RawMessage,
+    parse_options: ParseOptions,
+) -> NifResult<Term<'a>> {
+    let parsed = parse_owned(raw_message.as_slice(), &parse_options)?;
+    Ok(encode_parsed(env, parsed))
+}
+
+#[rustler::nif]
+fn get_subject(resource: ResourceArc<MessageResource>) -> Option<String> {
+    resource.message().subject().map(str::to_string)
+}
+
+#[rustler::nif(name = "get_attachments", schedule = "DirtyCpu")]
+fn get_message_attachments(
+    env: Env,
+    resource: ResourceArc<MessageResource>,
+    options: Options,
+) -> NifResult<Vec<Attachment>> {
+    let source = Source::Resource(resource.clone());
+    Ok(get_attachments(env, &source, resource.message(), &options)?)
+}
+
+#[rustler::nif]
+fn get_body(resource: ResourceArc<MessageResource>) -> Option<String> {
+    resource
+        .message()
+        .body_text(0)
+        .map(|body| body.into_owned())
+}
+
+/// Returns the preferred text/plain body part of a message and its text,
+/// if any.
+fn text_body_part<'m>(message: &'m Message) -> Option<(&'m MessagePart<'m>, &'m str)> {
+    message.text_body.iter().find_map(|&index| {
+        let part = &message.parts[index];
+
+        match &part.body {
+            PartType::Text(text) => Some((part, text.as_ref())),
+            _ => None,
+        }
+    })
+}
+
+/// Returns the preferred text/html body part of a message and its HTML, if
+/// any.
+fn html_body_part<'m>(message: &'m Message) -> Option<(&'m MessagePart<'m>, &'m str)> {
+    message.html_body.iter().find_map(|&index| {
+        let part = &message.parts[index];
+
+        match &part.body {
+            PartType::Html(html) => Some((part, html.as_ref())),
+            _ => None,
+        }
+    })
+}
+
+/// Returns the preferred text/plain body of a message, if any.
+pub fn text_body<'m>(message: &'m Message) -> Option<&'m str> {
+    text_body_part(message).map(|(_, text)| text)
+}
+
+/// Returns the preferred text/html body of a message, if any.
+pub fn html_body<'m>(message: &'m Message) -> Option<&'m str> {
+    html_body_part(message).map(|(_, html)| html)
+}
+
+#[rustler::nif]
+fn get_text_body(
+    resource: ResourceArc<MessageResource>,
+    body_options: BodyOptions,
+) -> Option<String> {
+    let (part, text) = text_body_part(resource.message())?;
+
+    Some(
+        decompressed_text(resource.message(), part, &body_options)
+            .unwrap_or_else(|| text.to_string()),
+    )
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_html_body(
+    resource: ResourceArc<MessageResource>,
+    html_options: HtmlOptions,
+) -> Option<String> {
+    let (part, html) = html_body_part(resource.message())?;
+    let html = match decompressed_text(resource.message(), part, &html_options.body_options) {
+        Some(html) => Cow::Owned(html),
+        None => Cow::Borrowed(html),
+    };
+
+    Some(match html_options.sanitize {
+        true => sanitize_html(&html, &html_options.remote_images),
+        false => html.into_owned(),
+    })
+}
@@ -0,0 +1,60 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use rustler::NifMap;
+
+static PARSES: AtomicU64 = AtomicU64::new(0);
+static PARSE_DURATION: AtomicU64 = AtomicU64::new(0);
+static BYTES_PARSED: AtomicU64 = AtomicU64::new(0);
+static ATTACHMENTS_DECODED: AtomicU64 = AtomicU64::new(0);
+static BYTES_WRITTEN: AtomicU64 = AtomicU64::new(0);
+
+/// Counters of the work done by the NIFs since the library was loaded,
+/// returned by `get_metrics/0`. Counters only ever grow, so that callers
+/// measure a call, or a period, from the difference of two readings.
+#[derive(NifMap)]
+pub struct Metrics {
+    /// Number of raw messages parsed.
+    parses: u64,
+    /// Time spent parsing raw messages, in nanoseconds.
+    parse_duration: u64,
+    /// Size of the raw messages parsed.
+    bytes_parsed: u64,
+    /// Number of attachments selected for extraction.
+    attachments_decoded: u64,
+    /// Bytes written to files by the disk extraction.
+    bytes_written: u64,
+}
+
+fn add(counter: &AtomicU64, value: u64) {
+    counter.fetch_add(value, Ordering::Relaxed);
+}
+
+/// Records the parse of a raw message of `size` bytes.
+pub fn record_parse(size: usize, duration: Duration) {
+    add(&PARSES, 1);
+    add(
+        &PARSE_DURATION,
+        u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX),
+    );
+    add(&BYTES_PARSED, size as u64);
+}
+
+pub fn record_attachments(count: usize) {
+    add(&ATTACHMENTS_DECODED, count as u64);
+}
+
+pub fn record_write(size: usize) {
+    add(&BYTES_WRITTEN, size as u64);
+}
+
+#[rustler::nif]
+fn get_metrics() -> Metrics {
+    Metrics {
+        parses: PARSES.load(Ordering::Relaxed),
+        parse_duration: PARSE_DURATION.load(Ordering::Relaxed),
+        bytes_parsed: BYTES_PARSED.load(Ordering::Relaxed),
+        attachments_decoded: ATTACHMENTS_DECODED.load(Ordering::Relaxed),
+        bytes_written: BYTES_WRITTEN.load(Ordering::Relaxed),
+    }
+}
@@ -0,0 +1,390 @@
+use rustler::{Atom, Decoder, Error, NifResult, NifUnitEnum, Term};
+
+use crate::atoms;
+use crate::attachment::{AttachmentPart, Disposition};
+use crate::decompress::DEFAULT_MAX_DECOMPRESSED_SIZE;
+use crate::digest::DigestAlgorithm;
+
+/// Default for `max_depth`, deep enough for long forwarding chains.
+const DEFAULT_MAX_DEPTH: usize = 10;
+
+/// Default for `max_archive_entry_size`: 64 MiB.
+const DEFAULT_MAX_ARCHIVE_ENTRY_SIZE: usize = 64 << 20;
+
+/// Default for `max_archive_size`: 256 MiB.
+const DEFAULT_MAX_ARCHIVE_SIZE: usize = 256 << 20;
+
+/// Options accepted by the attachment extraction NIFs, decoded from a
+/// keyword list.
+pub struct Options {
+    /// Whether inline parts are returned along with regular attachments.
+    pub include_inline: bool,
+    /// Content types an attachment must match, if given.
+    pub mime_types: Option<Vec<MimeTypePattern>>,
+    /// Lowercased extensions, with their leading dot, the name of an
+    /// attachment must end with, if given.
+    pub extensions: Option<Vec<String>>,
+    /// Glob pattern the name of an attachment must match, if given.
+    pub name_glob: Option<String>,
+    /// Minimum decoded size of returned attachments, in bytes. Smaller ones
+    /// are left out.
+    pub min_size: Option<usize>,
+    /// Maximum decoded size of returned attachments, in bytes. Larger ones
+    /// are left out, unlike with `max_attachment_size`.
+    pub max_size: Option<usize>,
+    /// Maximum decoded size of a single attachment, in bytes.
+    pub max_attachment_size: Option<usize>,
+    /// Maximum decoded size of all returned attachments, in bytes.
+    pub max_total_size: Option<usize>,
+    /// Maximum number of returned attachments.
+    pub max_attachments: Option<usize>,
+    /// Maximum number of nested messages to descend into.
+    pub max_depth: usize,
+    /// What to do with attachments exceeding any of the limits above.
+    pub on_limit: OnLimit,
+    /// How attached messages are returned.
+    pub nested: Nested,
+    /// Digests computed for every returned attachment.
+    pub digests: Vec<DigestAlgorithm>,
+    /// Whether attachments identical to an earlier one are left out.
+    pub dedupe: bool,
+    /// Whether TNEF parts are replaced by the files they contain.
+    pub expand_tnef: bool,
+    /// Whether files uuencoded in text bodies are returned as attachments.
+    pub uudecode: bool,
+    /// Whether ZIP, gzip and tar attachments are replaced by the files they
+    /// contain.
+    pub expand_archives: bool,
+    /// Maximum decompressed size of a file unpacked from an archive, in
+    /// bytes.
+    pub max_archive_entry_size: usize,
+    /// Maximum decompressed size of all the files unpacked from an archive,
+    /// in bytes.
+    pub max_archive_size: usize,
+    /// Which content type of an attachment `mime_types` is matched against.
+    pub match_content_type: ContentTypeSource,
+    /// How attachments without a name are named.
+    pub name_fallback: NameFallback,
+    /// Maximum number of bytes decoded by the call, in message bodies and
+    /// in the files unpacked from archives, TNEF parts, uuencoded text and
+    /// opaque-signed parts.
+    pub max_memory_bytes: Option<usize>,
+    /// Whether the headers of the part of each attachment are returned.
+    pub include_part_headers: bool,
+    /// Whether attachments compressed by a gateway are decompressed.
+    pub decompress: bool,
+    /// Maximum decompressed size of an attachment, in bytes.
+    pub max_decompressed_size: usize,
+}
+
+#[derive(Clone, Copy, NifUnitEnum)]
+pub enum OnLimit {
+    /// Abort the extraction with `{:error, :limit_exceeded}`.
+    Error,
+    /// Leave out the attachments that exceed a limit.
+    Skip,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, NifUnitEnum)]
+pub enum ContentTypeSource {
+    /// The content type declared by the Content-Type header.
+    Declared,
+    /// The content type detected from the contents of the attachment,
+    /// falling back to the declared one.
+    Detected,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, NifUnitEnum)]
+pub enum Nested {
+    /// Return the attachments of attached messages, but not the messages.
+    Flatten,
+    /// Return attached messages as `.eml` attachments without descending
+    /// into them.
+    Keep,
+    /// Return both attached messages and their attachments.
+    Both,
+}
+
+/// How an attachment without a name is named. Unless noted otherwise, the
+/// name ends with the extension of the detected or declared content type,
+/// when known.
+#[derive(Clone, PartialEq, Eq)]
+pub enum NameFallback {
+    /// `"untitled"`, or `"untitled.eml"` for attached messages.
+    Untitled,
+    /// `"untitled"`, e.g. `"untitled.pdf"`.
+    Extension,
+    /// The Content-ID of the attachment up to its `@`, e.g. `"image001.png"`
+    /// for `<image001.png@01D9C3A1>`, with an extension only if it has none.
+    /// Attachments without a Content-ID are named as with `Extension`.
+    ContentId,
+    /// The pattern with `%d` replaced by the position of the attachment
+    /// among the returned ones, starting at 1, e.g. `"attachment-3.pdf"`.
+    Indexed(String),
+}
+
+impl<'a> Decoder<'a> for NameFallback {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        if let Ok((tag, pattern)) = term.decode::<(Atom, String)>() {
+            return match tag == atoms::indexed() {
+                true => Ok(NameFallback::Indexed(pattern)),
+                false => Err(Error::BadArg),
+            };
+        }
+
+        let strategy: Atom = term.decode()?;
+
+        if strategy == atoms::untitled() {
+            Ok(NameFallback::Untitled)
+        } else if strategy == atoms::extension() {
+            Ok(NameFallback::Extension)
+        } else if strategy == atoms::content_id() {
+            Ok(NameFallback::ContentId)
+        } else {
+            Err(Error::BadArg)
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            include_inline: true,
+            mime_types: None,
+            extensions: None,
+            name_glob: None,
+            min_size: None,
+            max_size: None,
+            max_attachment_size: None,
+            max_total_size: None,
+            max_attachments: None,
+            max_depth: DEFAULT_MAX_DEPTH,
+            on_limit: OnLimit::Error,
+            nested: Nested::Flatten,
+            digests: Vec::new(),
+            dedupe: false,
+            expand_tnef: false,
+            uudecode: false,
+            expand_archives: false,
+            max_archive_entry_size: DEFAULT_MAX_ARCHIVE_ENTRY_SIZE,
+            max_archive_size: DEFAULT_MAX_ARCHIVE_SIZE,
+            match_content_type: ContentTypeSource::Declared,
+            name_fallback: NameFallback::Untitled,
+            max_memory_bytes: None,
+            include_part_headers: false,
+            decompress: false,
+            max_decompressed_size: DEFAULT_MAX_DECOMPRESSED_SIZE,
+        }
+    }
+}
+
+impl Options {
+    /// Returns whether an attachment passes all filters.
+    pub fn accepts(&self, attachment: &AttachmentPart) -> bool {
+        (self.include_inline || attachment.disposition() != Disposition::Inline)
+            && self.accepts_mime_type(attachment)
+            && self.accepts_name(attachment.name())
+            && self.accepts_size(attachment.contents().len())
+    }
+
+    /// Returns whether adding an attachment of `size` bytes to `count`
+    /// attachments totalling `total_size` bytes exceeds any of the limits.
+    pub fn exceeds_limits(&self, count: usize, size: usize, total_size: usize) -> bool {
+        self.max_attachments.is_some_and(|max| count >= max)
+            || self.max_attachment_size.is_some_and(|max| size > max)
+            || self
+                .max_total_size
+                .is_some_and(|max| total_size.saturating_add(size) > max)
+    }
+
+    fn accepts_mime_type(&self, attachment: &AttachmentPart) -> bool {
+        let content_type = match self.match_content_type {
+            ContentTypeSource::Detected => attachment
+                .detected_content_type()
+                .map(str::to_string)
+                .or_else(|| attachment.content_type()),
+            ContentTypeSource::Declared => attachment.content_type(),
+        };
+
+        match (&self.mime_types, content_type) {
+            (None, _) => true,
+            (Some(patterns), Some(content_type)) => {
+                let (ctype, subtype) = match content_type.split_once('/') {
+                    Some((ctype, subtype)) => (ctype, Some(subtype)),
+                    None => (content_type.as_str(), None),
+                };
+
+                patterns
+                    .iter()
+                    .any(|pattern| pattern.matches(ctype, subtype))
+            }
+            (Some(_), None) => false,
+        }
+    }
+
+    fn accepts_size(&self, size: usize) -> bool {
+        !(self.min_size.is_some_and(|min| size < min)
+            || self.max_size.is_some_and(|max| size > max))
+    }
+
+    fn accepts_name(&self, name: &str) -> bool {
+        let accepts_extension = match &self.extensions {
+            Some(extensions) => {
+                let name = name.to_lowercase();
+                extensions
+                    .iter()
+                    .any(|extension| name.ends_with(extension.as_str()))
+            }
+            None => true,
+        };
+
+        accepts_extension
+            && match &self.name_glob {
+                Some(pattern) => glob_matches(pattern, name),
+                None => true,
+            }
+    }
+}
+
+/// Returns whether `name` matches a glob pattern, case-insensitively, where
+/// `*` matches any sequence of characters and `?` any single character.
+fn glob_matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    let name: Vec<char> = name.chars().flat_map(char::to_lowercase).collect();
+
+    let (mut pattern_pos, mut name_pos) = (0, 0);
+    // Position of the last `*` and of the name character it was tried at,
+    // to backtrack to when the rest of the pattern does not match.
+    let mut backtrack = None;
+
+    while name_pos < name.len() {
+        match pattern.get(pattern_pos) {
+            Some('*') => {
+                backtrack = Some((pattern_pos, name_pos));
+                pattern_pos += 1;
+            }
+            Some(&ch) if ch == '?' || ch == name[name_pos] => {
+                pattern_pos += 1;
+                name_pos += 1;
+            }
+            _ => match backtrack {
+                Some((star_pos, star_name_pos)) => {
+                    backtrack = Some((star_pos, star_name_pos + 1));
+                    pattern_pos = star_pos + 1;
+                    name_pos = star_name_pos + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[pattern_pos..].iter().all(|&ch| ch == '*')
+}
+
+/// A `type/subtype` pattern where either side may be a `*` wildcard.
+///
+/// A pattern without a subtype, such as `"image"`, matches any subtype.
+pub struct MimeTypePattern {
+    ctype: String,
+    subtype: String,
+}
+
+impl MimeTypePattern {
+    fn new(pattern: &str) -> Self {
+        let (ctype, subtype) = pattern.split_once('/').unwrap_or((pattern, "*"));
+
+        MimeTypePattern {
+            ctype: ctype.trim().to_string(),
+            subtype: subtype.trim().to_string(),
+        }
+    }
+
+    fn matches(&self, ctype: &str, subtype: Option<&str>) -> bool {
+        let matches_ctype = self.ctype == "*" || self.ctype.eq_ignore_ascii_case(ctype);
+        let matches_subtype = self.subtype == "*"
+            || subtype.is_some_and(|subtype| self.subtype.eq_ignore_ascii_case(subtype));
+
+        matches_ctype && matches_subtype
+    }
+}
+
+impl Options {
+    /// Sets the option `key` from its keyword list value, failing with
+    /// `BadArg` for unknown options.
+    pub fn set(&mut self, key: Atom, value: Term) -> NifResult<()> {
+        if key == atoms::include_inline() {
+            self.include_inline = value.decode()?;
+        } else if key == atoms::mime_types() {
+            let patterns: Vec<String> = value.decode()?;
+            let patterns = patterns.iter().map(|p| MimeTypePattern::new(p)).collect();
+            self.mime_types = Some(patterns);
+        } else if key == atoms::extensions() {
+            let extensions: Vec<String> = value.decode()?;
+            let extensions = extensions
+                .iter()
+                .map(|extension| format!(".{}", extension.trim_start_matches('.').to_lowercase()))
+                .collect();
+            self.extensions = Some(extensions);
+        } else if key == atoms::name_glob() {
+            self.name_glob = Some(value.decode()?);
+        } else if key == atoms::min_size() {
+            self.min_size = Some(value.decode()?);
+        } else if key == atoms::max_size() {
+            self.max_size = Some(value.decode()?);
+        } else if key == atoms::max_attachment_size() {
+            self.max_attachment_size = Some(value.decode()?);
+        } else if key == atoms::max_total_size() {
+            self.max_total_size = Some(value.decode()?);
+        } else if key == atoms::max_attachments() {
+            self.max_attachments = Some(value.decode()?);
+        } else if key == atoms::max_depth() {
+            self.max_depth = value.decode()?;
+        } else if key == atoms::on_limit() {
+            self.on_limit = value.decode()?;
+        } else if key == atoms::nested() {
+            self.nested = value.decode()?;
+        } else if key == atoms::digests() {
+            self.digests = value.decode()?;
+        } else if key == atoms::dedupe() {
+            self.dedupe = value.decode()?;
+        } else if key == atoms::expand_tnef() {
+            self.expand_tnef = value.decode()?;
+        } else if key == atoms::uudecode() {
+            self.uudecode = value.decode()?;
+        } else if key == atoms::expand_archives() {
+            self.expand_archives = value.decode()?;
+        } else if key == atoms::max_archive_entry_size() {
+            self.max_archive_entry_size = value.decode()?;
+        } else if key == atoms::max_archive_size() {
+            self.max_archive_size = value.decode()?;
+        } else if key == atoms::match_content_type() {
+            self.match_content_type = value.decode()?;
+        } else if key == atoms::name_fallback() {
+            self.name_fallback = value.decode()?;
+        } else if key == atoms::max_memory_bytes() {
+            self.max_memory_bytes = Some(value.decode()?);
+        } else if key == atoms::include_part_headers() {
+            self.include_part_headers = value.decode()?;
+        } else if key == atoms::decompress() {
+            self.decompress = value.decode()?;
+        } else if key == atoms::max_decompressed_size() {
+            self.max_decompressed_size = value.decode()?;
+        } else {
+            return Err(Error::BadArg);
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Decoder<'a> for Options {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut options = Options::default();
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            options.set(key, value)?;
+        }
+
+        Ok(options)
+    }
+}
@@ -0,0 +1,193 @@
+use std::path::{Component, Path};
+
+use mail_parser::DateTime;
+use rustler::{Decoder, Error, NifResult, Term};
+
+/// A segment of a path template, either literal text or a placeholder.
+enum Segment {
+    Literal(String),
+    /// `{name}`: the sanitized attachment name.
+    Name,
+    /// `{stem}`: the sanitized attachment name without its extension.
+    Stem,
+    /// `{ext}`: the extension of the sanitized attachment name, dot
+    /// included, if any.
+    Ext,
+    /// `{index}`: the position of the attachment in the extraction,
+    /// starting from 0.
+    Index,
+    /// `{message_id}`: the sanitized Message-ID of the message.
+    MessageId,
+    /// `{sha256}`: the hex encoded SHA-256 digest of the attachment.
+    Sha256,
+    /// `{date:format}`: the Date of the message in UTC, formatted with the
+    /// `%Y`, `%m`, `%d`, `%H`, `%M` and `%S` directives.
+    Date(String),
+}
+
+/// The layout of the files written by `extract_attachments_to_disk/3`, as
+/// set by `path_template`, relative to the directory they are written to.
+pub struct PathTemplate {
+    segments: Vec<Segment>,
+}
+
+/// The values of the placeholders of a path template for an attachment,
+/// already safe to use in a file name.
+pub struct Fields<'a> {
+    pub name: &'a str,
+    pub index: usize,
+    pub message_id: &'a str,
+    pub sha256: &'a str,
+    pub date: Option<&'a DateTime>,
+}
+
+/// Value of the placeholders whose value is missing from the message.
+pub const UNKNOWN: &str = "unknown";
+
+impl Default for PathTemplate {
+    fn default() -> Self {
+        PathTemplate {
+            segments: vec![Segment::Name],
+        }
+    }
+}
+
+impl<'a> Decoder<'a> for PathTemplate {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let template = PathTemplate::parse(&term.decode::<String>()?).ok_or(Error::BadArg)?;
+
+        // Placeholders never expand to separators or `..`, so a template
+        // whose own components are safe always renders to a safe path.
+        let fields = Fields {
+            name: "name.ext",
+            index: 0,
+            message_id: "id",
+            sha256: "0",
+            date: Some(&DateTime::from_timestamp(0)),
+        };
+
+        match is_relative(&template.render(&fields)) {
+            true => Ok(template),
+            false => Err(Error::BadArg),
+        }
+    }
+}
+
+impl PathTemplate {
+    fn parse(template: &str) -> Option<Self> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with('}') {
+                return None;
+            }
+
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+
+            let end = start + rest[start..].find('}')?;
+            let placeholder = &rest[start + 1..end];
+
+            segments.push(match placeholder.split_once(':') {
+                Some(("date", format)) if valid_date_format(format) => {
+                    Segment::Date(format.to_string())
+                }
+                Some(_) => return None,
+                None => match placeholder {
+                    "name" => Segment::Name,
+                    "stem" => Segment::Stem,
+                    "ext" => Segment::Ext,
+                    "index" => Segment::Index,
+                    "message_id" => Segment::MessageId,
+                    "sha256" => Segment::Sha256,
+                    "date" => Segment::Date("%Y-%m-%d".to_string()),
+                    _ => return None,
+                },
+            });
+
+            rest = &rest[end + 1..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+
+        Some(PathTemplate { segments })
+    }
+
+    /// Returns the path of an attachment, relative to the directory it is
+    /// written to.
+    pub fn render(&self, fields: &Fields) -> String {
+        let (stem, ext) = match fields.name.rfind('.') {
+            Some(pos) if pos > 0 => fields.name.split_at(pos),
+            _ => (fields.name, ""),
+        };
+
+        let mut path = String::new();
+
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(text) => path.push_str(text),
+                Segment::Name => path.push_str(fields.name),
+                Segment::Stem => path.push_str(stem),
+                Segment::Ext => path.push_str(ext),
+                Segment::Index => path.push_str(&fields.index.to_string()),
+                Segment::MessageId => path.push_str(fields.message_id),
+                Segment::Sha256 => path.push_str(fields.sha256),
+                Segment::Date(format) => match fields.date {
+                    Some(date) => path.push_str(&format_date(date, format)),
+                    None => path.push_str(UNKNOWN),
+                },
+            }
+        }
+
+        path
+    }
+}
+
+/// Returns whether a path is made of file names only, with no root, prefix,
+/// `.` or `..` component.
+pub fn is_relative(path: &str) -> bool {
+    let mut components = Path::new(path).components().peekable();
+
+    components.peek().is_some()
+        && components.all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn valid_date_format(format: &str) -> bool {
+    let mut chars = format.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch == '%' && !matches!(chars.next(), Some('Y' | 'm' | 'd' | 'H' | 'M' | 'S' | '%')) {
+            return false;
+        }
+    }
+
+    true
+}
+
+fn format_date(date: &DateTime, format: &str) -> String {
+    let mut formatted = String::new();
+    let mut chars = format.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            formatted.push(ch);
+            continue;
+        }
+
+        match chars.next() {
+            Some('Y') => formatted.push_str(&format!("{:04}", date.year)),
+            Some('m') => formatted.push_str(&format!("{:02}", date.month)),
+            Some('d') => formatted.push_str(&format!("{:02}", date.day)),
+            Some('H') => formatted.push_str(&format!("{:02}", date.hour)),
+            Some('M') => formatted.push_str(&format!("{:02}", date.minute)),
+            Some('S') => formatted.push_str(&format!("{:02}", date.second)),
+            _ => formatted.push('%'),
+        }
+    }
+
+    formatted
+}
@@ -0,0 +1,211 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use rustler::{NifMap, ResourceArc};
+
+use crate::date::parse_date;
+use crate::header::unfolded_values;
+use crate::message::MessageResource;
+
+#[derive(NifMap)]
+pub struct ReceivedChain {
+    hops: Vec<Hop>,
+    originating_ip: Option<String>,
+}
+
+/// A Received header, e.g. `from mail.example.com (mail.example.com
+/// [192.0.2.1]) by mx.example.net with ESMTPS; Tue, 4 Jun 2024 10:00:00
+/// +0000`.
+#[derive(NifMap)]
+pub struct Hop {
+    from_host: Option<String>,
+    from_ip: Option<String>,
+    by_host: Option<String>,
+    protocol: Option<String>,
+    timestamp: Option<i64>,
+}
+
+/// A word or a parenthesized comment of the clauses of a Received header.
+enum Token<'v> {
+    Word(&'v str),
+    Comment(&'v str),
+}
+
+/// Splits the clauses of a Received header into words and top level
+/// comments, keeping the comments nested in them.
+fn tokens(clauses: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (pos, ch) in clauses.char_indices() {
+        match ch {
+            '(' => {
+                if depth == 0 {
+                    tokens.extend(words(&clauses[start..pos]));
+                    start = pos + 1;
+                }
+                depth += 1;
+            }
+            ')' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    tokens.push(Token::Comment(&clauses[start..pos]));
+                    start = pos + 1;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    if depth == 0 {
+        tokens.extend(words(&clauses[start..]));
+    }
+
+    tokens
+}
+
+fn words(text: &str) -> impl Iterator<Item = Token<'_>> {
+    text.split_whitespace().map(Token::Word)
+}
+
+/// Returns the first IP address of a text, either as an address literal
+/// such as `[192.0.2.1]` or `[IPv6:2001:db8::1]`, or as a bare address.
+fn ip_address(text: &str) -> Option<IpAddr> {
+    let literal = text
+        .split('[')
+        .skip(1)
+        .filter_map(|rest| rest.split_once(']'))
+        .find_map(|(literal, _)| address_literal(literal));
+
+    literal.or_else(|| {
+        text.split(|ch: char| ch.is_whitespace() || ch == '=' || ch == ',')
+            .find_map(address_literal)
+    })
+}
+
+fn address_literal(literal: &str) -> Option<IpAddr> {
+    let literal = literal.trim();
+    let literal = match literal.get(..5) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("IPv6:") => &literal[5..],
+        _ => literal,
+    };
+
+    literal.parse().ok()
+}
+
+/// Parses a Received header (RFC 5321), whose clauses are all optional.
+///
+/// The address of the `from` clause is taken from its comment, holding the
+/// address the connection actually came from, or else from the domain
+/// itself when it is an address literal.
+fn hop(value: &str) -> Hop {
+    let (clauses, date) = match value.rsplit_once(';') {
+        Some((clauses, date)) => (clauses, parse_date(date)),
+        None => (value, None),
+    };
+
+    let mut hop = Hop {
+        from_host: None,
+        from_ip: None,
+        by_host: None,
+        protocol: None,
+        timestamp: date,
+    };
+
+    let mut from_literal = None;
+    let mut from_comment_ip = None;
+    let mut in_from_clause = false;
+    let mut tokens = tokens(clauses).into_iter();
+
+    while let Some(token) = tokens.next() {
+        let keyword = match token {
+            Token::Word(word) => word.to_ascii_lowercase(),
+            Token::Comment(comment) if in_from_clause && from_comment_ip.is_none() => {
+                from_comment_ip = ip_address(comment);
+                continue;
+            }
+            Token::Comment(_) => continue,
+        };
+
+        if !matches!(
+            keyword.as_str(),
+            "from" | "by" | "via" | "with" | "id" | "for"
+        ) {
+            continue;
+        }
+
+        in_from_clause = keyword == "from";
+
+        let Some(Token::Word(value)) = tokens.next() else {
+            continue;
+        };
+
+        match keyword.as_str() {
+            "from" if hop.from_host.is_none() => {
+                from_literal = value
+                    .strip_prefix('[')
+                    .and_then(|literal| address_literal(literal.trim_end_matches(']')));
+                hop.from_host = Some(value.to_string());
+            }
+            "by" if hop.by_host.is_none() => hop.by_host = Some(value.to_string()),
+            "with" if hop.protocol.is_none() => hop.protocol = Some(value.to_string()),
+            _ => (),
+        }
+    }
+
+    hop.from_ip = from_comment_ip.or(from_literal).map(|ip| ip.to_string());
+    hop
+}
+
+/// Returns whether an address is routable on the internet, as opposed to
+/// private, loopback, link-local or shared (RFC 6598) addresses.
+fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_v4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_v4(&ip),
+            None => is_public_v6(ip),
+        },
+    }
+}
+
+fn is_public_v4(ip: &Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    let is_shared = first == 100 && (64..128).contains(&second);
+
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || is_shared)
+}
+
+fn is_public_v6(ip: &Ipv6Addr) -> bool {
+    let first_segment = ip.segments()[0];
+    let is_unique_local = first_segment & 0xFE00 == 0xFC00;
+    let is_link_local = first_segment & 0xFFC0 == 0xFE80;
+
+    !(ip.is_loopback() || ip.is_unspecified() || is_unique_local || is_link_local)
+}
+
+#[rustler::nif]
+fn get_received_chain(resource: ResourceArc<MessageResource>) -> ReceivedChain {
+    let hops: Vec<Hop> = unfolded_values(resource.message(), "Received")
+        .iter()
+        .map(|value| hop(value))
+        .collect();
+
+    // Each relay prepends its header, so the oldest hop is the last one.
+    let originating_ip = hops
+        .iter()
+        .rev()
+        .filter_map(|hop| hop.from_ip.as_ref())
+        .find(|ip| ip.parse().is_ok_and(|ip| is_public(&ip)))
+        .cloned();
+
+    ReceivedChain {
+        hops,
+        originating_ip,
+    }
+}
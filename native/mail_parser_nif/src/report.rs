@@ -0,0 +1,246 @@
+use mail_parser::{Message, MessagePart, MimeHeaders};
+use rustler::{Atom, Encoder, Env, NifMap, NifResult, NifUnitEnum, Term};
+
+use crate::atoms;
+use crate::error::{parse_message, ErrorReason};
+use crate::header::{headers, Header};
+use crate::iodata::RawMessage;
+
+#[derive(Clone, Copy, NifUnitEnum)]
+pub enum ReportType {
+    DeliveryStatus,
+    DispositionNotification,
+}
+
+#[derive(NifMap)]
+pub struct DeliveryStatus {
+    r#type: ReportType,
+    reporting_mta: Option<String>,
+    recipients: Vec<RecipientStatus>,
+    original_headers: Vec<Header>,
+}
+
+/// The delivery status of one recipient of the original message.
+#[derive(NifMap)]
+pub struct RecipientStatus {
+    final_recipient: Option<String>,
+    original_recipient: Option<String>,
+    action: Option<String>,
+    status: Option<String>,
+    diagnostic_code: Option<String>,
+    remote_mta: Option<String>,
+}
+
+/// A read receipt, or the notification that a message was deleted
+/// without being read.
+#[derive(NifMap)]
+pub struct DispositionNotification {
+    r#type: ReportType,
+    reporting_ua: Option<String>,
+    original_recipient: Option<String>,
+    final_recipient: Option<String>,
+    original_message_id: Option<String>,
+    /// Disposition type, e.g. `"displayed"` or `"deleted"`.
+    disposition: Option<String>,
+    original_headers: Vec<Header>,
+}
+
+/// A delivery status or message disposition notification.
+enum Report {
+    DeliveryStatus(DeliveryStatus),
+    DispositionNotification(DispositionNotification),
+}
+
+impl Encoder for Report {
+    fn encode<'a>(&self, env: Env<'a>) -> Term<'a> {
+        match self {
+            Report::DeliveryStatus(delivery_status) => delivery_status.encode(env),
+            Report::DispositionNotification(notification) => notification.encode(env),
+        }
+    }
+}
+
+/// The fields of a block of a report, e.g. the per-recipient fields of a
+/// delivery status, with lowercased names.
+struct Fields(Vec<(String, String)>);
+
+impl Fields {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(field_name, _)| field_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns the value of a typed field such as `rfc822; jane@example.com`
+    /// without its type.
+    fn typed(&self, name: &str) -> Option<String> {
+        let value = self.get(name)?;
+        let value = value
+            .split_once(';')
+            .map_or(value, |(_, value)| value)
+            .trim();
+        (!value.is_empty()).then(|| value.to_string())
+    }
+}
+
+/// Splits the body of a report part into its blocks of fields, which are
+/// separated by blank lines. Folded fields are unfolded.
+fn field_blocks(text: &str) -> Vec<Fields> {
+    let mut blocks = Vec::new();
+    let mut fields: Vec<(String, String)> = Vec::new();
+
+    for line in text.lines() {
+        if line.trim().is_empty() {
+            if !fields.is_empty() {
+                blocks.push(Fields(std::mem::take(&mut fields)));
+            }
+        } else if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            fields.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    if !fields.is_empty() {
+        blocks.push(Fields(fields));
+    }
+
+    blocks
+}
+
+fn is_content_type(part: &MessagePart, ctype: &str, subtypes: &[&str]) -> bool {
+    part.content_type().is_some_and(|content_type| {
+        let subtype = content_type.subtype().unwrap_or_default();
+
+        content_type.ctype().eq_ignore_ascii_case(ctype)
+            && subtypes
+                .iter()
+                .any(|expected| subtype.eq_ignore_ascii_case(expected))
+    })
+}
+
+/// Returns the headers of the original message a report refers to, found
+/// either as an attached message or as a `text/rfc822-headers` part.
+fn original_headers(message: &Message) -> Vec<Header> {
+    for part in &message.parts {
+        if let Some(original_message) = part.message() {
+            return headers(original_message, original_message.headers(), None);
+        }
+
+        if is_content_type(part, "text", &["rfc822-headers", "global-headers"]) {
+            if let Some(original_message) = Message::parse(part.contents()) {
+                return headers(&original_message, original_message.headers(), None);
+            }
+        }
+    }
+
+    Vec::new()
+}
+
+impl DeliveryStatus {
+    /// Returns the delivery status reported by a message, if it is a
+    /// delivery status notification.
+    fn new(message: &Message) -> Option<Self> {
+        let part = message.parts.iter().find(|part| {
+            is_content_type(
+                part,
+                "message",
+                &["delivery-status", "global-delivery-status"],
+            )
+        })?;
+
+        let mut blocks = field_blocks(&String::from_utf8_lossy(part.contents())).into_iter();
+        let message_fields = blocks.next()?;
+
+        let recipients = blocks
+            .map(|fields| RecipientStatus {
+                final_recipient: fields.typed("final-recipient"),
+                original_recipient: fields.typed("original-recipient"),
+                action: fields.get("action").map(str::to_ascii_lowercase),
+                status: fields
+                    .get("status")
+                    .and_then(|status| status.split_whitespace().next())
+                    .map(str::to_string),
+                diagnostic_code: fields.typed("diagnostic-code"),
+                remote_mta: fields.typed("remote-mta"),
+            })
+            .collect();
+
+        Some(DeliveryStatus {
+            r#type: ReportType::DeliveryStatus,
+            reporting_mta: message_fields.typed("reporting-mta"),
+            recipients,
+            original_headers: original_headers(message),
+        })
+    }
+}
+
+impl DispositionNotification {
+    /// Returns the disposition reported by a message, if it is a message
+    /// disposition notification.
+    fn new(message: &Message) -> Option<Self> {
+        let part = message.parts.iter().find(|part| {
+            is_content_type(
+                part,
+                "message",
+                &[
+                    "disposition-notification",
+                    "global-disposition-notification",
+                ],
+            )
+        })?;
+
+        let fields = field_blocks(&String::from_utf8_lossy(part.contents()))
+            .into_iter()
+            .next()?;
+
+        // E.g. `manual-action/MDN-sent-manually; displayed/error`, of which
+        // only the disposition type is kept.
+        let disposition = fields
+            .typed("disposition")
+            .and_then(|disposition| {
+                disposition
+                    .split(['/', ' '])
+                    .next()
+                    .map(str::to_ascii_lowercase)
+            })
+            .filter(|disposition| !disposition.is_empty());
+
+        let original_message_id = fields.get("original-message-id").map(|id| {
+            id.trim()
+                .trim_start_matches('<')
+                .trim_end_matches('>')
+                .to_string()
+        });
+
+        Some(DispositionNotification {
+            r#type: ReportType::DispositionNotification,
+            reporting_ua: fields.get("reporting-ua").map(str::to_string),
+            original_recipient: fields.typed("original-recipient"),
+            final_recipient: fields.typed("final-recipient"),
+            original_message_id,
+            disposition,
+            original_headers: original_headers(message),
+        })
+    }
+}
+
+impl Report {
+    fn new(message: &Message) -> Option<Self> {
+        DeliveryStatus::new(message)
+            .map(Report::DeliveryStatus)
+            .or_else(|| DispositionNotification::new(message).map(Report::DispositionNotification))
+    }
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn parse_dsn(raw_message: RawMessage) -> NifResult<(Atom, Report)> {
+    let message = parse_message(raw_message.as_slice())?;
+    let report = Report::new(&message).ok_or(ErrorReason::NotAReport)?;
+
+    Ok((atoms::ok(), report))
+}
@@ -0,0 +1,530 @@
+use mail_parser::decoders::html::add_html_token;
+use rustler::{Atom, Decoder, Error, NifResult, Term};
+
+use crate::atoms;
+use crate::decompress::BodyOptions;
+use crate::text::{entity_len, find_ignore_ascii_case, tag_end};
+
+/// Elements dropped along with their contents, as they run code or embed
+/// other documents, or as browsers read their contents as markup or as text
+/// depending on whether scripting is enabled.
+const REMOVED_ELEMENTS: [&str; 10] = [
+    "script",
+    "iframe",
+    "object",
+    "applet",
+    "frameset",
+    "noembed",
+    "noframes",
+    "noscript",
+    "xmp",
+    "plaintext",
+];
+
+/// Elements kept whose contents browsers read as text up to their closing
+/// tag, markup included, so that their contents are escaped rather than
+/// parsed. Inside SVG and MathML, where browsers parse them as markup, the
+/// escaped contents are text all the same.
+const RAW_TEXT_ELEMENTS: [&str; 3] = ["style", "textarea", "title"];
+
+/// Elements without contents that are dropped, as they load other
+/// documents or change how the rest of the document is loaded.
+const REMOVED_VOID_ELEMENTS: [&str; 5] = ["base", "meta", "link", "embed", "frame"];
+
+/// Attributes holding a URL that a browser may follow or load.
+const URL_ATTRIBUTES: [&str; 11] = [
+    "href",
+    "src",
+    "action",
+    "formaction",
+    "background",
+    "poster",
+    "cite",
+    "longdesc",
+    "lowsrc",
+    "dynsrc",
+    "xlink:href",
+];
+
+/// URL schemes running code when followed.
+const SCRIPT_SCHEMES: [&str; 3] = ["javascript:", "vbscript:", "livescript:"];
+
+/// URL schemes allowed in URL attributes, on top of relative URLs.
+const URL_SCHEMES: [&str; 4] = ["http:", "https:", "mailto:", "cid:"];
+
+/// Options accepted by `get_html_body/2`, decoded from a keyword list, on
+/// top of the options of `get_text_body/2`.
+#[derive(Default)]
+pub struct HtmlOptions {
+    /// Whether the HTML is sanitized before being returned.
+    pub sanitize: bool,
+    /// How the remote images of sanitized HTML are loaded.
+    pub remote_images: RemoteImages,
+    /// Whether the HTML is decompressed, if compressed by a gateway.
+    pub body_options: BodyOptions,
+}
+
+/// How the remote images of sanitized HTML are loaded.
+#[derive(Default)]
+pub enum RemoteImages {
+    /// Images are loaded from their URL.
+    #[default]
+    Allow,
+    /// Image URLs are removed.
+    Block,
+    /// Image URLs are replaced by a URL built from a template, with
+    /// `{url}` replaced by the percent-encoded image URL.
+    Proxy(String),
+}
+
+impl<'a> Decoder<'a> for RemoteImages {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        if let Ok(template) = term.decode::<String>() {
+            return Ok(RemoteImages::Proxy(template));
+        }
+
+        let remote_images = term.decode::<Atom>()?;
+
+        if remote_images == atoms::allow() {
+            Ok(RemoteImages::Allow)
+        } else if remote_images == atoms::block() {
+            Ok(RemoteImages::Block)
+        } else {
+            Err(Error::BadArg)
+        }
+    }
+}
+
+impl<'a> Decoder<'a> for HtmlOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut html_options = HtmlOptions::default();
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::sanitize() {
+                html_options.sanitize = value.decode()?;
+            } else if key == atoms::remote_images() {
+                html_options.remote_images = value.decode()?;
+            } else {
+                html_options.body_options.set(key, value)?;
+            }
+        }
+
+        Ok(html_options)
+    }
+}
+
+/// An attribute of a tag, with its value as found in the HTML, entities
+/// included.
+pub struct Attribute<'h> {
+    pub name: String,
+    pub value: Option<&'h str>,
+}
+
+/// Returns the name of a tag, lowercased, and its attributes, given the
+/// text between its `<` and `>`.
+pub fn parse_tag(tag: &str) -> (String, Vec<Attribute<'_>>) {
+    let is_separator = |ch: char| ch.is_whitespace() || ch == '/';
+
+    let name_end = tag.find(is_separator).unwrap_or(tag.len());
+    let name = tag[..name_end].to_ascii_lowercase();
+    let mut attributes = Vec::new();
+    let mut rest = &tag[name_end..];
+
+    loop {
+        rest = rest.trim_start_matches(is_separator);
+
+        if rest.is_empty() {
+            break;
+        }
+
+        let name_end = rest
+            .find(|ch: char| is_separator(ch) || ch == '=')
+            .unwrap_or(rest.len());
+        let name = rest[..name_end].to_ascii_lowercase();
+        rest = rest[name_end..].trim_start();
+
+        let Some(value) = rest.strip_prefix('=') else {
+            attributes.push(Attribute { name, value: None });
+            continue;
+        };
+
+        let text = value.trim_start();
+        let (value, value_end) = match text.chars().next() {
+            Some(quote @ ('"' | '\'')) => match text[1..].find(quote) {
+                Some(end) => (&text[1..end + 1], end + 2),
+                None => (&text[1..], text.len()),
+            },
+            _ => {
+                let end = text.find(char::is_whitespace).unwrap_or(text.len());
+                (&text[..end], end)
+            }
+        };
+
+        attributes.push(Attribute {
+            name,
+            value: Some(value),
+        });
+        rest = &text[value_end..];
+    }
+
+    (name, attributes)
+}
+
+/// Returns the character of the `&#number` or `&#xnumber` reference `html`
+/// starts with, its `;` being optional as for browsers, and its length.
+fn numeric_reference(html: &str) -> Option<(char, usize)> {
+    let reference = html.strip_prefix("&#")?;
+    let (radix, digits) = match reference.strip_prefix(['x', 'X']) {
+        Some(digits) => (16, digits),
+        None => (10, reference),
+    };
+
+    let digits_len = digits
+        .find(|ch: char| !ch.is_digit(radix))
+        .unwrap_or(digits.len());
+
+    if digits_len == 0 {
+        return None;
+    }
+
+    let ch = u32::from_str_radix(&digits[..digits_len], radix)
+        .ok()
+        .and_then(char::from_u32)
+        .filter(|&ch| ch != '\0')
+        .unwrap_or(char::REPLACEMENT_CHARACTER);
+    let len = html.len() - digits.len() + digits_len;
+
+    Some((ch, len + usize::from(html[len..].starts_with(';'))))
+}
+
+/// Decodes the entities of an attribute value.
+pub fn decode_entities(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(pos) = rest.find('&') {
+        decoded.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if let Some((ch, len)) = numeric_reference(rest) {
+            decoded.push(ch);
+            rest = &rest[len..];
+            continue;
+        }
+
+        match entity_len(rest) {
+            Some(len) => {
+                add_html_token(&mut decoded, &rest.as_bytes()[..len], false);
+                rest = &rest[len..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Returns a URL as a browser reads it, given its value with entities
+/// decoded: with the whitespace and control characters browsers ignore
+/// removed.
+fn normalize_url(value: &str) -> String {
+    value
+        .chars()
+        .filter(|ch| !ch.is_whitespace() && !ch.is_control())
+        .collect()
+}
+
+/// Returns whether a lowercased URL is relative, or of one of the allowed
+/// schemes.
+fn is_allowed_url(url: &str) -> bool {
+    match url.find([':', '/', '?', '#']) {
+        Some(pos) if url[pos..].starts_with(':') => URL_SCHEMES.contains(&&url[..=pos]),
+        _ => true,
+    }
+}
+
+/// Returns CSS as a browser reads it: with escapes decoded, whitespace and
+/// control characters removed, and lowercased.
+fn normalize_css(css: &str) -> String {
+    let mut normalized = String::with_capacity(css.len());
+    let mut chars = css.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            normalized.push(ch);
+            continue;
+        }
+
+        let mut code_point = String::new();
+
+        while code_point.len() < 6 {
+            match chars.next_if(char::is_ascii_hexdigit) {
+                Some(digit) => code_point.push(digit),
+                None => break,
+            }
+        }
+
+        if code_point.is_empty() {
+            normalized.extend(chars.next());
+        } else {
+            chars.next_if(|ch| ch.is_whitespace());
+            normalized.push(
+                u32::from_str_radix(&code_point, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .unwrap_or(char::REPLACEMENT_CHARACTER),
+            );
+        }
+    }
+
+    normalize_url(&normalized).to_ascii_lowercase()
+}
+
+/// Returns whether CSS runs code or, unless allowed, loads remote images.
+fn is_unsafe_style(css: &str, remote_images: &RemoteImages) -> bool {
+    let css = normalize_css(css);
+    let loads_resources = ["url(", "image-set(", "@import"]
+        .iter()
+        .any(|function| css.contains(function));
+
+    css.contains("expression(")
+        || (!matches!(remote_images, RemoteImages::Allow) && loads_resources)
+}
+
+/// Returns whether an attribute of an element loads a resource, such as
+/// an image or a video, when the document is displayed.
+fn loads_resource(element: &str, name: &str) -> bool {
+    match name {
+        "background" | "poster" | "lowsrc" | "dynsrc" => true,
+        "src" => matches!(
+            element,
+            "img" | "image" | "input" | "video" | "audio" | "source" | "track"
+        ),
+        "href" | "xlink:href" => matches!(element, "image" | "use" | "feimage"),
+        _ => false,
+    }
+}
+
+fn is_remote_url(url: &str) -> bool {
+    let url = url.to_ascii_lowercase();
+    url.starts_with("http:") || url.starts_with("https:") || url.starts_with("//")
+}
+
+/// Percent-encodes every byte of a URL but the unreserved characters of
+/// RFC 3986, for use as a query parameter.
+fn percent_encode(url: &str) -> String {
+    url.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Returns the value an attribute of a kept element is rewritten to, with
+/// entities decoded, or `None` if the attribute is dropped.
+fn sanitize_attribute<'h>(
+    element: &str,
+    attribute: &Attribute<'h>,
+    remote_images: &RemoteImages,
+) -> Option<Option<String>> {
+    let name = attribute.name.as_str();
+    let Some(value) = attribute.value else {
+        return (!name.starts_with("on")).then_some(None);
+    };
+
+    let value = decode_entities(value);
+    let url = normalize_url(&value);
+    let lowercase_url = url.to_ascii_lowercase();
+
+    // Script URLs are looked for in every attribute, as some, such as the
+    // `values` of SVG animations, end up in URLs.
+    if name.starts_with("on")
+        || SCRIPT_SCHEMES
+            .iter()
+            .any(|scheme| lowercase_url.contains(scheme))
+    {
+        return None;
+    }
+
+    if name == "style" {
+        return (!is_unsafe_style(&value, remote_images)).then_some(Some(value));
+    }
+
+    if name == "srcset" && !matches!(remote_images, RemoteImages::Allow) {
+        return None;
+    }
+
+    if !URL_ATTRIBUTES.contains(&name) {
+        return Some(Some(value));
+    }
+
+    // Images may be inlined, nothing else may be loaded from `data:` URLs.
+    let is_inline_image = name == "src" && element == "img" && lowercase_url.starts_with("data:");
+
+    if !is_inline_image && !is_allowed_url(&lowercase_url) {
+        return None;
+    }
+
+    // Forms may only be submitted within the document.
+    if (name == "action" || name == "formaction") && !url.starts_with('#') {
+        return None;
+    }
+
+    if loads_resource(element, name) && is_remote_url(&url) {
+        return match remote_images {
+            RemoteImages::Allow => Some(Some(url)),
+            RemoteImages::Block => None,
+            RemoteImages::Proxy(template) => {
+                Some(Some(template.replace("{url}", &percent_encode(&url))))
+            }
+        };
+    }
+
+    Some(Some(url))
+}
+
+/// Returns whether an element or attribute name is made of the characters
+/// of the names of the HTML, SVG and MathML specifications only.
+fn is_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | ':' | '.'))
+}
+
+/// Returns a tag rebuilt from its name and sanitized attributes, with
+/// attribute values double quoted and escaped.
+fn write_tag(
+    html: &mut String,
+    name: &str,
+    attributes: &[Attribute],
+    remote_images: &RemoteImages,
+) {
+    html.push('<');
+    html.push_str(name);
+
+    for attribute in attributes
+        .iter()
+        .filter(|attribute| is_name(&attribute.name))
+    {
+        let Some(value) = sanitize_attribute(name, attribute, remote_images) else {
+            continue;
+        };
+
+        html.push(' ');
+        html.push_str(&attribute.name);
+
+        if let Some(value) = value {
+            html.push_str("=\"");
+            html.push_str(
+                &value
+                    .replace('&', "&amp;")
+                    .replace('"', "&quot;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;"),
+            );
+            html.push('"');
+        }
+    }
+
+    html.push('>');
+}
+
+/// Returns HTML safe to embed in a web page: scripts, embedded documents,
+/// comments and event handler attributes are dropped, as are `javascript:`
+/// URLs, form actions leaving the document and, unless allowed, remote
+/// image URLs, which may also be rewritten to go through a proxy. URL
+/// attributes keep relative URLs and `http:`, `https:`, `mailto:` and `cid:`
+/// URLs only.
+///
+/// Tags are rebuilt from the attributes that are kept, with their values
+/// decoded and escaped again, so that malformed markup cannot smuggle
+/// attributes or entities past the sanitizer.
+pub fn sanitize_html(html: &str, remote_images: &RemoteImages) -> String {
+    let mut sanitized = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(pos) = rest.find('<') {
+        sanitized.push_str(&rest[..pos].replace('>', "&gt;"));
+        rest = &rest[pos..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let starts_tag = rest[1..]
+            .chars()
+            .next()
+            .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '/' || ch == '!' || ch == '?');
+
+        if !starts_tag {
+            sanitized.push_str("&lt;");
+            rest = &rest[1..];
+            continue;
+        }
+
+        let Some(end) = tag_end(rest) else {
+            break;
+        };
+
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        // Doctypes and processing instructions are of no use once embedded.
+        if tag.starts_with(['!', '?']) {
+            continue;
+        }
+
+        if let Some(closing_tag) = tag.strip_prefix('/') {
+            let (name, _) = parse_tag(closing_tag.trim_start());
+
+            if is_name(&name) && !REMOVED_VOID_ELEMENTS.contains(&name.as_str()) {
+                sanitized.push_str(&format!("</{name}>"));
+            }
+
+            continue;
+        }
+
+        let (name, attributes) = parse_tag(tag);
+
+        if REMOVED_ELEMENTS.contains(&name.as_str()) {
+            let closing_tag = format!("</{name}");
+            rest = find_ignore_ascii_case(rest, &closing_tag)
+                .and_then(|pos| Some(&rest[pos + tag_end(&rest[pos..])? + 1..]))
+                .unwrap_or("");
+        } else if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+            let closing_tag = format!("</{name}");
+            let (contents, after) = match find_ignore_ascii_case(rest, &closing_tag) {
+                Some(pos) => (
+                    &rest[..pos],
+                    tag_end(&rest[pos..]).map_or("", |end| &rest[pos + end + 1..]),
+                ),
+                None => (rest, ""),
+            };
+            rest = after;
+
+            if name == "style" && is_unsafe_style(contents, remote_images) {
+                continue;
+            }
+
+            write_tag(&mut sanitized, &name, &attributes, remote_images);
+            sanitized.push_str(&contents.replace('<', "&lt;"));
+            sanitized.push_str(&format!("</{name}>"));
+        } else if is_name(&name) && !REMOVED_VOID_ELEMENTS.contains(&name.as_str()) {
+            write_tag(&mut sanitized, &name, &attributes, remote_images);
+        }
+    }
+
+    // A tag left open at the end would swallow the markup following the
+    // embedded HTML.
+    sanitized.push_str(&rest.replace('<', "&lt;").replace('>', "&gt;"));
+    sanitized
+}
@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+
+use mail_parser::{Message, MessagePart, MimeHeaders};
+use rustler::{NifMap, NifUnitEnum, ResourceArc};
+
+use crate::attachment::{content_type, AttachmentPart, Disposition};
+use crate::der::{self, Reader};
+use crate::message::MessageResource;
+
+/// Object identifiers of the CMS content types (RFC 5652, RFC 5083).
+const SIGNED_DATA: &[u8] = b"\x2a\x86\x48\x86\xf7\x0d\x01\x07\x02";
+const ENVELOPED_DATA: &[u8] = b"\x2a\x86\x48\x86\xf7\x0d\x01\x07\x03";
+const AUTH_ENVELOPED_DATA: &[u8] = b"\x2a\x86\x48\x86\xf7\x0d\x01\x09\x10\x01\x17";
+
+/// Digest algorithms, by object identifier.
+const DIGEST_ALGORITHMS: [(&[u8], &str); 6] = [
+    (b"\x2a\x86\x48\x86\xf7\x0d\x02\x05", "md5"),
+    (b"\x2b\x0e\x03\x02\x1a", "sha1"),
+    (b"\x60\x86\x48\x01\x65\x03\x04\x02\x04", "sha224"),
+    (b"\x60\x86\x48\x01\x65\x03\x04\x02\x01", "sha256"),
+    (b"\x60\x86\x48\x01\x65\x03\x04\x02\x02", "sha384"),
+    (b"\x60\x86\x48\x01\x65\x03\x04\x02\x03", "sha512"),
+];
+
+/// Content encryption algorithms, by object identifier.
+const ENCRYPTION_ALGORITHMS: [(&[u8], &str); 8] = [
+    (b"\x60\x86\x48\x01\x65\x03\x04\x01\x02", "aes-128-cbc"),
+    (b"\x60\x86\x48\x01\x65\x03\x04\x01\x16", "aes-192-cbc"),
+    (b"\x60\x86\x48\x01\x65\x03\x04\x01\x2a", "aes-256-cbc"),
+    (b"\x60\x86\x48\x01\x65\x03\x04\x01\x06", "aes-128-gcm"),
+    (b"\x60\x86\x48\x01\x65\x03\x04\x01\x1a", "aes-192-gcm"),
+    (b"\x60\x86\x48\x01\x65\x03\x04\x01\x2e", "aes-256-gcm"),
+    (b"\x2a\x86\x48\x86\xf7\x0d\x03\x07", "des-ede3-cbc"),
+    (b"\x2a\x86\x48\x86\xf7\x0d\x03\x02", "rc2-cbc"),
+];
+
+/// Object identifiers of the certificate name attributes returned.
+const COMMON_NAME: &[u8] = b"\x55\x04\x03";
+const ORGANIZATION: &[u8] = b"\x55\x04\x0a";
+const EMAIL_ADDRESS: &[u8] = b"\x2a\x86\x48\x86\xf7\x0d\x01\x09\x01";
+
+/// Object identifier of the subject alternative name extension, and tag of
+/// the `rfc822Name` names it holds.
+const SUBJECT_ALT_NAME: &[u8] = b"\x55\x1d\x11";
+const RFC822_NAME: u8 = 0x81;
+
+#[derive(Clone, Copy, PartialEq, Eq, NifUnitEnum)]
+pub enum Protocol {
+    Smime,
+    Pgp,
+}
+
+#[derive(Default, NifMap)]
+pub struct SecurityInfo {
+    signed: bool,
+    encrypted: bool,
+    protocol: Option<Protocol>,
+    /// Digest algorithm of the signature, e.g. `"sha256"`.
+    digest_algorithm: Option<String>,
+    /// Content encryption algorithm of S/MIME messages, e.g. `"aes-256-cbc"`.
+    encryption_algorithm: Option<String>,
+    signers: Vec<Signer>,
+}
+
+/// The certificate an S/MIME message was signed with.
+#[derive(NifMap)]
+pub struct Signer {
+    /// Common name of the subject of the certificate.
+    name: Option<String>,
+    email: Option<String>,
+    organization: Option<String>,
+    /// Common name of the issuer of the certificate.
+    issuer: Option<String>,
+    /// Lowercase hex encoded serial number of the certificate.
+    serial_number: String,
+}
+
+impl From<&Message<'_>> for SecurityInfo {
+    fn from(message: &Message) -> Self {
+        let mut security_info = SecurityInfo::default();
+
+        for part in &message.parts {
+            let parameter = |name| {
+                part.content_type()
+                    .and_then(|content_type| content_type.attribute(name))
+                    .map(str::to_ascii_lowercase)
+            };
+
+            let Some(part_type) = content_type(part) else {
+                continue;
+            };
+
+            match part_type.to_ascii_lowercase().as_str() {
+                "multipart/signed" => {
+                    security_info.signed = true;
+                    security_info.set_protocol(parameter("protocol"));
+
+                    // The `micalg` parameter lists the digest algorithms,
+                    // e.g. `sha-256` for S/MIME or `pgp-sha256` for PGP.
+                    if let Some(micalg) = parameter("micalg") {
+                        let micalg = micalg.split(',').next().unwrap_or_default().trim();
+                        let digest_algorithm = micalg.trim_start_matches("pgp-").replace('-', "");
+                        security_info
+                            .digest_algorithm
+                            .get_or_insert(digest_algorithm);
+                    }
+                }
+                "multipart/encrypted" => {
+                    security_info.encrypted = true;
+                    security_info.set_protocol(parameter("protocol"));
+                }
+                "application/pkcs7-signature"
+                | "application/x-pkcs7-signature"
+                | "application/pkcs7-mime"
+                | "application/x-pkcs7-mime" => security_info.add_cms(part.contents()),
+                _ => {}
+            }
+        }
+
+        security_info
+    }
+}
+
+impl SecurityInfo {
+    /// Sets the protocol given by the `protocol` parameter of a
+    /// multipart/signed or multipart/encrypted part.
+    fn set_protocol(&mut self, protocol: Option<String>) {
+        let protocol = match protocol.as_deref() {
+            Some(protocol) if protocol.contains("pgp") => Protocol::Pgp,
+            Some(protocol) if protocol.contains("pkcs7") => Protocol::Smime,
+            _ => return,
+        };
+
+        self.protocol.get_or_insert(protocol);
+    }
+
+    /// Adds the summary of a CMS structure, as found in S/MIME parts.
+    fn add_cms(&mut self, bytes: &[u8]) {
+        let Some((content_type, content)) = content_info(bytes) else {
+            return;
+        };
+
+        if content_type == SIGNED_DATA {
+            self.signed = true;
+            self.protocol.get_or_insert(Protocol::Smime);
+
+            if let Some(signed_data) = signed_data(content) {
+                // The digest algorithm of the CMS structure is more reliable
+                // than the `micalg` parameter.
+                if let Some(digest_algorithm) = signed_data.digest_algorithm {
+                    self.digest_algorithm = Some(digest_algorithm.to_string());
+                }
+
+                self.signers.extend(signed_data.signers);
+            }
+        } else if content_type == ENVELOPED_DATA || content_type == AUTH_ENVELOPED_DATA {
+            self.encrypted = true;
+            self.protocol.get_or_insert(Protocol::Smime);
+            self.encryption_algorithm = encryption_algorithm(content).map(str::to_string);
+        }
+    }
+}
+
+/// The fields of a CMS SignedData structure returned.
+struct SignedData {
+    digest_algorithm: Option<&'static str>,
+    /// The signed content, unless the signature is detached.
+    content: Option<Vec<u8>>,
+    signers: Vec<Signer>,
+}
+
+/// A certificate, as found in a SignedData structure.
+struct Certificate<'d> {
+    serial_number: &'d [u8],
+    issuer: &'d [u8],
+    subject: &'d [u8],
+    extensions: Option<&'d [u8]>,
+}
+
+/// Returns the content type and content of a CMS ContentInfo structure.
+fn content_info(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let mut content_info = Reader::new(Reader::new(bytes).expect(der::SEQUENCE)?);
+    let content_type = content_info.expect(der::OBJECT_IDENTIFIER)?;
+    let content = Reader::new(content_info.expect(der::context(0))?).expect(der::SEQUENCE)?;
+
+    Some((content_type, content))
+}
+
+fn signed_data(content: &[u8]) -> Option<SignedData> {
+    let mut fields = Reader::new(content);
+    fields.expect(der::INTEGER)?;
+
+    let digest_algorithm = Reader::new(fields.expect(der::SET)?)
+        .next()
+        .and_then(|identifier| algorithm(identifier.contents, &DIGEST_ALGORITHMS));
+
+    let mut encapsulated_content_info = Reader::new(fields.expect(der::SEQUENCE)?);
+    encapsulated_content_info.expect(der::OBJECT_IDENTIFIER)?;
+    let content = encapsulated_content_info
+        .optional(der::context(0))
+        .and_then(|content| Reader::new(content).next())
+        .and_then(der::octet_string);
+
+    let certificates: Vec<Certificate> = fields
+        .optional(der::context(0))
+        .map(|certificates| {
+            Reader::new(certificates)
+                .filter_map(|certificate_value| certificate(certificate_value.contents))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    fields.optional(der::context(1));
+
+    let signers = Reader::new(fields.expect(der::SET)?)
+        .filter_map(|signer_info| signer(signer_info.contents, &certificates))
+        .collect();
+
+    Some(SignedData {
+        digest_algorithm,
+        content,
+        signers,
+    })
+}
+
+fn certificate(bytes: &[u8]) -> Option<Certificate<'_>> {
+    let mut certificate = Reader::new(bytes);
+    let mut fields = Reader::new(certificate.expect(der::SEQUENCE)?);
+
+    // The version is only present from version 2 on.
+    fields.optional(der::context(0));
+    let serial_number = fields.expect(der::INTEGER)?;
+    fields.expect(der::SEQUENCE)?;
+    let issuer = fields.expect(der::SEQUENCE)?;
+    fields.expect(der::SEQUENCE)?;
+    let subject = fields.expect(der::SEQUENCE)?;
+
+    let extensions = fields
+        .find(|field| field.tag == der::context(3))
+        .map(|extensions| extensions.contents);
+
+    Some(Certificate {
+        serial_number,
+        issuer,
+        subject,
+        extensions,
+    })
+}
+
+/// Returns the signer of a SignerInfo structure, as described by the
+/// certificate it refers to by issuer and serial number.
+fn signer(bytes: &[u8], certificates: &[Certificate]) -> Option<Signer> {
+    let mut fields = Reader::new(bytes);
+    fields.expect(der::INTEGER)?;
+
+    // Signers referring to their certificate by subject key identifier
+    // instead are left out.
+    let mut signer_id = Reader::new(fields.expect(der::SEQUENCE)?);
+    let issuer = signer_id.expect(der::SEQUENCE)?;
+    let serial_number = signer_id.expect(der::INTEGER)?;
+
+    let certificate = certificates.iter().find(|certificate| {
+        certificate.issuer == issuer && certificate.serial_number == serial_number
+    });
+
+    let subject_attribute = |attribute| {
+        certificate.and_then(|certificate| name_attribute(certificate.subject, attribute))
+    };
+
+    let email = subject_attribute(EMAIL_ADDRESS).or_else(|| {
+        certificate
+            .and_then(|certificate| certificate.extensions)
+            .and_then(alternative_email)
+    });
+
+    Some(Signer {
+        name: subject_attribute(COMMON_NAME),
+        email,
+        organization: subject_attribute(ORGANIZATION),
+        issuer: name_attribute(issuer, COMMON_NAME),
+        serial_number: serial_number
+            .iter()
+            .skip_while(|&&byte| byte == 0)
+            .map(|byte| format!("{byte:02x}"))
+            .collect(),
+    })
+}
+
+/// Returns the first value of an attribute of a distinguished name.
+fn name_attribute(name: &[u8], attribute: &[u8]) -> Option<String> {
+    Reader::new(name)
+        .flat_map(|relative_name| Reader::new(relative_name.contents))
+        .find_map(|attribute_value| {
+            let mut attribute_value = Reader::new(attribute_value.contents);
+
+            if attribute_value.expect(der::OBJECT_IDENTIFIER)? != attribute {
+                return None;
+            }
+
+            der::string(attribute_value.next()?)
+        })
+}
+
+/// Returns the first email address of the subject alternative name
+/// extension of a certificate.
+fn alternative_email(extensions: &[u8]) -> Option<String> {
+    Reader::new(Reader::new(extensions).expect(der::SEQUENCE)?).find_map(|extension| {
+        let mut fields = Reader::new(extension.contents);
+
+        if fields.expect(der::OBJECT_IDENTIFIER)? != SUBJECT_ALT_NAME {
+            return None;
+        }
+
+        fields.optional(der::BOOLEAN);
+        let general_names = Reader::new(fields.expect(der::OCTET_STRING)?).expect(der::SEQUENCE)?;
+
+        Reader::new(general_names)
+            .find(|general_name| general_name.tag == RFC822_NAME)
+            .map(|email| String::from_utf8_lossy(email.contents).into_owned())
+    })
+}
+
+/// Returns the content encryption algorithm of a CMS EnvelopedData or
+/// AuthEnvelopedData structure.
+fn encryption_algorithm(content: &[u8]) -> Option<&'static str> {
+    let mut fields = Reader::new(content);
+    fields.expect(der::INTEGER)?;
+    fields.optional(der::context(0));
+    fields.expect(der::SET)?;
+
+    let mut encrypted_content_info = Reader::new(fields.expect(der::SEQUENCE)?);
+    encrypted_content_info.expect(der::OBJECT_IDENTIFIER)?;
+    algorithm(
+        encrypted_content_info.expect(der::SEQUENCE)?,
+        &ENCRYPTION_ALGORITHMS,
+    )
+}
+
+/// Returns the name of the algorithm of an AlgorithmIdentifier structure.
+fn algorithm(identifier: &[u8], known: &[(&[u8], &'static str)]) -> Option<&'static str> {
+    let oid = Reader::new(identifier).expect(der::OBJECT_IDENTIFIER)?;
+
+    known
+        .iter()
+        .find(|(known_oid, _)| *known_oid == oid)
+        .map(|(_, name)| *name)
+}
+
+/// Returns the message wrapped by an opaque-signed S/MIME part, i.e. the
+/// content of its SignedData structure.
+pub fn signed_content(part: &MessagePart) -> Option<Vec<u8>> {
+    let is_pkcs7_mime = content_type(part).is_some_and(|part_type| {
+        part_type.eq_ignore_ascii_case("application/pkcs7-mime")
+            || part_type.eq_ignore_ascii_case("application/x-pkcs7-mime")
+    });
+
+    if !is_pkcs7_mime {
+        return None;
+    }
+
+    match content_info(part.contents())? {
+        (content_type, content) if content_type == SIGNED_DATA => signed_data(content)?.content,
+        _ => None,
+    }
+}
+
+/// An attachment of the message wrapped by an opaque-signed part. That
+/// message is parsed from the signed content on the fly, so its attachments
+/// are copied out of it.
+pub struct WrappedAttachment {
+    pub name: Option<String>,
+    pub raw_name: Option<String>,
+    pub content_type: Option<String>,
+    pub disposition: Disposition,
+    pub content_id: Option<String>,
+    pub contents: Vec<u8>,
+    pub part_headers: HashMap<String, Vec<String>>,
+}
+
+impl From<&AttachmentPart<'_>> for WrappedAttachment {
+    fn from(attachment: &AttachmentPart) -> Self {
+        WrappedAttachment {
+            name: attachment.given_name().map(str::to_string),
+            raw_name: attachment.raw_name().map(str::to_string),
+            content_type: attachment.content_type(),
+            disposition: attachment.disposition(),
+            content_id: attachment.content_id().map(str::to_string),
+            contents: attachment.contents().to_vec(),
+            part_headers: attachment.part_headers(),
+        }
+    }
+}
+
+#[rustler::nif]
+fn get_security_info(resource: ResourceArc<MessageResource>) -> SecurityInfo {
+    SecurityInfo::from(resource.message())
+}
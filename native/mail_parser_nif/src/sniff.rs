@@ -0,0 +1,208 @@
+use crate::archive::{
+    u16_at, u32_at, zip_entries, ZIP_END_OF_CENTRAL_DIRECTORY, ZIP_LOCAL_FILE_HEADER,
+};
+
+/// Content types recognized by the signature their contents start with.
+const SIGNATURES: [(&[u8], &str); 14] = [
+    (b"%PDF-", "application/pdf"),
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"II*\x00", "image/tiff"),
+    (b"MM\x00*", "image/tiff"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"7z\xbc\xaf\x27\x1c", "application/x-7z-compressed"),
+    (b"Rar!\x1a\x07", "application/vnd.rar"),
+    (b"{\\rtf", "application/rtf"),
+    (b"\x7fELF", "application/x-executable"),
+    (b"MZ", "application/vnd.microsoft.portable-executable"),
+    // Compound files hold legacy Office documents as well as other formats.
+    (
+        b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1",
+        "application/x-ole-storage",
+    ),
+];
+
+/// Stream holding the encrypted document of a password protected Office
+/// Open XML document.
+const ENCRYPTED_PACKAGE_STREAM: &str = "EncryptedPackage";
+
+/// Office Open XML content types, by the directory holding the document.
+const OOXML_DIRECTORIES: [(&str, &str); 3] = [
+    (
+        "word/",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    (
+        "xl/",
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+    ),
+    (
+        "ppt/",
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+    ),
+];
+
+/// Extensions of the content types attachments are commonly sent as.
+const EXTENSIONS: [(&str, &str); 40] = [
+    ("application/pdf", "pdf"),
+    ("image/png", "png"),
+    ("image/jpeg", "jpg"),
+    ("image/gif", "gif"),
+    ("image/tiff", "tiff"),
+    ("image/webp", "webp"),
+    ("image/svg+xml", "svg"),
+    ("application/zip", "zip"),
+    ("application/gzip", "gz"),
+    ("application/x-gzip", "gz"),
+    ("application/x-tar", "tar"),
+    ("application/x-7z-compressed", "7z"),
+    ("application/vnd.rar", "rar"),
+    ("application/x-rar-compressed", "rar"),
+    ("application/rtf", "rtf"),
+    ("text/rtf", "rtf"),
+    ("application/msword", "doc"),
+    ("application/vnd.ms-excel", "xls"),
+    ("application/vnd.ms-powerpoint", "ppt"),
+    (
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "docx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        "xlsx",
+    ),
+    (
+        "application/vnd.openxmlformats-officedocument.presentationml.presentation",
+        "pptx",
+    ),
+    ("application/vnd.oasis.opendocument.text", "odt"),
+    ("application/vnd.oasis.opendocument.spreadsheet", "ods"),
+    ("application/vnd.oasis.opendocument.presentation", "odp"),
+    ("application/epub+zip", "epub"),
+    ("application/json", "json"),
+    ("application/xml", "xml"),
+    ("application/pkcs7-signature", "p7s"),
+    ("application/pkcs7-mime", "p7m"),
+    ("application/pgp-signature", "asc"),
+    ("application/vnd.microsoft.portable-executable", "exe"),
+    ("text/plain", "txt"),
+    ("text/html", "html"),
+    ("text/csv", "csv"),
+    ("text/calendar", "ics"),
+    ("text/vcard", "vcf"),
+    ("text/xml", "xml"),
+    ("message/rfc822", "eml"),
+    ("message/global", "eml"),
+];
+
+/// Returns the extension of a `type/subtype` content type, without its
+/// leading dot, if it is a known one.
+pub fn extension(content_type: &str) -> Option<&'static str> {
+    EXTENSIONS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(content_type))
+        .map(|(_, extension)| *extension)
+}
+
+/// Returns the content type of an extension, without its leading dot, if
+/// it is a known one.
+pub fn content_type(extension: &str) -> Option<&'static str> {
+    EXTENSIONS
+        .iter()
+        .find(|(_, known)| known.eq_ignore_ascii_case(extension))
+        .map(|(content_type, _)| *content_type)
+}
+
+/// Detects the content type of a file from its contents, regardless of its
+/// name or declared content type.
+///
+/// Returns `None` for contents without a known signature, such as text.
+pub fn detect_content_type(bytes: &[u8]) -> Option<&'static str> {
+    if bytes.starts_with(ZIP_LOCAL_FILE_HEADER) || bytes.starts_with(ZIP_END_OF_CENTRAL_DIRECTORY) {
+        return Some(zip_content_type(bytes));
+    }
+
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+
+    // Both the POSIX and GNU formats record a magic after the first name.
+    if bytes.get(257..262) == Some(b"ustar".as_slice()) {
+        return Some("application/x-tar");
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| bytes.starts_with(signature))
+        .map(|(_, content_type)| *content_type)
+}
+
+/// Returns whether a file is password protected: a ZIP archive with
+/// encrypted entries, an encrypted PDF document or an encrypted Office Open
+/// XML document, which is stored as a compound file rather than a ZIP
+/// archive.
+pub fn is_encrypted(bytes: &[u8]) -> bool {
+    match detect_content_type(bytes) {
+        Some("application/pdf") => contains(bytes, b"/Encrypt"),
+        Some("application/x-ole-storage") => contains(bytes, &utf16(ENCRYPTED_PACKAGE_STREAM)),
+        Some(_) if bytes.starts_with(ZIP_LOCAL_FILE_HEADER) => zip_entries(bytes)
+            .into_iter()
+            .flatten()
+            .any(|entry| entry.is_encrypted()),
+        _ => false,
+    }
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle)
+}
+
+/// Encodes an ASCII name as UTF-16LE, as compound files store them.
+fn utf16(name: &str) -> Vec<u8> {
+    name.bytes().flat_map(|byte| [byte, 0]).collect()
+}
+
+/// Tells apart the formats based on ZIP archives, such as Office Open XML
+/// and OpenDocument documents, from plain archives.
+fn zip_content_type(bytes: &[u8]) -> &'static str {
+    if let Some(content_type) = opendocument_content_type(bytes) {
+        return content_type;
+    }
+
+    let ooxml_content_type = zip_entries(bytes).into_iter().flatten().find_map(|entry| {
+        OOXML_DIRECTORIES
+            .iter()
+            .find(|(directory, _)| entry.name.starts_with(directory.as_bytes()))
+            .map(|(_, content_type)| *content_type)
+    });
+
+    ooxml_content_type.unwrap_or("application/zip")
+}
+
+/// Returns the content type of an OpenDocument or EPUB file, which is
+/// stored uncompressed as the first entry of the archive, named `mimetype`.
+fn opendocument_content_type(bytes: &[u8]) -> Option<&'static str> {
+    let name_len = usize::from(u16_at(bytes, 26)?);
+    let extra_len = usize::from(u16_at(bytes, 28)?);
+    let contents_len = usize::try_from(u32_at(bytes, 18)?).ok()?;
+
+    if bytes.get(30..30 + name_len)? != b"mimetype" {
+        return None;
+    }
+
+    let contents_start = 30 + name_len + extra_len;
+    let content_type = bytes.get(contents_start..contents_start + contents_len)?;
+
+    [
+        "application/vnd.oasis.opendocument.text",
+        "application/vnd.oasis.opendocument.spreadsheet",
+        "application/vnd.oasis.opendocument.presentation",
+        "application/epub+zip",
+    ]
+    .into_iter()
+    .find(|known| content_type == known.as_bytes())
+}
@@ -0,0 +1,70 @@
+use std::collections::BTreeSet;
+
+use mail_parser::MimeHeaders;
+use rustler::{NifMap, NifResult, ResourceArc};
+
+use crate::attachment::select_attachments;
+use crate::message::{html_body, text_body, MessageResource};
+use crate::options::Options;
+use crate::structure::children;
+
+/// A summary of a message, for quota accounting and triage.
+#[derive(NifMap)]
+pub struct Stats {
+    size: usize,
+    header_size: usize,
+    parts: usize,
+    attachments: usize,
+    attachments_size: usize,
+    encodings: Vec<String>,
+    depth: usize,
+    has_text_body: bool,
+    has_html_body: bool,
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn stats(resource: ResourceArc<MessageResource>) -> NifResult<Stats> {
+    let message = resource.message();
+    let root_part = message.root_part();
+
+    let mut parts = 0;
+    let mut depth = 0;
+    let mut encodings = BTreeSet::new();
+    let mut stack = vec![(message, root_part, 0)];
+
+    while let Some((message, part, message_depth)) = stack.pop() {
+        parts += 1;
+        depth = depth.max(message_depth);
+        encodings.extend(
+            part.content_transfer_encoding()
+                .map(|encoding| encoding.to_ascii_lowercase()),
+        );
+
+        for (child_message, child) in children(message, part) {
+            // The root part of an attached message is one message deeper.
+            let child_depth = match std::ptr::eq(child_message, message) {
+                true => message_depth,
+                false => message_depth + 1,
+            };
+
+            stack.push((child_message, child, child_depth));
+        }
+    }
+
+    let attachments = select_attachments(message, &Options::default())?;
+
+    Ok(Stats {
+        size: message.raw_message.len(),
+        header_size: root_part.offset_body - root_part.offset_header,
+        parts,
+        attachments: attachments.len(),
+        attachments_size: attachments
+            .iter()
+            .map(|attachment| attachment.contents().len())
+            .sum(),
+        encodings: encodings.into_iter().collect(),
+        depth,
+        has_text_body: text_body(message).is_some(),
+        has_html_body: html_body(message).is_some(),
+    })
+}
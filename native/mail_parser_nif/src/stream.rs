@@ -0,0 +1,139 @@
+use mail_parser::Message;
+use rustler::{Atom, Binary, Decoder, Env, Error, NifResult, ResourceArc, Term};
+
+use crate::atoms;
+use crate::attachment::{contents, select_attachments_with_duplicates, AttachmentPart};
+use crate::error::parse_message;
+use crate::iodata::RawMessage;
+use crate::options::Options;
+use crate::structure::{address, part_at, part_paths, AttachmentInfo};
+
+/// Size of the chunks attachments are read in, unless set by `chunk_size`.
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Options accepted by `extract_attachments_stream/2`, on top of the
+/// attachment extraction options.
+struct StreamOptions {
+    options: Options,
+    /// Maximum size of the chunks attachments are read in, in bytes.
+    chunk_size: usize,
+}
+
+impl<'a> Decoder<'a> for StreamOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut stream_options = StreamOptions {
+            options: Options::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        };
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::chunk_size() {
+                stream_options.chunk_size = value.decode()?;
+            } else {
+                stream_options.options.set(key, value)?;
+            }
+        }
+
+        if stream_options.chunk_size == 0 {
+            return Err(Error::BadArg);
+        }
+
+        Ok(stream_options)
+    }
+}
+
+/// Where the contents of a streamed attachment are read from.
+enum StreamedContents {
+    /// The part at a path of the message, as returned by `part_paths/1`.
+    Part(Vec<usize>),
+    /// A file decoded from one of its parts when the stream was opened.
+    Owned(Vec<u8>),
+}
+
+/// The attachments of a message, read in chunks by
+/// `read_attachment_chunk/3`, which are sub-binaries of the resource.
+pub struct AttachmentStream {
+    message: Message<'static>,
+    attachments: Vec<StreamedContents>,
+    chunk_size: usize,
+}
+
+impl AttachmentStream {
+    fn contents(&self, index: usize) -> Option<&[u8]> {
+        match self.attachments.get(index)? {
+            StreamedContents::Part(path) => {
+                part_at(&self.message, path).map(|(_, part)| contents(part))
+            }
+            StreamedContents::Owned(bytes) => Some(bytes),
+        }
+    }
+}
+
+/// Parses a message and decodes its attachments up front, the stream only
+/// reading their decoded contents in chunks.
+#[rustler::nif(schedule = "DirtyCpu")]
+fn open_attachment_stream(
+    raw_message: RawMessage,
+    stream_options: StreamOptions,
+) -> NifResult<(Atom, ResourceArc<AttachmentStream>, Vec<AttachmentInfo>)> {
+    let message = parse_message(raw_message.as_slice())?.into_owned();
+    let options = &stream_options.options;
+    let paths = part_paths(&message);
+
+    let mut infos = Vec::new();
+    let mut attachments = Vec::new();
+
+    let (selected, duplicates) = select_attachments_with_duplicates(&message, options)?;
+
+    for (attachment, duplicates) in selected.into_iter().zip(duplicates) {
+        infos.push(AttachmentInfo::new(
+            &attachment,
+            &paths,
+            duplicates,
+            options,
+        ));
+
+        // Parts are read from the message, so that only the files decoded
+        // from them are copied.
+        let path = match &attachment {
+            AttachmentPart::Part(part, ..) | AttachmentPart::Unnamed(part, ..) => {
+                paths.get(&address(part))
+            }
+            _ => None,
+        };
+
+        attachments.push(match path {
+            Some(path) => StreamedContents::Part(path.clone()),
+            None => StreamedContents::Owned(attachment.contents().to_vec()),
+        });
+    }
+
+    let stream = AttachmentStream {
+        message,
+        attachments,
+        chunk_size: stream_options.chunk_size,
+    };
+
+    Ok((atoms::ok(), ResourceArc::new(stream), infos))
+}
+
+#[rustler::nif]
+fn read_attachment_chunk(
+    env: Env,
+    stream: ResourceArc<AttachmentStream>,
+    index: usize,
+    offset: usize,
+) -> NifResult<Option<Binary>> {
+    let size = stream.contents(index).ok_or(Error::BadArg)?.len();
+
+    if offset >= size {
+        return Ok(None);
+    }
+
+    let end = size.min(offset.saturating_add(stream.chunk_size));
+    let chunk = stream.make_binary(env, |stream| {
+        &stream.contents(index).unwrap_or_default()[offset..end]
+    });
+
+    Ok(Some(chunk))
+}
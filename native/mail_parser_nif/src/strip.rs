@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use mail_parser::{Message, MessagePart};
+use rustler::{Atom, Binary, Decoder, Env, NewBinary, NifResult, Term};
+use sha2::{Digest, Sha256};
+
+use crate::atoms;
+use crate::attachment::{select_attachments, AttachmentPart, LimitExceeded};
+use crate::error::parse_message;
+use crate::iodata::RawMessage;
+use crate::options::Options;
+
+/// Options accepted by `strip_attachments/2`, on top of the attachment
+/// extraction options selecting the attachments to strip.
+struct StripOptions {
+    options: Options,
+    /// Text of the part replacing each stripped attachment, which is
+    /// removed altogether if not given.
+    placeholder: Option<String>,
+}
+
+impl<'a> Decoder<'a> for StripOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut strip_options = StripOptions {
+            options: Options::default(),
+            placeholder: None,
+        };
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::placeholder() {
+                strip_options.placeholder = value.decode()?;
+            } else {
+                strip_options.options.set(key, value)?;
+            }
+        }
+
+        // Only whole parts can be stripped, not the files found in them.
+        strip_options.options.expand_tnef = false;
+        strip_options.options.expand_archives = false;
+        strip_options.options.uudecode = false;
+        strip_options.options.decompress = false;
+
+        Ok(strip_options)
+    }
+}
+
+/// Returns the ranges of the raw message covered by the parts of a message
+/// and of its nested messages, keyed by the address of the part.
+///
+/// Nested messages that were transfer encoded are parsed from a decoded
+/// copy, so their parts are left out.
+fn part_ranges(message: &Message, raw_message: &[u8]) -> HashMap<usize, (usize, usize)> {
+    let mut ranges = HashMap::new();
+    let mut stack = vec![message];
+
+    while let Some(message) = stack.pop() {
+        if message.raw_message.as_ptr() != raw_message.as_ptr() {
+            continue;
+        }
+
+        for part in &message.parts {
+            ranges.insert(address(part), (part.offset_header, part.offset_end));
+            stack.extend(part.message());
+        }
+    }
+
+    ranges
+}
+
+fn address(part: &MessagePart) -> usize {
+    part as *const MessagePart as usize
+}
+
+/// Returns the position of the delimiter line preceding a part starting at
+/// `start`, including the line break before it, which is part of the
+/// delimiter (RFC 2046).
+fn delimiter_start(raw_message: &[u8], start: usize) -> usize {
+    let line_start = raw_message[..start.saturating_sub(1)]
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |pos| pos + 1);
+
+    match raw_message[..line_start] {
+        [.., b'\r', b'\n'] => line_start - 2,
+        [.., b'\n'] => line_start - 1,
+        _ => line_start,
+    }
+}
+
+/// Returns the part replacing a stripped attachment, with the `{name}`,
+/// `{content_type}`, `{size}` and `{sha256}` placeholders of `text` filled
+/// in.
+fn placeholder_part(text: &str, attachment: &AttachmentPart, line_break: &str) -> String {
+    let text = text
+        .replace("{name}", attachment.name())
+        .replace(
+            "{content_type}",
+            attachment.content_type().as_deref().unwrap_or_default(),
+        )
+        .replace("{size}", &attachment.contents().len().to_string())
+        .replace(
+            "{sha256}",
+            &format!("{:x}", Sha256::digest(attachment.contents())),
+        );
+
+    let mut part = String::from("Content-Type: text/plain; charset=utf-8");
+    part.push_str(line_break);
+    part.push_str("Content-Disposition: inline");
+    part.push_str(line_break);
+
+    if !text.is_ascii() {
+        part.push_str("Content-Transfer-Encoding: 8bit");
+        part.push_str(line_break);
+    }
+
+    part.push_str(line_break);
+    part.push_str(&text.lines().collect::<Vec<_>>().join(line_break));
+    part
+}
+
+/// Returns the raw message with the selected attachments removed, or
+/// replaced by a placeholder part. Everything else is copied as is.
+fn strip(
+    message: &Message,
+    raw_message: &[u8],
+    strip_options: &StripOptions,
+) -> Result<Option<Vec<u8>>, LimitExceeded> {
+    let ranges = part_ranges(message, raw_message);
+    let line_break = if raw_message.windows(2).any(|pair| pair == b"\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+
+    let mut edits: Vec<(usize, usize, Option<String>)> = Vec::new();
+
+    for attachment in select_attachments(message, &strip_options.options)? {
+        // Attachments of the message wrapped by an opaque-signed part cannot
+        // be stripped without invalidating the signature.
+        let (AttachmentPart::Part(part, ..) | AttachmentPart::Unnamed(part, ..)) = &attachment
+        else {
+            continue;
+        };
+
+        // A message made of a single attachment is left as is, as stripping
+        // it would strip the message headers along with it.
+        let Some(&(start, end)) = ranges.get(&address(part)).filter(|(start, _)| *start > 0) else {
+            continue;
+        };
+
+        edits.push(match &strip_options.placeholder {
+            Some(text) => (
+                start,
+                end,
+                Some(placeholder_part(text, &attachment, line_break)),
+            ),
+            None => (delimiter_start(raw_message, start), end, None),
+        });
+    }
+
+    if edits.is_empty() {
+        return Ok(None);
+    }
+
+    // An attached message may be stripped along with its own attachments,
+    // which are then covered by its range already.
+    edits.sort_by_key(|&(start, _, _)| start);
+
+    let mut stripped = Vec::with_capacity(raw_message.len());
+    let mut pos = 0;
+
+    for (start, end, replacement) in edits {
+        if start < pos {
+            continue;
+        }
+
+        stripped.extend_from_slice(&raw_message[pos..start]);
+        stripped.extend_from_slice(replacement.unwrap_or_default().as_bytes());
+        pos = end;
+    }
+
+    stripped.extend_from_slice(&raw_message[pos..]);
+    Ok(Some(stripped))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn strip_attachments<'a>(
+    env: Env<'a>,
+    raw_message: RawMessage<'a>,
+    strip_options: StripOptions,
+) -> NifResult<(Atom, Binary<'a>)> {
+    let message = parse_message(raw_message.as_slice())?;
+
+    match strip(&message, raw_message.as_slice(), &strip_options)? {
+        Some(stripped) => {
+            let mut binary = NewBinary::new(env, stripped.len());
+            binary.as_mut_slice().copy_from_slice(&stripped);
+            Ok((atoms::ok(), binary.into()))
+        }
+        None => Ok((atoms::ok(), *raw_message)),
+    }
+}
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+
+use mail_parser::{Message, MessagePart, MimeHeaders, PartType};
+use rustler::{Atom, Binary, Env, NifMap, NifResult, ResourceArc};
+
+use crate::atoms;
+use crate::attachment::{
+    content_type, contents, select_attachments_with_duplicates, AttachmentPart, Disposition, Source,
+};
+use crate::digest::{digests, Digests};
+use crate::error::{parse_message, ErrorReason};
+use crate::header::{headers, Header};
+use crate::iodata::RawMessage;
+use crate::message::MessageResource;
+use crate::options::Options;
+
+/// Depth below which the children of a part are not listed, keeping the
+/// recursion needed to build and encode the tree bounded.
+const MAX_DEPTH: usize = 100;
+
+/// A part of the MIME tree, without its body.
+#[derive(NifMap)]
+pub struct PartStructure {
+    path: Vec<usize>,
+    content_type: Option<String>,
+    disposition: Option<Disposition>,
+    size: usize,
+    encoding: Option<String>,
+    children: Vec<PartStructure>,
+}
+
+impl PartStructure {
+    fn new(message: &Message, part: &MessagePart, path: Vec<usize>) -> Self {
+        let is_multipart = matches!(part.body, PartType::Multipart(_));
+
+        let children = if path.len() < MAX_DEPTH {
+            children(message, part)
+                .into_iter()
+                .enumerate()
+                .map(|(index, (message, child))| {
+                    let mut child_path = path.clone();
+                    child_path.push(index);
+                    PartStructure::new(message, child, child_path)
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        PartStructure {
+            content_type: content_type(part),
+            disposition: part.content_disposition().and_then(|content_disposition| {
+                if content_disposition.is_inline() {
+                    Some(Disposition::Inline)
+                } else if content_disposition.is_attachment() {
+                    Some(Disposition::Attachment)
+                } else {
+                    None
+                }
+            }),
+            size: if is_multipart {
+                part.raw_len()
+            } else {
+                contents(part).len()
+            },
+            encoding: part
+                .content_transfer_encoding()
+                .map(|encoding| encoding.to_ascii_lowercase()),
+            path,
+            children,
+        }
+    }
+}
+
+/// A single part of a message, with its decoded body.
+#[derive(NifMap)]
+pub struct Part<'a> {
+    path: Vec<usize>,
+    content_type: Option<String>,
+    headers: Vec<Header>,
+    content_bytes: Binary<'a>,
+}
+
+impl<'a> Part<'a> {
+    fn new(
+        env: Env<'a>,
+        source: &Source<'a>,
+        message: &Message,
+        part: &MessagePart,
+        path: Vec<usize>,
+    ) -> Self {
+        Part {
+            path,
+            content_type: content_type(part),
+            headers: headers(message, part.headers(), None),
+            content_bytes: source.binary(env, contents(part)),
+        }
+    }
+}
+
+/// The metadata of an attachment, without its contents.
+#[derive(NifMap)]
+pub struct AttachmentInfo {
+    name: String,
+    raw_name: Option<String>,
+    content_type: Option<String>,
+    size: usize,
+    disposition: Disposition,
+    content_id: Option<String>,
+    path: Vec<usize>,
+    digests: Digests,
+    part_headers: Option<HashMap<String, Vec<String>>>,
+    duplicates: usize,
+}
+
+/// A message attached to another, with the path of the part holding it.
+#[derive(NifMap)]
+pub struct EmbeddedMessage {
+    path: Vec<usize>,
+    content_type: Option<String>,
+    message: ResourceArc<MessageResource>,
+}
+
+impl AttachmentInfo {
+    /// Describes an attachment, found at one of the `paths` returned by
+    /// `part_paths/1`.
+    pub fn new(
+        attachment: &AttachmentPart,
+        paths: &HashMap<usize, Vec<usize>>,
+        duplicates: usize,
+        options: &Options,
+    ) -> Self {
+        AttachmentInfo {
+            name: attachment.name().to_string(),
+            raw_name: attachment.raw_name().map(str::to_string),
+            content_type: attachment.content_type(),
+            size: attachment.contents().len(),
+            disposition: attachment.disposition(),
+            content_id: attachment.content_id().map(str::to_string),
+            path: paths
+                .get(&address(attachment.part()))
+                .cloned()
+                .unwrap_or_default(),
+            digests: digests(&options.digests, attachment.contents()),
+            part_headers: options
+                .include_part_headers
+                .then(|| attachment.part_headers()),
+            duplicates,
+        }
+    }
+}
+
+/// Returns the children of a part along with the message they belong to.
+///
+/// The children of a multipart are its parts, an attached message has its
+/// root part as its only child.
+pub fn children<'m, 'x>(
+    message: &'m Message<'x>,
+    part: &'m MessagePart<'x>,
+) -> Vec<(&'m Message<'x>, &'m MessagePart<'x>)> {
+    match &part.body {
+        PartType::Multipart(part_ids) => part_ids
+            .iter()
+            .filter_map(|&part_id| Some((message, message.parts.get(part_id)?)))
+            .collect(),
+        PartType::Message(nested_message) => vec![(nested_message, nested_message.root_part())],
+        _ => Vec::new(),
+    }
+}
+
+/// Returns the part at `path`, where each index selects a child as listed
+/// by `get_mime_structure/1`, starting from the root part.
+pub fn part_at<'m, 'x>(
+    message: &'m Message<'x>,
+    path: &[usize],
+) -> Option<(&'m Message<'x>, &'m MessagePart<'x>)> {
+    path.iter()
+        .try_fold((message, message.root_part()), |(message, part), &index| {
+            children(message, part).get(index).copied()
+        })
+}
+
+/// Returns the path of every part of a message, keyed by the address of the
+/// part.
+pub fn part_paths(message: &Message) -> HashMap<usize, Vec<usize>> {
+    let mut paths = HashMap::new();
+    let mut stack = vec![(message, message.root_part(), Vec::new())];
+
+    while let Some((message, part, path)) = stack.pop() {
+        for (index, (message, child)) in children(message, part).into_iter().enumerate() {
+            let mut child_path = path.clone();
+            child_path.push(index);
+            stack.push((message, child, child_path));
+        }
+
+        paths.insert(address(part), path);
+    }
+
+    paths
+}
+
+/// Returns the address of a part, identifying it in the map returned by
+/// `part_paths/1`.
+pub fn address(part: &MessagePart) -> usize {
+    part as *const MessagePart as usize
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_mime_structure(resource: ResourceArc<MessageResource>) -> PartStructure {
+    let message = resource.message();
+    PartStructure::new(message, message.root_part(), Vec::new())
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_part<'a>(
+    env: Env<'a>,
+    raw_message: RawMessage<'a>,
+    path: Vec<usize>,
+) -> NifResult<(Atom, Part<'a>)> {
+    let message = parse_message(raw_message.as_slice())?;
+    let (message, part) = part_at(&message, &path).ok_or(ErrorReason::PartNotFound)?;
+    let source = Source::Binary(*raw_message);
+
+    Ok((atoms::ok(), Part::new(env, &source, message, part, path)))
+}
+
+#[rustler::nif]
+fn get_part(env: Env, resource: ResourceArc<MessageResource>, path: Vec<usize>) -> Option<Part> {
+    let (message, part) = part_at(resource.message(), &path)?;
+    let source = Source::Resource(resource.clone());
+
+    Some(Part::new(env, &source, message, part, path))
+}
+
+/// Returns the bytes of a part exactly as they appear in the message it
+/// belongs to, from its first header to the end of its still encoded body.
+pub fn raw_part<'m>(message: &'m Message, part: &MessagePart) -> &'m [u8] {
+    message
+        .raw_message
+        .get(part.offset_header..part.offset_end)
+        .unwrap_or_default()
+}
+
+#[rustler::nif]
+fn get_raw_part(
+    env: Env,
+    resource: ResourceArc<MessageResource>,
+    path: Vec<usize>,
+) -> Option<Binary> {
+    let (message, part) = part_at(resource.message(), &path)?;
+    let source = Source::Resource(resource.clone());
+
+    Some(source.binary(env, raw_part(message, part)))
+}
+
+/// Returns the messages attached to a message, at any depth, along with
+/// the path and content type of the part holding each, in the order they
+/// appear in the message.
+///
+/// The messages reference the parts of `resource` holding them rather than
+/// copying them, which would copy the messages nested in them again.
+fn embedded_messages(resource: &ResourceArc<MessageResource>) -> Vec<EmbeddedMessage> {
+    let mut embedded_messages = Vec::new();
+    let message = resource.message();
+    let mut stack = vec![(message, message.root_part(), Vec::new())];
+
+    while let Some((message, part, path)) = stack.pop() {
+        if let Some(nested_message) = MessageResource::nested(resource.clone(), path.clone()) {
+            embedded_messages.push(EmbeddedMessage {
+                path: path.clone(),
+                content_type: content_type(part),
+                message: ResourceArc::new(nested_message),
+            });
+        }
+
+        for (index, (message, child)) in children(message, part).into_iter().enumerate() {
+            let mut child_path = path.clone();
+            child_path.push(index);
+            stack.push((message, child, child_path));
+        }
+    }
+
+    embedded_messages.sort_by(|a, b| a.path.cmp(&b.path));
+    embedded_messages
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_embedded_messages(resource: ResourceArc<MessageResource>) -> Vec<EmbeddedMessage> {
+    embedded_messages(&resource)
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn list_attachments(
+    resource: ResourceArc<MessageResource>,
+    options: Options,
+) -> NifResult<Vec<AttachmentInfo>> {
+    let message = resource.message();
+    let paths = part_paths(message);
+
+    let (attachments, duplicates) = select_attachments_with_duplicates(message, &options)?;
+
+    let attachments = attachments
+        .into_iter()
+        .zip(duplicates)
+        .map(|(attachment, duplicates)| {
+            AttachmentInfo::new(&attachment, &paths, duplicates, &options)
+        })
+        .collect();
+
+    Ok(attachments)
+}
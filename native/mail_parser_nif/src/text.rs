@@ -0,0 +1,394 @@
+use mail_parser::decoders::html::add_html_token;
+use mail_parser::{Message, PartType};
+use rustler::{Atom, Decoder, Error, NifResult, ResourceArc, Term};
+
+use crate::atoms;
+use crate::message::MessageResource;
+use crate::sanitize::{decode_entities, parse_tag};
+
+/// Elements whose contents are not rendered.
+const HIDDEN_ELEMENTS: [&str; 4] = ["head", "script", "style", "title"];
+
+/// Elements rendered as a paragraph, separated by a blank line.
+const PARAGRAPH_ELEMENTS: [&str; 12] = [
+    "p",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "blockquote",
+    "pre",
+    "table",
+    "ul",
+    "ol",
+];
+
+/// Elements rendered on a line of their own.
+const LINE_ELEMENTS: [&str; 6] = ["br", "div", "li", "tr", "hr", "dd"];
+
+/// Lines starting a forwarded or quoted original message in common clients.
+const ORIGINAL_MESSAGE_SEPARATORS: [&str; 3] = [
+    "-----Original Message-----",
+    "---------- Forwarded message",
+    "________________________________",
+];
+
+/// Signatures added by mobile clients at the end of the messages they send.
+const MOBILE_SIGNATURES: [&str; 3] = ["Sent from my ", "Get Outlook for ", "Sent from Mail for "];
+
+/// Length of the longest named HTML entity.
+const MAX_ENTITY_LEN: usize = 32;
+
+/// Options accepted by `get_plaintext/2`, decoded from a keyword list.
+#[derive(Default)]
+pub struct TextOptions {
+    /// Maximum length of the returned text, in characters.
+    pub max_length: Option<usize>,
+}
+
+impl<'a> Decoder<'a> for TextOptions {
+    fn decode(term: Term<'a>) -> NifResult<Self> {
+        let mut text_options = TextOptions::default();
+
+        for (key, value) in term.decode::<Vec<(Atom, Term)>>()? {
+            if key == atoms::max_length() {
+                text_options.max_length = Some(value.decode()?);
+            } else {
+                return Err(Error::BadArg);
+            }
+        }
+
+        Ok(text_options)
+    }
+}
+
+/// Accumulates rendered text, collapsing whitespace and line breaks.
+#[derive(Default)]
+struct TextWriter {
+    text: String,
+    pending_space: bool,
+    pending_newlines: usize,
+}
+
+impl TextWriter {
+    fn push_str(&mut self, text: &str) {
+        for ch in text.chars() {
+            if ch.is_whitespace() {
+                self.pending_space = true;
+                continue;
+            }
+
+            if !self.text.is_empty() {
+                if self.pending_newlines > 0 {
+                    self.text.push_str(&"\n".repeat(self.pending_newlines));
+                } else if self.pending_space {
+                    self.text.push(' ');
+                }
+            }
+
+            self.pending_space = false;
+            self.pending_newlines = 0;
+            self.text.push(ch);
+        }
+    }
+
+    fn push_newlines(&mut self, count: usize) {
+        self.pending_newlines = self.pending_newlines.max(count);
+    }
+}
+
+/// Converts an HTML document into readable plain text.
+///
+/// Tags are stripped, the contents of hidden elements such as `<style>`
+/// dropped, entities decoded and whitespace collapsed. Block elements are
+/// rendered on lines of their own and links as `text (href)`.
+pub fn html_to_text(html: &str) -> String {
+    let mut writer = TextWriter::default();
+    let mut links: Vec<(usize, Option<String>)> = Vec::new();
+    let mut rest = html;
+
+    while let Some(pos) = rest.find(['<', '&']) {
+        writer.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+
+        if rest.starts_with('&') {
+            match entity_len(rest) {
+                Some(len) => {
+                    let mut decoded = String::new();
+                    add_html_token(&mut decoded, &rest.as_bytes()[..len], false);
+                    writer.push_str(&decoded);
+                    rest = &rest[len..];
+                }
+                None => {
+                    writer.push_str("&");
+                    rest = &rest[1..];
+                }
+            }
+
+            continue;
+        }
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+
+        let starts_tag = rest[1..]
+            .chars()
+            .next()
+            .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '/' || ch == '!');
+
+        if !starts_tag {
+            writer.push_str("<");
+            rest = &rest[1..];
+            continue;
+        }
+
+        let Some(end) = tag_end(rest) else {
+            break;
+        };
+
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let is_closing = tag.starts_with('/');
+        let name = tag
+            .trim_start_matches('/')
+            .split(|ch: char| ch.is_whitespace() || ch == '/')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if HIDDEN_ELEMENTS.contains(&name.as_str()) && !is_closing {
+            let closing_tag = format!("</{name}");
+            rest = find_ignore_ascii_case(rest, &closing_tag).map_or("", |pos| &rest[pos..]);
+        } else if PARAGRAPH_ELEMENTS.contains(&name.as_str()) {
+            writer.push_newlines(2);
+        } else if LINE_ELEMENTS.contains(&name.as_str()) {
+            writer.push_newlines(1);
+
+            if name == "li" && !is_closing {
+                writer.push_str("- ");
+            }
+        } else if name == "a" && !is_closing {
+            links.push((writer.text.len(), attribute(tag, "href")));
+        } else if name == "a" {
+            if let Some((start, Some(href))) = links.pop() {
+                let label = writer.text[start..].trim();
+                let target = href.strip_prefix("mailto:").unwrap_or(&href);
+
+                if !href.starts_with('#') && label != target {
+                    writer.push_str(&format!(" ({href})"));
+                }
+            }
+        }
+    }
+
+    writer.push_str(rest);
+    writer.text
+}
+
+/// Returns the length of the `&name;` or `&#number;` entity `html` starts
+/// with, if any.
+pub fn entity_len(html: &str) -> Option<usize> {
+    html.strip_prefix('&')?
+        .find(|ch: char| !ch.is_ascii_alphanumeric() && ch != '#')
+        .filter(|&len| len <= MAX_ENTITY_LEN && html[1 + len..].starts_with(';'))
+        .map(|len| len + 2)
+}
+
+/// Returns the position of the `>` closing the tag `html` starts with,
+/// skipping quoted attribute values.
+pub fn tag_end(html: &str) -> Option<usize> {
+    let mut quote = None;
+
+    html.char_indices().find_map(|(pos, ch)| {
+        match (quote, ch) {
+            (None, '"' | '\'') => quote = Some(ch),
+            (Some(open), _) if open == ch => quote = None,
+            (None, '>') => return Some(pos),
+            _ => (),
+        }
+        None
+    })
+}
+
+pub fn find_ignore_ascii_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// Returns the value of the attribute `name` of a tag, with its entities
+/// decoded, if present. The tag is tokenized as the sanitizer does, so that
+/// neither longer attribute names such as `data-href` nor quoted values
+/// are mistaken for the attribute.
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let (_, attributes) = parse_tag(tag);
+    let value = attributes
+        .into_iter()
+        .find(|attribute| attribute.name == name)?
+        .value?;
+
+    Some(decode_entities(value).trim().to_string())
+}
+
+/// Truncates `text` to at most `max_length` characters, preferring to cut
+/// at a word boundary.
+pub fn truncate(mut text: String, max_length: usize) -> String {
+    if let Some((end, next_ch)) = text.char_indices().nth(max_length) {
+        let end = match text[..end].rfind(char::is_whitespace) {
+            Some(word_end) if word_end > 0 && !next_ch.is_whitespace() => word_end,
+            _ => end,
+        };
+
+        text.truncate(end);
+        text.truncate(text.trim_end().len());
+    }
+
+    text
+}
+
+/// Returns the text a reader sees first, on a single line: quoted lines,
+/// reply headers such as `On ... wrote:` and everything after a signature
+/// or original message separator are left out.
+pub fn preview(text: &str) -> String {
+    let mut writer = TextWriter::default();
+
+    for line in text.lines() {
+        let trimmed_line = line.trim();
+
+        if is_separator(line) {
+            break;
+        }
+
+        if trimmed_line.starts_with('>') || is_reply_header(trimmed_line) {
+            continue;
+        }
+
+        writer.push_str(line);
+        writer.pending_space = true;
+    }
+
+    writer.text
+}
+
+/// Returns the newly written part of a reply: quoted lines and reply
+/// headers are left out, as is everything after a signature delimiter, an
+/// original message separator or an Outlook-style header block, and mobile
+/// signatures such as `Sent from my iPhone` ending the text.
+///
+/// Line breaks are kept, with the blank lines left by removed quotes
+/// collapsed.
+pub fn reply_text(text: &str) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut reply_lines: Vec<&str> = Vec::new();
+    let mut pos = 0;
+
+    while let Some(&line) = lines.get(pos) {
+        let trimmed_line = line.trim();
+        let next_line = lines.get(pos + 1).map_or("", |line| line.trim());
+
+        if is_separator(line) || is_header_block(trimmed_line, next_line) {
+            break;
+        }
+
+        pos += 1;
+
+        if trimmed_line.starts_with('>') || is_reply_header(trimmed_line) {
+            continue;
+        }
+
+        // Long reply headers are often wrapped, e.g. before the address.
+        if trimmed_line.starts_with("On ") && is_reply_header(next_line) {
+            pos += 1;
+            continue;
+        }
+
+        if trimmed_line.is_empty() && reply_lines.last().is_none_or(|line| line.is_empty()) {
+            continue;
+        }
+
+        reply_lines.push(line.trim_end());
+    }
+
+    while let Some(line) = reply_lines.last() {
+        if line.is_empty() || is_mobile_signature(line.trim()) {
+            reply_lines.pop();
+        } else {
+            break;
+        }
+    }
+
+    reply_lines.join("\n")
+}
+
+/// Returns whether a line is a signature delimiter or starts an original
+/// message.
+fn is_separator(line: &str) -> bool {
+    let trimmed_line = line.trim();
+
+    line == "-- "
+        || trimmed_line == "--"
+        || ORIGINAL_MESSAGE_SEPARATORS
+            .iter()
+            .any(|separator| trimmed_line.starts_with(separator))
+}
+
+/// Returns whether a line starts the header block Outlook quotes original
+/// messages with, e.g. `From: Joe` followed by `Sent: Tuesday`.
+fn is_header_block(line: &str, next_line: &str) -> bool {
+    line.starts_with("From:") && (next_line.starts_with("Sent:") || next_line.starts_with("Date:"))
+}
+
+/// Returns whether a line is a signature added by a mobile client.
+fn is_mobile_signature(line: &str) -> bool {
+    MOBILE_SIGNATURES
+        .iter()
+        .any(|signature| line.starts_with(signature))
+}
+
+/// Returns whether a line introduces a quoted reply, e.g. `On Tue, 17 May
+/// 2022, Joe wrote:`.
+fn is_reply_header(line: &str) -> bool {
+    line.ends_with("wrote:") || line.ends_with("schrieb:") || line.ends_with("a écrit :")
+}
+
+/// Returns the text body of a message or, for HTML-only messages, its HTML
+/// body converted to plain text.
+pub fn plaintext(message: &Message) -> Option<String> {
+    let index = *message.text_body.first()?;
+
+    match &message.parts.get(index)?.body {
+        PartType::Text(text) => Some(text.to_string()),
+        PartType::Html(html) => Some(html_to_text(html)),
+        _ => None,
+    }
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_plaintext(
+    resource: ResourceArc<MessageResource>,
+    text_options: TextOptions,
+) -> Option<String> {
+    let text = plaintext(resource.message())?;
+
+    Some(match text_options.max_length {
+        Some(max_length) => truncate(text, max_length),
+        None => text,
+    })
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_reply_text(resource: ResourceArc<MessageResource>) -> Option<String> {
+    plaintext(resource.message()).map(|text| reply_text(&text))
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn get_preview(resource: ResourceArc<MessageResource>, length: usize) -> String {
+    let text = plaintext(resource.message()).unwrap_or_default();
+    truncate(preview(&text), length)
+}
@@ -0,0 +1,38 @@
+use mail_parser::{HeaderValue, Message};
+use rustler::{NifMap, ResourceArc};
+
+use crate::message::MessageResource;
+
+#[derive(NifMap)]
+pub struct ThreadInfo {
+    message_id: Option<String>,
+    in_reply_to: Vec<String>,
+    references: Vec<String>,
+    base_subject: Option<String>,
+}
+
+impl From<&Message<'_>> for ThreadInfo {
+    fn from(message: &Message) -> Self {
+        ThreadInfo {
+            message_id: message.message_id().map(str::to_string),
+            in_reply_to: ids(message.in_reply_to()),
+            references: ids(message.references()),
+            base_subject: message.thread_name().map(str::to_string),
+        }
+    }
+}
+
+/// Returns the message ids of a message id header, in order and without
+/// their angle brackets.
+fn ids(value: &HeaderValue) -> Vec<String> {
+    match value {
+        HeaderValue::Text(id) => vec![id.to_string()],
+        HeaderValue::TextList(ids) => ids.iter().map(|id| id.to_string()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[rustler::nif]
+fn get_thread_info(resource: ResourceArc<MessageResource>) -> ThreadInfo {
+    ThreadInfo::from(resource.message())
+}
@@ -0,0 +1,256 @@
+use mail_parser::{MessagePart, MimeHeaders};
+
+use crate::attachment::{attachment_name, DEFAULT_CONTENT_TYPE};
+
+/// Signature every TNEF stream starts with.
+const TNEF_SIGNATURE: u32 = 0x223E_9F78;
+
+/// Level of the attributes describing an attachment, as opposed to the
+/// message itself.
+const LVL_ATTACHMENT: u8 = 0x02;
+
+/// Attribute starting a new attachment.
+const ATT_ATTACH_REND_DATA: u32 = 0x0006_9002;
+/// Attribute holding the short name of an attachment.
+const ATT_ATTACH_TITLE: u32 = 0x0001_8010;
+/// Attribute holding the contents of an attachment.
+const ATT_ATTACH_DATA: u32 = 0x0006_800F;
+/// Attribute holding the MAPI properties of an attachment.
+const ATT_ATTACHMENT: u32 = 0x0006_9005;
+
+const PR_ATTACH_DATA_BIN: u16 = 0x3701;
+const PR_ATTACH_FILENAME: u16 = 0x3704;
+const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+const PR_ATTACH_MIME_TAG: u16 = 0x370E;
+const PR_ATTACH_CONTENT_ID: u16 = 0x3712;
+
+/// First ID of the named properties, which are preceded by their name.
+const NAMED_PROPERTY_ID: u16 = 0x8000;
+
+/// Flag set on the type of multi-valued properties.
+const MV_FLAG: u16 = 0x1000;
+
+const PT_STRING8: u16 = 0x001E;
+const PT_UNICODE: u16 = 0x001F;
+
+/// A file unpacked from a TNEF part.
+#[derive(Default)]
+pub struct TnefAttachment<'m> {
+    pub name: Option<String>,
+    pub content_type: Option<String>,
+    pub content_id: Option<String>,
+    pub contents: &'m [u8],
+}
+
+/// Returns whether a part is a TNEF part, either by its content type or, as
+/// some clients label them `application/octet-stream`, by its name.
+pub fn is_tnef(part: &MessagePart) -> bool {
+    let is_tnef_type = part.content_type().is_some_and(|content_type| {
+        content_type.ctype().eq_ignore_ascii_case("application")
+            && content_type.subtype().is_some_and(|subtype| {
+                subtype.eq_ignore_ascii_case("ms-tnef")
+                    || subtype.eq_ignore_ascii_case("vnd.ms-tnef")
+            })
+    });
+
+    is_tnef_type || attachment_name(part).eq_ignore_ascii_case("winmail.dat")
+}
+
+/// Decodes the attachments of a TNEF stream, in order.
+///
+/// Returns `None` when the stream is not valid TNEF, so that the part can
+/// be returned as is. Attachments without contents, such as embedded
+/// messages, are left out.
+pub fn decode(bytes: &[u8]) -> Option<Vec<TnefAttachment<'_>>> {
+    let mut reader = Reader(bytes);
+    let mut attachments = Vec::new();
+    let mut data = Vec::new();
+
+    if reader.u32()? != TNEF_SIGNATURE {
+        return None;
+    }
+
+    // Legacy key, unused.
+    reader.take(2)?;
+
+    while !reader.0.is_empty() {
+        let level = reader.u8()?;
+        let id = reader.u32()?;
+        let len = reader.u32()?;
+        let value = reader.take(usize::try_from(len).ok()?)?;
+        // Checksum of the value, not verified.
+        reader.take(2)?;
+
+        if level != LVL_ATTACHMENT {
+            continue;
+        }
+
+        if id == ATT_ATTACH_REND_DATA {
+            attachments.push(TnefAttachment::default());
+            data.push(None);
+            continue;
+        }
+
+        let (Some(attachment), Some(data)) = (attachments.last_mut(), data.last_mut()) else {
+            continue;
+        };
+
+        match id {
+            ATT_ATTACH_TITLE if attachment.name.is_none() => {
+                attachment.name = Some(string8(value));
+            }
+            ATT_ATTACH_DATA => *data = Some(value),
+            ATT_ATTACHMENT => {
+                // Keep the attributes decoded so far if the properties are
+                // malformed.
+                let _ = set_properties(attachment, data, value);
+            }
+            _ => (),
+        }
+    }
+
+    let attachments = attachments
+        .into_iter()
+        .zip(data)
+        .filter_map(|(mut attachment, data)| {
+            attachment.contents = data?;
+            attachment
+                .content_type
+                .get_or_insert_with(|| DEFAULT_CONTENT_TYPE.to_string());
+            Some(attachment)
+        })
+        .collect();
+
+    Some(attachments)
+}
+
+/// Sets the name, content type and, for attachments carrying them as a
+/// property rather than as an attribute, contents of an attachment from its
+/// MAPI properties.
+fn set_properties<'m>(
+    attachment: &mut TnefAttachment,
+    data: &mut Option<&'m [u8]>,
+    properties: &'m [u8],
+) -> Option<()> {
+    let mut reader = Reader(properties);
+
+    for _ in 0..reader.u32()? {
+        let property_type = reader.u16()?;
+        let property_id = reader.u16()?;
+
+        if property_id >= NAMED_PROPERTY_ID {
+            // GUID, then either a numeric ID or a UTF-16 name.
+            reader.take(16)?;
+
+            if reader.u32()? == 0 {
+                reader.take(4)?;
+            } else {
+                let len = reader.u32()?;
+                reader.take_padded(usize::try_from(len).ok()?)?;
+            }
+        }
+
+        let values = reader.values(property_type)?;
+        let Some(&value) = values.first() else {
+            continue;
+        };
+
+        let string = || match property_type {
+            PT_UNICODE => Some(unicode(value)),
+            PT_STRING8 => Some(string8(value)),
+            _ => None,
+        };
+
+        match property_id {
+            PR_ATTACH_LONG_FILENAME => attachment.name = string().or(attachment.name.take()),
+            PR_ATTACH_FILENAME if attachment.name.is_none() => attachment.name = string(),
+            PR_ATTACH_MIME_TAG => attachment.content_type = string(),
+            PR_ATTACH_CONTENT_ID => attachment.content_id = string(),
+            PR_ATTACH_DATA_BIN if data.is_none() => *data = Some(value),
+            _ => (),
+        }
+    }
+
+    Some(())
+}
+
+/// Decodes a null terminated 8-bit string, assumed to be Latin-1.
+fn string8(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|&&byte| byte != 0)
+        .map(|&byte| byte as char)
+        .collect()
+}
+
+/// Decodes a null terminated UTF-16LE string.
+fn unicode(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+
+    String::from_utf16_lossy(&units)
+}
+
+/// Reads the little-endian values a TNEF stream is made of.
+struct Reader<'m>(&'m [u8]);
+
+impl<'m> Reader<'m> {
+    fn take(&mut self, len: usize) -> Option<&'m [u8]> {
+        if len > self.0.len() {
+            return None;
+        }
+
+        let (value, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Some(value)
+    }
+
+    /// Takes `len` bytes, skipping the padding aligning MAPI values on four
+    /// bytes.
+    fn take_padded(&mut self, len: usize) -> Option<&'m [u8]> {
+        let value = self.take(len)?;
+        self.take(len.wrapping_neg() % 4)?;
+        Some(value)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let bytes = self.take(2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let bytes = self.take(4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Reads the values of a MAPI property of type `property_type`.
+    fn values(&mut self, property_type: u16) -> Option<Vec<&'m [u8]>> {
+        let base_type = property_type & !MV_FLAG;
+
+        let fixed_len = match base_type {
+            0x0001..=0x0004 | 0x000A | 0x000B => Some(4),
+            0x0005..=0x0007 | 0x0014 | 0x0040 => Some(8),
+            0x0048 => Some(16),
+            0x000D | PT_STRING8 | PT_UNICODE | 0x0102 => None,
+            _ => return None,
+        };
+
+        match fixed_len {
+            Some(len) if property_type & MV_FLAG == 0 => Some(vec![self.take(len)?]),
+            Some(len) => (0..self.u32()?).map(|_| self.take(len)).collect(),
+            None => (0..self.u32()?)
+                .map(|_| {
+                    let len = self.u32()?;
+                    self.take_padded(usize::try_from(len).ok()?)
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+/// A file decoded from a uuencoded block of a text body.
+pub struct UuencodedFile {
+    pub name: String,
+    pub contents: Vec<u8>,
+}
+
+/// Decodes the `begin <mode> <name>` ... `end` blocks embedded in a text,
+/// in order.
+///
+/// Blocks with malformed lines or without an `end` line are left out.
+/// Lines shorter than their length character announces, usually because
+/// trailing spaces were stripped in transit, are padded with zeros.
+pub fn decode(text: &str) -> Vec<UuencodedFile> {
+    let mut files = Vec::new();
+    let mut lines = text.lines();
+
+    while let Some(line) = lines.next() {
+        let Some(name) = begin_line(line) else {
+            continue;
+        };
+
+        let mut contents = Vec::new();
+        let mut is_complete = false;
+
+        for line in lines.by_ref() {
+            if line.trim_end() == "end" {
+                is_complete = true;
+                break;
+            }
+
+            if !decode_line(line, &mut contents) {
+                break;
+            }
+        }
+
+        if is_complete {
+            files.push(UuencodedFile {
+                name: name.to_string(),
+                contents,
+            });
+        }
+    }
+
+    files
+}
+
+/// Returns the file name of a `begin <mode> <name>` line.
+fn begin_line(line: &str) -> Option<&str> {
+    let (mode, name) = line.strip_prefix("begin ")?.split_once(' ')?;
+    let name = name.trim();
+
+    let is_mode =
+        (3..=4).contains(&mode.len()) && mode.bytes().all(|byte| (b'0'..=b'7').contains(&byte));
+
+    (is_mode && !name.is_empty()).then_some(name)
+}
+
+/// Decodes a line of a uuencoded block into `contents`, returning `false`
+/// if it is malformed.
+fn decode_line(line: &str, contents: &mut Vec<u8>) -> bool {
+    let Some((&len_char, chars)) = line.trim_end_matches('\r').as_bytes().split_first() else {
+        return true;
+    };
+
+    let is_uuencoded = |byte: &u8| (b' '..=b'`').contains(byte);
+
+    if !is_uuencoded(&len_char) || !chars.iter().all(is_uuencoded) {
+        return false;
+    }
+
+    let len = usize::from((len_char - b' ') & 0x3F);
+    let start = contents.len();
+
+    for group in chars.chunks(4) {
+        let mut values = [0; 4];
+
+        for (value, &byte) in values.iter_mut().zip(group) {
+            *value = (byte - b' ') & 0x3F;
+        }
+
+        contents.extend_from_slice(&[
+            values[0] << 2 | values[1] >> 4,
+            values[1] << 4 | values[2] >> 2,
+            values[2] << 6 | values[3],
+        ]);
+    }
+
+    contents.resize(start + len, 0);
+    true
+}
@@ -0,0 +1,121 @@
+use mail_parser::{MessagePart, MimeHeaders};
+use rustler::{Atom, NifResult, NifStruct};
+
+use crate::atoms;
+use crate::attachment::attachment_name;
+use crate::content_line::content_lines;
+use crate::error::parse_message;
+use crate::iodata::RawMessage;
+
+#[derive(NifStruct)]
+#[module = "MailParser.Contact"]
+pub struct Contact {
+    name: Option<String>,
+    emails: Vec<String>,
+    phones: Vec<String>,
+    org: Option<String>,
+}
+
+/// Returns whether a part holds vCards, by its content type or, for
+/// attachments labelled `application/octet-stream`, by its `.vcf` name.
+fn is_vcard(part: &MessagePart) -> bool {
+    let is_vcard_type = part.content_type().is_some_and(|content_type| {
+        let subtype = content_type.subtype().unwrap_or_default();
+
+        content_type.ctype().eq_ignore_ascii_case("text")
+            && ["vcard", "x-vcard", "directory"]
+                .iter()
+                .any(|vcard_subtype| subtype.eq_ignore_ascii_case(vcard_subtype))
+    });
+
+    let name = attachment_name(part);
+    let has_vcard_name = name.len() > 4
+        && name.is_char_boundary(name.len() - 4)
+        && name[name.len() - 4..].eq_ignore_ascii_case(".vcf");
+
+    is_vcard_type || (part.is_binary() && has_vcard_name)
+}
+
+/// Returns the contacts of the `BEGIN:VCARD` ... `END:VCARD` blocks of a
+/// text, in order.
+fn contacts(text: &str) -> Vec<Contact> {
+    let mut contacts = Vec::new();
+    let mut contact: Option<Contact> = None;
+    // Formatted name, preferred over the structured `N` property.
+    let mut formatted_name = None;
+
+    for line in content_lines(text) {
+        let value = line.value.trim();
+
+        let Some(current_contact) = contact.as_mut() else {
+            if line.name == "BEGIN" && value.eq_ignore_ascii_case("VCARD") {
+                contact = Some(Contact {
+                    name: None,
+                    emails: Vec::new(),
+                    phones: Vec::new(),
+                    org: None,
+                });
+                formatted_name = None;
+            }
+
+            continue;
+        };
+
+        match line.name.as_str() {
+            "END" if value.eq_ignore_ascii_case("VCARD") => {
+                contacts.extend(contact.take().map(|mut contact| {
+                    contact.name = formatted_name.take().or(contact.name);
+                    contact
+                }));
+            }
+            "FN" if !value.is_empty() => formatted_name = Some(line.text().trim().to_string()),
+            "N" => {
+                // Family, given, additional names, prefixes and suffixes.
+                let components = line.components();
+                let name_parts = [3, 1, 2, 0, 4]
+                    .iter()
+                    .filter_map(|&index| components.get(index))
+                    .map(|component| component.trim())
+                    .filter(|component| !component.is_empty())
+                    .collect::<Vec<_>>();
+
+                if !name_parts.is_empty() {
+                    current_contact.name = Some(name_parts.join(" "));
+                }
+            }
+            "EMAIL" if !value.is_empty() => current_contact.emails.push(line.text()),
+            "TEL" if !value.is_empty() => {
+                let phone = match value.get(..4) {
+                    Some(scheme) if scheme.eq_ignore_ascii_case("tel:") => &value[4..],
+                    _ => value,
+                };
+
+                current_contact.phones.push(phone.to_string());
+            }
+            "ORG" => {
+                current_contact.org = line
+                    .components()
+                    .into_iter()
+                    .map(|component| component.trim().to_string())
+                    .find(|component| !component.is_empty());
+            }
+            _ => (),
+        }
+    }
+
+    contacts
+}
+
+#[rustler::nif(schedule = "DirtyCpu")]
+fn extract_contacts(raw_message: RawMessage) -> NifResult<(Atom, Vec<Contact>)> {
+    let message = parse_message(raw_message.as_slice())?;
+
+    let contacts = message
+        .parts
+        .iter()
+        .filter(|part| is_vcard(part))
+        .flat_map(|part| contacts(&String::from_utf8_lossy(part.contents())))
+        .collect();
+
+    Ok((atoms::ok(), contacts))
+}
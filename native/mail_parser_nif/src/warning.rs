@@ -0,0 +1,88 @@
+use mail_parser::{Message, MessagePart, MimeHeaders, PartType};
+use rustler::{NifMap, NifUnitEnum};
+
+use crate::structure::children;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, NifUnitEnum)]
+pub enum WarningReason {
+    /// No header could be parsed, so the whole input was taken as the body.
+    MissingHeaders,
+    /// The header block is not followed by a blank line and a body.
+    TruncatedHeaders,
+    /// A multipart does not end with its closing delimiter.
+    TruncatedBody,
+    /// A multipart has no boundary, or its boundary is not found in its
+    /// body, which is then kept as is.
+    MissingBoundary,
+    /// The body of a part, or an attached message, could not be decoded
+    /// and is kept as is.
+    InvalidEncodingInPart,
+}
+
+/// A problem found while parsing a damaged message, along with the path of
+/// the part it was found in, as listed by `get_mime_structure/1`.
+#[derive(Debug, NifMap)]
+pub struct Warning {
+    reason: WarningReason,
+    path: Vec<usize>,
+}
+
+impl Warning {
+    pub fn new(reason: WarningReason, path: Vec<usize>) -> Self {
+        Warning { reason, path }
+    }
+}
+
+/// Returns the problems the parser recovered from in a message, in the
+/// order of the parts they were found in.
+pub fn warnings(message: &Message) -> Vec<Warning> {
+    let mut warnings = Vec::new();
+    let mut stack = vec![(message, message.root_part(), Vec::new())];
+
+    while let Some((message, part, path)) = stack.pop() {
+        if let Some(reason) = part_warning(message, part, path.is_empty()) {
+            warnings.push(Warning::new(reason, path.clone()));
+        }
+
+        for (index, (message, child)) in children(message, part).into_iter().enumerate().rev() {
+            let mut child_path = path.clone();
+            child_path.push(index);
+            stack.push((message, child, child_path));
+        }
+    }
+
+    warnings
+}
+
+fn part_warning(message: &Message, part: &MessagePart, is_root: bool) -> Option<WarningReason> {
+    let content_type = part.content_type();
+    let is_multipart = content_type
+        .is_some_and(|content_type| content_type.ctype().eq_ignore_ascii_case("multipart"));
+
+    match &part.body {
+        // The parser keeps the headers of a message without a body as a
+        // root part marked as an encoding problem.
+        _ if is_root
+            && part.is_encoding_problem
+            && part.offset_body == message.raw_message.len() =>
+        {
+            Some(WarningReason::TruncatedHeaders)
+        }
+        _ if part.is_encoding_problem => Some(WarningReason::InvalidEncodingInPart),
+        PartType::Multipart(_) => {
+            let boundary = content_type?.attribute("boundary")?;
+            let closing_delimiter = format!("--{boundary}--");
+            let body = message
+                .raw_message
+                .get(part.offset_body..part.offset_end)
+                .unwrap_or_default();
+
+            (!body
+                .windows(closing_delimiter.len())
+                .any(|window| window == closing_delimiter.as_bytes()))
+            .then_some(WarningReason::TruncatedBody)
+        }
+        _ if is_multipart => Some(WarningReason::MissingBoundary),
+        _ => None,
+    }
+}
@@ -0,0 +1,110 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::thread;
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use rustler::types::atom::badarg;
+use rustler::{Atom, Encoder, Env, Error, OwnedEnv, ResourceArc, Term};
+
+use crate::atoms;
+use crate::iodata::RawMessage;
+use crate::message::{encode_parsed, parse_owned, ParseOptions};
+
+/// A message being parsed by `start_parse/2`, off the schedulers.
+pub struct ParseTask {
+    /// Set once the result is sent, or once the parse is cancelled by
+    /// `cancel_parse/1`, so that a single message is sent for a parse.
+    settled: AtomicBool,
+    /// Set by `cancel_parse/1`, so that `await_parse/1` drops a result sent
+    /// before the parse was cancelled.
+    cancelled: AtomicBool,
+}
+
+impl ParseTask {
+    /// Settles the task, returning whether it was not settled yet.
+    fn settle(&self) -> bool {
+        !self.settled.swap(true, Ordering::AcqRel)
+    }
+}
+
+/// Returns the pool parses are run on, with a thread per core, so that
+/// parses started beyond that wait for a thread rather than each taking one
+/// of their own. Its queue is unbounded, and its threads are not accounted
+/// for by the schedulers of the VM.
+fn pool() -> &'static ThreadPool {
+    static POOL: OnceLock<ThreadPool> = OnceLock::new();
+
+    POOL.get_or_init(|| {
+        let num_threads = thread::available_parallelism().map_or(1, usize::from);
+
+        ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .thread_name(|index| format!("mail_parser-parse-{index}"))
+            .build()
+            .expect("cannot spawn the parse threads")
+    })
+}
+
+/// Parses a raw message on the parse pool, sending `{ref, result}` to the
+/// calling process once parsed, unless the parse is cancelled first, or
+/// `{ref, :badarg}` if the raw message is not iodata.
+#[rustler::nif(name = "start_parse_task")]
+fn start_parse<'a>(
+    env: Env<'a>,
+    raw_message: Term<'a>,
+    parse_options: ParseOptions,
+    reference: Term<'a>,
+) -> (Atom, ResourceArc<ParseTask>) {
+    // Saving the raw message in an environment of its own only references
+    // its binaries, and iolists are only flattened on the pool, so that the
+    // calling scheduler does not copy the raw message.
+    let mut owned_env = OwnedEnv::new();
+    let saved_message = owned_env.save(raw_message);
+    let saved_reference = owned_env.save(reference);
+    let pid = env.pid();
+
+    let task = ResourceArc::new(ParseTask {
+        settled: AtomicBool::new(false),
+        cancelled: AtomicBool::new(false),
+    });
+    let pool_task = task.clone();
+
+    pool().spawn(move || {
+        // The message of a cancelled parse is never parsed, or dropped
+        // right away.
+        if pool_task.settled.load(Ordering::Acquire) {
+            return;
+        }
+
+        let result = owned_env.run(|env| {
+            let raw_message: RawMessage = saved_message.load(env).decode()?;
+            Ok::<_, Error>(parse_owned(raw_message.as_slice(), &parse_options))
+        });
+
+        if pool_task.settle() {
+            owned_env.send_and_clear(&pid, |env| {
+                let result = match result {
+                    Ok(Ok(parsed)) => encode_parsed(env, parsed),
+                    Ok(Err(reason)) => (atoms::error(), reason).encode(env),
+                    Err(_) => badarg().encode(env),
+                };
+
+                (saved_reference.load(env), result).encode(env)
+            });
+        }
+    });
+
+    (atoms::ok(), task)
+}
+
+/// Cancels a parse, returning whether its result was not sent yet.
+#[rustler::nif(name = "cancel_parse_task")]
+fn cancel_parse(task: ResourceArc<ParseTask>) -> bool {
+    task.cancelled.store(true, Ordering::Release);
+    task.settle()
+}
+
+#[rustler::nif]
+fn parse_cancelled(task: ResourceArc<ParseTask>) -> bool {
+    task.cancelled.load(Ordering::Acquire)
+}
@@ -1,7 +1,47 @@
 defmodule MailParserTest do
   use ExUnit.Case
 
-  doctest MailParser, except: [extract_nested_attachments: 1]
+  doctest MailParser,
+    except: [
+      extract_nested_attachments: 2,
+      extract_attachments_to_disk: 3,
+      extract_attachments_stream: 2,
+      parse: 2,
+      parse_yielding: 2,
+      start_parse: 2,
+      extract_addresses: 1,
+      get_header: 2,
+      get_date: 2,
+      get_thread_info: 1,
+      get_plaintext: 2,
+      get_preview: 2,
+      get_reply_text: 1,
+      get_html_body: 2,
+      get_mime_structure: 1,
+      extract_part: 2,
+      get_embedded_messages: 1,
+      list_attachments: 2,
+      stats: 1,
+      parse_mbox: 2,
+      extract_attachments_from_mbox: 2,
+      mbox_next: 2,
+      parse_maildir: 2,
+      maildir_next: 1,
+      extract_attachments_batch: 2,
+      extract_calendar_events: 1,
+      extract_contacts: 1,
+      parse_dsn: 1,
+      get_auth_results: 1,
+      get_received_chain: 1,
+      get_list_info: 1,
+      get_html_with_inline_parts: 2,
+      get_security_info: 1,
+      strip_attachments: 2,
+      build_message: 1,
+      to_json: 2,
+      parse_headers: 1,
+      get_metrics: 0
+    ]
 
   test "extracts attachments from raw message" do
     raw_message = File.read!("test/fixtures/example.txt")
@@ -24,6 +64,2700 @@ defmodule MailParserTest do
   end
 
   test "returns error if parsing fails" do
-    assert :error = MailParser.extract_nested_attachments("")
+    assert {:error, :empty_input} = MailParser.extract_nested_attachments("")
+  end
+
+  test "accepts raw messages that are not valid UTF-8" do
+    raw_message =
+      "From: joe@example.com\r\n" <>
+        "Subject: Gr\xFC\xDFe\r\n" <>
+        "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n" <>
+        "--b\r\n" <>
+        "Content-Type: text/plain; charset=iso-8859-1\r\n" <>
+        "Content-Transfer-Encoding: 8bit\r\n\r\n" <>
+        "Sch\xF6ne Gr\xFC\xDFe\r\n" <>
+        "--b\r\n" <>
+        "Content-Type: application/octet-stream\r\n" <>
+        "Content-Disposition: attachment; filename=\"data.bin\"\r\n" <>
+        "Content-Transfer-Encoding: 8bit\r\n\r\n" <>
+        "\xFF\xFE\x00\x01\r\n" <>
+        "--b--\r\n"
+
+    refute String.valid?(raw_message)
+
+    assert {:ok,
+            [
+              %MailParser.Attachment{
+                name: "data.bin",
+                content_bytes: <<0xFF, 0xFE, 0x00, 0x01>>
+              }
+            ]} = MailParser.extract_nested_attachments(raw_message)
+  end
+
+  test "returns contents that are not transfer-encoded without copying them" do
+    raw_message =
+      "From: joe@example.com\r\n" <>
+        "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n" <>
+        "--b\r\n" <>
+        "Content-Type: text/plain\r\n" <>
+        "Content-Disposition: attachment; filename=\"notes.txt\"\r\n\r\n" <>
+        "Some notes\r\n" <>
+        "--b--\r\n"
+
+    assert {:ok, [%MailParser.Attachment{content_bytes: "Some notes" = content_bytes}]} =
+             MailParser.extract_nested_attachments(raw_message)
+
+    assert :binary.referenced_byte_size(content_bytes) == byte_size(raw_message)
+  end
+
+  describe ":mime_types option" do
+    setup do
+      %{raw_message: File.read!("test/fixtures/example.txt")}
+    end
+
+    test "matches exact content types", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{name: "smime.p7s"}]} =
+               MailParser.extract_nested_attachments(raw_message,
+                 mime_types: ["application/x-pkcs7-signature"]
+               )
+    end
+
+    test "supports wildcards", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{content_type: "application/pdf"}]} =
+               MailParser.extract_nested_attachments(raw_message, mime_types: ["*/pdf"])
+
+      assert {:ok, [_, _]} =
+               MailParser.extract_nested_attachments(raw_message, mime_types: ["application/*"])
+
+      assert {:ok, []} =
+               MailParser.extract_nested_attachments(raw_message, mime_types: ["image/*"])
+    end
+
+    test "matches case-insensitively", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{content_type: "application/pdf"}]} =
+               MailParser.extract_nested_attachments(raw_message, mime_types: ["Application/PDF"])
+    end
+  end
+
+  describe "attachment limits" do
+    setup do
+      %{raw_message: File.read!("test/fixtures/example.txt")}
+    end
+
+    test "abort the extraction by default", %{raw_message: raw_message} do
+      assert {:error, :limit_exceeded} =
+               MailParser.extract_nested_attachments(raw_message, max_attachment_size: 1024)
+
+      assert {:error, :limit_exceeded} =
+               MailParser.extract_nested_attachments(raw_message, max_total_size: 3035)
+
+      assert {:error, :limit_exceeded} =
+               MailParser.extract_nested_attachments(raw_message, max_attachments: 1)
+    end
+
+    test "skip attachments exceeding a limit", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{name: "smime.p7s"}]} =
+               MailParser.extract_nested_attachments(raw_message,
+                 max_attachment_size: 1024,
+                 on_limit: :skip
+               )
+
+      assert {:ok, [%MailParser.Attachment{name: "Best 340 Klöckner FL-Stahl.pdf"}]} =
+               MailParser.extract_nested_attachments(raw_message,
+                 max_attachments: 1,
+                 on_limit: :skip
+               )
+    end
+
+    test "pass when not exceeded", %{raw_message: raw_message} do
+      assert {:ok, [_, _]} =
+               MailParser.extract_nested_attachments(raw_message,
+                 max_attachment_size: 3028,
+                 max_total_size: 3036,
+                 max_attachments: 2
+               )
+    end
+  end
+
+  describe ":max_depth option" do
+    setup do
+      %{raw_message: File.read!("test/fixtures/forwarded.txt")}
+    end
+
+    test "descends into nested messages", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{name: "invoice.txt"}]} =
+               MailParser.extract_nested_attachments(raw_message)
+
+      assert {:ok, [%MailParser.Attachment{name: "invoice.txt"}]} =
+               MailParser.extract_nested_attachments(raw_message, max_depth: 1)
+    end
+
+    test "limits the nesting depth", %{raw_message: raw_message} do
+      assert {:error, :limit_exceeded} =
+               MailParser.extract_nested_attachments(raw_message, max_depth: 0)
+
+      assert {:ok, []} =
+               MailParser.extract_nested_attachments(raw_message, max_depth: 0, on_limit: :skip)
+    end
+  end
+
+  describe ":nested option" do
+    setup do
+      %{raw_message: File.read!("test/fixtures/forwarded.txt")}
+    end
+
+    test "keeps attached messages intact", %{raw_message: raw_message} do
+      assert {:ok, [attachment]} =
+               MailParser.extract_nested_attachments(raw_message, nested: :keep)
+
+      assert %MailParser.Attachment{name: "Invoice.eml", content_type: "message/rfc822"} =
+               attachment
+
+      assert attachment.content_bytes =~ ~r/\AFrom: Arno Nuehm/
+      assert attachment.content_bytes =~ "Invoice 0340/2022"
+
+      assert {:ok, [%MailParser.Attachment{name: "invoice.txt"}]} =
+               MailParser.extract_nested_attachments(attachment.content_bytes)
+    end
+
+    test "returns attached messages and their attachments", %{raw_message: raw_message} do
+      assert {:ok,
+              [
+                %MailParser.Attachment{name: "Invoice.eml"},
+                %MailParser.Attachment{name: "invoice.txt"}
+              ]} = MailParser.extract_nested_attachments(raw_message, nested: :both)
+    end
+  end
+
+  describe ":digests option" do
+    test "computes the requested digests" do
+      raw_message = File.read!("test/fixtures/example.txt")
+
+      assert {:ok, [_pdf, %MailParser.Attachment{name: "smime.p7s", digests: digests}]} =
+               MailParser.extract_nested_attachments(raw_message, digests: [:sha256, :md5])
+
+      assert digests == %{
+               sha256: :sha256 |> :crypto.hash("redacted") |> Base.encode16(case: :lower),
+               md5: :md5 |> :crypto.hash("redacted") |> Base.encode16(case: :lower)
+             }
+    end
+
+    test "computes no digests by default" do
+      raw_message = File.read!("test/fixtures/example.txt")
+
+      assert {:ok, [pdf, _]} = MailParser.extract_nested_attachments(raw_message)
+      assert pdf.digests == %{}
+    end
+
+    test "applies to list_attachments/2" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/example.txt"))
+
+      assert [_, %{digests: %{sha1: sha1}}] =
+               MailParser.list_attachments(message, digests: [:sha1])
+
+      assert sha1 == :sha |> :crypto.hash("redacted") |> Base.encode16(case: :lower)
+    end
+  end
+
+  describe ":dedupe option" do
+    setup do
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: image/png; name=\"logo.png\"\r\n\r\n" <>
+          "PNG\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: image/png; name=\"logo-copy.png\"\r\n\r\n" <>
+          "PNG\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: image/png; name=\"other.png\"\r\n\r\n" <>
+          "GIF\r\n" <>
+          "--b--\r\n"
+
+      %{raw_message: raw_message}
+    end
+
+    test "drops identical attachments", %{raw_message: raw_message} do
+      assert {:ok, [_, _, _]} = MailParser.extract_nested_attachments(raw_message)
+
+      opts = [dedupe: true, max_attachments: 2]
+
+      assert {:ok,
+              [
+                %MailParser.Attachment{name: "logo.png", duplicates: 1},
+                %MailParser.Attachment{name: "other.png", duplicates: 0}
+              ]} = MailParser.extract_nested_attachments(raw_message, opts)
+    end
+
+    test "reports the duplicates left out", %{raw_message: raw_message} do
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert [%{name: "logo.png", duplicates: 1}, %{name: "other.png", duplicates: 0}] =
+               MailParser.list_attachments(message, dedupe: true)
+
+      assert [{:ok, [%{duplicates: 1}, %{duplicates: 0}]}] =
+               MailParser.extract_attachments_batch([raw_message], dedupe: true)
+
+      assert {:ok, [%{duplicates: 0}, %{duplicates: 0}, %{duplicates: 0}]} =
+               MailParser.extract_nested_attachments(raw_message)
+    end
+
+    @tag :tmp_dir
+    test "applies to disk extraction", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      assert {:ok,
+              [
+                %MailParser.ExtractedAttachment{original_name: "logo.png", duplicates: 1},
+                %MailParser.ExtractedAttachment{original_name: "other.png", duplicates: 0}
+              ]} = MailParser.extract_attachments_to_disk(raw_message, tmp_dir, dedupe: true)
+
+      assert Enum.sort(File.ls!(tmp_dir)) == ["logo.png", "other.png"]
+    end
+  end
+
+  describe "inline parts" do
+    setup do
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: text/html\r\n\r\n" <>
+          "<img src=\"cid:logo@example.com\">\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: image/png; name=\"logo.png\"\r\n" <>
+          "Content-ID: <logo@example.com>\r\n" <>
+          "Content-Disposition: inline; filename=\"logo.png\"\r\n\r\n" <>
+          "PNG\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: application/pdf; name=\"invoice.pdf\"\r\n" <>
+          "Content-Disposition: attachment; filename=\"invoice.pdf\"\r\n\r\n" <>
+          "PDF\r\n" <>
+          "--b--\r\n"
+
+      %{raw_message: raw_message}
+    end
+
+    test "are returned with their disposition and content id", %{raw_message: raw_message} do
+      assert {:ok,
+              [
+                %MailParser.Attachment{
+                  name: "logo.png",
+                  disposition: :inline,
+                  content_id: "logo@example.com"
+                },
+                %MailParser.Attachment{
+                  name: "invoice.pdf",
+                  disposition: :attachment,
+                  content_id: nil
+                }
+              ]} = MailParser.extract_nested_attachments(raw_message)
+    end
+
+    test "can be excluded", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{name: "invoice.pdf"}]} =
+               MailParser.extract_nested_attachments(raw_message, include_inline: false)
+
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert [%MailParser.Attachment{name: "invoice.pdf"}] =
+               MailParser.get_attachments(message, include_inline: false)
+    end
+  end
+
+  describe "extract_attachments_to_disk/3" do
+    @describetag :tmp_dir
+
+    setup do
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: text/plain\r\n" <>
+          "Content-Disposition: attachment; filename=\"../../evil.txt\"\r\n\r\n" <>
+          "Evil\r\n" <>
+          "--b--\r\n"
+
+      %{raw_message: raw_message}
+    end
+
+    test "writes attachments to the directory", %{tmp_dir: tmp_dir} do
+      raw_message = File.read!("test/fixtures/example.txt")
+      pdf_path = Path.join(tmp_dir, "Best 340 Klöckner FL-Stahl.pdf")
+      p7s_path = Path.join(tmp_dir, "smime.p7s")
+
+      pdf_content_bytes = File.read!("test/fixtures/sample.pdf")
+      pdf_sha256 = :sha256 |> :crypto.hash(pdf_content_bytes) |> Base.encode16(case: :lower)
+
+      assert {:ok,
+              [
+                %MailParser.ExtractedAttachment{
+                  path: ^pdf_path,
+                  original_name: "Best 340 Klöckner FL-Stahl.pdf",
+                  content_type: "application/pdf",
+                  size_bytes: 3028,
+                  sha256: ^pdf_sha256
+                },
+                %MailParser.ExtractedAttachment{
+                  path: ^p7s_path,
+                  original_name: "smime.p7s",
+                  content_type: "application/x-pkcs7-signature",
+                  size_bytes: 8
+                }
+              ]} = MailParser.extract_attachments_to_disk(raw_message, tmp_dir)
+
+      assert File.read!(pdf_path) == pdf_content_bytes
+      assert File.read!(p7s_path) == "redacted"
+    end
+
+    test "keeps files inside the directory", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "evil.txt")
+
+      assert {:ok, [extracted_attachment]} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir)
+
+      assert %MailParser.ExtractedAttachment{path: ^path, original_name: "../../evil.txt"} =
+               extracted_attachment
+
+      assert File.read!(path) == "Evil"
+    end
+
+    test "rejects unsafe names", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      assert {:error, {:unsafe_filename, "../../evil.txt"}} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir, sanitize: :reject)
+
+      assert File.ls!(tmp_dir) == []
+    end
+
+    test "overwrites existing files by default", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "evil.txt")
+      File.write!(path, "Existing")
+
+      assert {:ok, [%MailParser.ExtractedAttachment{path: ^path}]} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir)
+
+      assert File.read!(path) == "Evil"
+    end
+
+    test "aborts on conflicts", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "evil.txt")
+      File.write!(path, "Existing")
+
+      assert {:error, {:file_exists, ^path}} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir, on_conflict: :error)
+
+      assert File.read!(path) == "Existing"
+    end
+
+    test "renames on conflicts", %{tmp_dir: tmp_dir} do
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: image/png; name=\"image001.png\"\r\n\r\n" <>
+          "First\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: image/png; name=\"image001.png\"\r\n\r\n" <>
+          "Second\r\n" <>
+          "--b--\r\n"
+
+      File.write!(Path.join(tmp_dir, "image001.png"), "Existing")
+      first_path = Path.join(tmp_dir, "image001-1.png")
+      second_path = Path.join(tmp_dir, "image001-2.png")
+
+      assert {:ok,
+              [
+                %MailParser.ExtractedAttachment{path: ^first_path},
+                %MailParser.ExtractedAttachment{path: ^second_path}
+              ]} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir, on_conflict: :rename)
+
+      assert File.read!(first_path) == "First"
+      assert File.read!(second_path) == "Second"
+    end
+
+    test "leaves no temporary files behind", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      assert {:ok, [_]} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir, fsync: true)
+
+      assert File.ls!(tmp_dir) == ["evil.txt"]
+    end
+
+    test "lays files out with :path_template", %{tmp_dir: tmp_dir} do
+      raw_message = File.read!("test/fixtures/example.txt")
+      template = "{message_id}/{date:%Y/%m}/{index}-{name}"
+
+      assert {:ok, [%{path: pdf_path}, %{path: p7s_path}]} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir,
+                 path_template: template
+               )
+
+      assert pdf_path == Path.join(tmp_dir, "unknown/2022/05/0-Best 340 Klöckner FL-Stahl.pdf")
+      assert p7s_path == Path.join(tmp_dir, "unknown/2022/05/1-smime.p7s")
+      assert File.read!(p7s_path) == "redacted"
+    end
+
+    test "names files by their digest with :path_template", %{
+      raw_message: raw_message,
+      tmp_dir: tmp_dir
+    } do
+      raw_message = "Message-ID: <a/b@example.com>\r\n" <> raw_message
+      sha256 = :sha256 |> :crypto.hash("Evil") |> Base.encode16(case: :lower)
+      path = Path.join([tmp_dir, "a_b@example.com", sha256 <> ".txt"])
+
+      assert {:ok, [%MailParser.ExtractedAttachment{path: ^path, sha256: ^sha256}]} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir,
+                 path_template: "{message_id}/{sha256}{ext}"
+               )
+    end
+
+    test "rejects unsafe path templates", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      for template <- ["../{name}", "/{name}", "{size}", "{name"] do
+        assert_raise ArgumentError, fn ->
+          MailParser.extract_attachments_to_disk(raw_message, tmp_dir, path_template: template)
+        end
+      end
+    end
+
+    test "writes large attachments", %{tmp_dir: tmp_dir} do
+      content_bytes = :binary.copy("0123456789abcdef", 10_000)
+      sha256 = :sha256 |> :crypto.hash(content_bytes) |> Base.encode16(case: :lower)
+
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: application/octet-stream; name=\"large.bin\"\r\n" <>
+          "Content-Transfer-Encoding: base64\r\n\r\n" <>
+          Base.encode64(content_bytes) <>
+          "\r\n--b--\r\n"
+
+      assert {:ok, [extracted_attachment]} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir)
+
+      assert %MailParser.ExtractedAttachment{path: path, size_bytes: 160_000, sha256: ^sha256} =
+               extracted_attachment
+
+      assert File.read!(path) == content_bytes
+    end
+
+    test "writes in place with atomic: false", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "evil.txt")
+
+      assert {:ok, [%MailParser.ExtractedAttachment{path: ^path}]} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir, atomic: false)
+
+      assert File.read!(path) == "Evil"
+    end
+
+    test "returns the POSIX error and path of failed writes", %{
+      raw_message: raw_message,
+      tmp_dir: tmp_dir
+    } do
+      directory = Path.join(tmp_dir, "missing")
+      path = Path.join(directory, "evil.txt")
+
+      assert {:error, {:io, :enoent, ^path}} =
+               MailParser.extract_attachments_to_disk(raw_message, directory, atomic: false)
+    end
+
+    test "rolls back the renames and directories of a failed extraction", %{tmp_dir: tmp_dir} do
+      raw_message = File.read!("test/fixtures/example.txt")
+      pdf_path = Path.join(tmp_dir, "Best 340 Klöckner FL-Stahl.pdf")
+      p7s_path = Path.join(tmp_dir, "smime.p7s")
+      File.write!(pdf_path, "Existing")
+      File.mkdir_p!(Path.join(p7s_path, "contents"))
+
+      assert {:error, {:io, _, ^p7s_path}} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir)
+
+      assert File.read!(pdf_path) == "Existing"
+      assert Enum.sort(File.ls!(tmp_dir)) == ["Best 340 Klöckner FL-Stahl.pdf", "smime.p7s"]
+
+      File.rm!(pdf_path)
+      File.mkdir_p!(Path.join([tmp_dir, "1", "smime.p7s", "contents"]))
+
+      assert {:error, {:io, _, _}} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir,
+                 path_template: "{index}/{name}"
+               )
+
+      assert Enum.sort(File.ls!(tmp_dir)) == ["1", "smime.p7s"]
+    end
+
+    test "only removes the files it created when a write fails", %{tmp_dir: tmp_dir} do
+      raw_message = File.read!("test/fixtures/example.txt")
+      pdf_path = Path.join(tmp_dir, "Best 340 Klöckner FL-Stahl.pdf")
+      p7s_path = Path.join(tmp_dir, "smime.p7s")
+      File.mkdir!(p7s_path)
+
+      assert {:error, {:io, _, ^p7s_path}} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir, atomic: false)
+
+      refute File.exists?(pdf_path)
+
+      File.write!(pdf_path, "Existing")
+
+      assert {:error, {:io, _, ^p7s_path}} =
+               MailParser.extract_attachments_to_disk(raw_message, tmp_dir, atomic: false)
+
+      assert File.exists?(pdf_path)
+    end
+  end
+
+  describe "extract_attachments_stream/2" do
+    test "streams the contents of each attachment in chunks" do
+      raw_message = File.read!("test/fixtures/example.txt")
+      {:ok, attachments} = MailParser.extract_nested_attachments(raw_message)
+
+      assert {:ok, stream} = MailParser.extract_attachments_stream(raw_message, chunk_size: 1000)
+
+      assert [
+               {%{name: "Best 340 Klöckner FL-Stahl.pdf", size: 3028}, pdf_chunks},
+               {%{name: "smime.p7s", size: 8}, _}
+             ] = Enum.to_list(stream)
+
+      assert Enum.map(pdf_chunks, &byte_size/1) == [1000, 1000, 1000, 28]
+
+      assert Enum.map(stream, fn {_, chunks} -> Enum.join(chunks) end) ==
+               Enum.map(attachments, & &1.content_bytes)
+    end
+
+    test "streams files decoded from the message" do
+      raw_message = File.read!("test/fixtures/uuencoded.txt")
+
+      assert {:ok, stream} = MailParser.extract_attachments_stream(raw_message, uudecode: true)
+      assert [{%{name: "greeting.txt"}, chunks}] = Enum.to_list(stream)
+      assert Enum.join(chunks) == String.duplicate("Hello from 1995!\n", 4)
+    end
+  end
+
+  describe "parse/1" do
+    test "returns a handle that can be queried repeatedly" do
+      raw_message = File.read!("test/fixtures/example.txt")
+
+      assert {:ok, message} = MailParser.parse(raw_message)
+      assert MailParser.get_subject(message) == "Bestellung 0340/2022"
+      assert MailParser.get_body(message) =~ "Bestellung 0340/2022"
+
+      assert [
+               %MailParser.Attachment{name: "Best 340 Klöckner FL-Stahl.pdf"},
+               %MailParser.Attachment{name: "smime.p7s"}
+             ] = MailParser.get_attachments(message)
+
+      assert MailParser.get_subject(message) == "Bestellung 0340/2022"
+    end
+
+    test "returns error if parsing fails" do
+      assert {:error, :empty_input} = MailParser.parse("")
+      assert {:error, :empty_input} = MailParser.parse(" \r\n")
+      assert {:error, :not_a_message} = MailParser.parse("just some text\r\n")
+    end
+  end
+
+  describe "extract_addresses/1" do
+    test "extracts the addresses of each address header" do
+      raw_message = File.read!("test/fixtures/example.txt")
+
+      assert {:ok, addresses} = MailParser.extract_addresses(raw_message)
+
+      assert addresses.from == [
+               %MailParser.Address{
+                 name: "Joe Schmoe",
+                 email: "joe@example.com",
+                 ascii_email: "joe@example.com",
+                 unicode_email: "joe@example.com"
+               }
+             ]
+
+      assert [%MailParser.Address{name: "Max Mustermann", email: "max.mustermann@example.com"}] =
+               addresses.to
+
+      assert [%MailParser.Address{email: "Arno.Nuehm@example.com"}] = addresses.cc
+      assert addresses.bcc == []
+      assert addresses.reply_to == []
+      assert addresses.sender == []
+    end
+
+    test "flattens address groups" do
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "To: Friends: a@example.com, Bob <b@example.com>;, carol@example.com\r\n\r\n" <>
+          "Hello"
+
+      assert {:ok, %{to: to}} = MailParser.extract_addresses(raw_message)
+
+      assert [
+               %MailParser.Address{email: "a@example.com", group: "Friends"},
+               %MailParser.Address{name: "Bob", email: "b@example.com", group: "Friends"},
+               %MailParser.Address{email: "carol@example.com", group: nil}
+             ] = to
+    end
+
+    test "returns error if parsing fails" do
+      assert {:error, :empty_input} = MailParser.extract_addresses("")
+    end
+
+    test "returns both forms of internationalized addresses" do
+      raw_message =
+        "From: Jörg Müller <joerg@münchen.de>\r\n" <>
+          "To: info@xn--bcher-kva.example, 用户 <用户@例子.广告>\r\n" <>
+          "Subject: Grüße aus Köln ✓\r\n\r\n" <>
+          "Hallo"
+
+      assert {:ok, addresses} = MailParser.extract_addresses(raw_message)
+      assert %{from: [from], to: [info, user]} = addresses
+
+      assert from == %MailParser.Address{
+               name: "Jörg Müller",
+               email: "joerg@münchen.de",
+               ascii_email: "joerg@xn--mnchen-3ya.de",
+               unicode_email: "joerg@münchen.de"
+             }
+
+      assert %MailParser.Address{
+               email: "info@xn--bcher-kva.example",
+               ascii_email: "info@xn--bcher-kva.example",
+               unicode_email: "info@bücher.example"
+             } = info
+
+      assert %MailParser.Address{
+               name: "用户",
+               email: "用户@例子.广告",
+               ascii_email: nil,
+               unicode_email: "用户@例子.广告"
+             } = user
+
+      assert {:ok, %{"subject" => ["Grüße aus Köln ✓"]}} = MailParser.parse_headers(raw_message)
+    end
+  end
+
+  describe "get_header/2" do
+    setup do
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Subject: =?utf-8?q?Gr=C3=BC=C3=9Fe?=\r\n  aus Berlin\r\n" <>
+          "X-Spam-Status: No, score=-1.2\r\n" <>
+          "x-spam-status: Yes\r\n\r\n" <>
+          "Hello"
+
+      {:ok, message} = MailParser.parse(raw_message)
+      %{message: message}
+    end
+
+    test "returns every occurrence in order", %{message: message} do
+      assert [
+               %MailParser.Header{name: "X-Spam-Status", value: "No, score=-1.2"},
+               %MailParser.Header{name: "x-spam-status", value: "Yes"}
+             ] = MailParser.get_header(message, "X-SPAM-STATUS")
+
+      assert MailParser.get_header(message, "X-Missing") == []
+    end
+
+    test "returns raw and decoded values", %{message: message} do
+      assert [header] = MailParser.get_header(message, "Subject")
+      assert header.value == "Grüße aus Berlin"
+      assert header.raw_value == "=?utf-8?q?Gr=C3=BC=C3=9Fe?=\r\n  aus Berlin"
+    end
+
+    test "get_headers/1 returns all headers", %{message: message} do
+      names = message |> MailParser.get_headers() |> Enum.map(& &1.name)
+      assert names == ["From", "Subject", "X-Spam-Status", "x-spam-status"]
+    end
+  end
+
+  describe "get_date/2" do
+    test "returns the date in UTC" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/example.txt"))
+
+      assert MailParser.get_date(message) == 1_652_774_704
+      assert MailParser.get_date(message, :iso8601) == "2022-05-17T08:05:04Z"
+    end
+
+    test "normalizes malformed dates" do
+      for {date, expected} <- [
+            {"17 May 22 03:05 EST", "2022-05-17T08:05:00Z"},
+            {"Tue, 17 May 2022 10:05:04 +0200 (CEST)", "2022-05-17T08:05:04Z"},
+            {"Tue May 17 08:05:04 2022", "2022-05-17T08:05:04Z"},
+            {"2022-05-17T08:05:04Z", "2022-05-17T08:05:04Z"}
+          ] do
+        {:ok, message} = MailParser.parse("Date: #{date}\r\n\r\nHello")
+        assert MailParser.get_date(message, :iso8601) == expected
+      end
+
+      {:ok, message} = MailParser.parse("Date: garbage\r\n\r\nHello")
+      assert MailParser.get_date(message) == nil
+    end
+
+    test "get_resent_dates/2 returns every resent date" do
+      raw_message =
+        "Resent-Date: Wed, 1 Jun 2022 14:30:00 +0000\r\n" <>
+          "Resent-Date: 1 Jun 2022 10:30 EDT\r\n" <>
+          "Date: Tue, 17 May 2022 08:05:04 +0000\r\n\r\n" <>
+          "Hello"
+
+      {:ok, message} = MailParser.parse(raw_message)
+      assert MailParser.get_resent_dates(message) == [1_654_093_800, 1_654_093_800]
+    end
+  end
+
+  describe "get_thread_info/1" do
+    test "returns the threading headers" do
+      raw_message =
+        "Subject: Re: Fwd: [billing] Invoice\r\n" <>
+          "Message-ID: <c@example.com>\r\n" <>
+          "In-Reply-To: <b@example.com>\r\n" <>
+          "References: <a@example.com>\r\n <b@example.com>\r\n\r\n" <>
+          "Hello"
+
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert MailParser.get_thread_info(message) == %{
+               message_id: "c@example.com",
+               in_reply_to: ["b@example.com"],
+               references: ["a@example.com", "b@example.com"],
+               base_subject: "Invoice"
+             }
+    end
+
+    test "returns empty values without threading headers" do
+      {:ok, message} = MailParser.parse("From: joe@example.com\r\n\r\nHello")
+
+      assert MailParser.get_thread_info(message) == %{
+               message_id: nil,
+               in_reply_to: [],
+               references: [],
+               base_subject: nil
+             }
+    end
+  end
+
+  describe "get_text_body/2 and get_html_body/2" do
+    test "decode the preferred alternative into UTF-8" do
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Content-Type: multipart/alternative; boundary=\"b\"\r\n\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: text/plain; charset=Shift_JIS\r\n" <>
+          "Content-Transfer-Encoding: base64\r\n\r\n" <>
+          "grGC8YLJgr+CzQ==\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: text/html; charset=iso-8859-1\r\n" <>
+          "Content-Transfer-Encoding: quoted-printable\r\n\r\n" <>
+          "<p>Gr=FC=DFe</p>\r\n" <>
+          "--b--\r\n"
+
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert MailParser.get_text_body(message) == "こんにちは"
+      assert MailParser.get_html_body(message) == "<p>Grüße</p>"
+    end
+
+    test "do not convert between text and HTML" do
+      {:ok, message} = MailParser.parse("From: joe@example.com\r\n\r\nHello")
+
+      assert MailParser.get_text_body(message) == "Hello"
+      assert MailParser.get_html_body(message) == nil
+    end
+  end
+
+  describe "get_html_body/2 :sanitize option" do
+    setup do
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Content-Type: text/html\r\n\r\n" <>
+          "<!-- comment --><p onclick=\"steal()\" class=\"lead\">Hello" <>
+          "<script>steal()</script></p>" <>
+          "<a href=\"java&#x09;script:steal()\">Click</a>" <>
+          "<form action=\"https://evil.example.com\"><input type=\"submit\"></form>" <>
+          "<img src=\"https://tracker.example.com/pixel.gif?id=1\" alt=\"Pixel\">" <>
+          "<img src=\"cid:logo\">"
+
+      {:ok, message} = MailParser.parse(raw_message)
+      %{message: message}
+    end
+
+    test "strips scripts, event handlers and external form actions", %{message: message} do
+      assert MailParser.get_html_body(message, sanitize: true) ==
+               ~s(<p class="lead">Hello</p><a>Click</a><form><input type="submit"></form>) <>
+                 ~s(<img src="https://tracker.example.com/pixel.gif?id=1" alt="Pixel">) <>
+                 ~s(<img src="cid:logo">)
+
+      assert MailParser.get_html_body(message) =~ "<script>"
+    end
+
+    test "blocks or rewrites remote images", %{message: message} do
+      assert MailParser.get_html_body(message, sanitize: true, remote_images: :block) =~
+               ~s(</form><img alt="Pixel"><img src="cid:logo">)
+
+      assert MailParser.get_html_body(message,
+               sanitize: true,
+               remote_images: "https://proxy.example.com/?url={url}"
+             ) =~
+               ~s(<img src="https://proxy.example.com/?url=) <>
+                 ~s(https%3A%2F%2Ftracker.example.com%2Fpixel.gif%3Fid%3D1" alt="Pixel">)
+    end
+
+    test "blocks or rewrites every remote resource" do
+      {:ok, message} =
+        MailParser.parse(
+          "Content-Type: text/html\r\n\r\n" <>
+            "<video poster=\"https://t.example.com/p\"" <>
+            " src=\"https://t.example.com/v\"></video>" <>
+            "<audio src=\"https://t.example.com/a\"></audio>" <>
+            "<source src=\"https://t.example.com/s\"><track src=\"https://t.example.com/t\">" <>
+            "<svg><image href=\"https://t.example.com/i\"></image>" <>
+            "<use xlink:href=\"https://t.example.com/u\"></use>" <>
+            "<feImage href=\"https://t.example.com/f\"></feImage></svg>"
+        )
+
+      assert MailParser.get_html_body(message, sanitize: true, remote_images: :block) ==
+               "<video></video><audio></audio><source><track>" <>
+                 "<svg><image></image><use></use><feimage></feimage></svg>"
+
+      proxied =
+        MailParser.get_html_body(message,
+          sanitize: true,
+          remote_images: "https://proxy.example.com/?url={url}"
+        )
+
+      refute proxied =~ ~s("https://t.example.com)
+
+      for resource <- ~w(p v a s t i u f) do
+        assert proxied =~ "?url=https%3A%2F%2Ft.example.com%2F#{resource}\""
+      end
+    end
+
+    test "blocks styles loading remote images, escaped or uppercased" do
+      for style <- [
+            "<style>a { background: URL(https://t.example.com/p) }</style>",
+            "<style>@import \"https://t.example.com/a.css\";</style>",
+            "<p style=\"background: u\\72l(https://t.example.com/p)\"></p>",
+            "<p style=\"background: image-set('https://t.example.com/p' 1x)\"></p>"
+          ] do
+        {:ok, message} = MailParser.parse("Content-Type: text/html\r\n\r\n" <> style)
+        html = MailParser.get_html_body(message, sanitize: true, remote_images: :block)
+        refute html =~ "t.example"
+      end
+
+      {:ok, message} =
+        MailParser.parse("Content-Type: text/html\r\n\r\n<style>a { x: EXPRESSION(1) }</style>")
+
+      assert MailParser.get_html_body(message, sanitize: true) == ""
+    end
+
+    test "escapes the contents of raw text elements and attribute values" do
+      {:ok, message} =
+        MailParser.parse(
+          "Content-Type: text/html\r\n\r\n" <>
+            "<style><img title=\"</style><img src=x onerror=alert(1)>\"></style>"
+        )
+
+      assert MailParser.get_html_body(message, sanitize: true) ==
+               ~s(<style>&lt;img title="</style><img src="x">"&gt;</style>)
+    end
+
+    test "drops URLs of schemes not allowed, numeric references included" do
+      {:ok, message} =
+        MailParser.parse(
+          "Content-Type: text/html\r\n\r\n" <>
+            "<a href=\"&#106avascript:alert(1)\">Click</a>" <>
+            "<a href=\"data:text/html,hello\">Data</a>" <>
+            "<a href=\"https://example.com/?a=1&amp;b=2\">Link</a>"
+        )
+
+      assert MailParser.get_html_body(message, sanitize: true) ==
+               ~s(<a>Click</a><a>Data</a><a href="https://example.com/?a=1&amp;b=2">Link</a>)
+    end
+  end
+
+  describe "get_plaintext/2" do
+    setup do
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Content-Type: text/html; charset=utf-8\r\n\r\n" <>
+          "<html><head><style>p { color: red; }</style></head><body>\r\n" <>
+          "<p>Hello&nbsp;<b>Max</b>,</p>\r\n" <>
+          "<p>your   invoice is <a href=\"https://example.com/invoice\">online</a>.</p>\r\n" <>
+          "<ul><li>Total: 10 &euro;</li></ul>\r\n" <>
+          "</body></html>\r\n"
+
+      {:ok, message} = MailParser.parse(raw_message)
+      %{message: message}
+    end
+
+    test "converts HTML-only messages", %{message: message} do
+      assert MailParser.get_plaintext(message) ==
+               "Hello Max,\n\n" <>
+                 "your invoice is online (https://example.com/invoice).\n\n" <>
+                 "- Total: 10 €"
+    end
+
+    test "truncates to :max_length", %{message: message} do
+      assert MailParser.get_plaintext(message, max_length: 12) == "Hello Max,"
+    end
+
+    test "reads links from the href attribute only" do
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Content-Type: text/html; charset=utf-8\r\n\r\n" <>
+          "<p><a data-href=\"https://tracker.example\" title='href=\"/x\"'\r\n" <>
+          "   HREF = \"https://example.com/?a=1&amp;b=2\">the list</a></p>\r\n" <>
+          "<p><a title=\"href=https://example.com\">no link</a></p>"
+
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert MailParser.get_plaintext(message) ==
+               "the list (https://example.com/?a=1&b=2)\n\nno link"
+    end
+
+    test "returns text bodies as they are" do
+      {:ok, message} = MailParser.parse("From: joe@example.com\r\n\r\n<p>Hello</p>")
+      assert MailParser.get_plaintext(message) == "<p>Hello</p>"
+    end
+  end
+
+  describe "get_preview/2" do
+    test "skips quoted replies and signatures" do
+      raw_message =
+        "From: joe@example.com\r\n\r\n" <>
+          "Sounds good,\r\n" <>
+          "see you on Tuesday.\r\n\r\n" <>
+          "On Mon, 16 May 2022, Max wrote:\r\n" <>
+          "> Shall we meet?\r\n\r\n" <>
+          "Joe\r\n" <>
+          "-- \r\n" <>
+          "Joe Schmoe, Example Inc.\r\n"
+
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert MailParser.get_preview(message) == "Sounds good, see you on Tuesday. Joe"
+      assert MailParser.get_preview(message, 15) == "Sounds good,"
+    end
+
+    test "returns an empty preview without a body" do
+      {:ok, message} = MailParser.parse("From: joe@example.com\r\n\r\n")
+      assert MailParser.get_preview(message) == ""
+    end
+  end
+
+  describe "get_reply_text/1" do
+    test "strips quoted replies and signatures" do
+      raw_message =
+        "From: joe@example.com\r\n\r\n" <>
+          "Sounds good.\r\n\r\n" <>
+          "> Shall we meet?\r\n\r\n" <>
+          "See you on Tuesday.\r\n\r\n" <>
+          "Sent from my iPhone\r\n\r\n" <>
+          "On Mon, 16 May 2022 at 10:00, Max Doe <\r\n" <>
+          "max@example.com> wrote:\r\n" <>
+          "> Shall we meet?\r\n"
+
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert MailParser.get_reply_text(message) == "Sounds good.\n\nSee you on Tuesday."
+    end
+
+    test "stops at Outlook-style quoted messages" do
+      raw_message =
+        "From: joe@example.com\r\n\r\n" <>
+          "Done, thanks.\r\n\r\n" <>
+          "From: Max Doe <max@example.com>\r\n" <>
+          "Sent: Monday, May 16, 2022 10:00 AM\r\n" <>
+          "To: Joe\r\n\r\n" <>
+          "Can you fix it?\r\n"
+
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert MailParser.get_reply_text(message) == "Done, thanks."
+    end
+  end
+
+  describe "get_mime_structure/1" do
+    test "returns the part tree" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/example.txt"))
+
+      assert %{
+               path: [],
+               content_type: "multipart/signed",
+               disposition: nil,
+               children: [
+                 %{
+                   path: [0],
+                   content_type: "multipart/mixed",
+                   children: [
+                     %{
+                       path: [0, 0],
+                       content_type: "multipart/alternative",
+                       children: [
+                         %{path: [0, 0, 0], content_type: "text/plain", children: []},
+                         %{path: [0, 0, 1], content_type: "text/html", children: []}
+                       ]
+                     },
+                     %{
+                       path: [0, 1],
+                       content_type: "application/pdf",
+                       disposition: :attachment,
+                       size: 3028,
+                       encoding: "base64",
+                       children: []
+                     }
+                   ]
+                 },
+                 %{path: [1], content_type: "application/x-pkcs7-signature", size: 8}
+               ]
+             } = MailParser.get_mime_structure(message)
+    end
+
+    test "descends into attached messages" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/forwarded.txt"))
+
+      assert %{children: [_, %{content_type: "message/rfc822", children: [nested_root]}]} =
+               MailParser.get_mime_structure(message)
+
+      assert %{
+               path: [1, 0],
+               content_type: "multipart/mixed",
+               children: [%{path: [1, 0, 0]}, %{path: [1, 0, 1], size: 18}]
+             } = nested_root
+    end
+  end
+
+  describe "get_part/2" do
+    test "returns the part at a path" do
+      raw_message = File.read!("test/fixtures/example.txt")
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert %{path: [0, 1], content_type: "application/pdf", headers: headers} =
+               part = MailParser.get_part(message, [0, 1])
+
+      assert part.content_bytes == File.read!("test/fixtures/sample.pdf")
+
+      assert %MailParser.Header{value: "base64"} =
+               Enum.find(headers, &(&1.name == "Content-Transfer-Encoding"))
+
+      assert {:ok, ^part} = MailParser.extract_part(raw_message, [0, 1])
+    end
+
+    test "returns parts of attached messages" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/forwarded.txt"))
+
+      assert %{content_type: "text/plain", content_bytes: "Invoice 0340/2022\n"} =
+               MailParser.get_part(message, [1, 0, 1])
+    end
+
+    test "returns nil for missing parts" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/example.txt"))
+
+      assert MailParser.get_part(message, [5]) == nil
+      assert MailParser.get_part(message, [1, 0]) == nil
+      assert {:error, :empty_input} = MailParser.extract_part("", [])
+
+      assert {:error, :part_not_found} =
+               MailParser.extract_part(File.read!("test/fixtures/example.txt"), [5])
+    end
+  end
+
+  describe "get_raw_part/2" do
+    setup do
+      signed_part =
+        "Content-Type: multipart/mixed; boundary=c\r\n\r\n" <>
+          "--c\r\nContent-Type: text/plain\r\nContent-Transfer-Encoding: base64\r\n\r\n" <>
+          "aGk=\r\n--c--\r\n"
+
+      raw_message =
+        "Subject: Signed\r\n" <>
+          "Content-Type: multipart/signed; protocol=\"application/pgp-signature\"; " <>
+          "boundary=b\r\n" <>
+          "\r\n--b\r\n" <>
+          signed_part <>
+          "\r\n--b\r\nContent-Type: application/pgp-signature\r\n\r\nsignature\r\n--b--\r\n"
+
+      {:ok, message} = MailParser.parse(raw_message)
+      %{raw_message: raw_message, signed_part: signed_part, message: message}
+    end
+
+    test "returns parts as they appear in the message", %{
+      raw_message: raw_message,
+      signed_part: signed_part,
+      message: message
+    } do
+      assert MailParser.get_raw_part(message, [0]) == signed_part
+      assert MailParser.get_raw_part(message, []) == raw_message
+
+      assert MailParser.get_raw_part(message, [0, 0]) ==
+               "Content-Type: text/plain\r\nContent-Transfer-Encoding: base64\r\n\r\naGk="
+    end
+
+    test "returns nil for missing parts", %{message: message} do
+      assert MailParser.get_raw_part(message, [2]) == nil
+    end
+  end
+
+  describe "get_embedded_messages/1" do
+    test "returns attached messages as parsed messages" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/forwarded.txt"))
+
+      assert [%{path: [1], content_type: "message/rfc822", message: forwarded}] =
+               MailParser.get_embedded_messages(message)
+
+      assert MailParser.get_subject(forwarded) == "Invoice"
+      assert [%MailParser.Attachment{name: "invoice.txt"}] = MailParser.get_attachments(forwarded)
+    end
+
+    test "returns messages attached to attached messages" do
+      raw_message =
+        "Subject: Outer\r\nContent-Type: multipart/mixed; boundary=b\r\n\r\n" <>
+          "--b\r\nContent-Type: text/plain\r\n\r\nSee below\r\n" <>
+          "--b\r\nContent-Type: message/rfc822\r\n\r\n" <>
+          "Subject: Middle\r\nContent-Type: multipart/mixed; boundary=c\r\n\r\n" <>
+          "--c\r\nContent-Type: message/rfc822\r\n\r\n" <>
+          "Subject: Inner\r\n\r\nHi\r\n--c--\r\n--b--\r\n"
+
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert [%{path: [1], message: middle}, %{path: [1, 0, 0], message: inner}] =
+               MailParser.get_embedded_messages(message)
+
+      assert MailParser.get_subject(middle) == "Middle"
+      assert MailParser.get_text_body(inner) == "Hi"
+      assert [%{path: [0]}] = MailParser.get_embedded_messages(middle)
+    end
+
+    test "keeps the message they are attached to alive" do
+      [%{message: forwarded}] =
+        "test/fixtures/forwarded.txt"
+        |> File.read!()
+        |> MailParser.parse()
+        |> then(fn {:ok, message} -> MailParser.get_embedded_messages(message) end)
+
+      :erlang.garbage_collect()
+
+      assert MailParser.get_subject(forwarded) == "Invoice"
+      assert [%MailParser.Attachment{name: "invoice.txt"}] = MailParser.get_attachments(forwarded)
+    end
+  end
+
+  describe "list_attachments/2" do
+    test "lists attachments without their contents" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/example.txt"))
+
+      assert [
+               %{
+                 name: "Best 340 Klöckner FL-Stahl.pdf",
+                 content_type: "application/pdf",
+                 size: 3028,
+                 disposition: :attachment,
+                 content_id: nil,
+                 path: [0, 1] = pdf_path
+               },
+               %{name: "smime.p7s", size: 8, path: [1]}
+             ] = MailParser.list_attachments(message)
+
+      assert %{content_bytes: content_bytes} = MailParser.get_part(message, pdf_path)
+      assert byte_size(content_bytes) == 3028
+    end
+
+    test "accepts extraction options" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/forwarded.txt"))
+
+      assert [%{name: "invoice.txt", path: [1, 0, 1]}] = MailParser.list_attachments(message)
+
+      assert [%{name: "Invoice.eml", content_type: "message/rfc822", path: [1]}] =
+               MailParser.list_attachments(message, nested: :keep)
+    end
+  end
+
+  describe "stats/1" do
+    test "summarizes the size and structure of a message" do
+      raw_message =
+        "Subject: Stats\r\nContent-Type: multipart/mixed; boundary=b\r\n\r\n" <>
+          "--b\r\nContent-Type: multipart/alternative; boundary=a\r\n\r\n" <>
+          "--a\r\nContent-Type: text/plain\r\n" <>
+          "Content-Transfer-Encoding: Quoted-Printable\r\n\r\nHi=21\r\n" <>
+          "--a\r\nContent-Type: text/html\r\n\r\n<p>Hi!</p>\r\n--a--\r\n" <>
+          "--b\r\nContent-Type: message/rfc822\r\n\r\n" <>
+          "Subject: Inner\r\nContent-Type: application/pdf; name=a.pdf\r\n" <>
+          "Content-Transfer-Encoding: base64\r\n\r\nJVBERi0=\r\n--b--\r\n"
+
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert MailParser.stats(message) == %{
+               size: byte_size(raw_message),
+               header_size: 61,
+               parts: 6,
+               attachments: 1,
+               attachments_size: 5,
+               encodings: ["base64", "quoted-printable"],
+               depth: 1,
+               has_text_body: true,
+               has_html_body: true
+             }
+    end
+
+    test "counts a single part message" do
+      {:ok, message} = MailParser.parse("Subject: Hi\r\n\r\nHello")
+
+      assert %{
+               size: 20,
+               header_size: 15,
+               parts: 1,
+               attachments: 0,
+               encodings: [],
+               depth: 0,
+               has_text_body: true,
+               has_html_body: false
+             } = MailParser.stats(message)
+    end
+  end
+
+  describe "parse_mbox/2" do
+    test "parses every message" do
+      assert {:ok, [{:ok, first}, {:ok, second}]} =
+               MailParser.parse_mbox("test/fixtures/archive.mbox")
+
+      assert MailParser.get_subject(first) == "First"
+      assert MailParser.get_body(first) =~ "Hello\nFrom the team"
+      assert MailParser.get_subject(second) == "Second"
+    end
+
+    test "accepts :max_messages" do
+      assert {:ok, [{:ok, _}]} =
+               MailParser.parse_mbox("test/fixtures/archive.mbox", max_messages: 1)
+    end
+
+    test "returns an error for missing files" do
+      assert {:error, {:io, :enoent, "test/fixtures/missing.mbox"}} =
+               MailParser.parse_mbox("test/fixtures/missing.mbox")
+    end
+  end
+
+  describe "extract_attachments_from_mbox/2" do
+    test "extracts attachments per message" do
+      assert {:ok, [{:ok, [attachment]}, {:ok, []}]} =
+               MailParser.extract_attachments_from_mbox("test/fixtures/archive.mbox")
+
+      assert %MailParser.Attachment{name: "notes.txt", content_bytes: "Notes"} = attachment
+    end
+
+    test "applies extraction options" do
+      assert {:ok, [{:error, :limit_exceeded}, {:ok, []}]} =
+               MailParser.extract_attachments_from_mbox("test/fixtures/archive.mbox",
+                 max_attachment_size: 1
+               )
+    end
+  end
+
+  describe "mbox_next/2" do
+    setup do
+      {:ok, mbox} = MailParser.mbox_open("test/fixtures/archive.mbox")
+      %{mbox: mbox}
+    end
+
+    test "reads one message at a time", %{mbox: mbox} do
+      assert {:ok, first} = MailParser.mbox_next(mbox)
+      assert MailParser.get_subject(first) == "First"
+      assert {:ok, second} = MailParser.mbox_next(mbox)
+      assert MailParser.get_subject(second) == "Second"
+      assert :eof = MailParser.mbox_next(mbox)
+    end
+
+    test "returns attachments", %{mbox: mbox} do
+      assert {:ok, [%MailParser.Attachment{name: "notes.txt", content_bytes: "Notes"}]} =
+               MailParser.mbox_next(mbox, attachments: true)
+
+      assert {:ok, []} = MailParser.mbox_next(mbox, attachments: true)
+    end
+
+    test "returns :eof once closed", %{mbox: mbox} do
+      assert :ok = MailParser.mbox_close(mbox)
+      assert :eof = MailParser.mbox_next(mbox)
+    end
+
+    test "mbox_open/1 returns an error for missing files" do
+      assert {:error, {:io, :enoent, "test/fixtures/missing.mbox"}} =
+               MailParser.mbox_open("test/fixtures/missing.mbox")
+    end
+  end
+
+  describe "parse_maildir/2" do
+    @describetag :tmp_dir
+
+    setup %{tmp_dir: tmp_dir} do
+      for directory <- ["cur", "new", "tmp"], do: File.mkdir!(Path.join(tmp_dir, directory))
+
+      File.write!(Path.join(tmp_dir, "cur/2.host:2,S"), "Subject: Second\n\nBody")
+      File.write!(Path.join(tmp_dir, "cur/1.host:2,S"), "Subject: First\n\nBody")
+      File.write!(Path.join(tmp_dir, "cur/.hidden"), "Subject: Hidden\n\nBody")
+      File.write!(Path.join(tmp_dir, "new/3.host"), "")
+      File.write!(Path.join(tmp_dir, "tmp/4.host"), "Subject: Pending\n\nBody")
+
+      %{root: tmp_dir}
+    end
+
+    test "parses the messages of cur and new", %{root: root} do
+      for parallel <- [false, true] do
+        assert {:ok, [{first_path, {:ok, first}}, {second_path, {:ok, second}}, third]} =
+                 MailParser.parse_maildir(root, parallel: parallel)
+
+        assert first_path == Path.join(root, "cur/1.host:2,S")
+        assert MailParser.get_subject(first) == "First"
+        assert second_path == Path.join(root, "cur/2.host:2,S")
+        assert MailParser.get_subject(second) == "Second"
+        assert {Path.join(root, "new/3.host"), {:error, :empty_input}} == third
+      end
+    end
+
+    test "accepts :max_messages", %{root: root} do
+      assert {:ok, [{_, {:ok, _}}]} = MailParser.parse_maildir(root, max_messages: 1)
+    end
+
+    test "reads one message at a time", %{root: root} do
+      assert {:ok, maildir} = MailParser.maildir_open(root)
+      assert {_, {:ok, first}} = MailParser.maildir_next(maildir)
+      assert MailParser.get_subject(first) == "First"
+      assert {_, {:ok, _}} = MailParser.maildir_next(maildir)
+      assert {_, {:error, :empty_input}} = MailParser.maildir_next(maildir)
+      assert :eof = MailParser.maildir_next(maildir)
+    end
+
+    test "returns an error for missing directories", %{root: root} do
+      missing = Path.join(root, "missing")
+
+      assert {:error, {:io, :enoent, path}} = MailParser.parse_maildir(missing)
+      assert path == Path.join(missing, "cur")
+    end
+  end
+
+  describe "parse_file/2" do
+    test "parses a message file" do
+      assert {:ok, message} = MailParser.parse_file("test/fixtures/example.txt")
+      assert MailParser.get_subject(message) == "Bestellung 0340/2022"
+      assert MailParser.get_text_body(message) =~ "Mit freundlichen Grüßen"
+    end
+
+    @tag :tmp_dir
+    test "accepts the options of parse/2", %{tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "headerless.eml")
+      File.write!(path, "just some text\r\n")
+
+      assert MailParser.parse_file(path) == {:error, :not_a_message}
+
+      assert {:ok, message, [%{reason: :missing_headers}]} =
+               MailParser.parse_file(path, lenient: true)
+
+      assert MailParser.get_text_body(message) == "just some text\r\n"
+    end
+
+    test "returns an error for missing files" do
+      assert {:error, {:io, :enoent, "test/fixtures/missing.eml"}} =
+               MailParser.parse_file("test/fixtures/missing.eml")
+    end
+  end
+
+  describe "extract_attachments_from_file/2" do
+    test "extracts attachments from a message file" do
+      assert {:ok, [pdf, _]} =
+               MailParser.extract_attachments_from_file("test/fixtures/example.txt")
+
+      assert pdf.content_bytes == File.read!("test/fixtures/sample.pdf")
+
+      assert {:ok, [%MailParser.Attachment{name: "smime.p7s"}]} =
+               MailParser.extract_attachments_from_file("test/fixtures/example.txt",
+                 mime_types: ["application/x-pkcs7-signature"]
+               )
+    end
+  end
+
+  describe "extract_attachments_batch/2" do
+    test "returns the attachments of each message in order" do
+      raw_message = File.read!("test/fixtures/example.txt")
+
+      assert [{:ok, [pdf, _]}, {:error, :empty_input}, {:ok, [file_pdf, _]}] =
+               MailParser.extract_attachments_batch([
+                 raw_message,
+                 "",
+                 {:file, "test/fixtures/example.txt"}
+               ])
+
+      assert pdf.content_bytes == File.read!("test/fixtures/sample.pdf")
+      assert file_pdf == pdf
+    end
+
+    test "applies the options to every message" do
+      assert [
+               {:ok, [%MailParser.Attachment{name: "smime.p7s"}]},
+               {:error, {:io, :enoent, "test/fixtures/missing.eml"}}
+             ] =
+               MailParser.extract_attachments_batch(
+                 [{:file, "test/fixtures/example.txt"}, {:file, "test/fixtures/missing.eml"}],
+                 mime_types: ["application/x-pkcs7-signature"]
+               )
+    end
+  end
+
+  describe "expand_tnef option" do
+    test "returns TNEF parts as is by default" do
+      raw_message = File.read!("test/fixtures/tnef.eml")
+
+      assert {:ok, [%MailParser.Attachment{name: "winmail.dat"}]} =
+               MailParser.extract_nested_attachments(raw_message)
+    end
+
+    test "replaces TNEF parts with the files they contain" do
+      raw_message = File.read!("test/fixtures/tnef.eml")
+
+      assert {:ok, [report, notes]} =
+               MailParser.extract_nested_attachments(raw_message, expand_tnef: true)
+
+      assert %MailParser.Attachment{
+               name: "quarterly report.csv",
+               content_type: "text/csv",
+               content_bytes: "a,b\n1,2\n",
+               disposition: :attachment
+             } = report
+
+      assert %MailParser.Attachment{
+               name: "notes.txt",
+               content_type: "application/octet-stream",
+               content_bytes: "Some notes"
+             } = notes
+    end
+
+    test "applies the filters to the contained files" do
+      raw_message = File.read!("test/fixtures/tnef.eml")
+
+      assert {:ok, [%MailParser.Attachment{name: "quarterly report.csv"}]} =
+               MailParser.extract_nested_attachments(raw_message,
+                 expand_tnef: true,
+                 mime_types: ["text/*"]
+               )
+    end
+  end
+
+  describe "uudecode option" do
+    test "ignores uuencoded blocks by default" do
+      raw_message = File.read!("test/fixtures/uuencoded.txt")
+
+      assert {:ok, []} = MailParser.extract_nested_attachments(raw_message)
+    end
+
+    test "returns uuencoded files as attachments" do
+      raw_message = File.read!("test/fixtures/uuencoded.txt")
+
+      assert {:ok, [attachment]} =
+               MailParser.extract_nested_attachments(raw_message, uudecode: true)
+
+      assert %MailParser.Attachment{
+               name: "greeting.txt",
+               content_type: "application/octet-stream",
+               disposition: :attachment
+             } = attachment
+
+      assert attachment.content_bytes == String.duplicate("Hello from 1995!\n", 4)
+    end
+
+    test "returns uuencoded files of parsed messages" do
+      {:ok, message} = MailParser.parse_file("test/fixtures/uuencoded.txt")
+
+      assert [%MailParser.Attachment{name: "greeting.txt"}] =
+               MailParser.get_attachments(message, uudecode: true)
+    end
+  end
+
+  describe "extract_calendar_events/1" do
+    test "returns the events of a calendar invitation" do
+      raw_message = File.read!("test/fixtures/invite.eml")
+
+      assert {:ok, [event]} = MailParser.extract_calendar_events(raw_message)
+
+      assert event == %{
+               method: "REQUEST",
+               uid: "standup-42@example.com",
+               summary: "Weekly standup, planning & review",
+               organizer: %{name: "Doe, Jane", email: "jane@example.com", status: nil},
+               attendees: [
+                 %{name: "John Smith", email: "john@example.com", status: "needs-action"},
+                 %{name: nil, email: "jane@example.com", status: "accepted"}
+               ],
+               dtstart: "2024-06-03T10:00:00",
+               dtend: "2024-06-03T10:30:00",
+               timezone: "Europe/Berlin",
+               rrule: "FREQ=WEEKLY;BYDAY=MO"
+             }
+    end
+
+    test "returns no events for messages without calendar parts" do
+      raw_message = File.read!("test/fixtures/example.txt")
+
+      assert {:ok, []} = MailParser.extract_calendar_events(raw_message)
+    end
+  end
+
+  describe "extract_contacts/1" do
+    test "returns the contacts of attached vCards" do
+      raw_message = File.read!("test/fixtures/contact.eml")
+
+      assert {:ok, [jane, john]} = MailParser.extract_contacts(raw_message)
+
+      assert jane == %MailParser.Contact{
+               name: "Jane Doe",
+               emails: ["jane@example.com", "jane.doe@example.org"],
+               phones: ["+49 170 1234567"],
+               org: "Example, Inc."
+             }
+
+      assert john == %MailParser.Contact{
+               name: "Dr. John Smith",
+               emails: [],
+               phones: ["+1-555-0100"],
+               org: nil
+             }
+    end
+
+    test "returns no contacts for messages without vCards" do
+      raw_message = File.read!("test/fixtures/example.txt")
+
+      assert {:ok, []} = MailParser.extract_contacts(raw_message)
+    end
+  end
+
+  describe "parse_dsn/1" do
+    test "returns the status of each recipient" do
+      raw_message = File.read!("test/fixtures/bounce.eml")
+
+      assert {:ok, %{type: :delivery_status} = dsn} = MailParser.parse_dsn(raw_message)
+      assert dsn.reporting_mta == "mx.example.com"
+
+      assert dsn.recipients == [
+               %{
+                 final_recipient: "nobody@example.net",
+                 original_recipient: "nobody@example.net",
+                 action: "failed",
+                 status: "5.1.1",
+                 diagnostic_code:
+                   "550 5.1.1 <nobody@example.net>: Recipient address rejected: User unknown",
+                 remote_mta: "mail.example.net"
+               }
+             ]
+    end
+
+    test "returns the headers of the original message" do
+      raw_message = File.read!("test/fixtures/bounce.eml")
+
+      assert {:ok, %{original_headers: original_headers}} = MailParser.parse_dsn(raw_message)
+
+      assert %MailParser.Header{value: "<original@example.com>"} =
+               Enum.find(original_headers, &(&1.name == "Message-ID"))
+    end
+
+    test "returns the disposition of read receipts" do
+      raw_message = File.read!("test/fixtures/receipt.eml")
+
+      assert {:ok, mdn} = MailParser.parse_dsn(raw_message)
+
+      assert mdn == %{
+               type: :disposition_notification,
+               reporting_ua: "laptop.example.com; Example Mail 2.1",
+               original_recipient: "john@example.com",
+               final_recipient: "john@example.com",
+               original_message_id: "original@example.com",
+               disposition: "displayed",
+               original_headers: []
+             }
+    end
+
+    test "returns an error for other messages" do
+      assert {:error, :not_a_report} =
+               MailParser.parse_dsn(File.read!("test/fixtures/example.txt"))
+    end
+  end
+
+  describe "get_auth_results/1" do
+    setup do
+      {:ok, message} = MailParser.parse_file("test/fixtures/authenticated.eml")
+      %{message: message}
+    end
+
+    test "returns each method of the Authentication-Results headers", %{message: message} do
+      assert %{authentication_results: [dkim, spf, dmarc]} = MailParser.get_auth_results(message)
+
+      assert dkim == %{
+               authserv_id: "mx.example.net",
+               method: "dkim",
+               result: "pass",
+               reason: nil,
+               domain: "example.com",
+               selector: "s1",
+               properties: %{
+                 "header.d" => "example.com",
+                 "header.s" => "s1",
+                 "header.b" => "abcdef"
+               }
+             }
+
+      assert %{method: "spf", result: "pass", domain: "example.com"} = spf
+
+      assert %{method: "dmarc", result: "fail", reason: "policy override"} = dmarc
+    end
+
+    test "returns the Received-SPF and DKIM-Signature headers", %{message: message} do
+      assert %{received_spf: [spf], dkim_signatures: [signature]} =
+               MailParser.get_auth_results(message)
+
+      assert spf == %{result: "pass", domain: "example.com", client_ip: "192.0.2.1"}
+      assert signature == %{domain: "example.com", selector: "s1", algorithm: "rsa-sha256"}
+    end
+
+    test "returns empty lists for messages without these headers" do
+      {:ok, message} = MailParser.parse_file("test/fixtures/example.txt")
+
+      assert %{authentication_results: [], received_spf: [], dkim_signatures: []} =
+               MailParser.get_auth_results(message)
+    end
+  end
+
+  describe "get_received_chain/1" do
+    setup do
+      {:ok, message} = MailParser.parse_file("test/fixtures/relayed.eml")
+      %{message: message}
+    end
+
+    test "returns the Received headers as hops, most recent first", %{message: message} do
+      assert %{hops: [inbox, submission, local]} = MailParser.get_received_chain(message)
+
+      assert inbox == %{
+               from_host: "mx.example.net",
+               from_ip: "198.51.100.7",
+               by_host: "inbox.example.org",
+               protocol: "ESMTPS",
+               timestamp: 1_717_495_205
+             }
+
+      assert %{from_host: "[10.0.0.12]", from_ip: "203.0.113.42", timestamp: 1_717_495_198} =
+               submission
+
+      assert %{from_host: "localhost", from_ip: "127.0.0.1", by_host: "laptop.local"} = local
+    end
+
+    test "returns the oldest public address as the originating IP", %{message: message} do
+      assert %{originating_ip: "203.0.113.42"} = MailParser.get_received_chain(message)
+    end
+
+    test "returns no hops for messages without Received headers" do
+      {:ok, message} = MailParser.parse("Subject: Hello\r\n\r\nHello!")
+
+      assert %{hops: [], originating_ip: nil} = MailParser.get_received_chain(message)
+    end
+  end
+
+  describe "get_list_info/1" do
+    test "returns the mailing list headers" do
+      {:ok, message} = MailParser.parse_file("test/fixtures/newsletter.eml")
+
+      assert MailParser.get_list_info(message) == %{
+               id: "news.example.org",
+               name: "Example News",
+               unsubscribe: [
+                 "mailto:unsubscribe@example.org?subject=unsubscribe",
+                 "https://example.org/unsubscribe?id=42"
+               ],
+               one_click_unsubscribe: "https://example.org/unsubscribe?id=42",
+               post: [],
+               precedence: "bulk",
+               auto_submitted: nil,
+               suppress_auto_reply: true
+             }
+    end
+
+    test "requires List-Unsubscribe-Post for one-click unsubscribing" do
+      {:ok, message} =
+        MailParser.parse(
+          "List-Unsubscribe: <https://example.org/unsubscribe>\r\n" <>
+            "List-Post: <mailto:dev@example.org>\r\n\r\nHello!"
+        )
+
+      assert %{
+               unsubscribe: ["https://example.org/unsubscribe"],
+               one_click_unsubscribe: nil,
+               post: ["mailto:dev@example.org"]
+             } = MailParser.get_list_info(message)
+    end
+
+    test "suppresses auto replies to automated messages only" do
+      {:ok, message} = MailParser.parse("Auto-Submitted: auto-replied\r\n\r\nAway")
+
+      assert %{auto_submitted: "auto-replied", suppress_auto_reply: true} =
+               MailParser.get_list_info(message)
+
+      {:ok, message} = MailParser.parse("Auto-Submitted: no\r\n\r\nHello!")
+      assert %{id: nil, suppress_auto_reply: false} = MailParser.get_list_info(message)
+    end
+  end
+
+  describe "get_html_with_inline_parts/2" do
+    setup do
+      {:ok, message} = MailParser.parse_file("test/fixtures/inline_image.eml")
+      %{message: message}
+    end
+
+    test "returns the HTML body and the parts its cid: URLs refer to", %{message: message} do
+      assert %{html: html, inline_parts: inline_parts} =
+               MailParser.get_html_with_inline_parts(message)
+
+      assert html =~ ~s(<img src="cid:logo@example.com" alt="Logo">)
+
+      assert inline_parts == %{
+               "logo@example.com" => %{
+                 content_type: "image/png",
+                 content_bytes: Base.decode64!("iVBORw0KGgo=")
+               }
+             }
+    end
+
+    test "replaces cid: URLs with data: URIs", %{message: message} do
+      assert %{html: html} = MailParser.get_html_with_inline_parts(message, data_uris: true)
+
+      assert html =~ ~s(<img src="data:image/png;base64,iVBORw0KGgo=" alt="Logo">)
+      assert html =~ ~s(<img src="cid:missing@example.com">)
+    end
+
+    test "returns nil for messages without an HTML body" do
+      {:ok, message} = MailParser.parse("Subject: Hello\r\n\r\nHello!")
+
+      assert MailParser.get_html_with_inline_parts(message) == nil
+    end
+  end
+
+  describe "detected content types" do
+    setup do
+      raw_message =
+        "Content-Type: multipart/mixed; boundary=\"mixed\"\r\n\r\n" <>
+          "--mixed\r\n" <>
+          "Content-Type: application/octet-stream\r\n" <>
+          "Content-Disposition: attachment; filename=\"scan\"\r\n\r\n" <>
+          "%PDF-1.4 scanned\r\n" <>
+          "--mixed\r\n" <>
+          "Content-Type: text/plain\r\n" <>
+          "Content-Disposition: attachment; filename=\"notes.txt\"\r\n\r\n" <>
+          "Some notes\r\n" <>
+          "--mixed--\r\n"
+
+      %{raw_message: raw_message}
+    end
+
+    test "are detected from the contents of attachments", %{raw_message: raw_message} do
+      assert {:ok, [scan, notes]} = MailParser.extract_nested_attachments(raw_message)
+
+      assert %MailParser.Attachment{
+               content_type: "application/octet-stream",
+               detected_content_type: "application/pdf"
+             } = scan
+
+      assert %MailParser.Attachment{content_type: "text/plain", detected_content_type: nil} =
+               notes
+    end
+
+    test "can be matched instead of the declared ones", %{raw_message: raw_message} do
+      assert {:ok, []} = MailParser.extract_nested_attachments(raw_message, mime_types: ["*/pdf"])
+
+      assert {:ok, [%MailParser.Attachment{name: "scan"}, %MailParser.Attachment{}]} =
+               MailParser.extract_nested_attachments(raw_message,
+                 mime_types: ["application/pdf", "text/*"],
+                 match_content_type: :detected
+               )
+    end
+  end
+
+  describe ":extensions and :name_glob options" do
+    setup do
+      %{raw_message: File.read!("test/fixtures/example.txt")}
+    end
+
+    test "match the extension of attachment names", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{name: "smime.p7s"}]} =
+               MailParser.extract_nested_attachments(raw_message, extensions: ["P7S"])
+
+      assert {:ok, [_, _]} =
+               MailParser.extract_nested_attachments(raw_message, extensions: [".pdf", ".p7s"])
+    end
+
+    test "match attachment names against a glob pattern", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{name: "Best 340 Klöckner FL-Stahl.pdf"}]} =
+               MailParser.extract_nested_attachments(raw_message, name_glob: "best*.pdf")
+
+      assert {:ok, [%MailParser.Attachment{name: "smime.p7s"}]} =
+               MailParser.extract_nested_attachments(raw_message, name_glob: "smime.p?s")
+
+      assert {:ok, []} = MailParser.extract_nested_attachments(raw_message, name_glob: "*.docx")
+    end
+
+    test "are combined with the other filters", %{raw_message: raw_message} do
+      assert {:ok, []} =
+               MailParser.extract_nested_attachments(raw_message,
+                 extensions: [".pdf"],
+                 mime_types: ["application/x-pkcs7-signature"]
+               )
+    end
+  end
+
+  describe ":min_size and :max_size options" do
+    setup do
+      %{raw_message: File.read!("test/fixtures/example.txt")}
+    end
+
+    test "leave out attachments outside the size range", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{name: "Best 340 Klöckner FL-Stahl.pdf"}]} =
+               MailParser.extract_nested_attachments(raw_message, min_size: 9)
+
+      assert {:ok, [%MailParser.Attachment{name: "smime.p7s"}]} =
+               MailParser.extract_nested_attachments(raw_message, max_size: 8)
+
+      assert {:ok, [_, _]} =
+               MailParser.extract_nested_attachments(raw_message, min_size: 8, max_size: 4096)
+    end
+
+    test "do not count towards the limits", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{name: "smime.p7s"}]} =
+               MailParser.extract_nested_attachments(raw_message,
+                 max_size: 1024,
+                 max_attachments: 1
+               )
+    end
+  end
+
+  describe ":expand_archives option" do
+    setup do
+      %{raw_message: File.read!("test/fixtures/archives.eml")}
+    end
+
+    test "keeps archives by default", %{raw_message: raw_message} do
+      assert {:ok,
+              [
+                %MailParser.Attachment{name: "invoices.zip"},
+                %MailParser.Attachment{name: "logs.tgz"}
+              ]} = MailParser.extract_nested_attachments(raw_message)
+    end
+
+    test "replaces archives by the files they contain", %{raw_message: raw_message} do
+      assert {:ok, [invoice_1, invoice_2, log]} =
+               MailParser.extract_nested_attachments(raw_message, expand_archives: true)
+
+      assert %MailParser.Attachment{
+               name: "invoices/invoice-1.txt",
+               content_type: "application/octet-stream",
+               disposition: :attachment
+             } = invoice_1
+
+      assert invoice_1.content_bytes == String.duplicate("Invoice 1: 100 EUR\n", 20)
+
+      assert %MailParser.Attachment{
+               name: "invoices/invoice-2.pdf",
+               content_type: "application/pdf",
+               content_bytes: "%PDF-1.4 invoice 2\n"
+             } = invoice_2
+
+      assert %MailParser.Attachment{name: "logs/app.log", content_bytes: "started\nstopped\n"} =
+               log
+    end
+
+    test "keeps archives exceeding the size limits as is", %{raw_message: raw_message} do
+      assert {:ok,
+              [
+                %MailParser.Attachment{name: "invoices.zip"},
+                %MailParser.Attachment{name: "logs/app.log"}
+              ]} =
+               MailParser.extract_nested_attachments(raw_message,
+                 expand_archives: true,
+                 max_archive_entry_size: 100
+               )
+    end
+  end
+
+  describe ":decompress option" do
+    setup do
+      body = :zlib.gzip(:unicode.characters_to_binary("Grüße", :unicode, :latin1))
+      forward = :zlib.gzip("Subject: Forwarded\r\n\r\nHi\r\n")
+
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: text/plain; charset=iso-8859-1\r\n" <>
+          "Content-Encoding: gzip\r\n" <>
+          "Content-Transfer-Encoding: base64\r\n\r\n" <>
+          Base.encode64(body) <>
+          "\r\n--b\r\n" <>
+          "Content-Type: text/csv\r\n" <>
+          "Content-Disposition: attachment; filename=\"report.csv\"\r\n" <>
+          "Content-Encoding: deflate\r\n" <>
+          "Content-Transfer-Encoding: base64\r\n\r\n" <>
+          Base.encode64(:zlib.compress("a,b\n1,2\n")) <>
+          "\r\n--b\r\n" <>
+          "Content-Type: application/gzip\r\n" <>
+          "Content-Disposition: attachment; filename=\"forward.eml.gz\"\r\n" <>
+          "Content-Transfer-Encoding: base64\r\n\r\n" <>
+          Base.encode64(forward) <>
+          "\r\n--b--\r\n"
+
+      %{raw_message: raw_message}
+    end
+
+    test "decompresses attachments", %{raw_message: raw_message} do
+      assert {:ok, [report, forward]} =
+               MailParser.extract_nested_attachments(raw_message, decompress: true)
+
+      assert %MailParser.Attachment{
+               name: "report.csv",
+               content_type: "text/csv",
+               content_bytes: "a,b\n1,2\n"
+             } = report
+
+      assert %MailParser.Attachment{
+               name: "forward.eml",
+               content_type: "message/rfc822",
+               content_bytes: "Subject: Forwarded\r\n\r\nHi\r\n"
+             } = forward
+
+      assert {:ok, [_, %MailParser.Attachment{name: "forward.eml.gz"}]} =
+               MailParser.extract_nested_attachments(raw_message)
+    end
+
+    test "keeps attachments exceeding :max_decompressed_size as is", %{
+      raw_message: raw_message
+    } do
+      assert {:ok, [report, %MailParser.Attachment{name: "forward.eml.gz"}]} =
+               MailParser.extract_nested_attachments(raw_message,
+                 decompress: true,
+                 max_decompressed_size: 4
+               )
+
+      assert %MailParser.Attachment{name: "report.csv"} = report
+      refute report.content_bytes == "a,b\n1,2\n"
+    end
+
+    test "decompresses text bodies", %{raw_message: raw_message} do
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert MailParser.get_text_body(message, decompress: true) == "Grüße"
+      refute MailParser.get_text_body(message) == "Grüße"
+    end
+  end
+
+  describe "encrypted attachments" do
+    test "are flagged" do
+      raw_message = File.read!("test/fixtures/encrypted.eml")
+
+      assert {:ok, [zip, pdf, readme]} = MailParser.extract_nested_attachments(raw_message)
+      assert %MailParser.Attachment{name: "invoice.zip", encrypted: true} = zip
+      assert %MailParser.Attachment{name: "statement.pdf", encrypted: true} = pdf
+      assert %MailParser.Attachment{name: "readme.txt", encrypted: false} = readme
+    end
+
+    test "are not expanded" do
+      raw_message = File.read!("test/fixtures/encrypted.eml")
+
+      assert {:ok, [%MailParser.Attachment{name: "invoice.zip"} | _]} =
+               MailParser.extract_nested_attachments(raw_message, expand_archives: true)
+    end
+  end
+
+  describe "get_security_info/1" do
+    test "returns the signers of S/MIME signed messages" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/smime_signed.eml"))
+
+      assert %{
+               signed: true,
+               encrypted: false,
+               protocol: :smime,
+               digest_algorithm: "sha256",
+               encryption_algorithm: nil,
+               signers: [signer]
+             } = MailParser.get_security_info(message)
+
+      assert signer == %{
+               name: "John Doe",
+               email: "john@example.com",
+               organization: "Example Corp",
+               issuer: "John Doe",
+               serial_number: "1234abcd"
+             }
+    end
+
+    test "recognizes opaque-signed and encrypted S/MIME messages" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/smime_opaque.eml"))
+
+      assert %{signed: true, protocol: :smime, signers: [%{name: "John Doe"}]} =
+               MailParser.get_security_info(message)
+
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/smime_encrypted.eml"))
+
+      assert %{
+               signed: false,
+               encrypted: true,
+               protocol: :smime,
+               encryption_algorithm: "aes-256-cbc",
+               signers: []
+             } = MailParser.get_security_info(message)
+    end
+
+    test "recognizes PGP/MIME messages" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/pgp_signed.eml"))
+
+      assert %{signed: true, protocol: :pgp, digest_algorithm: "sha512", signers: []} =
+               MailParser.get_security_info(message)
+
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/example.txt"))
+
+      assert %{signed: false, encrypted: false, protocol: nil} =
+               MailParser.get_security_info(message)
+    end
+  end
+
+  describe "opaque-signed messages" do
+    setup do
+      %{raw_message: File.read!("test/fixtures/smime_opaque.eml")}
+    end
+
+    test "return the attachments of the wrapped message", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{name: "contract.txt", content_bytes: contents}]} =
+               MailParser.extract_nested_attachments(raw_message)
+
+      assert contents == "Signed contract terms."
+    end
+
+    test "are returned as is with nested: :keep", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{name: "smime.p7m"}]} =
+               MailParser.extract_nested_attachments(raw_message, nested: :keep)
+
+      assert {:ok, [%MailParser.Attachment{name: "smime.p7m"}, %MailParser.Attachment{}]} =
+               MailParser.extract_nested_attachments(raw_message, nested: :both)
+    end
+  end
+
+  describe "strip_attachments/2" do
+    setup do
+      %{raw_message: File.read!("test/fixtures/example.txt")}
+    end
+
+    test "removes the selected attachments", %{raw_message: raw_message} do
+      assert {:ok, stripped} =
+               MailParser.strip_attachments(raw_message, mime_types: ["application/pdf"])
+
+      assert byte_size(stripped) < byte_size(raw_message)
+
+      assert {:ok, [%MailParser.Attachment{name: "smime.p7s"}]} =
+               MailParser.extract_nested_attachments(stripped)
+
+      {:ok, message} = MailParser.parse(raw_message)
+      {:ok, stripped_message} = MailParser.parse(stripped)
+
+      assert MailParser.get_text_body(stripped_message) == MailParser.get_text_body(message)
+      assert MailParser.get_headers(stripped_message) == MailParser.get_headers(message)
+    end
+
+    test "replaces attachments with a placeholder", %{raw_message: raw_message} do
+      assert {:ok, stripped} =
+               MailParser.strip_attachments(raw_message,
+                 extensions: [".p7s"],
+                 placeholder: "Removed {name} ({size} bytes)"
+               )
+
+      assert stripped =~ "Removed smime.p7s ("
+
+      assert {:ok, [%MailParser.Attachment{name: "Best 340 Klöckner FL-Stahl.pdf"}]} =
+               MailParser.extract_nested_attachments(stripped)
+    end
+
+    test "returns the message as is when nothing is selected", %{raw_message: raw_message} do
+      assert {:ok, ^raw_message} =
+               MailParser.strip_attachments(raw_message, extensions: [".docx"])
+    end
+  end
+
+  describe "build_message/1" do
+    test "composes a message that parses back" do
+      raw_message =
+        MailParser.build_message(%{
+          headers: [
+            {"From", "\"Jöhn Doe\" <john@example.com>"},
+            {"To", "jane@example.org"},
+            {"Subject", "Grüße aus Köln"}
+          ],
+          text: "Hällo\nSee the attached notes.",
+          html: "<p>Hällo</p><img src=\"cid:logo@example.com\">",
+          attachments: [
+            %{name: "Übersicht.txt", content_type: "text/plain", content_bytes: "Notes"},
+            %{
+              name: "logo.png",
+              content_type: "image/png",
+              content_bytes: <<0x89, "PNG">>,
+              inline: true,
+              content_id: "logo@example.com"
+            }
+          ]
+        })
+
+      assert raw_message =~ "Subject: =?utf-8?q?Gr=C3=BC=C3=9Fe?= aus =?utf-8?q?K=C3=B6ln?=\r\n"
+
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert MailParser.get_subject(message) == "Grüße aus Köln"
+      assert MailParser.get_text_body(message) == "Hällo\r\nSee the attached notes."
+
+      assert %{"logo@example.com" => %{content_bytes: <<0x89, "PNG">>}} =
+               MailParser.get_html_with_inline_parts(message).inline_parts
+
+      assert {:ok, [%MailParser.Attachment{name: "Übersicht.txt", content_bytes: "Notes"}]} =
+               MailParser.extract_nested_attachments(raw_message, include_inline: false)
+    end
+
+    test "accepts extracted attachments" do
+      {:ok, attachments} =
+        MailParser.extract_nested_attachments(File.read!("test/fixtures/example.txt"))
+
+      raw_message =
+        MailParser.build_message(%{headers: %{"Subject" => "Fwd"}, attachments: attachments})
+
+      assert {:ok, extracted} = MailParser.extract_nested_attachments(raw_message)
+
+      assert Enum.map(extracted, &{&1.name, &1.content_bytes}) ==
+               Enum.map(attachments, &{&1.name, &1.content_bytes})
+    end
+
+    test "rejects headers breaking lines or describing the MIME structure" do
+      assert_raise ArgumentError, fn ->
+        MailParser.build_message(%{headers: [{"Subject", "Hi\r\nBcc: eve@example.com"}]})
+      end
+
+      assert_raise ArgumentError, fn ->
+        MailParser.build_message(%{headers: [{"Content-Type", "text/html"}]})
+      end
+    end
+
+    test "rejects attachment fields breaking lines or invalid content types" do
+      for field <- [
+            name: "a.txt\r\nBcc: eve@example.com",
+            content_type: "text/plain\r\nBcc: eve@example.com",
+            content_type: "text/plain; charset=utf-8",
+            content_id: "a\r\nBcc: eve@example.com"
+          ] do
+        attachment = Map.new([field, content_bytes: "A"])
+
+        assert_raise ArgumentError, fn ->
+          MailParser.build_message(%{text: "Hi", attachments: [attachment]})
+        end
+      end
+    end
+  end
+
+  describe "to_json/2" do
+    test "serializes the message" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/encrypted.eml"))
+      json = MailParser.to_json(message)
+
+      assert json =~ ~s({"subject":"Protected documents","message_id":"encrypted@example.com",)
+      assert json =~ ~s("date":"2024-06-04T10:00:00Z")
+      assert json =~ ~s("from":[{"name":"John Doe","email":"john@example.com","group":null}])
+      assert json =~ ~s({"name":"readme.txt","raw_name":"readme.txt","content_type":"text/plain",)
+      refute json =~ ~s("content_bytes")
+    end
+
+    test "escapes strings" do
+      {:ok, message} =
+        MailParser.parse("From: \"A \\\"B\\\"\" <a@example.com>\r\nSubject: a\tb\r\n\r\nHi\r\n")
+
+      json = MailParser.to_json(message)
+
+      assert json =~ ~S("subject":"a\tb")
+      assert json =~ ~S("name":"A \"B\"")
+      assert json =~ ~S("text_body":"Hi\r\n")
+    end
+
+    test "includes base64 encoded attachment contents" do
+      {:ok, message} = MailParser.parse(File.read!("test/fixtures/encrypted.eml"))
+      json = MailParser.to_json(message, content_bytes: true)
+
+      assert json =~ ~s("name":"readme.txt")
+      assert json =~ ~s("content_bytes":"Tm90aGluZyB0byBoaWRlLg=="})
+    end
+  end
+
+  describe "parse_headers/1" do
+    test "returns the decoded header values by lowercase name" do
+      raw_message =
+        "Received: from a\r\nReceived: from b\r\n" <>
+          "Subject: =?utf-8?q?Gr=C3=BC=C3=9Fe?=\r\n  aus Berlin\r\n\r\n" <>
+          "Not-A-Header: body\r\n"
+
+      assert {:ok, headers} = MailParser.parse_headers(raw_message)
+
+      assert headers == %{
+               "received" => ["from a", "from b"],
+               "subject" => ["Grüße aus Berlin"]
+             }
+    end
+
+    test "matches the headers of a full parse" do
+      raw_message = File.read!("test/fixtures/example.txt")
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert {:ok, headers} = MailParser.parse_headers(raw_message)
+
+      for header <- MailParser.get_headers(message) do
+        assert header.value in headers[String.downcase(header.name)]
+      end
+    end
+
+    test "returns an error without headers" do
+      assert MailParser.parse_headers("") == {:error, :empty_input}
+    end
+  end
+
+  describe "lenient parsing" do
+    test "returns the warnings of damaged messages" do
+      raw_message =
+        "Subject: Report\r\nContent-Type: multipart/mixed; boundary=b\r\n\r\n" <>
+          "--b\r\nContent-Type: text/plain\r\n\r\nSee attached.\r\n" <>
+          "--b\r\nContent-Type: application/pdf\r\nContent-Transfer-Encoding: base64\r\n\r\nJVBER"
+
+      assert {:ok, message, warnings} = MailParser.parse(raw_message, lenient: true)
+
+      assert warnings == [
+               %{reason: :truncated_body, path: []},
+               %{reason: :invalid_encoding_in_part, path: [1]}
+             ]
+
+      assert MailParser.get_subject(message) == "Report"
+      assert MailParser.get_text_body(message) == "See attached."
+    end
+
+    test "salvages input without headers" do
+      assert MailParser.parse("just some text\r\n") == {:error, :not_a_message}
+
+      assert {:ok, message, [%{reason: :missing_headers, path: []}]} =
+               MailParser.parse("just some text\r\n", lenient: true)
+
+      assert MailParser.get_text_body(message) == "just some text\r\n"
+    end
+
+    test "returns no warnings for well-formed messages" do
+      raw_message = File.read!("test/fixtures/example.txt")
+
+      assert {:ok, _message, []} = MailParser.parse(raw_message, lenient: true)
+    end
+
+    test "reports headers without a body" do
+      assert {:ok, message, [%{reason: :truncated_headers, path: []}]} =
+               MailParser.parse("Subject: Hi\r\nFrom: john@example.com", lenient: true)
+
+      assert MailParser.get_subject(message) == "Hi"
+    end
+  end
+
+  describe ":name_fallback option" do
+    setup do
+      raw_message =
+        "Content-Type: multipart/mixed; boundary=\"mixed\"\r\n\r\n" <>
+          "--mixed\r\n" <>
+          "Content-Type: text/plain\r\n\r\n" <>
+          "See attached.\r\n" <>
+          "--mixed\r\n" <>
+          "Content-Type: application/octet-stream\r\n" <>
+          "Content-Disposition: attachment\r\n\r\n" <>
+          "%PDF-1.4 scanned\r\n" <>
+          "--mixed\r\n" <>
+          "Content-Type: image/png\r\n" <>
+          "Content-ID: <logo@example.com>\r\n\r\n" <>
+          "logo\r\n" <>
+          "--mixed\r\n" <>
+          "Content-Type: text/plain; name=\"notes.txt\"\r\n\r\n" <>
+          "Some notes\r\n" <>
+          "--mixed--\r\n"
+
+      %{raw_message: raw_message}
+    end
+
+    test "names unnamed attachments untitled by default", %{raw_message: raw_message} do
+      assert {:ok, attachments} = MailParser.extract_nested_attachments(raw_message)
+      assert Enum.map(attachments, & &1.name) == ["untitled", "untitled", "notes.txt"]
+    end
+
+    test "names unnamed attachments after their content type or Content-ID",
+         %{raw_message: raw_message} do
+      assert {:ok, attachments} =
+               MailParser.extract_nested_attachments(raw_message, name_fallback: :extension)
+
+      assert Enum.map(attachments, & &1.name) == ["untitled.pdf", "untitled.png", "notes.txt"]
+
+      assert {:ok, attachments} =
+               MailParser.extract_nested_attachments(raw_message, name_fallback: :content_id)
+
+      assert Enum.map(attachments, & &1.name) == ["untitled.pdf", "logo.png", "notes.txt"]
+    end
+
+    test "numbers unnamed attachments by their position", %{raw_message: raw_message} do
+      options = [name_fallback: {:indexed, "attachment-%d"}]
+      names = ["attachment-1.pdf", "attachment-2.png", "notes.txt"]
+
+      assert {:ok, attachments} = MailParser.extract_nested_attachments(raw_message, options)
+      assert Enum.map(attachments, & &1.name) == names
+
+      {:ok, message} = MailParser.parse(raw_message)
+      assert Enum.map(MailParser.list_attachments(message, options), & &1.name) == names
+    end
+  end
+
+  describe "attachment names" do
+    setup do
+      head =
+        "Subject: =?UTF-8?Q?Gr=C3=BC=C3=9Fe?=\r\n =?UTF-8?Q?_aus_K=C3=B6ln?=\r\n" <>
+          "Content-Type: multipart/mixed; boundary=\"mixed\"\r\n\r\n" <>
+          "--mixed\r\n" <>
+          "Content-Type: text/plain\r\n\r\n" <>
+          "See attached.\r\n" <>
+          "--mixed\r\n" <>
+          "Content-Type: application/pdf"
+
+      %{head: head, tail: "\r\n\r\n%PDF-1.4\r\n--mixed--\r\n"}
+    end
+
+    test "are decoded from RFC 2047 encoded words", %{head: head, tail: tail} do
+      raw_message =
+        head <>
+          "\r\nContent-Disposition: attachment;\r\n" <>
+          " filename=\"=?UTF-8?Q?Kl=C3=B6ckner?= =?UTF-8?Q?_Bericht.pdf?=\"" <> tail
+
+      assert {:ok, [attachment]} = MailParser.extract_nested_attachments(raw_message)
+      assert attachment.name == "Klöckner Bericht.pdf"
+      assert attachment.raw_name == "=?UTF-8?Q?Kl=C3=B6ckner?= =?UTF-8?Q?_Bericht.pdf?="
+    end
+
+    test "are decoded when a character is split across encoded words",
+         %{head: head, tail: tail} do
+      raw_message =
+        head <>
+          "\r\nContent-Disposition: attachment;\r\n" <>
+          " filename=\"=?UTF-8?B?S2zD?=\r\n =?UTF-8?B?tmNrbmVyLnBkZg==?=\"" <> tail
+
+      assert {:ok, [%MailParser.Attachment{name: "Klöckner.pdf"}]} =
+               MailParser.extract_nested_attachments(raw_message)
+    end
+
+    test "join RFC 2231 continuations in order", %{head: head, tail: tail} do
+      raw_message =
+        head <>
+          "\r\nContent-Disposition: attachment;\r\n" <>
+          " filename*1*=%20Bericht.pdf;\r\n" <>
+          " filename*0*=UTF-8''Kl%C3%B6ckner" <> tail
+
+      assert {:ok, [attachment]} = MailParser.extract_nested_attachments(raw_message)
+      assert attachment.name == "Klöckner Bericht.pdf"
+      assert attachment.raw_name == "UTF-8''Kl%C3%B6ckner%20Bericht.pdf"
+
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert [%{name: "Klöckner Bericht.pdf", raw_name: "UTF-8''Kl%C3%B6ckner%20Bericht.pdf"}] =
+               MailParser.list_attachments(message)
+    end
+
+    test "prefer the RFC 2231 filename", %{head: head, tail: tail} do
+      raw_message =
+        head <>
+          "\r\nContent-Disposition: attachment; filename=\"Klockner.pdf\";\r\n" <>
+          " filename*=iso-8859-1'de'Kl%F6ckner.pdf" <> tail
+
+      assert {:ok, [%MailParser.Attachment{name: "Klöckner.pdf"}]} =
+               MailParser.extract_nested_attachments(raw_message)
+    end
+
+    test "fall back to the Content-Type name", %{head: head, tail: tail} do
+      assert {:ok, [%MailParser.Attachment{name: "scan.pdf", raw_name: "scan.pdf"}]} =
+               MailParser.extract_nested_attachments(head <> "; name=scan.pdf" <> tail)
+
+      assert {:ok, [%MailParser.Attachment{name: "untitled", raw_name: nil}]} =
+               MailParser.extract_nested_attachments(head <> tail)
+    end
+
+    test "leave subjects decoded", %{head: head, tail: tail} do
+      assert {:ok, %{"subject" => ["Grüße aus Köln"]}} = MailParser.parse_headers(head <> tail)
+    end
+  end
+
+  describe "iodata input" do
+    setup do
+      raw_message = File.read!("test/fixtures/example.txt")
+      [headers, body] = :binary.split(raw_message, "\n\n")
+
+      # An improper list, with nested lists and bytes.
+      %{raw_message: raw_message, iodata: [[headers, ?\n] | ["\n", [body]] ++ ""]}
+    end
+
+    test "is parsed as the binary it stands for", %{raw_message: raw_message, iodata: iodata} do
+      assert IO.iodata_to_binary(iodata) == raw_message
+
+      assert MailParser.extract_nested_attachments(iodata) ==
+               MailParser.extract_nested_attachments(raw_message)
+
+      assert MailParser.extract_addresses(iodata) == MailParser.extract_addresses(raw_message)
+
+      {:ok, message} = MailParser.parse(iodata)
+      assert MailParser.get_subject(message) == "Bestellung 0340/2022"
+    end
+
+    test "is accepted in batches", %{raw_message: raw_message, iodata: iodata} do
+      assert [{:ok, attachments}, {:error, :empty_input}] =
+               MailParser.extract_attachments_batch([iodata, []])
+
+      assert {:ok, attachments} == MailParser.extract_nested_attachments(raw_message)
+    end
+
+    test "raises for anything else" do
+      assert_raise ArgumentError, fn -> MailParser.parse(["From: a@example.com", :body]) end
+      assert_raise ArgumentError, fn -> MailParser.parse(["From: a@example.com" | 256]) end
+    end
+  end
+
+  describe "parse_yielding/2" do
+    test "parses big messages as parse/2 does" do
+      raw_message =
+        "Subject: Archive\r\nContent-Type: multipart/mixed; boundary=b\r\n\r\n" <>
+          "--b\r\nContent-Type: text/plain\r\n\r\nSee attached.\r\n" <>
+          "--b\r\nContent-Type: application/octet-stream; name=data.bin\r\n" <>
+          "Content-Transfer-Encoding: base64\r\n\r\n" <>
+          String.duplicate(Base.encode64(:binary.copy("data", 57)) <> "\r\n", 100_000) <>
+          "--b--\r\n"
+
+      assert {:ok, message} = MailParser.parse_yielding(raw_message)
+      assert {:ok, expected} = MailParser.parse(raw_message)
+
+      assert MailParser.get_subject(message) == "Archive"
+      assert MailParser.get_attachments(message) == MailParser.get_attachments(expected)
+    end
+
+    test "accepts the options of parse/2" do
+      assert {:ok, message, [%{reason: :truncated_headers, path: []}]} =
+               MailParser.parse_yielding("Subject: Hi\r\nFrom: john@example.com", lenient: true)
+
+      assert MailParser.get_subject(message) == "Hi"
+    end
+
+    test "returns the reason no message was found" do
+      assert MailParser.parse_yielding("") == {:error, :empty_input}
+      assert MailParser.parse_yielding(["just some ", "text\r\n"]) == {:error, :not_a_message}
+    end
+
+    test "flattens iolists and raises for anything else" do
+      assert {:ok, message} = MailParser.parse_yielding(["Subject: Hi\r\n", ?\r, ?\n | "Hello"])
+      assert MailParser.get_subject(message) == "Hi"
+
+      assert_raise ArgumentError, fn -> MailParser.parse_yielding(["Subject: Hi", :body]) end
+    end
+  end
+
+  describe ":max_memory_bytes option" do
+    setup do
+      attach = fn name, contents ->
+        "Subject: Archive\r\nContent-Type: multipart/mixed; boundary=b\r\n\r\n" <>
+          "--b\r\nContent-Type: application/octet-stream; name=#{name}\r\n" <>
+          "Content-Transfer-Encoding: base64\r\n\r\n" <>
+          Base.encode64(contents) <> "\r\n--b--\r\n"
+      end
+
+      %{
+        raw_message: attach.("data.bin", :binary.copy("data", 25_000)),
+        zip_bomb: attach.("zeros.gz", :zlib.gzip(:binary.copy(<<0>>, 10_000_000)))
+      }
+    end
+
+    test "bounds the decoded bodies", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{name: "data.bin"}]} =
+               MailParser.extract_nested_attachments(raw_message, max_memory_bytes: 200_000)
+
+      assert MailParser.extract_nested_attachments(raw_message, max_memory_bytes: 50_000) ==
+               {:error, :budget_exceeded}
+
+      assert MailParser.extract_attachments_batch([raw_message], max_memory_bytes: 50_000) ==
+               [{:error, :budget_exceeded}]
+    end
+
+    test "stops archives decompressing beyond it", %{zip_bomb: zip_bomb} do
+      assert {:ok, [%MailParser.Attachment{name: "zeros"}]} =
+               MailParser.extract_nested_attachments(zip_bomb, expand_archives: true)
+
+      assert MailParser.extract_nested_attachments(zip_bomb,
+               expand_archives: true,
+               max_memory_bytes: 1_000_000
+             ) == {:error, :budget_exceeded}
+    end
+
+    test "bounds parsed messages", %{raw_message: raw_message} do
+      assert {:ok, _message} = MailParser.parse(raw_message, max_memory_bytes: 200_000)
+      assert MailParser.parse(raw_message, max_memory_bytes: 50_000) == {:error, :budget_exceeded}
+
+      assert MailParser.parse_yielding(raw_message, max_memory_bytes: 50_000) ==
+               {:error, :budget_exceeded}
+    end
+
+    @tag :tmp_dir
+    test "bounds parsed files", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "archive.eml")
+      File.write!(path, raw_message)
+
+      assert {:ok, _message} = MailParser.parse_file(path, max_memory_bytes: 200_000)
+      assert MailParser.parse_file(path, max_memory_bytes: 50_000) == {:error, :budget_exceeded}
+
+      # Bodies that need no decoding are kept in what was read from the file.
+      File.write!(path, "Subject: Plain\r\n\r\n" <> :binary.copy("data", 25_000))
+      assert {:ok, _message} = MailParser.parse_file(path, max_memory_bytes: 50_000)
+    end
+
+    @tag :tmp_dir
+    test "bounds the messages of mboxes", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      path = Path.join(tmp_dir, "archive.mbox")
+      plain_message = "From: max@example.com\nSubject: Plain\n\nHello\n"
+
+      File.write!(path, [
+        "From joe@example.com Tue May 17 08:05:04 2022\n",
+        raw_message,
+        "\nFrom max@example.com Wed May 18 10:15:00 2022\n",
+        plain_message
+      ])
+
+      assert {:ok, [{:error, :budget_exceeded}, {:ok, plain}]} =
+               MailParser.parse_mbox(path, max_memory_bytes: 50_000)
+
+      assert MailParser.get_subject(plain) == "Plain"
+
+      {:ok, mbox} = MailParser.mbox_open(path)
+      assert MailParser.mbox_next(mbox, max_memory_bytes: 50_000) == {:error, :budget_exceeded}
+      assert {:ok, plain} = MailParser.mbox_next(mbox, max_memory_bytes: 50_000)
+      assert MailParser.get_subject(plain) == "Plain"
+    end
+
+    @tag :tmp_dir
+    test "bounds the messages of Maildirs", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      for directory <- ["cur", "new", "tmp"], do: File.mkdir!(Path.join(tmp_dir, directory))
+      File.write!(Path.join(tmp_dir, "cur/1.host:2,S"), raw_message)
+      File.write!(Path.join(tmp_dir, "cur/2.host:2,S"), "Subject: Plain\n\nBody")
+
+      for parallel <- [false, true] do
+        assert {:ok, [{_, {:error, :budget_exceeded}}, {_, {:ok, plain}}]} =
+                 MailParser.parse_maildir(tmp_dir, max_memory_bytes: 50_000, parallel: parallel)
+
+        assert MailParser.get_subject(plain) == "Plain"
+      end
+    end
+  end
+
+  describe "start_parse/2" do
+    setup do
+      %{raw_message: File.read!("test/fixtures/example.txt")}
+    end
+
+    test "returns a task to await the parsed message", %{raw_message: raw_message} do
+      assert {:ok, task} = MailParser.start_parse(raw_message)
+      assert {:ok, message} = MailParser.await_parse(task)
+      assert MailParser.get_subject(message) == "Bestellung 0340/2022"
+    end
+
+    test "can be cancelled from another process", %{raw_message: raw_message} do
+      assert {:ok, task} = MailParser.start_parse(raw_message)
+      assert Task.await(Task.async(fn -> MailParser.cancel_parse(task) end)) == :ok
+      assert MailParser.await_parse(task) == {:error, :cancelled}
+    end
+
+    test "queues parses beyond the threads of the pool", %{raw_message: raw_message} do
+      tasks =
+        for _ <- 1..(System.schedulers_online() * 4) do
+          {:ok, task} = MailParser.start_parse(raw_message)
+          task
+        end
+
+      for task <- tasks do
+        assert {:ok, message} = MailParser.await_parse(task)
+        assert MailParser.get_subject(message) == "Bestellung 0340/2022"
+      end
+    end
+  end
+
+  describe ":include_part_headers option" do
+    setup do
+      raw_message =
+        "Subject: Resume\r\nContent-Type: multipart/mixed; boundary=b\r\n\r\n" <>
+          "--b\r\nContent-Type: text/plain\r\n\r\nSee attached.\r\n" <>
+          "--b\r\nContent-Type: application/pdf; name=\"resume.pdf\"\r\n" <>
+          "Content-Disposition: attachment; filename=\"resume.pdf\"; size=5;\r\n" <>
+          "\tcreation-date=\"Tue, 03 Oct 2023 10:00:00 GMT\"\r\n" <>
+          "Content-Description: =?utf-8?q?R=C3=A9sum=C3=A9?=\r\n" <>
+          "X-Attachment-Id: f_lnb1\r\n" <>
+          "Content-Transfer-Encoding: base64\r\n\r\nJVBERi0=\r\n--b--\r\n"
+
+      %{raw_message: raw_message}
+    end
+
+    test "returns the headers of the part of each attachment", %{raw_message: raw_message} do
+      assert {:ok, [%MailParser.Attachment{part_headers: part_headers}]} =
+               MailParser.extract_nested_attachments(raw_message, include_part_headers: true)
+
+      assert part_headers == %{
+               "content-type" => [~s(application/pdf; name="resume.pdf")],
+               "content-disposition" => [
+                 ~s(attachment; filename="resume.pdf"; size=5; ) <>
+                   ~s(creation-date="Tue, 03 Oct 2023 10:00:00 GMT")
+               ],
+               "content-description" => ["Résumé"],
+               "x-attachment-id" => ["f_lnb1"],
+               "content-transfer-encoding" => ["base64"]
+             }
+
+      assert {:ok, [%MailParser.Attachment{part_headers: nil}]} =
+               MailParser.extract_nested_attachments(raw_message)
+    end
+
+    test "applies to list_attachments/2 and to_json/2", %{raw_message: raw_message} do
+      {:ok, message} = MailParser.parse(raw_message)
+
+      assert [%{part_headers: %{"x-attachment-id" => ["f_lnb1"]}}] =
+               MailParser.list_attachments(message, include_part_headers: true)
+
+      assert MailParser.to_json(message, include_part_headers: true) =~
+               ~s("part_headers":{"content-description":["Résumé"],)
+
+      refute MailParser.to_json(message) =~ "part_headers"
+    end
+  end
+
+  describe "get_metrics/0" do
+    setup do
+      raw_message =
+        "From: joe@example.com\r\n" <>
+          "Content-Type: multipart/mixed; boundary=\"b\"\r\n\r\n" <>
+          "--b\r\n" <>
+          "Content-Type: text/plain\r\n" <>
+          "Content-Disposition: attachment; filename=\"notes.txt\"\r\n\r\n" <>
+          "Notes\r\n" <>
+          "--b--\r\n"
+
+      %{raw_message: raw_message}
+    end
+
+    test "counts parsed messages and decoded attachments", %{raw_message: raw_message} do
+      before = MailParser.get_metrics()
+      {:ok, [_]} = MailParser.extract_nested_attachments(raw_message)
+      metrics = MailParser.get_metrics()
+
+      assert metrics.parses - before.parses >= 1
+      assert metrics.bytes_parsed - before.bytes_parsed >= byte_size(raw_message)
+      assert metrics.parse_duration > before.parse_duration
+      assert metrics.attachments_decoded - before.attachments_decoded >= 1
+    end
+
+    @tag :tmp_dir
+    test "counts bytes written to disk", %{raw_message: raw_message, tmp_dir: tmp_dir} do
+      before = MailParser.get_metrics()
+      {:ok, [_]} = MailParser.extract_attachments_to_disk(raw_message, tmp_dir)
+
+      assert MailParser.get_metrics().bytes_written - before.bytes_written >= byte_size("Notes")
+    end
   end
 end